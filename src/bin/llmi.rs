@@ -8,9 +8,13 @@ use std::path::PathBuf;
 use std::process;
 use std::time::Instant;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use colored::Colorize;
-use llm_lang::{execute, ExecuteOptions, Value};
+use llm_lang::{execute, test_examples, ExecuteOptions, Value};
+use llm_lang::runtime::cache::CacheMode;
+use llm_lang::runtime::intent::IntentRouting;
+use llm_lang::runtime::tools;
+use llm_lang::runtime::example::ExampleCheck;
 
 /// LLM.lang Interpreter
 #[derive(Parser, Debug)]
@@ -18,9 +22,14 @@ use llm_lang::{execute, ExecuteOptions, Value};
     name = "llmi",
     version = llm_lang::VERSION,
     author = "LLM.lang Contributors",
-    about = "Interpreter for the LLM.lang programming language"
+    about = "Interpreter for the LLM.lang programming language",
+    args_conflicts_with_subcommands = true
 )]
 struct Cli {
+    /// The subcommand to run instead of executing a file
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// The input file to execute
     #[clap(value_parser)]
     input: Option<PathBuf>,
@@ -41,6 +50,72 @@ struct Cli {
     #[clap(long)]
     no_parallel: bool,
 
+    /// The maximum number of threads used by parallel blocks
+    #[clap(long, default_value_t = 4)]
+    threads: usize,
+
+    /// A local embedding model directory (tokenizer.json and embeddings.bin)
+    #[clap(long)]
+    embedding_model: Option<PathBuf>,
+
+    /// The number of dimensions of embeddings
+    #[clap(long, default_value_t = 32)]
+    embedding_dimension: usize,
+
+    /// The base URL of an OpenAI-compatible server, such as http://localhost:8080/v1
+    ///
+    /// The key in the LLM_API_KEY environment variable, if any, is sent to the server.
+    #[clap(long)]
+    llm_url: Option<String>,
+
+    /// The model to request from the language model server
+    #[clap(long)]
+    llm_model: Option<String>,
+
+    /// The model to request embeddings from the language model server with
+    #[clap(long)]
+    llm_embedding_model: Option<String>,
+
+    /// A file to cache language model and embedding responses in
+    #[clap(long)]
+    cache: Option<PathBuf>,
+
+    /// How the response cache is used: record, replay (fail on a miss) or passthrough
+    #[clap(long, default_value = "record")]
+    cache_mode: CacheMode,
+
+    /// The maximum number of tokens to send to and receive from language models
+    #[clap(long)]
+    token_limit: Option<u64>,
+
+    /// A tokenizer file (tokenizer.json) to count tokens with, instead of counting words
+    #[clap(long)]
+    tokenizer: Option<PathBuf>,
+
+    /// The confidence a probabilistic value needs to be used by `??`
+    #[clap(long, default_value_t = 0.5)]
+    confidence_threshold: f64,
+
+    /// The confidence an utterance needs to go to an intent handler other than the fallback
+    #[clap(long, default_value_t = 0.75)]
+    intent_threshold: f64,
+
+    /// How utterances that do not match an intent pattern exactly are routed: embedding or model
+    #[clap(long, default_value = "embedding")]
+    intent_routing: IntentRouting,
+
+    /// The number of tools a language model may call in one `agent` call before it must answer
+    #[clap(long, default_value_t = 8)]
+    max_tool_steps: usize,
+
+    /// The number of chunks of a `stream` that are held before the language model waits for them to be read
+    #[clap(long, default_value_t = 16)]
+    stream_buffer: usize,
+
+    /// How long a `stream` without a timeout waits for each chunk, in milliseconds
+    #[clap(long)]
+    stream_timeout: Option<u64>,
+
     /// Whether to disable vector operations
     #[clap(long)]
     no_vectors: bool,
@@ -58,6 +133,17 @@ struct Cli {
     interactive: bool,
 }
 
+/// Subcommands of the interpreter
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the functions of a file on their `examples for` blocks and report mismatches
+    Test {
+        /// The input file to test
+        #[clap(value_parser)]
+        input: PathBuf,
+    },
+}
+
 fn main() {
     // Parse command-line arguments
     let cli = Cli::parse();
@@ -70,26 +156,97 @@ fn main() {
         parallel: !cli.no_parallel,
         vectors: !cli.no_vectors,
         nlp: !cli.no_nlp,
+        max_threads: cli.threads,
+        embedding_dimension: cli.embedding_dimension,
+        embedding_model: cli.embedding_model,
+        llm_url: cli.llm_url,
+        llm_model: cli.llm_model,
+        llm_embedding_model: cli.llm_embedding_model,
+        llm_api_key: std::env::var("LLM_API_KEY").ok(),
+        cache: cli.cache,
+        cache_mode: cli.cache_mode,
+        max_tokens: cli.token_limit,
+        tokenizer: cli.tokenizer,
+        confidence_threshold: cli.confidence_threshold,
+        intent_threshold: cli.intent_threshold,
+        intent_routing: cli.intent_routing,
+        max_tool_steps: cli.max_tool_steps,
+        stream_buffer: cli.stream_buffer,
+        stream_timeout: cli.stream_timeout,
     };
 
+    // Check if we should test the examples of a file
+    if let Some(Command::Test { input }) = cli.command {
+        let source = read_source(&input);
+        test_source(&source, options);
+        return;
+    }
+
     // Check if we should start an interactive REPL
     if cli.interactive || cli.input.is_none() {
         run_repl(options);
     } else {
         // Execute the input file
         let input = cli.input.unwrap();
-        let source = match fs::read_to_string(&input) {
-            Ok(source) => source,
-            Err(err) => {
-                eprintln!("{}: Failed to read input file: {}", "Error".red().bold(), err);
-                process::exit(1);
-            }
-        };
+        let source = read_source(&input);
 
         execute_source(&source, &input.to_string_lossy(), options, cli.stats);
     }
 }
 
+/// Read an input file, exiting if it cannot be read
+fn read_source(input: &PathBuf) -> String {
+    match fs::read_to_string(input) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("{}: Failed to read input file: {}", "Error".red().bold(), err);
+            process::exit(1);
+        }
+    }
+}
+
+/// Check the functions of LLM.lang source code against their examples
+fn test_source(source: &str, options: ExecuteOptions) {
+    let checks = match test_examples(source, options) {
+        Ok(checks) => checks,
+        Err(err) => {
+            eprintln!("{}: {}", "Error".red().bold(), err);
+            process::exit(1);
+        }
+    };
+
+    let failed: Vec<&ExampleCheck> = checks.iter().filter(|check| !check.passed()).collect();
+
+    for check in &checks {
+        let call = check.call();
+
+        if check.passed() {
+            println!("{} {}", "ok".green().bold(), call);
+        } else {
+            println!("{} {}", "FAILED".red().bold(), call);
+
+            for line in check.diff().lines() {
+                if line.starts_with('-') {
+                    println!("    {}", line.red());
+                } else {
+                    println!("    {}", line.green());
+                }
+            }
+        }
+    }
+
+    println!(
+        "\n{}: {} passed, {} failed",
+        "Examples".blue().bold(),
+        checks.len() - failed.len(),
+        failed.len()
+    );
+
+    if !failed.is_empty() {
+        process::exit(1);
+    }
+}
+
 /// Execute LLM.lang source code
 fn execute_source(source: &str, filename: &str, options: ExecuteOptions, show_stats: bool) {
     let start_time = Instant::now();
@@ -110,6 +267,29 @@ fn execute_source(source: &str, filename: &str, options: ExecuteOptions, show_st
                 println!("  Time: {:.2?}", elapsed);
                 println!("  Peak memory: {} bytes", result.stats.peak_memory);
                 println!("  Instructions executed: {}", result.stats.instructions);
+                println!("  Tokens: {}", result.stats.tokens);
+
+                for usage in &result.stats.token_usage {
+                    println!(
+                        "    {}:{}: {} tokens ({} prompt, {} completion) in {} call{}",
+                        filename,
+                        usage.location.start_line,
+                        usage.total_tokens(),
+                        usage.prompt_tokens,
+                        usage.completion_tokens,
+                        usage.calls,
+                        if usage.calls == 1 { "" } else { "s" }
+                    );
+                }
+
+                println!("  Tool calls: {}", result.stats.tool_calls.len());
+
+                for call in &result.stats.tool_calls {
+                    match &call.result {
+                        Ok(value) => println!("    {}({}) -> {}", call.tool, tools::value_to_json(&call.arguments), tools::value_to_json(value)),
+                        Err(error) => println!("    {}({}) failed: {}", call.tool, tools::value_to_json(&call.arguments), error),
+                    }
+                }
             }
         }
         Err(err) => {
@@ -239,5 +419,8 @@ fn format_value(value: &Value) -> String {
         Value::Function(name) => format!("<function {}>", name),
         Value::Vector(v) => format!("<vector with {} dimensions>", v.len()),
         Value::Context(name) => format!("<context {}>", name),
+        Value::Prob { value, confidence, .. } => {
+            format!("{} ({:.0}% confident)", format_value(value), confidence * 100.0)
+        }
     }
 }
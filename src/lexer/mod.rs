@@ -153,6 +153,7 @@ impl Lexer {
             
             // Operators
             '+' | '-' | '*' | '/' | '%' | '=' | '!' | '<' | '>' | '&' | '|' | '^' => self.operator(),
+            '?' if self.peek_next() == Some('?') => self.operator(),
             
             // Delimiters
             '(' | ')' | '{' | '}' | '[' | ']' | ';' | ',' | '.' | ':' => self.delimiter(),
@@ -302,7 +303,7 @@ impl Lexer {
         // Check if it's a keyword
         let kind = match identifier {
             "context" | "fn" | "var" | "if" | "else" | "when" | "otherwise" | "parallel" | "select" |
-            "return" | "with" | "within" | "intent" | "examples" | "transform" | "into" | "apply" |
+            "return" | "with" | "within" | "deadline" | "intent" | "examples" | "transform" | "into" | "apply" |
             "for" | "in" | "true" | "false" | "null" | "and" | "or" | "not" | "vector" | "to" |
             "Int" | "Float" | "String" | "Bool" | "List" | "Map" | "Vector" | "Context" |
             "fastest" | "best" | "all" | "path" => TokenKind::Keyword,
@@ -341,6 +342,9 @@ impl Lexer {
                 self.next();
             } else if c.is_digit(10) {
                 self.next();
+            } else if c == '_' && self.peek_next().is_some_and(|next| next.is_ascii_digit()) {
+                // Skip digit separators, as in 10_000
+                self.next();
             } else if c == 'e' || c == 'E' {
                 // Handle scientific notation
                 self.next();
@@ -367,13 +371,13 @@ impl Lexer {
             }
         }
         
-        // Get the number
-        let number = &self.source[start_position..self.position];
+        // Get the number, without digit separators
+        let number = self.source[start_position..self.position].replace('_', "");
         
         // Create the token
         Token {
             kind: if is_float { TokenKind::FloatLiteral } else { TokenKind::IntLiteral },
-            value: number.to_string(),
+            value: number,
             location: SourceLocation::new(
                 start_line,
                 start_column,
@@ -624,6 +628,9 @@ impl Lexer {
                     self.next();
                 }
             }
+            '?' if self.peek() == Some('?') => {
+                self.next();
+            }
             _ => {}
         }
         
@@ -758,6 +765,15 @@ mod tests {
         assert_eq!(tokens[1].kind, TokenKind::FloatLiteral);
         assert_eq!(tokens[1].value, "3.14");
         assert_eq!(tokens[2].kind, TokenKind::Eof);
+        
+        // Digit separators are dropped
+        let tokens = Lexer::new("10_000 1_000.5 7_").tokenize().unwrap();
+        
+        assert_eq!(tokens[0].value, "10000");
+        assert_eq!(tokens[1].kind, TokenKind::FloatLiteral);
+        assert_eq!(tokens[1].value, "1000.5");
+        assert_eq!(tokens[2].value, "7");
+        assert_eq!(tokens[3].kind, TokenKind::Identifier);
     }
     
     #[test]
@@ -839,6 +855,19 @@ mod tests {
         assert_eq!(tokens[13].kind, TokenKind::Eof);
     }
     
+    #[test]
+    fn test_tokenize_probability_operator() {
+        let mut lexer = Lexer::new("guess ?? 0");
+        let tokens = lexer.tokenize().unwrap();
+        
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(tokens[1].kind, TokenKind::Operator);
+        assert_eq!(tokens[1].value, "??");
+        
+        // A single question mark is not an operator
+        assert!(Lexer::new("guess ? 0").tokenize().is_err());
+    }
+    
     #[test]
     fn test_tokenize_delimiter() {
         let mut lexer = Lexer::new("( ) { } [ ] ; , . :");
@@ -160,6 +160,7 @@ pub const KEYWORDS: &[&str] = &[
     "otherwise",
     "for",
     "in",
+    "deadline",
     
     // Parallel execution keywords
     "parallel",
@@ -211,6 +212,9 @@ pub const OPERATORS: &[&str] = &[
     // Logical operators
     "&&", "||", "!",
     
+    // Probability operators
+    "??",
+    
     // Arrow operators
     "->", "=>",
     
@@ -70,6 +70,45 @@ pub fn compile(source: &str, options: CompileOptions) -> Result<CompiledProgram,
 ///
 /// A result containing the execution result or an error
 pub fn execute(source: &str, options: ExecuteOptions) -> Result<ExecutionResult, ExecutionError> {
+    let (mut engine, analyzed_ast) = prepare(source, options)?;
+    
+    // Execute the analyzed AST
+    let value = engine.execute(analyzed_ast)?;
+    
+    // Create the execution result
+    let result = ExecutionResult {
+        value,
+        stats: engine.get_stats(),
+    };
+    
+    Ok(result)
+}
+
+/// Check the functions of LLM.lang source code against their examples
+///
+/// Only the declarations of the program are registered, without running its
+/// statements. Then every function declared with `fn` that also has an
+/// `examples for` block is run on each example input.
+///
+/// # Arguments
+///
+/// * `source` - The LLM.lang source code
+/// * `options` - Execution options
+///
+/// # Returns
+///
+/// A result containing one check per example or an error
+pub fn test_examples(source: &str, options: ExecuteOptions) -> Result<Vec<runtime::example::ExampleCheck>, ExecutionError> {
+    let (mut engine, analyzed_ast) = prepare(source, options)?;
+    
+    // Register the functions and examples
+    engine.declare(&analyzed_ast)?;
+    
+    Ok(engine.check_examples())
+}
+
+/// Analyze LLM.lang source code and create the engine to execute it
+fn prepare(source: &str, options: ExecuteOptions) -> Result<(Engine, Ast), ExecutionError> {
     // Create a lexer
     let mut lexer = Lexer::new(source);
     
@@ -88,19 +127,49 @@ pub fn execute(source: &str, options: ExecuteOptions) -> Result<ExecutionResult,
     // Analyze the AST
     let analyzed_ast = analyzer.analyze(ast).map_err(|e| ExecutionError::SemanticError(e))?;
     
-    // Create a runtime engine
-    let mut engine = Engine::new(options.into());
+    // Load the embedding model, if any
+    let embedding_model = options.embedding_model.clone();
+    let (llm_url, llm_model, llm_api_key) = (options.llm_url.clone(), options.llm_model.clone(), options.llm_api_key.clone());
+    let llm_embedding_model = options.llm_embedding_model.clone();
+    let (cache, cache_mode) = (options.cache.clone(), options.cache_mode);
+    let tokenizer = options.tokenizer.clone();
+    let mut engine_options: runtime::engine::EngineOptions = options.into();
+    
+    if let Some(path) = embedding_model {
+        let provider = runtime::vector::LocalModelEmbedder::load(&path, engine_options.embedding_dimension)?;
+        engine_options.embedding_provider = Some(std::sync::Arc::new(provider));
+    }
     
-    // Execute the analyzed AST
-    let value = engine.execute(analyzed_ast)?;
+    // Connect to the language model server, if any
+    if let Some(url) = llm_url {
+        let model = llm_model.as_deref().unwrap_or(runtime::llm::DEFAULT_MODEL);
+        let mut backend = runtime::llm::OpenAiBackend::new(&url, model)?;
+        
+        if let Some(embedding_model) = llm_embedding_model {
+            backend = backend.with_embedding_model(&embedding_model);
+        }
+        
+        if let Some(api_key) = llm_api_key {
+            backend = backend.with_api_key(&api_key);
+        }
+        
+        engine_options.llm_backend = Some(std::sync::Arc::new(backend));
+    }
     
-    // Create the execution result
-    let result = ExecutionResult {
-        value,
-        stats: engine.get_stats(),
-    };
+    // Open the response cache, if any
+    if let Some(path) = cache {
+        let cache = runtime::cache::ResponseCache::open(&path, cache_mode)?;
+        engine_options.response_cache = Some(std::sync::Arc::new(cache));
+    }
     
-    Ok(result)
+    // Load the tokenizer, if any
+    if let Some(path) = tokenizer {
+        let counter = runtime::budget::TokenCounter::from_file(&path)?;
+        engine_options.token_counter = Some(std::sync::Arc::new(counter));
+    }
+    
+    // Create a runtime engine
+    Ok((Engine::new(engine_options), analyzed_ast))
 }
 
 /// Options for compiling LLM.lang source code
@@ -150,6 +219,57 @@ pub struct ExecuteOptions {
     
     /// Whether to enable natural language processing
     pub nlp: bool,
+    
+    /// The maximum number of threads used by parallel blocks
+    pub max_threads: usize,
+    
+    /// The number of dimensions of embeddings
+    pub embedding_dimension: usize,
+    
+    /// A local embedding model directory to use instead of the default hash embedder
+    pub embedding_model: Option<std::path::PathBuf>,
+    
+    /// The base URL of an OpenAI-compatible server to use instead of the mock language model
+    pub llm_url: Option<String>,
+    
+    /// The model to request from the language model server
+    pub llm_model: Option<String>,
+    
+    /// The model to request embeddings from the language model server with
+    pub llm_embedding_model: Option<String>,
+    
+    /// The key to send to the language model server
+    pub llm_api_key: Option<String>,
+    
+    /// A file to cache language model and embedding responses in
+    pub cache: Option<std::path::PathBuf>,
+    
+    /// How the response cache is used
+    pub cache_mode: runtime::cache::CacheMode,
+    
+    /// The maximum number of tokens the program may send to and receive from language models
+    pub max_tokens: Option<u64>,
+    
+    /// A tokenizer file (tokenizer.json) to count tokens with instead of counting words
+    pub tokenizer: Option<std::path::PathBuf>,
+    
+    /// The confidence a probabilistic value needs to be used by `??`
+    pub confidence_threshold: f64,
+    
+    /// The confidence a routed utterance needs to go to an intent handler other than the fallback
+    pub intent_threshold: f64,
+    
+    /// How utterances that do not match an intent pattern exactly are routed
+    pub intent_routing: runtime::intent::IntentRouting,
+    
+    /// The number of tools a language model may call in one `agent` call before it must answer
+    pub max_tool_steps: usize,
+    
+    /// The number of chunks of a `stream` that are held before the language model waits for them to be read
+    pub stream_buffer: usize,
+    
+    /// How long a `stream` without a timeout waits for each chunk, in milliseconds
+    pub stream_timeout: Option<u64>,
 }
 
 impl Default for ExecuteOptions {
@@ -161,6 +281,23 @@ impl Default for ExecuteOptions {
             parallel: true,
             vectors: true,
             nlp: true,
+            max_threads: 4,
+            embedding_dimension: runtime::vector::DEFAULT_EMBEDDING_DIMENSION,
+            embedding_model: None,
+            llm_url: None,
+            llm_model: None,
+            llm_embedding_model: None,
+            llm_api_key: None,
+            cache: None,
+            cache_mode: runtime::cache::CacheMode::Record,
+            max_tokens: None,
+            tokenizer: None,
+            confidence_threshold: runtime::engine::DEFAULT_CONFIDENCE_THRESHOLD,
+            intent_threshold: runtime::intent::DEFAULT_INTENT_THRESHOLD,
+            intent_routing: runtime::intent::IntentRouting::Embedding,
+            max_tool_steps: runtime::tools::DEFAULT_MAX_TOOL_STEPS,
+            stream_buffer: runtime::stream::DEFAULT_STREAM_BUFFER,
+            stream_timeout: None,
         }
     }
 }
@@ -216,10 +353,19 @@ pub struct ExecutionStats {
     
     /// The number of instructions executed
     pub instructions: u64,
+    
+    /// The number of tokens sent to and received from language models
+    pub tokens: u64,
+    
+    /// The token usage of each expression that called a language model, in source order
+    pub token_usage: Vec<runtime::budget::TokenUsage>,
+    
+    /// The tools called by language models, in call order
+    pub tool_calls: Vec<runtime::tools::ToolCall>,
 }
 
 /// A value in the LLM.lang runtime
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum Value {
     /// No value
     Void,
@@ -250,6 +396,61 @@ pub enum Value {
     
     /// A context value
     Context(String),
+    
+    /// A value that is only correct with some confidence
+    Prob {
+        /// The most likely value
+        value: Box<Value>,
+        
+        /// The confidence in the most likely value, between 0.0 and 1.0
+        confidence: f64,
+        
+        /// The less likely values with their confidences, most likely first
+        alternatives: Vec<(Value, f64)>,
+    },
+}
+
+impl Value {
+    /// Create a probabilistic value
+    ///
+    /// The alternatives are sorted so that the most likely one comes first.
+    pub fn prob(value: Value, confidence: f64, mut alternatives: Vec<(Value, f64)>) -> Self {
+        alternatives.sort_by(|a, b| b.1.total_cmp(&a.1));
+        
+        Value::Prob {
+            value: Box::new(value),
+            confidence,
+            alternatives,
+        }
+    }
+    
+    /// Get the most likely value
+    ///
+    /// Values that are not probabilistic are returned as they are.
+    pub fn most_likely(&self) -> &Value {
+        match self {
+            Value::Prob { value, .. } => value.most_likely(),
+            other => other,
+        }
+    }
+    
+    /// Turn a probabilistic value into its most likely value
+    pub fn into_most_likely(self) -> Value {
+        match self {
+            Value::Prob { value, .. } => value.into_most_likely(),
+            other => other,
+        }
+    }
+    
+    /// Get the confidence in the most likely value
+    ///
+    /// Values that are not probabilistic are certain.
+    pub fn confidence(&self) -> f64 {
+        match self {
+            Value::Prob { value, confidence, .. } => confidence * value.confidence(),
+            _ => 1.0,
+        }
+    }
 }
 
 /// Errors that can occur during compilation
@@ -319,6 +520,19 @@ impl From<ExecuteOptions> for runtime::engine::EngineOptions {
             vectors: options.vectors,
             nlp: options.nlp,
             self_modifying: true,
+            max_threads: options.max_threads,
+            embedding_dimension: options.embedding_dimension,
+            embedding_provider: None,
+            llm_backend: None,
+            response_cache: None,
+            max_tokens: options.max_tokens,
+            token_counter: None,
+            confidence_threshold: options.confidence_threshold,
+            intent_threshold: options.intent_threshold,
+            intent_routing: options.intent_routing,
+            max_tool_steps: options.max_tool_steps,
+            stream_buffer: options.stream_buffer,
+            stream_timeout: options.stream_timeout,
         }
     }
 }
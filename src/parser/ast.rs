@@ -15,7 +15,7 @@ pub struct Ast {
 }
 
 /// An AST node
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Node {
     /// The node type
     pub kind: NodeKind,
@@ -31,7 +31,7 @@ pub struct Node {
 }
 
 /// Node types
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum NodeKind {
     /// A program
     Program,
@@ -78,9 +78,28 @@ pub enum NodeKind {
     /// A within statement
     Within,
     
+    /// A deadline statement
+    Deadline,
+    
+    /// A token budget statement
+    Budget,
+    
     /// An intent statement
     Intent,
     
+    /// An intent handler declaration
+    IntentHandler,
+    
+    /// A block of functions that language models can call
+    Tools,
+    /// A prompt declaration
+    Prompt,
+    /// An expression rendering a prompt without calling the language model
+    Render,
+    
+    /// An expression streaming the completion of a prompt in chunks
+    Stream,
+    
     /// A parallel statement
     Parallel,
     
@@ -99,6 +118,15 @@ pub enum NodeKind {
     /// An example
     Example,
     
+    /// A transformation definition
+    Transform,
+    
+    /// An expression applying a transformer to a value
+    TransformApply,
+    
+    /// A record type declaration
+    TypeDeclaration,
+    
     /// An assignment expression
     Assignment,
     
@@ -279,6 +307,30 @@ pub enum Statement {
         body: Box<Statement>,
     },
     
+    /// A deadline statement
+    Deadline {
+        /// The timeout in milliseconds
+        timeout: u64,
+        
+        /// The body
+        body: Box<Statement>,
+        
+        /// The block to run if the deadline passes
+        otherwise: Option<Box<Statement>>,
+    },
+    
+    /// A token budget statement
+    Budget {
+        /// The number of tokens the body may use
+        tokens: u64,
+        
+        /// The body
+        body: Box<Statement>,
+        
+        /// The block to run if the budget runs out
+        otherwise: Option<Box<Statement>>,
+    },
+    
     /// An intent statement
     Intent(Expression),
     
@@ -345,6 +397,15 @@ pub enum Type {
     
     /// A semantic type
     Semantic(String),
+    
+    /// A list type with the type of its items
+    ListOf(Box<Type>),
+    
+    /// A map type with the type of its values
+    MapOf(Box<Type>),
+    
+    /// A record type declared with `type`
+    Named(String),
 }
 
 impl fmt::Display for Type {
@@ -359,6 +420,9 @@ impl fmt::Display for Type {
             Type::Vector => write!(f, "Vector"),
             Type::Context => write!(f, "Context"),
             Type::Semantic(name) => write!(f, "{}", name),
+            Type::ListOf(item) => write!(f, "List<{}>", item),
+            Type::MapOf(value) => write!(f, "Map<{}>", value),
+            Type::Named(name) => write!(f, "{}", name),
         }
     }
 }
@@ -5,6 +5,7 @@
 
 pub mod ast;
 pub mod error;
+pub mod template;
 
 use std::iter::Peekable;
 use std::slice::Iter;
@@ -70,6 +71,16 @@ impl Parser {
                 // Parse a function declaration
                 let function = self.parse_function_declaration()?;
                 program.children.push(Box::new(function));
+            } else if self.check_intent_handler() {
+                // Parse an intent handler declaration
+                self.advance();
+                let handler = self.parse_intent_handler()?;
+                program.children.push(Box::new(handler));
+            } else if self.check_tools_block() {
+                // Parse a tools block
+                self.advance();
+                let tools = self.parse_tools_block()?;
+                program.children.push(Box::new(tools));
             } else {
                 // Parse a statement
                 let statement = self.parse_statement()?;
@@ -112,6 +123,26 @@ impl Parser {
                 // Parse a variable declaration
                 let variable = self.parse_variable_declaration()?;
                 context.children.push(Box::new(variable));
+            } else if self.check_type_declaration() {
+                // Parse a type declaration
+                self.advance();
+                let declaration = self.parse_type_declaration()?;
+                context.children.push(Box::new(declaration));
+            } else if self.check_prompt_declaration() {
+                // Parse a prompt declaration
+                self.advance();
+                let declaration = self.parse_prompt_declaration()?;
+                context.children.push(Box::new(declaration));
+            } else if self.check_intent_handler() {
+                // Parse an intent handler declaration
+                self.advance();
+                let handler = self.parse_intent_handler()?;
+                context.children.push(Box::new(handler));
+            } else if self.check_tools_block() {
+                // Parse a tools block
+                self.advance();
+                let tools = self.parse_tools_block()?;
+                context.children.push(Box::new(tools));
             } else {
                 // Unexpected token
                 let token = self.peek().unwrap();
@@ -228,9 +259,25 @@ impl Parser {
         } else if self.match_keyword("Bool") {
             Ok(Type::Bool)
         } else if self.match_keyword("List") {
-            Ok(Type::List)
+            // Parse the item type (if any)
+            if self.match_operator("<") {
+                let item = self.parse_type()?;
+                self.consume_operator(">", "Expected '>' after list item type")?;
+                
+                Ok(Type::ListOf(Box::new(item)))
+            } else {
+                Ok(Type::List)
+            }
         } else if self.match_keyword("Map") {
-            Ok(Type::Map)
+            // Parse the value type (if any)
+            if self.match_operator("<") {
+                let value = self.parse_type()?;
+                self.consume_operator(">", "Expected '>' after map value type")?;
+                
+                Ok(Type::MapOf(Box::new(value)))
+            } else {
+                Ok(Type::Map)
+            }
         } else if self.match_keyword("Vector") {
             Ok(Type::Vector)
         } else if self.match_keyword("Context") {
@@ -238,6 +285,10 @@ impl Parser {
         } else if self.check_token(TokenKind::SemanticType) {
             let token = self.advance().unwrap();
             Ok(Type::Semantic(token.value.clone()))
+        } else if self.match_token(TokenKind::Identifier) {
+            // A record type declared with `type`
+            let token = self.previous().unwrap();
+            Ok(Type::Named(token.value.clone()))
         } else {
             let token = self.peek().unwrap();
             Err(ParserError::new(
@@ -290,6 +341,17 @@ impl Parser {
         } else if self.match_keyword("examples") {
             // Parse an examples statement
             self.parse_examples_statement()
+        } else if self.match_keyword("transform") {
+            // Parse a transform statement
+            self.parse_transform_statement()
+        } else if self.check_type_declaration() {
+            // Parse a type declaration
+            self.advance();
+            self.parse_type_declaration()
+        } else if self.check_prompt_declaration() {
+            // Parse a prompt declaration
+            self.advance();
+            self.parse_prompt_declaration()
         } else if self.match_keyword("for") {
             // Parse a for statement
             self.parse_for_statement()
@@ -302,6 +364,13 @@ impl Parser {
         } else if self.match_keyword("within") {
             // Parse a within statement
             self.parse_within_statement()
+        } else if self.match_keyword("deadline") {
+            // Parse a deadline statement
+            self.parse_deadline_statement()
+        } else if self.check_budget_statement() {
+            // Parse a budget statement
+            self.advance();
+            self.parse_budget_statement()
         } else if self.match_keyword("intent") {
             // Parse an intent statement
             self.parse_intent_statement()
@@ -381,6 +450,9 @@ impl Parser {
         } else if token.kind == TokenKind::SemanticType {
             // We've consumed a semantic type, so set the type
             typ = Some(Type::Semantic(token.value.clone()));
+        } else if token.value == "vector" {
+            // Vector declarations are typed
+            typ = Some(Type::Vector);
         }
         
         // Parse the variable name
@@ -551,8 +623,8 @@ impl Parser {
     fn parse_for_statement(&mut self) -> ParserResult<Node> {
         // We've already consumed the "for" keyword
         
-        // Parse the loop variable
-        self.consume_delimiter("(", "Expected '(' after 'for'")?;
+        // Parse the loop variable, which may be parenthesized with the collection
+        let parenthesized = self.match_delimiter("(");
         let variable = self.consume_identifier("Expected loop variable")?;
         
         // Parse the "in" keyword
@@ -562,7 +634,9 @@ impl Parser {
         let collection = self.parse_expression()?;
         
         // Parse the closing parenthesis
-        self.consume_delimiter(")", "Expected ')' after collection expression")?;
+        if parenthesized {
+            self.consume_delimiter(")", "Expected ')' after collection expression")?;
+        }
         
         // Parse the loop body
         let body = self.parse_block()?;
@@ -678,12 +752,119 @@ impl Parser {
         Ok(within_statement)
     }
     
+    /// Parse a deadline statement
+    fn parse_deadline_statement(&mut self) -> ParserResult<Node> {
+        // We've already consumed the "deadline" keyword
+        
+        // Parse the timeout
+        let timeout = self.parse_duration()?;
+        
+        // Parse the body
+        let body = self.parse_block()?;
+        
+        // Create a deadline statement node
+        let location = self.current_location();
+        let mut deadline_statement = Node {
+            kind: NodeKind::Deadline,
+            location,
+            children: Vec::new(),
+            attributes: std::collections::HashMap::new(),
+        };
+        
+        // Add the timeout attribute
+        deadline_statement.attributes.insert("timeout".to_string(), timeout.to_string());
+        
+        // Add the body as a child
+        deadline_statement.children.push(Box::new(body));
+        
+        // Parse the otherwise block (if any)
+        if self.match_keyword("otherwise") {
+            let otherwise = self.parse_block()?;
+            deadline_statement.children.push(Box::new(otherwise));
+        }
+        
+        Ok(deadline_statement)
+    }
+    
+    /// Check if the next tokens start a budget statement
+    ///
+    /// `budget` is not a reserved keyword, so it only starts a statement when
+    /// it is followed by `tokens`.
+    fn check_budget_statement(&self) -> bool {
+        self.peek().is_some_and(|token| token.is_identifier_with_value("budget"))
+            && self.tokens.get(self.position + 1).is_some_and(|token| token.is_identifier_with_value("tokens"))
+    }
+    
+    /// Parse a budget statement
+    fn parse_budget_statement(&mut self) -> ParserResult<Node> {
+        // We've already consumed the "budget" word
+        self.advance();
+        
+        // Parse the number of tokens
+        let tokens = if self.match_token(TokenKind::IntLiteral) {
+            let token = self.previous().unwrap();
+            token.value.parse::<u64>().map_err(|_| {
+                ParserError::new(&format!("Invalid token budget: {}", token.value), token.location.clone())
+            })?
+        } else {
+            let token = self.peek().unwrap();
+            return Err(ParserError::new(
+                &format!("Expected number of tokens, got {}", token.value),
+                token.location.clone(),
+            ));
+        };
+        
+        // Parse the body
+        let body = self.parse_block()?;
+        
+        // Create a budget statement node
+        let location = self.current_location();
+        let mut budget_statement = Node {
+            kind: NodeKind::Budget,
+            location,
+            children: Vec::new(),
+            attributes: std::collections::HashMap::new(),
+        };
+        
+        // Add the tokens attribute
+        budget_statement.attributes.insert("tokens".to_string(), tokens.to_string());
+        
+        // Add the body as a child
+        budget_statement.children.push(Box::new(body));
+        
+        // Parse the otherwise block (if any)
+        if self.match_keyword("otherwise") {
+            let otherwise = self.parse_block()?;
+            budget_statement.children.push(Box::new(otherwise));
+        }
+        
+        Ok(budget_statement)
+    }
+    
     /// Parse an intent statement
+    ///
+    /// The intent is either the free text after a colon, or an expression.
     fn parse_intent_statement(&mut self) -> ParserResult<Node> {
         // We've already consumed the "intent" keyword
         
-        // Parse the colon
-        self.consume_delimiter(":", "Expected ':' after 'intent'")?;
+        // Parse an intent expression
+        if !self.match_delimiter(":") {
+            let expression = self.parse_expression()?;
+            
+            self.consume_delimiter(";", "Expected ';' after intent expression")?;
+            
+            let location = self.current_location();
+            let mut intent_statement = Node {
+                kind: NodeKind::Intent,
+                location,
+                children: Vec::new(),
+                attributes: std::collections::HashMap::new(),
+            };
+            
+            intent_statement.children.push(Box::new(expression));
+            
+            return Ok(intent_statement);
+        }
         
         // Create an intent statement node
         let location = self.current_location();
@@ -723,6 +904,107 @@ impl Parser {
         Ok(intent_statement)
     }
     
+    /// Check if the next tokens start an intent handler declaration
+    ///
+    /// `on` is not a reserved keyword, so it only starts a declaration when it
+    /// is followed by `intent`.
+    fn check_intent_handler(&self) -> bool {
+        self.peek().is_some_and(|token| token.is_identifier_with_value("on"))
+            && self.tokens.get(self.position + 1).is_some_and(|token| token.is_keyword_with_value("intent"))
+    }
+    
+    /// Parse an intent handler declaration
+    fn parse_intent_handler(&mut self) -> ParserResult<Node> {
+        // We've already consumed the "on" word
+        self.consume_keyword("intent", "Expected 'intent' after 'on'")?;
+        
+        // Parse the pattern, or `otherwise` for the fallback handler
+        let pattern = if self.match_keyword("otherwise") {
+            None
+        } else if self.match_token(TokenKind::StringLiteral) {
+            let value = &self.previous().unwrap().value;
+            Some(value[1..value.len() - 1].to_string())
+        } else {
+            let token = self.peek().unwrap();
+            return Err(ParserError::new(
+                &format!("Expected intent pattern or 'otherwise', got {}", token.value),
+                token.location.clone(),
+            ));
+        };
+        
+        // Parse the handler function
+        self.consume_keyword("fn", "Expected 'fn' after intent pattern")?;
+        let function = self.parse_function_declaration()?;
+        
+        // Create an intent handler node
+        let location = self.current_location();
+        let mut handler = Node {
+            kind: NodeKind::IntentHandler,
+            location,
+            children: Vec::new(),
+            attributes: std::collections::HashMap::new(),
+        };
+        
+        // Add the pattern attribute, or mark the fallback handler
+        match pattern {
+            Some(pattern) => handler.attributes.insert("pattern".to_string(), pattern),
+            None => handler.attributes.insert("fallback".to_string(), "true".to_string()),
+        };
+        
+        // Add the function as a child
+        handler.children.push(Box::new(function));
+        
+        Ok(handler)
+    }
+    
+    /// Check if the next tokens start a tools block
+    ///
+    /// `tools` is not a reserved keyword, so it only starts a block when it is
+    /// followed by `{`.
+    fn check_tools_block(&self) -> bool {
+        self.peek().is_some_and(|token| token.is_identifier_with_value("tools"))
+            && self.tokens.get(self.position + 1).is_some_and(|token| token.kind == TokenKind::Delimiter && token.value == "{")
+    }
+    
+    /// Parse a tools block
+    fn parse_tools_block(&mut self) -> ParserResult<Node> {
+        // We've already consumed the "tools" word
+        self.consume_delimiter("{", "Expected '{' after 'tools'")?;
+        
+        // Create a tools node
+        let location = self.current_location();
+        let mut tools = Node {
+            kind: NodeKind::Tools,
+            location,
+            children: Vec::new(),
+            attributes: std::collections::HashMap::new(),
+        };
+        
+        // Parse the functions, each with an optional description
+        while !self.check_delimiter("}") && !self.is_at_end() {
+            let description = if self.match_token(TokenKind::StringLiteral) {
+                let value = &self.previous().unwrap().value;
+                Some(value[1..value.len() - 1].to_string())
+            } else {
+                None
+            };
+            
+            self.consume_keyword("fn", "Expected tool function")?;
+            let mut function = self.parse_function_declaration()?;
+            
+            if let Some(description) = description {
+                function.attributes.insert("description".to_string(), description);
+            }
+            
+            tools.children.push(Box::new(function));
+        }
+        
+        // Consume the closing brace
+        self.consume_delimiter("}", "Expected '}' after tools")?;
+        
+        Ok(tools)
+    }
+    
     /// Parse a parallel statement
     fn parse_parallel_statement(&mut self) -> ParserResult<Node> {
         // We've already consumed the "parallel" keyword
@@ -758,6 +1040,12 @@ impl Parser {
             "best"
         } else if self.match_keyword("all") {
             "all"
+        } else if self.match_identifier("first_success") {
+            "first_success"
+        } else if self.match_identifier("vote") {
+            "vote"
+        } else if self.match_identifier("merge") {
+            "merge"
         } else {
             let token = self.peek().unwrap();
             return Err(ParserError::new(
@@ -769,6 +1057,25 @@ impl Parser {
         // Add the strategy attribute
         parallel_statement.attributes.insert("strategy".to_string(), strategy.to_string());
         
+        // Parse the optional scorer for the best strategy
+        if strategy == "best" && self.match_identifier("by") {
+            self.parse_parallel_scorer(&mut parallel_statement)?;
+        }
+        
+        // Parse the merge function, which follows the paths
+        if strategy == "merge" {
+            self.consume_keyword("with", "Expected 'with' after 'merge'")?;
+            
+            let merger = self.parse_expression()?;
+            parallel_statement.children.push(Box::new(merger));
+        }
+        
+        // Parse the optional timeout for each path
+        if self.match_identifier("timeout") {
+            let timeout = self.parse_duration()?;
+            parallel_statement.attributes.insert("timeout".to_string(), timeout.to_string());
+        }
+        
         // Check if we're in a variable declaration context
         // If we are, we don't consume the semicolon here, as it will be consumed by the variable declaration parser
         // If we're not, we consume the semicolon
@@ -779,6 +1086,79 @@ impl Parser {
         Ok(parallel_statement)
     }
     
+    /// Parse the scorer of a `select best by` clause
+    fn parse_parallel_scorer(&mut self, parallel_statement: &mut Node) -> ParserResult<()> {
+        if self.match_token(TokenKind::SemanticType) {
+            // Validation against a semantic type
+            let token = self.previous().unwrap();
+            let semantic_type = token.value.trim_matches('~').to_string();
+            
+            parallel_statement.attributes.insert("scorer".to_string(), "type".to_string());
+            parallel_statement.attributes.insert("scorer_type".to_string(), semantic_type);
+        } else if self.match_identifier("similarity") || self.match_identifier("length") {
+            // Built-in scorers without arguments
+            let scorer = self.previous().unwrap().value.clone();
+            
+            parallel_statement.attributes.insert("scorer".to_string(), scorer);
+        } else if self.match_identifier("judge") {
+            // An NLP judge with the given criteria
+            let criteria = if self.match_token(TokenKind::StringLiteral) {
+                let value = &self.previous().unwrap().value;
+                value[1..value.len() - 1].to_string()
+            } else if self.match_token(TokenKind::NaturalLanguage) {
+                let value = &self.previous().unwrap().value;
+                value.trim_start_matches("#\"").trim_end_matches("\"#").to_string()
+            } else {
+                let token = self.peek().unwrap();
+                return Err(ParserError::new(
+                    &format!("Expected judge criteria, got {}", token.value),
+                    token.location.clone(),
+                ));
+            };
+            
+            parallel_statement.attributes.insert("scorer".to_string(), "judge".to_string());
+            parallel_statement.attributes.insert("criteria".to_string(), criteria);
+        } else {
+            // A user function that scores each result
+            let scorer = self.parse_expression()?;
+            
+            parallel_statement.attributes.insert("scorer".to_string(), "function".to_string());
+            parallel_statement.children.push(Box::new(scorer));
+        }
+        
+        Ok(())
+    }
+    
+    /// Parse a duration such as `500ms` or `2s`, returning it in milliseconds
+    fn parse_duration(&mut self) -> ParserResult<u64> {
+        let amount = if self.match_token(TokenKind::IntLiteral) || self.match_token(TokenKind::FloatLiteral) {
+            let token = self.previous().unwrap();
+            token.value.parse::<f64>().map_err(|_| {
+                ParserError::new(&format!("Invalid duration: {}", token.value), token.location.clone())
+            })?
+        } else {
+            let token = self.peek().unwrap();
+            return Err(ParserError::new(
+                &format!("Expected duration, got {}", token.value),
+                token.location.clone(),
+            ));
+        };
+        
+        let millis = if self.match_identifier("ms") {
+            amount
+        } else if self.match_identifier("s") {
+            amount * 1000.0
+        } else {
+            let token = self.peek().unwrap();
+            return Err(ParserError::new(
+                &format!("Expected duration unit 'ms' or 's', got {}", token.value),
+                token.location.clone(),
+            ));
+        };
+        
+        Ok(millis.round() as u64)
+    }
+    
     /// Check if we're in a variable declaration context
     fn is_in_variable_declaration(&self) -> bool {
         // Look back through the tokens to see if we're in a variable declaration
@@ -877,10 +1257,54 @@ impl Parser {
         Ok(examples)
     }
     
+    /// Parse the input of an example or a transformation, with the number of arguments it gives
+    ///
+    /// Two or more values in parentheses are the arguments of a function that
+    /// takes several, and are kept as a list literal. Any other expression,
+    /// including a list, is the single argument of the function.
+    fn parse_example_input(&mut self) -> ParserResult<(Node, usize)> {
+        if self.check_delimiter("(") {
+            let start = self.position;
+            let location = self.current_location();
+            self.advance();
+            
+            let first = self.parse_expression()?;
+            
+            if self.match_delimiter(",") {
+                let mut arguments = vec![first, self.parse_expression()?];
+                
+                while self.match_delimiter(",") {
+                    arguments.push(self.parse_expression()?);
+                }
+                
+                self.consume_delimiter(")", "Expected ')' after example arguments")?;
+                
+                let mut input = Node {
+                    kind: NodeKind::Literal,
+                    location,
+                    children: Vec::new(),
+                    attributes: std::collections::HashMap::new(),
+                };
+                
+                input.attributes.insert("type".to_string(), "List".to_string());
+                
+                let arity = arguments.len();
+                input.children = arguments.into_iter().map(Box::new).collect();
+                
+                return Ok((input, arity));
+            }
+            
+            // A single value in parentheses is an ordinary expression
+            self.position = start;
+        }
+        
+        Ok((self.parse_expression()?, 1))
+    }
+    
     /// Parse an example
     fn parse_example(&mut self) -> ParserResult<Node> {
         // Parse the input expression
-        let input = self.parse_expression()?;
+        let (input, arity) = self.parse_example_input()?;
         
         // Parse the arrow
         self.consume_operator("->", "Expected '->' after input expression")?;
@@ -900,6 +1324,11 @@ impl Parser {
             attributes: std::collections::HashMap::new(),
         };
         
+        // Record the number of arguments, if there are several
+        if arity > 1 {
+            example.attributes.insert("arity".to_string(), arity.to_string());
+        }
+        
         // Add the input and output as children
         example.children.push(Box::new(input));
         example.children.push(Box::new(output));
@@ -907,6 +1336,232 @@ impl Parser {
         Ok(example)
     }
     
+    /// Parse a transform statement
+    ///
+    /// A statement with `into` defines a transformation of the anonymous
+    /// transformer, or of a named one if the input is preceded by `name:`.
+    /// Without `into`, the statement applies a transformer to a value.
+    fn parse_transform_statement(&mut self) -> ParserResult<Node> {
+        // We've already consumed the "transform" keyword
+        
+        // Parse the transformer name (if any)
+        let named = self.check_token(TokenKind::Identifier) && self.tokens.get(self.position + 1)
+            .is_some_and(|token| token.kind == TokenKind::Delimiter && token.value == ":");
+        
+        let mut name = None;
+        
+        if named {
+            name = Some(self.consume_identifier("Expected transformer name")?.value);
+            self.consume_delimiter(":", "Expected ':' after transformer name")?;
+        }
+        
+        // Parse the input expression, which can give several arguments to a named transformer
+        let (input, arity) = if name.is_some() {
+            self.parse_example_input()?
+        } else {
+            (self.parse_expression()?, 1)
+        };
+        
+        if name.is_none() && !self.check_keyword("into") {
+            // Parse the rest of a transformer application
+            let application = self.finish_transform_expression(input)?;
+            
+            // Consume the semicolon
+            self.consume_delimiter(";", "Expected ';' after expression")?;
+            
+            // Create a statement node
+            let location = self.current_location();
+            let mut statement = Node {
+                kind: NodeKind::Statement,
+                location,
+                children: Vec::new(),
+                attributes: std::collections::HashMap::new(),
+            };
+            
+            // Add the application as a child
+            statement.children.push(Box::new(application));
+            
+            return Ok(statement);
+        }
+        
+        // Parse the "into" keyword
+        self.consume_keyword("into", "Expected 'into' after transformation input")?;
+        
+        // Parse the output expression
+        let output = self.parse_expression()?;
+        
+        // Consume the semicolon
+        self.consume_delimiter(";", "Expected ';' after transformation output")?;
+        
+        // Create a transform node
+        let location = self.current_location();
+        let mut transform = Node {
+            kind: NodeKind::Transform,
+            location,
+            children: Vec::new(),
+            attributes: std::collections::HashMap::new(),
+        };
+        
+        // Add the name attribute (if any)
+        if let Some(name) = name {
+            transform.attributes.insert("name".to_string(), name);
+        }
+        
+        // Record the number of arguments, if there are several
+        if arity > 1 {
+            transform.attributes.insert("arity".to_string(), arity.to_string());
+        }
+        
+        // Add the input and output as children
+        transform.children.push(Box::new(input));
+        transform.children.push(Box::new(output));
+        
+        Ok(transform)
+    }
+    
+    /// Check if the current tokens start a type declaration
+    ///
+    /// `type` is not a reserved keyword, so it only starts a declaration when it
+    /// is followed by the type name.
+    fn check_type_declaration(&self) -> bool {
+        self.peek().is_some_and(|token| token.is_identifier_with_value("type"))
+            && self.tokens.get(self.position + 1).is_some_and(|token| token.kind == TokenKind::Identifier)
+    }
+    
+    /// Parse a type declaration
+    fn parse_type_declaration(&mut self) -> ParserResult<Node> {
+        // We've already consumed the "type" word
+        
+        // Parse the type name
+        let name = self.consume_identifier("Expected type name")?;
+        
+        // Create a type declaration node
+        let location = self.current_location();
+        let mut declaration = Node {
+            kind: NodeKind::TypeDeclaration,
+            location,
+            children: Vec::new(),
+            attributes: std::collections::HashMap::new(),
+        };
+        
+        // Add the name attribute
+        declaration.attributes.insert("name".to_string(), name.value);
+        
+        // Parse the fields, which are written like parameters
+        self.consume_delimiter("{", "Expected '{' after type name")?;
+        
+        while !self.check_delimiter("}") && !self.is_at_end() {
+            let field = self.parse_parameter()?;
+            declaration.children.push(Box::new(field));
+            
+            if !self.match_delimiter(",") {
+                break;
+            }
+        }
+        
+        self.consume_delimiter("}", "Expected '}' after type fields")?;
+        
+        Ok(declaration)
+    }
+    
+    /// Check if the current tokens start a prompt declaration
+    ///
+    /// `prompt` is not a reserved keyword, so it only starts a declaration
+    /// when it is followed by the prompt name and `(`.
+    fn check_prompt_declaration(&self) -> bool {
+        self.peek().is_some_and(|token| token.is_identifier_with_value("prompt"))
+            && self.tokens.get(self.position + 1).is_some_and(|token| token.kind == TokenKind::Identifier)
+            && self.tokens.get(self.position + 2).is_some_and(|token| token.kind == TokenKind::Delimiter && token.value == "(")
+    }
+    
+    /// Parse a prompt declaration
+    fn parse_prompt_declaration(&mut self) -> ParserResult<Node> {
+        // We've already consumed the "prompt" word
+        
+        // Parse the prompt name
+        let name = self.consume_identifier("Expected prompt name")?;
+        
+        // Create a prompt node
+        let location = self.current_location();
+        let mut prompt = Node {
+            kind: NodeKind::Prompt,
+            location,
+            children: Vec::new(),
+            attributes: std::collections::HashMap::new(),
+        };
+        
+        // Add the name attribute
+        prompt.attributes.insert("name".to_string(), name.value);
+        
+        // Parse the parameters
+        self.consume_delimiter("(", "Expected '(' after prompt name")?;
+        
+        if !self.check_delimiter(")") {
+            prompt.children.push(Box::new(self.parse_parameter()?));
+            
+            while self.match_delimiter(",") {
+                prompt.children.push(Box::new(self.parse_parameter()?));
+            }
+        }
+        
+        self.consume_delimiter(")", "Expected ')' after parameter list")?;
+        
+        // Parse the type of the reply (if any)
+        if self.match_operator("->") {
+            let typ = self.parse_type()?;
+            prompt.attributes.insert("return_type".to_string(), typ.to_string());
+        }
+        
+        // Parse the version (if any)
+        if self.match_identifier("version") {
+            let token = self.consume_string("Expected version string after 'version'")?;
+            prompt.attributes.insert("version".to_string(), token.value[1..token.value.len() - 1].to_string());
+        }
+        
+        // Parse the template, which is the only thing in the body
+        self.consume_delimiter("{", "Expected '{' before prompt template")?;
+        
+        let template = if self.match_token(TokenKind::NaturalLanguage) {
+            let value = &self.previous().unwrap().value;
+            value.trim_start_matches("#\"").trim_end_matches("\"#").to_string()
+        } else if self.match_token(TokenKind::StringLiteral) {
+            let value = &self.previous().unwrap().value;
+            value[1..value.len() - 1].to_string()
+        } else {
+            let token = self.peek().unwrap();
+            return Err(ParserError::new("Expected prompt template", token.location.clone()));
+        };
+        
+        prompt.attributes.insert("template".to_string(), template);
+        
+        self.consume_delimiter("}", "Expected '}' after prompt template")?;
+        
+        Ok(prompt)
+    }
+    
+    /// Finish parsing a transformer application
+    fn finish_transform_expression(&mut self, value: Node) -> ParserResult<Node> {
+        // Create a transform application node
+        let location = self.current_location();
+        let mut application = Node {
+            kind: NodeKind::TransformApply,
+            location,
+            children: Vec::new(),
+            attributes: std::collections::HashMap::new(),
+        };
+        
+        // Parse the transformer name (if any)
+        if self.match_keyword("with") {
+            let name = self.consume_identifier("Expected transformer name after 'with'")?;
+            application.attributes.insert("name".to_string(), name.value);
+        }
+        
+        // Add the value as a child
+        application.children.push(Box::new(value));
+        
+        Ok(application)
+    }
+    
     /// Parse an apply statement
     fn parse_apply_statement(&mut self) -> ParserResult<Node> {
         // We've already consumed the "apply" keyword
@@ -1007,7 +1662,7 @@ impl Parser {
     
     /// Parse an assignment expression
     fn parse_assignment(&mut self) -> ParserResult<Node> {
-        let expr = self.parse_logical_or()?;
+        let expr = self.parse_coalesce()?;
         
         if self.match_operator("=") {
             // Parse the right-hand side
@@ -1075,6 +1730,39 @@ impl Parser {
         }
     }
     
+    /// Parse a `??` expression, which uses a fallback for uncertain values
+    ///
+    /// The operator is right-associative, so `a ?? b ?? c` tries `a`, then `b`,
+    /// then `c`.
+    fn parse_coalesce(&mut self) -> ParserResult<Node> {
+        let expr = self.parse_logical_or()?;
+        
+        if self.match_operator("??") {
+            // Parse the fallback
+            let fallback = self.parse_coalesce()?;
+            
+            // Create a binary expression
+            let location = self.current_location();
+            let mut binary = Node {
+                kind: NodeKind::Binary,
+                location,
+                children: Vec::new(),
+                attributes: std::collections::HashMap::new(),
+            };
+            
+            // Add the operator attribute
+            binary.attributes.insert("operator".to_string(), "??".to_string());
+            
+            // Add the value and the fallback as children
+            binary.children.push(Box::new(expr));
+            binary.children.push(Box::new(fallback));
+            
+            Ok(binary)
+        } else {
+            Ok(expr)
+        }
+    }
+    
     /// Parse a logical OR expression
     fn parse_logical_or(&mut self) -> ParserResult<Node> {
         let mut expr = self.parse_logical_and()?;
@@ -1317,11 +2005,75 @@ impl Parser {
             unary.children.push(Box::new(operand));
             
             Ok(unary)
+        } else if self.check_render_expression() {
+            // Parse a prompt rendering
+            self.advance();
+            let call = self.parse_call()?;
+            
+            // Create a render node
+            let location = self.current_location();
+            let mut render = Node {
+                kind: NodeKind::Render,
+                location,
+                children: Vec::new(),
+                attributes: std::collections::HashMap::new(),
+            };
+            
+            // Add the call as a child
+            render.children.push(Box::new(call));
+            
+            Ok(render)
+        } else if self.check_stream_expression() {
+            // Parse a stream of the completion of a prompt
+            self.advance();
+            let prompt = self.parse_call()?;
+            
+            // Create a stream node
+            let location = self.current_location();
+            let mut stream = Node {
+                kind: NodeKind::Stream,
+                location,
+                children: Vec::new(),
+                attributes: std::collections::HashMap::new(),
+            };
+            
+            // Parse how long to wait for each chunk (if given)
+            if self.match_identifier("timeout") {
+                let timeout = self.parse_duration()?;
+                stream.attributes.insert("timeout".to_string(), timeout.to_string());
+            }
+            
+            // Add the prompt as a child
+            stream.children.push(Box::new(prompt));
+            
+            Ok(stream)
         } else {
             self.parse_call()
         }
     }
     
+    /// Check if the current tokens start a stream expression
+    ///
+    /// `stream` is not a reserved keyword, so it only starts an expression
+    /// when it is followed by natural language, a string or a name.
+    fn check_stream_expression(&self) -> bool {
+        self.peek().is_some_and(|token| token.is_identifier_with_value("stream"))
+            && self.tokens.get(self.position + 1).is_some_and(|token| matches!(
+                token.kind,
+                TokenKind::NaturalLanguage | TokenKind::StringLiteral | TokenKind::Identifier
+            ))
+    }
+    
+    /// Check if the current tokens start a render expression
+    ///
+    /// `render` is not a reserved keyword, so it only starts an expression
+    /// when it is followed by a prompt name and `(`.
+    fn check_render_expression(&self) -> bool {
+        self.peek().is_some_and(|token| token.is_identifier_with_value("render"))
+            && self.tokens.get(self.position + 1).is_some_and(|token| token.kind == TokenKind::Identifier)
+            && self.tokens.get(self.position + 2).is_some_and(|token| token.kind == TokenKind::Delimiter && token.value == "(")
+    }
+    
     /// Parse a call expression
     fn parse_call(&mut self) -> ParserResult<Node> {
         let mut expr = self.parse_primary()?;
@@ -1413,6 +2165,10 @@ impl Parser {
         if self.match_keyword("parallel") {
             // Parse a parallel expression
             self.parse_parallel_statement()
+        } else if self.match_keyword("transform") {
+            // Parse a transformer application
+            let value = self.parse_expression()?;
+            self.finish_transform_expression(value)
         } else if self.match_keyword("true") || self.match_keyword("false") {
             // Parse a boolean literal
             let token = self.previous().unwrap();
@@ -1523,8 +2279,15 @@ impl Parser {
                 attributes: std::collections::HashMap::new(),
             };
             
-            // Add the value attribute
-            natural.attributes.insert("value".to_string(), token.value.clone());
+            // Add the value attribute, without the delimiters
+            let value = token.value.trim_start_matches("#\"").trim_end_matches("\"#").to_string();
+            natural.attributes.insert("value".to_string(), value);
+            
+            // Parse the output type (if any)
+            if self.match_identifier("as") {
+                let typ = self.parse_type()?;
+                natural.attributes.insert("type".to_string(), typ.to_string());
+            }
             
             Ok(natural)
         } else if self.match_token(TokenKind::Semantic) {
@@ -1673,6 +2436,18 @@ impl Parser {
         }
     }
     
+    /// Consume the current token if it is an identifier with the given value
+    ///
+    /// This is used for contextual words that are not reserved keywords.
+    fn match_identifier(&mut self, value: &str) -> bool {
+        if !self.is_at_end() && self.peek().unwrap().is_identifier_with_value(value) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+    
     /// Consume the current token if it is an identifier
     fn consume_identifier(&mut self, error_message: &str) -> ParserResult<Token> {
         if self.check_token(TokenKind::Identifier) {
@@ -2029,4 +2804,351 @@ mod tests {
         assert_eq!(initializer.get_attribute("type").unwrap(), "Int");
         assert_eq!(initializer.get_attribute("value").unwrap(), "42");
     }
+    
+    #[test]
+    fn test_parse_parallel_select_best_by() {
+        let parse = |source: &str| {
+            let tokens = crate::lexer::Lexer::new(source).tokenize().unwrap();
+            Parser::new(tokens).parse().unwrap()
+        };
+        
+        // A semantic type scorer
+        let ast = parse("parallel { a: { 1; } b: { 2; } } select best by ~EmailAddress~;");
+        let parallel = ast.root.get_child(0).unwrap();
+        
+        assert_eq!(parallel.kind, NodeKind::Parallel);
+        assert_eq!(parallel.get_attribute("scorer").unwrap(), "type");
+        assert_eq!(parallel.get_attribute("scorer_type").unwrap(), "EmailAddress");
+        
+        // A judge scorer
+        let ast = parse("parallel { a: { 1; } } select best by judge \"is polite\";");
+        let parallel = ast.root.get_child(0).unwrap();
+        
+        assert_eq!(parallel.get_attribute("scorer").unwrap(), "judge");
+        assert_eq!(parallel.get_attribute("criteria").unwrap(), "is polite");
+        
+        // A function scorer follows the paths
+        let ast = parse("parallel { a: { 1; } b: { 2; } } select best by rank;");
+        let parallel = ast.root.get_child(0).unwrap();
+        
+        assert_eq!(parallel.get_attribute("scorer").unwrap(), "function");
+        assert_eq!(parallel.child_count(), 3);
+        assert_eq!(parallel.get_child(2).unwrap().kind, NodeKind::Identifier);
+    }
+    
+    #[test]
+    fn test_parse_parallel_strategies_and_timeout() {
+        let parse = |source: &str| {
+            let tokens = crate::lexer::Lexer::new(source).tokenize().unwrap();
+            Parser::new(tokens).parse()
+        };
+        
+        let ast = parse("parallel { a: { 1; } b: { 2; } } select first_success timeout 500ms;").unwrap();
+        let parallel = ast.root.get_child(0).unwrap();
+        
+        assert_eq!(parallel.get_attribute("strategy").unwrap(), "first_success");
+        assert_eq!(parallel.get_attribute("timeout").unwrap(), "500");
+        
+        let ast = parse("parallel { a: { 1; } } select vote timeout 1.5s;").unwrap();
+        let parallel = ast.root.get_child(0).unwrap();
+        
+        assert_eq!(parallel.get_attribute("strategy").unwrap(), "vote");
+        assert_eq!(parallel.get_attribute("timeout").unwrap(), "1500");
+        
+        // The merge function follows the paths
+        let ast = parse("parallel { a: { 1; } b: { 2; } } select merge with combine;").unwrap();
+        let parallel = ast.root.get_child(0).unwrap();
+        
+        assert_eq!(parallel.get_attribute("strategy").unwrap(), "merge");
+        assert_eq!(parallel.child_count(), 3);
+        assert_eq!(parallel.get_child(2).unwrap().kind, NodeKind::Identifier);
+        
+        assert!(parse("parallel { a: { 1; } } select merge combine;").is_err());
+        assert!(parse("parallel { a: { 1; } } select all timeout 5 minutes;").is_err());
+    }
+    
+    #[test]
+    fn test_parse_deadline_statement() {
+        let tokens = crate::lexer::Lexer::new("deadline 2s { 1; } otherwise { 2; }").tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        let deadline = ast.root.get_child(0).unwrap();
+        
+        assert_eq!(deadline.kind, NodeKind::Deadline);
+        assert_eq!(deadline.get_attribute("timeout").unwrap(), "2000");
+        assert_eq!(deadline.child_count(), 2);
+        
+        let tokens = crate::lexer::Lexer::new("deadline 250ms { 1; }").tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        let deadline = ast.root.get_child(0).unwrap();
+        
+        assert_eq!(deadline.get_attribute("timeout").unwrap(), "250");
+        assert_eq!(deadline.child_count(), 1);
+    }
+    
+    #[test]
+    fn test_parse_transform_statement() {
+        let source = r#"
+            transform "hello" into "Hello";
+            transform plural: "apple" into "apples";
+            var a = transform "world";
+            var b = transform "box" with plural;
+        "#;
+        let tokens = crate::lexer::Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        
+        let anonymous = ast.root.get_child(0).unwrap();
+        assert_eq!(anonymous.kind, NodeKind::Transform);
+        assert!(anonymous.get_attribute("name").is_none());
+        assert_eq!(anonymous.child_count(), 2);
+        
+        let named = ast.root.get_child(1).unwrap();
+        assert_eq!(named.get_attribute("name").unwrap(), "plural");
+        
+        let application = ast.root.get_child(2).unwrap().get_child(0).unwrap();
+        assert_eq!(application.kind, NodeKind::TransformApply);
+        assert!(application.get_attribute("name").is_none());
+        
+        let application = ast.root.get_child(3).unwrap().get_child(0).unwrap();
+        assert_eq!(application.get_attribute("name").unwrap(), "plural");
+        
+        let tokens = crate::lexer::Lexer::new("transform plural: \"apple\";").tokenize().unwrap();
+        let error = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(error.message, "Expected 'into' after transformation input");
+    }
+    
+    #[test]
+    fn test_parse_examples_statement() {
+        let source = r#"
+            examples for add {
+                (1, 2) -> 3;
+                (1 + 1) * 2 -> 4;
+                [1, 2] -> 3;
+            }
+            transform add: (2, 5) into 7;
+        "#;
+        let tokens = crate::lexer::Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        
+        let examples = ast.root.get_child(0).unwrap();
+        assert_eq!(examples.kind, NodeKind::Examples);
+        assert_eq!(examples.child_count(), 3);
+        
+        // Values in parentheses are the arguments of the function
+        let arguments = examples.get_child(0).unwrap();
+        assert_eq!(arguments.get_attribute("arity").unwrap(), "2");
+        assert_eq!(arguments.get_child(0).unwrap().get_attribute("type").unwrap(), "List");
+        assert_eq!(arguments.get_child(0).unwrap().child_count(), 2);
+        
+        // A parenthesised expression and a list are single arguments
+        assert!(examples.get_child(1).unwrap().get_attribute("arity").is_none());
+        assert_eq!(examples.get_child(1).unwrap().get_child(0).unwrap().kind, NodeKind::Binary);
+        assert!(examples.get_child(2).unwrap().get_attribute("arity").is_none());
+        
+        let transform = ast.root.get_child(1).unwrap();
+        assert_eq!(transform.get_attribute("arity").unwrap(), "2");
+    }
+    
+    #[test]
+    fn test_parse_budget_statement() {
+        let tokens = crate::lexer::Lexer::new("budget tokens 10_000 { 1; } otherwise { 2; }").tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        
+        let statement = ast.root.get_child(0).unwrap();
+        assert_eq!(statement.kind, NodeKind::Budget);
+        assert_eq!(statement.get_attribute("tokens").unwrap(), "10000");
+        assert_eq!(statement.child_count(), 2);
+        
+        // `budget` is still a valid name
+        let tokens = crate::lexer::Lexer::new("var budget = 5; budget = budget + 1;").tokenize().unwrap();
+        assert!(Parser::new(tokens).parse().is_ok());
+        
+        let tokens = crate::lexer::Lexer::new("budget tokens lots { 1; }").tokenize().unwrap();
+        let error = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(error.message, "Expected number of tokens, got lots");
+    }
+    
+    #[test]
+    fn test_parse_intent_handler() {
+        let source = r#"
+            on intent "refund order {order_id}" fn handleRefund(order_id: Int) { order_id; }
+            on intent otherwise fn handleUnknown(text: String) { text; }
+            intent "refund order 42";
+            intent: refund order 42;
+        "#;
+        let tokens = crate::lexer::Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        
+        let handler = ast.root.get_child(0).unwrap();
+        assert_eq!(handler.kind, NodeKind::IntentHandler);
+        assert_eq!(handler.get_attribute("pattern").unwrap(), "refund order {order_id}");
+        assert_eq!(handler.get_child(0).unwrap().get_attribute("name").unwrap(), "handleRefund");
+        
+        let fallback = ast.root.get_child(1).unwrap();
+        assert_eq!(fallback.get_attribute("fallback").unwrap(), "true");
+        assert!(fallback.get_attribute("pattern").is_none());
+        
+        let intent = ast.root.get_child(2).unwrap();
+        assert_eq!(intent.kind, NodeKind::Intent);
+        assert_eq!(intent.get_child(0).unwrap().kind, NodeKind::Literal);
+        
+        let intent = ast.root.get_child(3).unwrap();
+        assert_eq!(intent.get_child(0).unwrap().get_attribute("value").unwrap(), "refund order 42");
+        
+        // `on` is still a valid name
+        let tokens = crate::lexer::Lexer::new("var on = 1; on = on + 1;").tokenize().unwrap();
+        assert!(Parser::new(tokens).parse().is_ok());
+        
+        let tokens = crate::lexer::Lexer::new("on intent refund fn f() { 1; }").tokenize().unwrap();
+        let error = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(error.message, "Expected intent pattern or 'otherwise', got refund");
+    }
+    
+    #[test]
+    fn test_parse_prompt_declaration() {
+        let source = r##"
+            prompt Summarize(text: String, max_words: Int) -> String version "2" {
+                #"Summarize ${text} in ${max_words} words"#
+            }
+            var summary = render Summarize("the report", 10);
+        "##;
+        let tokens = crate::lexer::Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        
+        let prompt = ast.root.get_child(0).unwrap();
+        assert_eq!(prompt.kind, NodeKind::Prompt);
+        assert_eq!(prompt.get_attribute("name").unwrap(), "Summarize");
+        assert_eq!(prompt.get_attribute("return_type").unwrap(), "String");
+        assert_eq!(prompt.get_attribute("version").unwrap(), "2");
+        assert_eq!(prompt.get_attribute("template").unwrap(), "Summarize ${text} in ${max_words} words");
+        assert_eq!(prompt.child_count(), 2);
+        
+        let render = ast.root.get_child(1).unwrap().get_child(0).unwrap();
+        assert_eq!(render.kind, NodeKind::Render);
+        assert_eq!(render.get_child(0).unwrap().kind, NodeKind::Call);
+        
+        // `prompt` and `render` are still valid names
+        let tokens = crate::lexer::Lexer::new("var prompt = 1; var render = prompt + 1;").tokenize().unwrap();
+        assert!(Parser::new(tokens).parse().is_ok());
+        
+        let tokens = crate::lexer::Lexer::new("prompt Greet(name: String) { return name; }").tokenize().unwrap();
+        let error = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(error.message, "Expected prompt template");
+    }
+    
+    #[test]
+    fn test_parse_stream_expression() {
+        let source = r##"
+            for chunk in stream #"Tell me a story"# timeout 2s {
+                print(chunk);
+            }
+            var chunks = stream Summarize(text);
+        "##;
+        let tokens = crate::lexer::Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        
+        let for_statement = ast.root.get_child(0).unwrap();
+        assert_eq!(for_statement.kind, NodeKind::For);
+        assert_eq!(for_statement.get_attribute("variable").unwrap(), "chunk");
+        
+        let stream = for_statement.get_child(0).unwrap();
+        assert_eq!(stream.kind, NodeKind::Stream);
+        assert_eq!(stream.get_attribute("timeout").unwrap(), "2000");
+        assert_eq!(stream.get_child(0).unwrap().kind, NodeKind::NaturalLanguage);
+        
+        let stream = ast.root.get_child(1).unwrap().get_child(0).unwrap();
+        assert_eq!(stream.kind, NodeKind::Stream);
+        assert_eq!(stream.get_attribute("timeout"), None);
+        assert_eq!(stream.get_child(0).unwrap().kind, NodeKind::Call);
+        
+        // `stream` is still a valid name, and for loops can still be parenthesized
+        let tokens = crate::lexer::Lexer::new("var stream = [1]; for (item in stream) { print(item); }").tokenize().unwrap();
+        assert!(Parser::new(tokens).parse().is_ok());
+    }
+    
+    #[test]
+    fn test_parse_for_without_parentheses() {
+        let tokens = crate::lexer::Lexer::new("for item in items { print(item); }").tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        
+        let for_statement = ast.root.get_child(0).unwrap();
+        assert_eq!(for_statement.kind, NodeKind::For);
+        assert_eq!(for_statement.get_attribute("variable").unwrap(), "item");
+        
+        // A parenthesized loop must close its parentheses
+        let tokens = crate::lexer::Lexer::new("for (item in items { print(item); }").tokenize().unwrap();
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+    
+    #[test]
+    fn test_parse_tools_block() {
+        let source = r#"
+            tools {
+                "Look up an order by its id"
+                fn lookupOrder(id: Int) -> String { return "shipped"; }
+                fn cancelOrder(id: Int) -> Bool { return true; }
+            }
+        "#;
+        let tokens = crate::lexer::Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        
+        let tools = ast.root.get_child(0).unwrap();
+        assert_eq!(tools.kind, NodeKind::Tools);
+        assert_eq!(tools.child_count(), 2);
+        assert_eq!(tools.get_child(0).unwrap().get_attribute("description").unwrap(), "Look up an order by its id");
+        assert!(tools.get_child(1).unwrap().get_attribute("description").is_none());
+        
+        // `tools` is still a valid name
+        let tools = crate::lexer::Lexer::new("var tools = [1]; tools = tools + [2];").tokenize().unwrap();
+        assert!(Parser::new(tools).parse().is_ok());
+        
+        let tokens = crate::lexer::Lexer::new("tools { var a = 1; }").tokenize().unwrap();
+        let error = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(error.message, "Expected tool function");
+    }
+    
+    #[test]
+    fn test_parse_structured_natural_language() {
+        let source = r##"
+            type User { name: String, email: ~EmailAddress~, tags: List<String> }
+            var users = #"find premium users"# as List<User>;
+            var summary = #"summarize the report"#;
+        "##;
+        let tokens = crate::lexer::Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        
+        let declaration = ast.root.get_child(0).unwrap();
+        assert_eq!(declaration.kind, NodeKind::TypeDeclaration);
+        assert_eq!(declaration.get_attribute("name").unwrap(), "User");
+        assert_eq!(declaration.child_count(), 3);
+        assert_eq!(declaration.get_child(1).unwrap().get_attribute("type").unwrap(), "~EmailAddress~");
+        assert_eq!(declaration.get_child(2).unwrap().get_attribute("type").unwrap(), "List<String>");
+        
+        let structured = ast.root.get_child(1).unwrap().get_child(0).unwrap();
+        assert_eq!(structured.kind, NodeKind::NaturalLanguage);
+        assert_eq!(structured.get_attribute("value").unwrap(), "find premium users");
+        assert_eq!(structured.get_attribute("type").unwrap(), "List<User>");
+        
+        let unstructured = ast.root.get_child(2).unwrap().get_child(0).unwrap();
+        assert!(unstructured.get_attribute("type").is_none());
+        
+        let tokens = crate::lexer::Lexer::new("var a = #\"find users\"# as;").tokenize().unwrap();
+        let error = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(error.message, "Expected type, got ;");
+    }
+    
+    #[test]
+    fn test_parse_coalesce_expression() {
+        let tokens = crate::lexer::Lexer::new("var x = a or b ?? c ?? d;").tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        let coalesce = ast.root.get_child(0).unwrap().get_child(0).unwrap();
+        
+        // `??` binds more loosely than `or` and groups to the right
+        assert_eq!(coalesce.kind, NodeKind::Binary);
+        assert_eq!(coalesce.get_attribute("operator").unwrap(), "??");
+        assert_eq!(coalesce.get_child(0).unwrap().get_attribute("operator").unwrap(), "or");
+        
+        let fallback = coalesce.get_child(1).unwrap();
+        assert_eq!(fallback.get_attribute("operator").unwrap(), "??");
+        assert_eq!(fallback.get_child(1).unwrap().get_attribute("name").unwrap(), "d");
+    }
 }
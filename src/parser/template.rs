@@ -0,0 +1,444 @@
+//! Template module for LLM.lang
+//!
+//! This module parses the small languages written inside strings: the patterns
+//! of `on intent` handlers, such as `refund order {order_id}`, and the
+//! templates of `prompt` declarations, such as `Summarize ${text}`. Both the
+//! semantic analyzer, which checks them, and the runtime, which uses them,
+//! depend on this module, which depends on neither.
+
+use std::fmt;
+
+use crate::utils::stable_hash;
+
+/// The placeholder that stands for the examples of a prompt
+pub const EXAMPLES_SLOT: &str = "examples";
+
+/// A part of an intent pattern
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// A word that the utterance must contain, in lowercase
+    Word(String),
+
+    /// A slot that is filled in with words of the utterance
+    Slot(String),
+}
+
+/// An intent pattern, such as `refund order {order_id}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntentPattern {
+    /// The pattern as written
+    text: String,
+
+    /// The words and slots of the pattern
+    segments: Vec<Segment>,
+}
+
+impl IntentPattern {
+    /// Parse an intent pattern
+    ///
+    /// Slots are identifiers in braces. Two slots must be separated by at
+    /// least one word, so that it is clear where one ends and the next begins.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut rest = text;
+
+        while let Some(start) = rest.find('{') {
+            segments.extend(words(&rest[..start]).map(|(word, _)| Segment::Word(word)));
+
+            let end = rest[start..].find('}')
+                .ok_or_else(|| format!("Unclosed slot in intent pattern '{}'", text))?;
+            let name = rest[start + 1..start + end].trim();
+
+            if name.is_empty()
+                || name.starts_with(|c: char| c.is_ascii_digit())
+                || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(format!("Invalid slot name '{}' in intent pattern '{}'", name, text));
+            }
+
+            match segments.last() {
+                Some(Segment::Slot(previous)) => {
+                    return Err(format!(
+                        "Slots '{}' and '{}' must be separated by a word in intent pattern '{}'",
+                        previous, name, text
+                    ));
+                }
+                _ if segments.contains(&Segment::Slot(name.to_string())) => {
+                    return Err(format!("Duplicate slot '{}' in intent pattern '{}'", name, text));
+                }
+                _ => segments.push(Segment::Slot(name.to_string())),
+            }
+
+            rest = &rest[start + end + 1..];
+        }
+
+        segments.extend(words(rest).map(|(word, _)| Segment::Word(word)));
+
+        if segments.is_empty() {
+            return Err("Empty intent pattern".to_string());
+        }
+
+        Ok(Self {
+            text: text.to_string(),
+            segments,
+        })
+    }
+
+    /// Get the pattern as written
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Get the names of the slots, in order
+    pub fn slots(&self) -> Vec<&str> {
+        self.segments.iter()
+            .filter_map(|segment| match segment {
+                Segment::Slot(name) => Some(name.as_str()),
+                Segment::Word(_) => None,
+            })
+            .collect()
+    }
+
+    /// Describe the pattern in words, with the slots replaced by their names
+    ///
+    /// This is the text that utterances are compared with by embedding.
+    pub fn description(&self) -> String {
+        self.segments.iter()
+            .map(|segment| match segment {
+                Segment::Word(word) => word.clone(),
+                Segment::Slot(name) => name.replace('_', " "),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Match an utterance word for word, ignoring case and punctuation
+    ///
+    /// Returns the slot values, or `None` if the utterance does not match.
+    pub fn match_exact(&self, utterance: &str) -> Option<Vec<(String, String)>> {
+        let words: Vec<(String, &str)> = words(utterance).collect();
+        let mut slots = Vec::new();
+
+        if Self::match_segments(&self.segments, &words, &mut slots) {
+            Some(slots)
+        } else {
+            None
+        }
+    }
+
+    /// Match the remaining segments against the remaining words
+    fn match_segments(segments: &[Segment], words: &[(String, &str)], slots: &mut Vec<(String, String)>) -> bool {
+        match segments.split_first() {
+            None => words.is_empty(),
+            Some((Segment::Word(word), rest)) => {
+                words.first().is_some_and(|(first, _)| first == word)
+                    && Self::match_segments(rest, &words[1..], slots)
+            }
+            Some((Segment::Slot(name), rest)) => {
+                // Try the shortest value first
+                for end in 1..=words.len() {
+                    if Self::match_segments(rest, &words[end..], slots) {
+                        slots.insert(0, (name.clone(), join(&words[..end])));
+                        return true;
+                    }
+                }
+
+                false
+            }
+        }
+    }
+
+    /// Extract the slot values from an utterance that does not match exactly
+    ///
+    /// Each slot takes the words that follow the pattern word before it, up
+    /// to the pattern word after it, and keeps the longest prefix of them that
+    /// `fits` accepts for the slot, given its name and value. This is how a
+    /// slot passed as an `Int` leaves out the "please" in "order 42, please".
+    /// Returns `None` if a slot cannot be filled in.
+    pub fn extract(&self, utterance: &str, fits: impl Fn(&str, &str) -> bool) -> Option<Vec<(String, String)>> {
+        let words: Vec<(String, &str)> = words(utterance).collect();
+        let mut slots = Vec::new();
+        let mut position = 0;
+
+        for (i, segment) in self.segments.iter().enumerate() {
+            let name = match segment {
+                Segment::Slot(name) => name,
+                Segment::Word(_) => continue,
+            };
+
+            // Start after the word before the slot, if any
+            let start = match i.checked_sub(1).map(|i| &self.segments[i]) {
+                Some(Segment::Word(anchor)) => {
+                    position + words[position..].iter().position(|(word, _)| word == anchor)? + 1
+                }
+                _ => position,
+            };
+
+            // End at the word after the slot, if any
+            let end = match self.segments.get(i + 1) {
+                Some(Segment::Word(stop)) => {
+                    start + words[start..].iter().position(|(word, _)| word == stop).unwrap_or(words.len() - start)
+                }
+                _ => words.len(),
+            };
+
+            let value = (start + 1..=end).rev()
+                .map(|end| join(&words[start..end]))
+                .find(|value| fits(name, value))?;
+
+            slots.push((name.clone(), value));
+            position = end;
+        }
+
+        Some(slots)
+    }
+
+    /// Get the fraction of the pattern's words that the utterance contains
+    pub fn overlap(&self, utterance: &str) -> f64 {
+        let utterance: Vec<String> = words(utterance).map(|(word, _)| word).collect();
+        let pattern: Vec<&String> = self.segments.iter()
+            .filter_map(|segment| match segment {
+                Segment::Word(word) => Some(word),
+                Segment::Slot(_) => None,
+            })
+            .collect();
+
+        if pattern.is_empty() {
+            return 0.0;
+        }
+
+        let matches = pattern.iter().filter(|word| utterance.contains(word)).count();
+        matches as f64 / pattern.len() as f64
+    }
+}
+
+impl fmt::Display for IntentPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Split text into words, each in lowercase and as written, without surrounding punctuation
+fn words(text: &str) -> impl Iterator<Item = (String, &str)> {
+    text.split_whitespace()
+        .map(|word| word.trim_matches(|c: char| c.is_ascii_punctuation() && c != '_' && c != '$'))
+        .filter(|word| !word.is_empty())
+        .map(|word| (word.to_lowercase(), word))
+}
+
+/// Join words as written
+fn join(words: &[(String, &str)]) -> String {
+    words.iter().map(|(_, word)| *word).collect::<Vec<_>>().join(" ")
+}
+
+/// A part of a prompt template
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplatePart {
+    /// Text that is rendered as written
+    Text(String),
+
+    /// A placeholder for the value of a parameter
+    Parameter(String),
+
+    /// The placeholder for the examples of the prompt
+    Examples,
+}
+
+/// A prompt template, such as `Summarize ${text} in ${max_words} words`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptTemplate {
+    /// The template, without the indentation of its lines
+    text: String,
+
+    /// The text and placeholders of the template
+    parts: Vec<TemplatePart>,
+}
+
+impl PromptTemplate {
+    /// Parse a prompt template
+    ///
+    /// Placeholders are identifiers in `${` and `}`. The indentation that the
+    /// lines after the first have in common is removed, as is whitespace at
+    /// the start and end of the template, so that templates can be indented
+    /// with the code around them.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = dedent(text);
+        let mut parts = Vec::new();
+        let mut rest = text.as_str();
+
+        while let Some(start) = rest.find("${") {
+            if start > 0 {
+                parts.push(TemplatePart::Text(rest[..start].to_string()));
+            }
+
+            let end = start + rest[start..].find('}').ok_or_else(|| {
+                format!("Unclosed placeholder '{}' in prompt template", rest[start..].lines().next().unwrap_or(""))
+            })?;
+
+            let name = rest[start + 2..end].trim();
+            let valid = name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+
+            if !valid {
+                return Err(format!("Invalid placeholder '{}' in prompt template: expected a parameter name", &rest[start..=end]));
+            }
+
+            parts.push(if name == EXAMPLES_SLOT { TemplatePart::Examples } else { TemplatePart::Parameter(name.to_string()) });
+            rest = &rest[end + 1..];
+        }
+
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Text(rest.to_string()));
+        }
+
+        Ok(Self { text, parts })
+    }
+
+    /// Get the template as written, without its indentation
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Get the text and placeholders of the template, in order
+    pub fn parts(&self) -> &[TemplatePart] {
+        &self.parts
+    }
+
+    /// Get the names of the parameters that the template uses, in order of first use
+    pub fn parameters(&self) -> Vec<&str> {
+        let mut parameters = Vec::new();
+
+        for part in &self.parts {
+            if let TemplatePart::Parameter(name) = part {
+                if !parameters.contains(&name.as_str()) {
+                    parameters.push(name.as_str());
+                }
+            }
+        }
+
+        parameters
+    }
+
+    /// Check whether the template has a placeholder for examples
+    pub fn uses_examples(&self) -> bool {
+        self.parts.contains(&TemplatePart::Examples)
+    }
+}
+
+/// Get the version of a template that does not declare one
+///
+/// The version changes whenever the template does.
+pub fn template_version(template: &str) -> String {
+    stable_hash(template)[..8].to_string()
+}
+
+/// Remove the indentation that the lines after the first have in common, and surrounding whitespace
+///
+/// The indentation is compared character by character, so lines indented
+/// with different kinds of whitespace only lose what they share.
+fn dedent(text: &str) -> String {
+    let indentation = text.lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .reduce(|common, indentation| {
+            let shared = common.char_indices()
+                .zip(indentation.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(common.len().min(indentation.len()), |((i, _), _)| i);
+
+            &common[..shared]
+        })
+        .unwrap_or("");
+
+    let lines: Vec<&str> = text.lines()
+        .enumerate()
+        .map(|(i, line)| if i == 0 || line.trim().is_empty() { line.trim_end() } else { line.strip_prefix(indentation).unwrap_or(line) })
+        .collect();
+
+    lines.join("\n").trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slots(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_intent_pattern_parse() {
+        let pattern = IntentPattern::parse("refund order {order_id} to {account}").unwrap();
+        assert_eq!(pattern.slots(), vec!["order_id", "account"]);
+        assert_eq!(pattern.description(), "refund order order id to account");
+        assert_eq!(pattern.to_string(), "refund order {order_id} to {account}");
+
+        assert!(IntentPattern::parse("").is_err());
+        assert!(IntentPattern::parse("refund {order").is_err());
+        assert!(IntentPattern::parse("refund {1st}").is_err());
+        assert!(IntentPattern::parse("move {from} {to}").is_err());
+        assert!(IntentPattern::parse("move {item} to {item}").is_err());
+    }
+
+    #[test]
+    fn test_intent_pattern_match_exact() {
+        let pattern = IntentPattern::parse("refund order {order_id}").unwrap();
+        assert_eq!(pattern.match_exact("Refund order 42."), Some(slots(&[("order_id", "42")])));
+        assert_eq!(pattern.match_exact("refund order"), None);
+        assert_eq!(pattern.match_exact("please refund order 42"), None);
+
+        let pattern = IntentPattern::parse("book a flight from {origin} to {destination}").unwrap();
+        assert_eq!(
+            pattern.match_exact("Book a flight from New York to San Francisco!"),
+            Some(slots(&[("origin", "New York"), ("destination", "San Francisco")]))
+        );
+    }
+
+    #[test]
+    fn test_intent_pattern_extract() {
+        let number = |_: &str, value: &str| value.parse::<i64>().is_ok();
+        let pattern = IntentPattern::parse("refund order {order_id}").unwrap();
+        assert_eq!(pattern.extract("I'd like a refund for order 42, please", number), Some(slots(&[("order_id", "42")])));
+        assert_eq!(pattern.extract("I'd like a refund for order forty", number), None);
+        assert_eq!(pattern.extract("I'd like a refund for my order", number), None);
+        assert_eq!(pattern.extract("what's the weather", number), None);
+
+        // A slot that takes any text keeps all of its words
+        let pattern = IntentPattern::parse("weather in {city} tomorrow").unwrap();
+        assert_eq!(
+            pattern.extract("what will the weather in Paris be like tomorrow?", |_, _| true),
+            Some(slots(&[("city", "Paris be like")]))
+        );
+
+        let name = |_: &str, value: &str| value.split(' ').all(|word| word.starts_with(char::is_uppercase));
+        assert_eq!(pattern.extract("what will the weather in Paris be like tomorrow?", name), Some(slots(&[("city", "Paris")])));
+        assert_eq!(pattern.overlap("what will the weather be like tomorrow?"), 2.0 / 3.0);
+    }
+
+    #[test]
+    fn test_prompt_template_parse() {
+        let template = PromptTemplate::parse("Summarize ${text} in ${ max_words } words, like ${examples}. ${text}").unwrap();
+        assert_eq!(template.parameters(), vec!["text", "max_words"]);
+        assert!(template.uses_examples());
+
+        let template = PromptTemplate::parse("Translate:\n            ${text}\n\n            into French\n        ").unwrap();
+        assert_eq!(template.as_str(), "Translate:\n${text}\n\ninto French");
+        assert!(!template.uses_examples());
+
+        // Lines indented with different whitespace keep what they do not share
+        let template = PromptTemplate::parse("Translate:\n  \u{3000}${text}\n   into French").unwrap();
+        assert_eq!(template.as_str(), "Translate:\n\u{3000}${text}\n into French");
+
+        let template = PromptTemplate::parse("Translate:\n\u{3000}${text}\n into French").unwrap();
+        assert_eq!(template.as_str(), "Translate:\n\u{3000}${text}\n into French");
+
+        assert_eq!(
+            PromptTemplate::parse("Summarize ${text").unwrap_err(),
+            "Unclosed placeholder '${text' in prompt template"
+        );
+        assert_eq!(
+            PromptTemplate::parse("Costs ${5 + 1}").unwrap_err(),
+            "Invalid placeholder '${5 + 1}' in prompt template: expected a parameter name"
+        );
+    }
+}
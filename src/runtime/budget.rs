@@ -0,0 +1,556 @@
+//! Budget module for the LLM.lang runtime
+//!
+//! This module provides token counting and token budgets for the language
+//! model calls of the LLM.lang runtime. Every call is charged to the budget of
+//! the run and of the enclosing `budget` blocks, and its usage is recorded for
+//! the expression that made it.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use tokenizers::pre_tokenizers::whitespace::Whitespace;
+use tokenizers::{OffsetReferential, OffsetType, PreTokenizedString, PreTokenizer, Tokenizer};
+
+use crate::utils::SourceLocation;
+use super::error::RuntimeError;
+use super::llm::{ChatMessage, LlmBackend};
+use super::vector::VectorEmbedding;
+
+thread_local! {
+    /// The budget charged by code that has no access to the engine
+    static CURRENT: RefCell<Option<TokenBudget>> = const { RefCell::new(None) };
+
+    /// The innermost expression being executed that may call a language model
+    static CALL_SITE: RefCell<Option<SourceLocation>> = const { RefCell::new(None) };
+}
+
+/// Counts the tokens of text
+///
+/// Without a tokenizer file, tokens are approximated by the words and runs of
+/// punctuation of the text, which is usually a slight undercount.
+pub struct TokenCounter {
+    /// The tokenizer of the model, if one was loaded
+    tokenizer: Option<Tokenizer>,
+}
+
+impl TokenCounter {
+    /// Create a token counter that counts words and punctuation
+    pub fn new() -> Self {
+        Self { tokenizer: None }
+    }
+
+    /// Load a token counter from a tokenizer file, such as a model's `tokenizer.json`
+    pub fn from_file(path: &Path) -> Result<Self, RuntimeError> {
+        let tokenizer = Tokenizer::from_file(path).map_err(|e| {
+            RuntimeError::new(
+                &format!("Failed to load tokenizer from '{}': {}", path.display(), e),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )
+        })?;
+
+        Ok(Self {
+            tokenizer: Some(tokenizer),
+        })
+    }
+
+    /// Count the tokens of text
+    pub fn count(&self, text: &str) -> u64 {
+        if let Some(tokenizer) = &self.tokenizer {
+            if let Ok(encoding) = tokenizer.encode(text, false) {
+                return encoding.len() as u64;
+            }
+        }
+
+        let mut pretokenized = PreTokenizedString::from(text);
+
+        match Whitespace.pre_tokenize(&mut pretokenized) {
+            Ok(()) => pretokenized.get_splits(OffsetReferential::Original, OffsetType::Byte).len() as u64,
+            Err(_) => text.split_whitespace().count() as u64,
+        }
+    }
+
+    /// Get the byte offsets of the tokens of text, in order
+    ///
+    /// Tokens that do not come from the text, such as special tokens, are left out.
+    pub fn spans(&self, text: &str) -> Vec<(usize, usize)> {
+        if let Some(tokenizer) = &self.tokenizer {
+            if let Ok(encoding) = tokenizer.encode(text, false) {
+                return encoding.get_offsets().iter().copied().filter(|(start, end)| start < end).collect();
+            }
+        }
+
+        let mut pretokenized = PreTokenizedString::from(text);
+
+        match Whitespace.pre_tokenize(&mut pretokenized) {
+            Ok(()) => pretokenized.get_splits(OffsetReferential::Original, OffsetType::Byte)
+                .into_iter()
+                .map(|(_, offsets, _)| offsets)
+                .collect(),
+            Err(_) => text.split_whitespace()
+                .map(|word| {
+                    let start = word.as_ptr() as usize - text.as_ptr() as usize;
+                    (start, start + word.len())
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Default for TokenCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for TokenCounter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenCounter")
+            .field("tokenizer", &self.tokenizer.as_ref().map(|tokenizer| tokenizer.get_vocab_size(true)))
+            .finish()
+    }
+}
+
+/// The token usage of the language model calls made by one expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenUsage {
+    /// The expression that made the calls
+    pub location: SourceLocation,
+
+    /// The number of calls
+    pub calls: u64,
+
+    /// The number of tokens sent to the model
+    pub prompt_tokens: u64,
+
+    /// The number of tokens the model replied with
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Get the number of tokens sent and received
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// The shared state of a token budget
+#[derive(Debug, Default)]
+struct BudgetState {
+    /// The number of tokens that may be used, if limited
+    limit: Option<u64>,
+
+    /// The number of tokens used
+    used: AtomicU64,
+
+    /// Whether a call was refused or failed because this budget ran out
+    exhausted: AtomicBool,
+
+    /// The budget this budget was derived from
+    parent: Option<TokenBudget>,
+
+    /// The token usage by expression, recorded by budgets without a parent
+    usage: Mutex<BTreeMap<(String, usize, usize), TokenUsage>>,
+}
+
+/// A token budget
+///
+/// Clones of a budget share the same state. Tokens charged to a child budget
+/// are also charged to its parent, so a call fails when it would exceed any of
+/// the enclosing budgets.
+#[derive(Debug, Clone, Default)]
+pub struct TokenBudget {
+    /// The shared budget state
+    state: Arc<BudgetState>,
+}
+
+/// Restores the previous current budget when dropped
+pub struct BudgetGuard {
+    /// The budget that was current before
+    previous: Option<TokenBudget>,
+}
+
+/// Restores the previous call site when dropped
+pub struct CallSiteGuard {
+    /// The call site that was current before, if this guard replaced it
+    previous: Option<Option<SourceLocation>>,
+}
+
+impl TokenBudget {
+    /// Create a new budget for a run, with an optional limit
+    pub fn new(limit: Option<u64>) -> Self {
+        Self {
+            state: Arc::new(BudgetState {
+                limit,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Create a child budget that may use at most the given number of tokens
+    pub fn child(&self, limit: u64) -> Self {
+        Self {
+            state: Arc::new(BudgetState {
+                limit: Some(limit),
+                parent: Some(self.clone()),
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Get the number of tokens that may be used, if limited
+    pub fn limit(&self) -> Option<u64> {
+        self.state.limit
+    }
+
+    /// Get the number of tokens used
+    pub fn used(&self) -> u64 {
+        self.state.used.load(Ordering::SeqCst)
+    }
+
+    /// Check whether a call was refused or failed because this budget, rather than a parent, ran out
+    pub fn is_exhausted(&self) -> bool {
+        self.state.exhausted.load(Ordering::SeqCst)
+    }
+
+    /// Get the token usage of each expression that called a language model, in source order
+    pub fn usage(&self) -> Vec<TokenUsage> {
+        self.root().state.usage.lock().unwrap_or_else(PoisonError::into_inner).values().cloned().collect()
+    }
+
+    /// Get the budget this budget was ultimately derived from
+    fn root(&self) -> &TokenBudget {
+        match &self.state.parent {
+            Some(parent) => parent.root(),
+            None => self,
+        }
+    }
+
+    /// Get this budget and the budgets it was derived from, innermost first
+    fn chain(&self) -> Vec<&TokenBudget> {
+        let mut chain = vec![self];
+
+        while let Some(parent) = &chain[chain.len() - 1].state.parent {
+            chain.push(parent);
+        }
+
+        chain
+    }
+
+    /// Return an error if the budget or one of its parents cannot afford more tokens
+    ///
+    /// The innermost budget that cannot afford them is marked as exhausted.
+    pub fn check(&self, tokens: u64, location: &SourceLocation) -> Result<(), RuntimeError> {
+        for budget in self.chain() {
+            if let Some(limit) = budget.state.limit {
+                if budget.used() + tokens > limit {
+                    budget.state.exhausted.store(true, Ordering::SeqCst);
+                    return Err(RuntimeError::token_budget_exceeded(limit, location.clone()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Charge a call to the budget and its parents, and record its usage
+    ///
+    /// Returns an error if the call exceeded one of the budgets, which is
+    /// marked as exhausted.
+    pub fn charge(&self, location: &SourceLocation, prompt_tokens: u64, completion_tokens: u64) -> Result<(), RuntimeError> {
+        let tokens = prompt_tokens + completion_tokens;
+
+        for budget in self.chain() {
+            budget.state.used.fetch_add(tokens, Ordering::SeqCst);
+        }
+
+        {
+            let key = (location.file.clone(), location.start_line, location.start_column);
+            let mut usage = self.root().state.usage.lock().unwrap_or_else(PoisonError::into_inner);
+            let entry = usage.entry(key).or_insert_with(|| TokenUsage {
+                location: location.clone(),
+                calls: 0,
+                prompt_tokens: 0,
+                completion_tokens: 0,
+            });
+
+            entry.calls += 1;
+            entry.prompt_tokens += prompt_tokens;
+            entry.completion_tokens += completion_tokens;
+        }
+
+        self.check(0, location)
+    }
+
+    /// Make this the current budget of the calling thread until the guard is dropped
+    pub fn enter(&self) -> BudgetGuard {
+        let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+        BudgetGuard { previous }
+    }
+
+    /// Get the current budget of the calling thread
+    pub fn current() -> Option<TokenBudget> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Attribute the language model calls of the calling thread to an expression until the guard is dropped
+    ///
+    /// Calls are only attributed while a budget is current, since usage is
+    /// not recorded otherwise.
+    pub fn enter_call_site(location: &SourceLocation) -> CallSiteGuard {
+        if CURRENT.with(|current| current.borrow().is_none()) {
+            return CallSiteGuard { previous: None };
+        }
+
+        let previous = CALL_SITE.with(|site| site.replace(Some(location.clone())));
+        CallSiteGuard { previous: Some(previous) }
+    }
+
+    /// Get the expression the language model calls of the calling thread are attributed to
+    pub fn call_site() -> SourceLocation {
+        CALL_SITE.with(|site| site.borrow().clone()).unwrap_or_else(|| SourceLocation::new(0, 0, 0, 0, ""))
+    }
+}
+
+impl Drop for BudgetGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+impl Drop for CallSiteGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            CALL_SITE.with(|site| *site.borrow_mut() = previous);
+        }
+    }
+}
+
+/// A language model backend that counts the tokens of another backend's calls
+///
+/// Each call is checked against the current budget before it is made, with
+/// the tokens of the prompt, and charged to it afterwards with the tokens of
+/// the reply. Calls made without a current budget are not counted.
+#[derive(Debug)]
+pub struct MeteredBackend {
+    /// The backend whose calls are counted
+    backend: Arc<dyn LlmBackend>,
+
+    /// The token counter
+    counter: Arc<TokenCounter>,
+}
+
+impl MeteredBackend {
+    /// Create a new metered backend
+    pub fn new(backend: Arc<dyn LlmBackend>, counter: Arc<TokenCounter>) -> Self {
+        Self { backend, counter }
+    }
+
+    /// Make a call with a prompt of the given number of tokens, charging it to the current budget
+    fn metered<T, F, C>(&self, prompt_tokens: u64, call: F, completion_tokens: C) -> Result<T, RuntimeError>
+    where
+        F: FnOnce() -> Result<T, RuntimeError>,
+        C: FnOnce(&T) -> u64,
+    {
+        let Some(budget) = TokenBudget::current() else {
+            return call();
+        };
+
+        let location = TokenBudget::call_site();
+        budget.check(prompt_tokens, &location)?;
+
+        let result = call()?;
+        budget.charge(&location, prompt_tokens, completion_tokens(&result))?;
+
+        Ok(result)
+    }
+}
+
+impl LlmBackend for MeteredBackend {
+    fn name(&self) -> String {
+        self.backend.name()
+    }
+
+    fn complete(&self, prompt: &str) -> Result<String, RuntimeError> {
+        self.metered(
+            self.counter.count(prompt),
+            || self.backend.complete(prompt),
+            |reply| self.counter.count(reply),
+        )
+    }
+
+    fn complete_stream(&self, prompt: &str, chunk: &mut dyn FnMut(&str) -> bool) -> Result<(), RuntimeError> {
+        // The completion is charged once the stream ends, for the chunks that were streamed
+        self.metered(
+            self.counter.count(prompt),
+            || {
+                let mut completion = String::new();
+                self.backend.complete_stream(prompt, &mut |piece| {
+                    completion.push_str(piece);
+                    chunk(piece)
+                })?;
+
+                Ok(completion)
+            },
+            |completion| self.counter.count(completion),
+        )?;
+
+        Ok(())
+    }
+
+    fn chat(&self, messages: &[ChatMessage]) -> Result<String, RuntimeError> {
+        self.metered(
+            messages.iter().map(|message| self.counter.count(&message.content)).sum(),
+            || self.backend.chat(messages),
+            |reply| self.counter.count(reply),
+        )
+    }
+
+    fn embed(&self, text: &str) -> Result<VectorEmbedding, RuntimeError> {
+        self.metered(self.counter.count(text), || self.backend.embed(text), |_| 0)
+    }
+
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<VectorEmbedding>, RuntimeError> {
+        let prompt_tokens = texts.iter().map(|text| self.counter.count(text)).sum();
+
+        self.metered(prompt_tokens, || self.backend.embed_batch(texts), |_| 0)
+    }
+
+    fn classify(&self, text: &str, categories: &[String]) -> Result<Vec<f64>, RuntimeError> {
+        let prompt_tokens = self.counter.count(text)
+            + categories.iter().map(|category| self.counter.count(category)).sum::<u64>();
+
+        self.metered(prompt_tokens, || self.backend.classify(text, categories), |_| 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::testing::TestBackend;
+
+    #[test]
+    fn test_token_counter() {
+        let counter = TokenCounter::new();
+
+        assert_eq!(counter.count("hello world"), 2);
+        assert_eq!(counter.count("Hello, world!"), 4);
+        assert_eq!(counter.count(""), 0);
+        assert_eq!(counter.spans("Hello, world!"), vec![(0, 5), (5, 6), (7, 12), (12, 13)]);
+
+        let path = std::env::temp_dir().join(format!("llm_lang_tokenizer_{}.json", std::process::id()));
+
+        // A word-level tokenizer that splits on whitespace only
+        std::fs::write(&path, r#"{
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": [],
+            "normalizer": null,
+            "pre_tokenizer": { "type": "WhitespaceSplit" },
+            "post_processor": null,
+            "decoder": null,
+            "model": {
+                "type": "WordLevel",
+                "vocab": { "[UNK]": 0, "hello": 1 },
+                "unk_token": "[UNK]"
+            }
+        }"#).unwrap();
+
+        let counter = TokenCounter::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(counter.count("Hello, world!"), 2);
+        assert_eq!(counter.spans("Hello, world!"), vec![(0, 6), (7, 13)]);
+        assert!(TokenCounter::from_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_token_budget_charge() {
+        let run = TokenBudget::new(None);
+        let block = run.child(10);
+        let here = SourceLocation::new(2, 5, 2, 20, "test.llm");
+        let there = SourceLocation::new(1, 1, 1, 10, "test.llm");
+
+        assert!(block.charge(&here, 3, 2).is_ok());
+        assert!(block.charge(&here, 2, 1).is_ok());
+        assert!(run.charge(&there, 50, 50).is_ok());
+
+        // Tokens charged to a block are also charged to the run
+        assert_eq!(block.used(), 8);
+        assert_eq!(run.used(), 108);
+
+        // The usage is recorded in source order
+        let usage = block.usage();
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage[0].location, there);
+        assert_eq!((usage[1].calls, usage[1].prompt_tokens, usage[1].completion_tokens), (2, 5, 3));
+        assert_eq!(usage[1].total_tokens(), 8);
+
+        // A prompt that does not fit is refused before it is sent
+        let error = block.check(3, &here).unwrap_err();
+        assert_eq!(error.message, "Token budget of 10 tokens exceeded");
+        assert!(block.is_exhausted());
+        assert!(!run.is_exhausted());
+        assert_eq!(block.used(), 8);
+
+        // A reply that does not fit fails after it is charged
+        let block = run.child(5);
+        assert!(block.charge(&here, 3, 3).is_err());
+        assert!(block.is_exhausted());
+    }
+
+    #[test]
+    fn test_token_budget_parent_exhausted() {
+        let run = TokenBudget::new(Some(5));
+        let block = run.child(100);
+        let here = SourceLocation::new(1, 1, 1, 1, "");
+
+        assert!(block.check(10, &here).is_err());
+        assert!(run.is_exhausted());
+        assert!(!block.is_exhausted());
+    }
+
+    #[test]
+    fn test_metered_backend() {
+        let backend = MeteredBackend::new(Arc::new(TestBackend::replying("a three word reply")), Arc::new(TokenCounter::new()));
+        let budget = TokenBudget::new(None);
+        let here = SourceLocation::new(3, 1, 3, 9, "test.llm");
+
+        // Calls made outside of a budget are not counted
+        backend.complete("hello").unwrap();
+
+        {
+            let _budget = budget.enter();
+            let _site = TokenBudget::enter_call_site(&here);
+
+            backend.chat(&[ChatMessage::system("be brief"), ChatMessage::user("hello")]).unwrap();
+            backend.classify("hello there", &["greeting".to_string(), "farewell".to_string()]).unwrap();
+            backend.complete_stream("hello", &mut |_| true).unwrap();
+        }
+
+        assert_eq!(TokenBudget::call_site().start_line, 0);
+        assert!(TokenBudget::current().is_none());
+
+        // Call sites are not tracked without a budget
+        {
+            let _site = TokenBudget::enter_call_site(&here);
+            assert_eq!(TokenBudget::call_site().start_line, 0);
+        }
+
+        let usage = budget.usage();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].location, here);
+        assert_eq!((usage[0].calls, usage[0].prompt_tokens, usage[0].completion_tokens), (3, 8, 8));
+
+        // A call that cannot be afforded is not made
+        let budget = TokenBudget::new(Some(1));
+        let _budget = budget.enter();
+        assert!(backend.complete("hello world").is_err());
+        assert!(budget.usage().is_empty());
+    }
+}
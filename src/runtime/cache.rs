@@ -0,0 +1,737 @@
+//! Cache module for the LLM.lang runtime
+//!
+//! This module provides a cache of language model and embedding responses,
+//! stored in a local JSON file. Recording the responses of a run and replaying
+//! them later makes programs that use natural language cheap to re-run and
+//! deterministic to test, without network access.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::utils::{stable_hash, SourceLocation};
+use super::error::RuntimeError;
+use super::llm::{ChatMessage, LlmBackend};
+use super::vector::{EmbeddingProvider, VectorEmbedding};
+
+/// The number of recorded responses after which the cache file is written
+pub const SAVE_BATCH: usize = 32;
+
+/// How long a recorded response is kept before the cache file is written, in seconds
+pub const SAVE_INTERVAL: u64 = 5;
+
+/// How a response cache is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CacheMode {
+    /// Return cached responses, and call the backend and save its response on a miss
+    #[default]
+    Record,
+
+    /// Return cached responses, and fail on a miss without calling the backend
+    Replay,
+
+    /// Always call the backend, without reading or writing the cache
+    Passthrough,
+}
+
+impl FromStr for CacheMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "record" => Ok(CacheMode::Record),
+            "replay" => Ok(CacheMode::Replay),
+            "passthrough" => Ok(CacheMode::Passthrough),
+            _ => Err(format!("Unknown cache mode '{}': expected record, replay or passthrough", s)),
+        }
+    }
+}
+
+impl fmt::Display for CacheMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheMode::Record => write!(f, "record"),
+            CacheMode::Replay => write!(f, "replay"),
+            CacheMode::Passthrough => write!(f, "passthrough"),
+        }
+    }
+}
+
+/// A cached request and its response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    /// The request, kept to tell requests with the same key apart and to make the file readable
+    request: serde_json::Value,
+
+    /// The response of the backend
+    response: serde_json::Value,
+}
+
+/// The entries of a response cache and the state of its file
+#[derive(Debug)]
+struct CacheState {
+    /// The cached entries by key
+    entries: BTreeMap<String, CacheEntry>,
+
+    /// The number of responses recorded since the file was written
+    unsaved: usize,
+
+    /// When the file was last written, or the cache was opened
+    saved_at: Instant,
+}
+
+/// A cache of backend responses, stored in a JSON file
+///
+/// Entries are keyed by a hash of the request, which includes the name of the
+/// backend as well as the prompt and parameters. Recorded responses are
+/// written to the file in batches of `SAVE_BATCH`, once they are
+/// `SAVE_INTERVAL` seconds old, by `flush`, and when the cache is dropped. The
+/// engine flushes the cache at the end of every run.
+#[derive(Debug)]
+pub struct ResponseCache {
+    /// The cache file
+    path: PathBuf,
+
+    /// How the cache is used
+    mode: CacheMode,
+
+    /// The cached entries and what has not been saved
+    state: Mutex<CacheState>,
+}
+
+impl ResponseCache {
+    /// Open the cache stored in a file
+    ///
+    /// A missing file is an empty cache. The file is not read in passthrough mode.
+    pub fn open(path: &Path, mode: CacheMode) -> Result<Self, RuntimeError> {
+        let entries = if mode != CacheMode::Passthrough && path.exists() {
+            let json = std::fs::read_to_string(path).map_err(|e| {
+                RuntimeError::new(
+                    &format!("Failed to load response cache from '{}': {}", path.display(), e),
+                    SourceLocation::new(0, 0, 0, 0, ""),
+                )
+            })?;
+
+            serde_json::from_str(&json).map_err(|e| {
+                RuntimeError::new(
+                    &format!("Invalid response cache '{}': {}", path.display(), e),
+                    SourceLocation::new(0, 0, 0, 0, ""),
+                )
+            })?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            mode,
+            state: Mutex::new(CacheState {
+                entries,
+                unsaved: 0,
+                saved_at: Instant::now(),
+            }),
+        })
+    }
+
+    /// Get the cache file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get how the cache is used
+    pub fn mode(&self) -> CacheMode {
+        self.mode
+    }
+
+    /// Get the number of cached responses
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner).entries.len()
+    }
+
+    /// Check whether no responses are cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the cached response to a request, or compute it according to the mode
+    ///
+    /// The backend is not locked while the response is computed, so requests
+    /// made in parallel may both miss and compute the same response.
+    pub fn get_or_compute<T, F>(&self, request: serde_json::Value, compute: F) -> Result<T, RuntimeError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Result<T, RuntimeError>,
+    {
+        if let Some(response) = self.lookup(&request)? {
+            return Ok(response);
+        }
+
+        let response = compute()?;
+        self.record(request, &response)?;
+
+        Ok(response)
+    }
+
+    /// Get the cached response to a request, if it should not be computed
+    ///
+    /// Returns `None` if the response is to be computed and recorded, and an
+    /// error if it is missing in replay mode.
+    pub fn lookup<T: DeserializeOwned>(&self, request: &serde_json::Value) -> Result<Option<T>, RuntimeError> {
+        if self.mode == CacheMode::Passthrough {
+            return Ok(None);
+        }
+
+        let key = cache_key(request);
+
+        let cached = self.state.lock().unwrap_or_else(PoisonError::into_inner)
+            .entries
+            .get(&key)
+            .filter(|entry| entry.request == *request)
+            .map(|entry| entry.response.clone());
+
+        if let Some(response) = cached {
+            return serde_json::from_value(response).map(Some).map_err(|e| {
+                RuntimeError::new(
+                    &format!("Invalid cached response {} in '{}': {}", key, self.path.display(), e),
+                    SourceLocation::new(0, 0, 0, 0, ""),
+                )
+            });
+        }
+
+        if self.mode == CacheMode::Replay {
+            let operation = request["operation"].as_str().unwrap_or("unknown");
+
+            return Err(RuntimeError::new(
+                &format!("No cached response for {} request {} in '{}' (replay mode)", operation, key, self.path.display()),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+
+        Ok(None)
+    }
+
+    /// Record the computed response to a request, unless the cache is passed through
+    pub fn record<T: Serialize>(&self, request: serde_json::Value, response: &T) -> Result<(), RuntimeError> {
+        self.record_all(vec![(request, response)])
+    }
+
+    /// Record the computed responses to several requests, writing the file at most once
+    pub fn record_all<T: Serialize>(&self, responses: Vec<(serde_json::Value, &T)>) -> Result<(), RuntimeError> {
+        if self.mode == CacheMode::Passthrough || responses.is_empty() {
+            return Ok(());
+        }
+
+        let mut entries = Vec::new();
+
+        for (request, response) in responses {
+            let response = serde_json::to_value(response).map_err(|e| {
+                RuntimeError::new(
+                    &format!("Failed to serialize response: {}", e),
+                    SourceLocation::new(0, 0, 0, 0, ""),
+                )
+            })?;
+
+            entries.push((cache_key(&request), CacheEntry { request, response }));
+        }
+
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.unsaved += entries.len();
+        state.entries.extend(entries);
+
+        if state.unsaved >= SAVE_BATCH || state.saved_at.elapsed() >= Duration::from_secs(SAVE_INTERVAL) {
+            self.save(&mut state)?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the cached list of floats for a request, or compute it according to the mode
+    ///
+    /// The floats are stored as strings, since JSON numbers are not always
+    /// parsed back to exactly the same float.
+    pub fn get_or_compute_floats<F>(&self, request: serde_json::Value, compute: F) -> Result<Vec<f64>, RuntimeError>
+    where
+        F: FnOnce() -> Result<Vec<f64>, RuntimeError>,
+    {
+        let floats: Vec<String> = self.get_or_compute(request, || {
+            Ok(compute()?.iter().map(f64::to_string).collect())
+        })?;
+
+        self.parse_floats(&floats)
+    }
+
+    /// Get the cached lists of floats for several requests, computing the missing ones at once
+    ///
+    /// `compute` is given the positions of the requests that are not cached
+    /// and returns their floats in the same order. The computed floats are
+    /// recorded together.
+    pub fn get_or_compute_float_batch<F>(&self, requests: Vec<serde_json::Value>, compute: F) -> Result<Vec<Vec<f64>>, RuntimeError>
+    where
+        F: FnOnce(&[usize]) -> Result<Vec<Vec<f64>>, RuntimeError>,
+    {
+        let mut results = Vec::with_capacity(requests.len());
+        let mut missing = Vec::new();
+
+        for (i, request) in requests.iter().enumerate() {
+            match self.lookup::<Vec<String>>(request)? {
+                Some(floats) => results.push(Some(self.parse_floats(&floats)?)),
+                None => {
+                    results.push(None);
+                    missing.push(i);
+                }
+            }
+        }
+
+        if missing.is_empty() {
+            return Ok(results.into_iter().flatten().collect());
+        }
+
+        let computed = compute(&missing)?;
+
+        if computed.len() != missing.len() {
+            return Err(RuntimeError::new(
+                &format!("Expected {} results, got {}", missing.len(), computed.len()),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+
+        let floats: Vec<Vec<String>> = computed.iter().map(|values| values.iter().map(f64::to_string).collect()).collect();
+        self.record_all(missing.iter().zip(&floats).map(|(&i, floats)| (requests[i].clone(), floats)).collect())?;
+
+        for (i, values) in missing.into_iter().zip(computed) {
+            results[i] = Some(values);
+        }
+
+        Ok(results.into_iter().flatten().collect())
+    }
+
+    /// Parse floats stored as strings
+    fn parse_floats(&self, floats: &[String]) -> Result<Vec<f64>, RuntimeError> {
+        floats.iter()
+            .map(|float| float.parse().map_err(|_| {
+                RuntimeError::new(
+                    &format!("Invalid cached float '{}' in '{}'", float, self.path.display()),
+                    SourceLocation::new(0, 0, 0, 0, ""),
+                )
+            }))
+            .collect()
+    }
+
+    /// Write the responses recorded since the file was last written
+    pub fn flush(&self) -> Result<(), RuntimeError> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        self.save(&mut state)
+    }
+
+    /// Write the entries to the cache file, if any are unsaved
+    fn save(&self, state: &mut CacheState) -> Result<(), RuntimeError> {
+        if state.unsaved == 0 {
+            return Ok(());
+        }
+
+        let json = serde_json::to_string_pretty(&state.entries).map_err(|e| {
+            RuntimeError::new(
+                &format!("Failed to serialize response cache: {}", e),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )
+        })?;
+
+        // Write a temporary file first, so that the cache is never left half written
+        let temporary = self.path.with_extension("tmp");
+
+        std::fs::write(&temporary, json)
+            .and_then(|_| std::fs::rename(&temporary, &self.path))
+            .map_err(|e| {
+                RuntimeError::new(
+                    &format!("Failed to save response cache to '{}': {}", self.path.display(), e),
+                    SourceLocation::new(0, 0, 0, 0, ""),
+                )
+            })?;
+
+        state.unsaved = 0;
+        state.saved_at = Instant::now();
+
+        Ok(())
+    }
+}
+
+impl Drop for ResponseCache {
+    fn drop(&mut self) {
+        // An error cannot be reported here, which is why the engine flushes after every run
+        let _ = self.flush();
+    }
+}
+
+/// Hash a request into a cache key
+fn cache_key(request: &serde_json::Value) -> String {
+    stable_hash(&request.to_string())
+}
+
+/// A language model backend that caches the responses of another backend
+#[derive(Debug)]
+pub struct CachedBackend {
+    /// The backend whose responses are cached
+    backend: Arc<dyn LlmBackend>,
+
+    /// The cache
+    cache: Arc<ResponseCache>,
+}
+
+impl CachedBackend {
+    /// Create a new cached backend
+    pub fn new(backend: Arc<dyn LlmBackend>, cache: Arc<ResponseCache>) -> Self {
+        Self { backend, cache }
+    }
+
+    /// Get the cache request for the embedding of a text
+    fn embed_request(&self, text: &str) -> serde_json::Value {
+        json!({
+            "backend": self.backend.name(),
+            "operation": "embed",
+            "text": text,
+        })
+    }
+}
+
+impl LlmBackend for CachedBackend {
+    fn name(&self) -> String {
+        self.backend.name()
+    }
+
+    fn complete(&self, prompt: &str) -> Result<String, RuntimeError> {
+        let request = json!({
+            "backend": self.backend.name(),
+            "operation": "complete",
+            "prompt": prompt,
+        });
+
+        self.cache.get_or_compute(request, || self.backend.complete(prompt))
+    }
+
+    fn complete_stream(&self, prompt: &str, chunk: &mut dyn FnMut(&str) -> bool) -> Result<(), RuntimeError> {
+        // Streamed completions share their cache entries with complete
+        let request = json!({
+            "backend": self.backend.name(),
+            "operation": "complete",
+            "prompt": prompt,
+        });
+
+        if let Some(completion) = self.cache.lookup::<String>(&request)? {
+            chunk(&completion);
+            return Ok(());
+        }
+
+        let mut completion = String::new();
+        let mut stopped = false;
+
+        self.backend.complete_stream(prompt, &mut |piece| {
+            completion.push_str(piece);
+            stopped = !chunk(piece);
+            !stopped
+        })?;
+
+        // A completion that was not streamed to the end is not cached
+        if !stopped {
+            self.cache.record(request, &completion)?;
+        }
+
+        Ok(())
+    }
+
+    fn chat(&self, messages: &[ChatMessage]) -> Result<String, RuntimeError> {
+        let request = json!({
+            "backend": self.backend.name(),
+            "operation": "chat",
+            "messages": messages.iter()
+                .map(|message| json!({ "role": message.role, "content": message.content }))
+                .collect::<Vec<_>>(),
+        });
+
+        self.cache.get_or_compute(request, || self.backend.chat(messages))
+    }
+
+    fn embed(&self, text: &str) -> Result<VectorEmbedding, RuntimeError> {
+        self.cache.get_or_compute_floats(self.embed_request(text), || self.backend.embed(text))
+    }
+
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<VectorEmbedding>, RuntimeError> {
+        let requests = texts.iter().map(|text| self.embed_request(text)).collect();
+
+        self.cache.get_or_compute_float_batch(requests, |missing| {
+            self.backend.embed_batch(&missing.iter().map(|&i| texts[i]).collect::<Vec<_>>())
+        })
+    }
+
+    fn classify(&self, text: &str, categories: &[String]) -> Result<Vec<f64>, RuntimeError> {
+        let request = json!({
+            "backend": self.backend.name(),
+            "operation": "classify",
+            "text": text,
+            "categories": categories,
+        });
+
+        self.cache.get_or_compute_floats(request, || self.backend.classify(text, categories))
+    }
+}
+
+/// An embedding provider that caches the embeddings of another provider
+#[derive(Debug)]
+pub struct CachedEmbedder {
+    /// The provider whose embeddings are cached
+    provider: Arc<dyn EmbeddingProvider>,
+
+    /// The cache
+    cache: Arc<ResponseCache>,
+}
+
+impl CachedEmbedder {
+    /// Create a new cached embedder
+    pub fn new(provider: Arc<dyn EmbeddingProvider>, cache: Arc<ResponseCache>) -> Self {
+        Self { provider, cache }
+    }
+
+    /// Get the cache request for the embedding of a text
+    fn request(&self, text: &str) -> serde_json::Value {
+        json!({
+            "embedder": self.provider.name(),
+            "operation": "embed",
+            "text": text,
+        })
+    }
+}
+
+impl EmbeddingProvider for CachedEmbedder {
+    fn name(&self) -> String {
+        self.provider.name()
+    }
+
+    fn embed(&self, text: &str) -> Result<VectorEmbedding, RuntimeError> {
+        self.cache.get_or_compute_floats(self.request(text), || self.provider.embed(text))
+    }
+
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<VectorEmbedding>, RuntimeError> {
+        let requests = texts.iter().map(|text| self.request(text)).collect();
+
+        self.cache.get_or_compute_float_batch(requests, |missing| {
+            self.provider.embed_batch(&missing.iter().map(|&i| texts[i]).collect::<Vec<_>>())
+        })
+    }
+
+    fn dimension(&self) -> usize {
+        self.provider.dimension()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::testing::TestBackend;
+
+    /// A backend that echoes its input
+    fn echoing() -> TestBackend {
+        TestBackend::new()
+            .on_complete(|prompt| Ok(format!("completed {}", prompt)))
+            .on_chat(|messages| Ok(format!("replied to {}", messages[messages.len() - 1].content)))
+            .on_embed(|text| Ok(vec![text.len() as f64, 0.5]))
+    }
+
+
+    fn cache_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("llm_lang_cache_{}_{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_cache_mode_from_str() {
+        assert_eq!("record".parse(), Ok(CacheMode::Record));
+        assert_eq!("replay".parse(), Ok(CacheMode::Replay));
+        assert_eq!("passthrough".parse(), Ok(CacheMode::Passthrough));
+        assert!("rewind".parse::<CacheMode>().is_err());
+        assert_eq!(CacheMode::Replay.to_string(), "replay");
+    }
+
+    #[test]
+    fn test_cached_backend_record_and_replay() {
+        let path = cache_path("record");
+        let backend = Arc::new(echoing());
+
+        // Recording calls the backend once per distinct request
+        let cache = Arc::new(ResponseCache::open(&path, CacheMode::Record).unwrap());
+        let cached = CachedBackend::new(backend.clone(), cache.clone());
+
+        assert_eq!(cached.chat(&[ChatMessage::user("hello")]).unwrap(), "replied to hello");
+        assert_eq!(cached.chat(&[ChatMessage::user("hello")]).unwrap(), "replied to hello");
+        assert_eq!(cached.embed("cat").unwrap(), vec![3.0, 0.5]);
+        assert_eq!(cached.classify("cat", &["animal".to_string()]).unwrap(), vec![1.0]);
+        assert_eq!(backend.calls(), 3);
+        assert_eq!(cache.len(), 3);
+
+        // The responses are written together
+        assert!(!path.exists());
+        cache.flush().unwrap();
+        assert!(path.exists());
+
+        // Replaying reads the file and never calls the backend
+        let replay = Arc::new(ResponseCache::open(&path, CacheMode::Replay).unwrap());
+        let cached = CachedBackend::new(backend.clone(), replay);
+
+        assert_eq!(cached.chat(&[ChatMessage::user("hello")]).unwrap(), "replied to hello");
+        assert_eq!(cached.embed("cat").unwrap(), vec![3.0, 0.5]);
+        assert_eq!(backend.calls(), 3);
+
+        let error = cached.complete("hello").unwrap_err();
+        assert!(error.message.starts_with("No cached response for complete request"), "{}", error.message);
+        assert_eq!(backend.calls(), 3);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cached_backend_stream() {
+        let path = cache_path("stream");
+        let backend = Arc::new(crate::runtime::nlp::MockBackend);
+        let cache = Arc::new(ResponseCache::open(&path, CacheMode::Record).unwrap());
+        let cached = CachedBackend::new(backend, cache.clone());
+
+        let mut chunks = Vec::new();
+        let mut collect = |chunk: &str| { chunks.push(chunk.to_string()); chunks.len() < 3 };
+
+        // A stream that is stopped early is not cached
+        cached.complete_stream("Once upon a time", &mut collect).unwrap();
+        assert_eq!(chunks, vec!["Once ", "upon ", "a "]);
+        assert_eq!(cache.len(), 0);
+
+        // A stream that ends is cached, and replayed as one chunk
+        let mut chunks = Vec::new();
+        cached.complete_stream("Once upon a time", &mut |chunk| { chunks.push(chunk.to_string()); true }).unwrap();
+        assert!(chunks.len() > 1);
+        assert_eq!(cache.len(), 1);
+
+        let mut replayed = Vec::new();
+        cached.complete_stream("Once upon a time", &mut |chunk| { replayed.push(chunk.to_string()); true }).unwrap();
+        assert_eq!(replayed, vec![chunks.concat()]);
+        assert_eq!(cached.complete("Once upon a time").unwrap(), chunks.concat());
+
+        cache.flush().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cached_backend_passthrough() {
+        let path = cache_path("passthrough");
+        let backend = Arc::new(echoing());
+
+        let cache = Arc::new(ResponseCache::open(&path, CacheMode::Passthrough).unwrap());
+        let cached = CachedBackend::new(backend.clone(), cache.clone());
+
+        cached.complete("hello").unwrap();
+        cached.complete("hello").unwrap();
+
+        assert_eq!(backend.calls(), 2);
+        assert!(cache.is_empty());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_cached_embedder() {
+        let path = cache_path("embedder");
+        let provider: Arc<dyn EmbeddingProvider> = Arc::new(crate::runtime::vector::HashEmbedder::new(8));
+
+        let cache = Arc::new(ResponseCache::open(&path, CacheMode::Record).unwrap());
+        let embedding = CachedEmbedder::new(provider.clone(), cache).embed("hello world").unwrap();
+
+        let replay = Arc::new(ResponseCache::open(&path, CacheMode::Replay).unwrap());
+        let cached = CachedEmbedder::new(provider, replay);
+
+        assert_eq!(cached.embed("hello world").unwrap(), embedding);
+        assert_eq!(cached.dimension(), 8);
+        assert!(cached.embed("goodbye").is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// An embedding provider that records the texts of each batch it embeds
+    #[derive(Debug, Default)]
+    struct Batches(Mutex<Vec<Vec<String>>>);
+
+    impl EmbeddingProvider for Batches {
+        fn name(&self) -> String {
+            "batches".to_string()
+        }
+
+        fn embed(&self, text: &str) -> Result<VectorEmbedding, RuntimeError> {
+            Ok(self.embed_batch(&[text])?.remove(0))
+        }
+
+        fn embed_batch(&self, texts: &[&str]) -> Result<Vec<VectorEmbedding>, RuntimeError> {
+            self.0.lock().unwrap().push(texts.iter().map(|text| text.to_string()).collect());
+            Ok(texts.iter().map(|text| vec![text.len() as f64]).collect())
+        }
+
+        fn dimension(&self) -> usize {
+            1
+        }
+    }
+
+    #[test]
+    fn test_cached_embedder_batch() {
+        let path = cache_path("embedder_batch");
+        let provider = Arc::new(Batches::default());
+        let cache = Arc::new(ResponseCache::open(&path, CacheMode::Record).unwrap());
+        let cached = CachedEmbedder::new(provider.clone(), cache.clone());
+
+        cached.embed("a").unwrap();
+
+        // Only the texts that are not cached are embedded, in one call
+        assert_eq!(cached.embed_batch(&["bb", "a", "ccc"]).unwrap(), vec![vec![2.0], vec![1.0], vec![3.0]]);
+        assert_eq!(*provider.0.lock().unwrap(), vec![vec!["a"], vec!["bb", "ccc"]]);
+        assert_eq!(cache.len(), 3);
+
+        cached.embed_batch(&["a", "ccc"]).unwrap();
+        assert_eq!(provider.0.lock().unwrap().len(), 2);
+
+        cache.flush().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cache_saves_in_batches() {
+        let path = cache_path("batches");
+        let cache = Arc::new(ResponseCache::open(&path, CacheMode::Record).unwrap());
+        let cached = CachedBackend::new(Arc::new(echoing()), cache.clone());
+
+        for i in 0..SAVE_BATCH - 1 {
+            cached.complete(&i.to_string()).unwrap();
+        }
+
+        assert!(!path.exists());
+
+        // A full batch is written
+        cached.complete("last").unwrap();
+        assert_eq!(ResponseCache::open(&path, CacheMode::Replay).unwrap().len(), SAVE_BATCH);
+
+        // The rest is written when the cache is dropped
+        cached.complete("after").unwrap();
+        drop(cached);
+        drop(cache);
+        assert_eq!(ResponseCache::open(&path, CacheMode::Replay).unwrap().len(), SAVE_BATCH + 1);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let request = json!({ "operation": "complete", "prompt": "hello" });
+
+        // The key is stable, and differs for different requests
+        assert_eq!(cache_key(&request), cache_key(&request.clone()));
+        assert_eq!(cache_key(&request).len(), 16);
+        assert_ne!(cache_key(&request), cache_key(&json!({ "operation": "complete", "prompt": "hello!" })));
+    }
+}
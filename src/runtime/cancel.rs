@@ -0,0 +1,238 @@
+//! Cancellation module for the LLM.lang runtime
+//!
+//! This module provides cancellation tokens for the LLM.lang runtime,
+//! which allow long-running work to be stopped cooperatively.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::utils::SourceLocation;
+use super::error::RuntimeError;
+
+thread_local! {
+    /// The token checked by code that has no access to the engine
+    static CURRENT: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+}
+
+/// The shared state of a cancellation token
+#[derive(Debug, Default)]
+struct TokenState {
+    /// Whether the token has been cancelled
+    cancelled: AtomicBool,
+
+    /// The time at which the token expires, and the timeout it was created with
+    deadline: Option<(Instant, Duration)>,
+
+    /// The token this token was derived from
+    parent: Option<CancellationToken>,
+}
+
+/// A cancellation token
+///
+/// Clones of a token share the same state, so cancelling one cancels all of
+/// them. A child token is cancelled when its parent is, but cancelling a child
+/// does not affect the parent.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    /// The shared token state
+    state: Arc<TokenState>,
+}
+
+/// Restores the previous current token when dropped
+pub struct CurrentGuard {
+    /// The token that was current before
+    previous: Option<CancellationToken>,
+}
+
+impl CancellationToken {
+    /// Create a new cancellation token
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a child token that is cancelled along with this one
+    pub fn child(&self) -> Self {
+        Self {
+            state: Arc::new(TokenState {
+                cancelled: AtomicBool::new(false),
+                deadline: None,
+                parent: Some(self.clone()),
+            }),
+        }
+    }
+
+    /// Create a child token that is also cancelled once the timeout has elapsed
+    pub fn with_deadline(&self, timeout: Duration) -> Self {
+        Self {
+            state: Arc::new(TokenState {
+                cancelled: AtomicBool::new(false),
+                deadline: Some((Instant::now() + timeout, timeout)),
+                parent: Some(self.clone()),
+            }),
+        }
+    }
+
+    /// Cancel the token
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Check if the token or one of its parents has been cancelled or has expired
+    pub fn is_cancelled(&self) -> bool {
+        if self.state.cancelled.load(Ordering::SeqCst) {
+            return true;
+        }
+
+        if let Some((deadline, _)) = self.state.deadline {
+            if Instant::now() >= deadline {
+                return true;
+            }
+        }
+
+        match &self.state.parent {
+            Some(parent) => parent.is_cancelled(),
+            None => false,
+        }
+    }
+
+    /// Get the timeout of the first expired deadline of the token or its parents
+    pub fn expired_deadline(&self) -> Option<Duration> {
+        if let Some((deadline, timeout)) = self.state.deadline {
+            if Instant::now() >= deadline {
+                return Some(timeout);
+            }
+        }
+
+        self.state.parent.as_ref().and_then(|parent| parent.expired_deadline())
+    }
+
+    /// Return an error if the token has been cancelled or has expired
+    pub fn check(&self, location: &SourceLocation) -> Result<(), RuntimeError> {
+        if let Some(timeout) = self.expired_deadline() {
+            Err(RuntimeError::deadline_exceeded(timeout.as_millis() as u64, location.clone()))
+        } else if self.is_cancelled() {
+            Err(RuntimeError::cancelled(location.clone()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Make this the current token of the calling thread until the guard is dropped
+    pub fn enter(&self) -> CurrentGuard {
+        let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+
+        CurrentGuard { previous }
+    }
+
+    /// Get the current token of the calling thread
+    pub fn current() -> Option<CancellationToken> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Return an error if the current token of the calling thread has been cancelled or has expired
+    ///
+    /// This is used by the standard library, the natural language processor and
+    /// the interoperability manager, which run on behalf of the engine but have
+    /// no access to it.
+    pub fn check_current() -> Result<(), RuntimeError> {
+        match Self::current() {
+            Some(token) => token.check(&SourceLocation::new(0, 0, 0, 0, "")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for CurrentGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancellation_token_cancel() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+
+        assert!(!token.is_cancelled());
+
+        clone.cancel();
+
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn test_cancellation_token_child() {
+        let parent = CancellationToken::new();
+        let child = parent.child();
+        let sibling = parent.child();
+
+        child.cancel();
+
+        assert!(child.is_cancelled());
+        assert!(!sibling.is_cancelled());
+        assert!(!parent.is_cancelled());
+
+        parent.cancel();
+
+        assert!(sibling.is_cancelled());
+    }
+
+    #[test]
+    fn test_cancellation_token_across_threads() {
+        let token = CancellationToken::new();
+        let child = token.child();
+
+        let handle = std::thread::spawn(move || {
+            while !child.is_cancelled() {
+                std::thread::yield_now();
+            }
+        });
+
+        token.cancel();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_cancellation_token_deadline() {
+        let token = CancellationToken::new();
+        let child = token.with_deadline(Duration::from_millis(10));
+        let location = SourceLocation::new(1, 1, 1, 1, "test.llm");
+
+        assert!(child.check(&location).is_ok());
+
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert!(child.is_cancelled());
+        assert!(!token.is_cancelled());
+        assert_eq!(child.expired_deadline(), Some(Duration::from_millis(10)));
+        assert_eq!(child.check(&location).unwrap_err().message, "Deadline of 10ms exceeded");
+
+        // Cancelling explicitly is reported as a cancellation
+        let other = token.child();
+        other.cancel();
+        assert_eq!(other.check(&location).unwrap_err().message, "Execution was cancelled");
+    }
+
+    #[test]
+    fn test_cancellation_token_current() {
+        assert!(CancellationToken::check_current().is_ok());
+
+        let token = CancellationToken::new();
+
+        {
+            let _guard = token.enter();
+            token.cancel();
+
+            assert!(CancellationToken::check_current().is_err());
+        }
+
+        assert!(CancellationToken::current().is_none());
+    }
+}
@@ -4,6 +4,7 @@
 //! which manages execution contexts and variable scopes.
 
 use std::collections::HashMap;
+use std::sync::Arc;
 use crate::Value;
 use crate::parser::ast::Node;
 use super::error::RuntimeError;
@@ -105,9 +106,13 @@ impl ExecutionContext {
 }
 
 /// A context manager
+///
+/// Execution contexts are shared copy-on-write, so cloning a context manager
+/// to fork execution is cheap until one of the copies is modified.
+#[derive(Debug, Clone)]
 pub struct Context {
     /// The execution contexts
-    contexts: HashMap<String, ExecutionContext>,
+    contexts: HashMap<String, Arc<ExecutionContext>>,
     
     /// The current context
     current_context: String,
@@ -124,7 +129,7 @@ impl Context {
     pub fn new() -> Self {
         // Create the global context
         let mut contexts = HashMap::new();
-        contexts.insert("global".to_string(), ExecutionContext::new("global"));
+        contexts.insert("global".to_string(), Arc::new(ExecutionContext::new("global")));
         
         Self {
             contexts,
@@ -136,7 +141,7 @@ impl Context {
     
     /// Create a new context
     pub fn create_context(&mut self, name: &str) {
-        self.contexts.insert(name.to_string(), ExecutionContext::new(name));
+        self.contexts.insert(name.to_string(), Arc::new(ExecutionContext::new(name)));
     }
     
     /// Switch to a context
@@ -168,7 +173,7 @@ impl Context {
     /// Register a function in the current context
     pub fn register_function(&mut self, name: &str, node: &Node) {
         if let Some(context) = self.contexts.get_mut(&self.current_context) {
-            context.register_function(name, node);
+            Arc::make_mut(context).register_function(name, node);
         }
     }
     
@@ -189,7 +194,7 @@ impl Context {
             scope.register_variable(name, value);
         } else if let Some(context) = self.contexts.get_mut(&self.current_context) {
             // Register the variable in the current context
-            context.register_variable(name, value);
+            Arc::make_mut(context).register_variable(name, value);
         }
     }
     
@@ -234,7 +239,7 @@ impl Context {
         // Check the current context
         if let Some(context) = self.contexts.get_mut(&self.current_context) {
             if context.variables.contains_key(name) {
-                context.assign_variable(name, value)?;
+                Arc::make_mut(context).assign_variable(name, value)?;
                 return Ok(());
             }
         }
@@ -243,7 +248,7 @@ impl Context {
         if self.current_context != "global" {
             if let Some(context) = self.contexts.get_mut("global") {
                 if context.variables.contains_key(name) {
-                    context.assign_variable(name, value)?;
+                    Arc::make_mut(context).assign_variable(name, value)?;
                     return Ok(());
                 }
             }
@@ -254,6 +259,28 @@ impl Context {
             crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
         ))
     }
+    
+    /// Get the name of the current context
+    pub fn current_context(&self) -> &str {
+        &self.current_context
+    }
+    
+    /// Get the variables and functions of a context
+    pub fn get_context_state(&self, name: &str) -> Option<(HashMap<String, Value>, HashMap<String, Node>)> {
+        self.contexts.get(name).map(|context| {
+            (context.variables.clone(), context.functions.clone())
+        })
+    }
+    
+    /// Replace the variables and functions of a context, creating it if needed
+    pub fn set_context_state(&mut self, name: &str, variables: HashMap<String, Value>, functions: HashMap<String, Node>) {
+        let context = Arc::make_mut(
+            self.contexts.entry(name.to_string()).or_insert_with(|| Arc::new(ExecutionContext::new(name)))
+        );
+        
+        context.variables = variables;
+        context.functions = functions;
+    }
 }
 
 #[cfg(test)]
@@ -409,4 +436,39 @@ mod tests {
         
         assert_eq!(context.get_variable("test"), Some(Value::Int(42)));
     }
+    
+    #[test]
+    fn test_context_clone_is_isolated() {
+        let mut context = Context::new();
+        
+        context.register_variable("test", Value::Int(42));
+        
+        let mut fork = context.clone();
+        fork.assign_variable("test", Value::Int(43)).unwrap();
+        fork.register_variable("other", Value::Int(1));
+        
+        assert_eq!(context.get_variable("test"), Some(Value::Int(42)));
+        assert_eq!(context.get_variable("other"), None);
+        assert_eq!(fork.get_variable("test"), Some(Value::Int(43)));
+    }
+    
+    #[test]
+    fn test_context_set_context_state() {
+        let mut context = Context::new();
+        
+        context.register_variable("old", Value::Int(1));
+        context.set_context_state(
+            "global",
+            HashMap::from([("new".to_string(), Value::Int(2))]),
+            HashMap::new(),
+        );
+        
+        assert_eq!(context.get_variable("old"), None);
+        assert_eq!(context.get_variable("new"), Some(Value::Int(2)));
+        
+        let (variables, functions) = context.get_context_state("global").unwrap();
+        assert_eq!(variables.len(), 1);
+        assert!(functions.is_empty());
+        assert!(context.get_context_state("missing").is_none());
+    }
 }
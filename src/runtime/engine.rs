@@ -3,21 +3,97 @@
 //! This module provides the execution engine for the LLM.lang runtime,
 //! which executes the abstract syntax tree (AST) produced by the parser.
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock};
 use std::time::{Duration, Instant};
 
 use crate::parser::ast::{Ast, Node, NodeKind};
+use crate::parser::template::IntentPattern;
+use crate::semantic::analyzer::Symbol;
 use crate::Value;
 
-use super::error::RuntimeError;
+use super::error::{RuntimeError, RuntimeErrorKind};
 use super::memory::Memory;
 use super::context::Context;
-use super::vector::Vector;
-use super::parallel::Parallel;
-use super::example::Example;
-use super::nlp::NLP;
+use super::vector::{EmbeddingProvider, HashEmbedder, Vector, DEFAULT_EMBEDDING_DIMENSION};
+use super::parallel::{Parallel, ParallelTask};
+use super::example::{Example, ExampleCheck};
+use super::nlp::{MockBackend, NLP};
+use super::llm::LlmBackend;
+use super::cache::{CachedBackend, CachedEmbedder, ResponseCache};
+use super::budget::{MeteredBackend, TokenBudget, TokenCounter};
+use super::schema::{RecordTypes, Schema};
+use super::intent::{self, IntentRouter, IntentRouting, DEFAULT_INTENT_THRESHOLD};
+use super::tools::{Tool, ToolCall, DEFAULT_MAX_TOOL_STEPS};
+use super::prompt::{Prompt, Prompts};
+use super::stream::{TextStream, DEFAULT_STREAM_BUFFER};
+use super::rag::{self, Chunk, DEFAULT_RAG_SOURCES};
 use super::interop::Interop;
 use super::modify::{Modify, Modification};
+use super::snapshot::ContextSnapshot;
+use super::cancel::CancellationToken;
+use super::index::{self as vector_index, IndexEntry, VectorIndex};
+
+/// Functions that are implemented by the engine because they need access to runtime state
+const RUNTIME_FUNCTIONS: &[&str] = &[
+    "currentContext",
+    "saveContext",
+    "loadContext",
+    "diffContexts",
+    "pathScores",
+    "classify",
+    "routeIntent",
+    "agent",
+    "promptVersion",
+    "usePromptVersion",
+    "indexCreate",
+    "indexAdd",
+    "indexSearch",
+    "indexSearchWhere",
+    "indexRemove",
+    "indexSize",
+    "indexSave",
+    "indexLoad",
+    "chunkByTokens",
+    "indexAddChunks",
+    "ragRetrieve",
+    "ragAnswer",
+    "pythonEval",
+    "pythonExec",
+    "pythonImport",
+    "pythonCall",
+];
+
+/// The confidence a probabilistic value needs to be used by `??`, unless configured otherwise
+pub const DEFAULT_CONFIDENCE_THRESHOLD: f64 = 0.5;
+
+/// A vector index shared between an engine and its forks
+type SharedIndex = Arc<RwLock<Box<dyn VectorIndex>>>;
+
+/// Get the document an index entry came from, given by its `source` metadata
+fn entry_source(entry: &IndexEntry) -> Option<&str> {
+    match entry.metadata.get("source") {
+        Some(Value::String(source)) => Some(source),
+        _ => None,
+    }
+}
+
+/// A change made to the vector indexes by a forked engine
+///
+/// Forks record their changes so that the join of a parallel block can apply
+/// the changes of the selected paths to the engine that forked them.
+#[derive(Debug, Clone)]
+enum IndexChange {
+    /// An index was created or loaded, replacing any index with the same name
+    Register(String, Box<dyn VectorIndex>),
+    
+    /// An entry was added to an index
+    Add(String, IndexEntry),
+    
+    /// An entry was removed from an index
+    Remove(String, String),
+}
 
 /// Options for the runtime engine
 #[derive(Debug, Clone)]
@@ -42,6 +118,45 @@ pub struct EngineOptions {
     
     /// Whether to enable self-modifying capabilities
     pub self_modifying: bool,
+    
+    /// The maximum number of threads used by parallel blocks
+    pub max_threads: usize,
+    
+    /// The number of dimensions of the default embedding provider
+    pub embedding_dimension: usize,
+    
+    /// The embedding provider, replacing the default hash embedder
+    pub embedding_provider: Option<Arc<dyn EmbeddingProvider>>,
+    
+    /// The language model backend, replacing the default mock backend
+    pub llm_backend: Option<Arc<dyn LlmBackend>>,
+    
+    /// The cache of language model and embedding responses, if any
+    pub response_cache: Option<Arc<ResponseCache>>,
+    
+    /// The maximum number of tokens a run may send to and receive from language models
+    pub max_tokens: Option<u64>,
+    
+    /// The token counter, replacing the default word counter
+    pub token_counter: Option<Arc<TokenCounter>>,
+    
+    /// The confidence a probabilistic value needs to be used by `??`
+    pub confidence_threshold: f64,
+    
+    /// The confidence a routed utterance needs to go to an intent handler other than the fallback
+    pub intent_threshold: f64,
+    
+    /// How utterances that do not match an intent pattern exactly are routed
+    pub intent_routing: IntentRouting,
+    
+    /// The number of tools a language model may call in one `agent` call before it must answer
+    pub max_tool_steps: usize,
+    
+    /// The number of chunks of a `stream` that are held before the language model waits for them to be read
+    pub stream_buffer: usize,
+    
+    /// How long a `stream` without a timeout waits for each chunk, in milliseconds
+    pub stream_timeout: Option<u64>,
 }
 
 impl Default for EngineOptions {
@@ -54,6 +169,19 @@ impl Default for EngineOptions {
             vectors: true,
             nlp: true,
             self_modifying: true,
+            max_threads: 4,
+            embedding_dimension: DEFAULT_EMBEDDING_DIMENSION,
+            embedding_provider: None,
+            llm_backend: None,
+            response_cache: None,
+            max_tokens: None,
+            token_counter: None,
+            confidence_threshold: DEFAULT_CONFIDENCE_THRESHOLD,
+            intent_threshold: DEFAULT_INTENT_THRESHOLD,
+            intent_routing: IntentRouting::Embedding,
+            max_tool_steps: DEFAULT_MAX_TOOL_STEPS,
+            stream_buffer: DEFAULT_STREAM_BUFFER,
+            stream_timeout: None,
         }
     }
 }
@@ -95,6 +223,60 @@ pub struct Engine {
     
     /// The peak memory usage in bytes
     peak_memory: usize,
+    
+    /// The scores of the paths in the most recent `select best` block
+    path_scores: HashMap<String, f64>,
+    
+    /// The token used to stop execution cooperatively
+    cancellation: CancellationToken,
+    
+    /// The vector indexes by name
+    indexes: HashMap<String, SharedIndex>,
+    
+    /// The indexes a fork still shares with its parent, which are copied before they are changed
+    shared_indexes: HashSet<String>,
+    
+    /// The changes a fork has made to the indexes, or `None` if the engine is not a fork
+    index_changes: Option<Vec<IndexChange>>,
+    
+    /// The identifiers of the entries of each index by the document they came from, per `source` metadata
+    index_sources: HashMap<String, HashMap<String, BTreeSet<String>>>,
+    
+    /// The record types declared with `type`
+    types: RecordTypes,
+    
+    /// The intent handlers declared with `on intent`
+    intents: IntentRouter,
+    
+    /// The names of the functions declared in `tools` blocks
+    tools: Vec<String>,
+    
+    /// The prompts declared with `prompt`, with all of their versions
+    prompts: Prompts,
+    
+    /// The tools called by language models during the run
+    tool_calls: Vec<ToolCall>,
+    
+    /// The token budget of the run, or of the innermost `budget` block being executed
+    budget: TokenBudget,
+}
+
+/// The outcome of a path executed by a parallel block
+struct PathOutcome {
+    /// The path result
+    result: Result<Value, RuntimeError>,
+    
+    /// The number of instructions executed by the path
+    instructions: u64,
+    
+    /// The peak memory usage of the path in bytes
+    peak_memory: usize,
+    
+    /// The changes made to the vector indexes by the path
+    index_changes: Vec<IndexChange>,
+    
+    /// The tools called by language models in the path
+    tool_calls: Vec<ToolCall>,
 }
 
 impl Engine {
@@ -104,11 +286,16 @@ impl Engine {
             execution_time: self.start_time.map_or(0, |t| t.elapsed().as_millis() as u64),
             peak_memory: self.peak_memory,
             instructions: self.instructions,
+            tokens: self.budget.used(),
+            token_usage: self.budget.usage(),
+            tool_calls: self.tool_calls.clone(),
         }
     }
 
     /// Create a new execution engine
     pub fn new(options: EngineOptions) -> Self {
+        let budget = TokenBudget::new(options.max_tokens);
+        
         let mut engine = Self {
             options,
             memory: Memory::new(),
@@ -122,21 +309,98 @@ impl Engine {
             start_time: None,
             instructions: 0,
             peak_memory: 0,
+            path_scores: HashMap::new(),
+            cancellation: CancellationToken::new(),
+            indexes: HashMap::new(),
+            shared_indexes: HashSet::new(),
+            index_sources: HashMap::new(),
+            index_changes: None,
+            types: RecordTypes::new(),
+            intents: IntentRouter::new(),
+            tools: Vec::new(),
+            prompts: Prompts::new(),
+            tool_calls: Vec::new(),
+            budget,
+        };
+        
+        // Limit the number of threads used by parallel blocks
+        let max_threads = engine.options.max_threads;
+        engine.parallel.set_max_threads(max_threads);
+        
+        // Share one embedding provider between the vector engine, examples and standard library
+        let mut provider = match &engine.options.embedding_provider {
+            Some(provider) => Arc::clone(provider),
+            None => Arc::new(HashEmbedder::new(engine.options.embedding_dimension)),
         };
         
+        if let Some(cache) = &engine.options.response_cache {
+            provider = Arc::new(CachedEmbedder::new(provider, Arc::clone(cache)));
+        }
+        
+        engine.set_embedding_provider(provider);
+        
+        // Send natural language tasks to the language model backend, if any, through the cache
+        let mut backend = engine.options.llm_backend.clone().unwrap_or_else(|| Arc::new(MockBackend));
+        
+        if let Some(cache) = &engine.options.response_cache {
+            backend = Arc::new(CachedBackend::new(backend, Arc::clone(cache)));
+        }
+        
+        // Count the tokens of every task, including those answered from the cache
+        let counter = engine.options.token_counter.clone().unwrap_or_default();
+        engine.nlp.set_backend(Arc::new(MeteredBackend::new(backend, counter)));
+        
         // Register standard library functions
         engine.register_stdlib_functions();
         
         engine
     }
     
+    /// Fork the engine for an isolated execution path
+    ///
+    /// The fork starts with the same contexts, memory and vector indexes as
+    /// this engine, but changes made by either side are not visible to the
+    /// other. Indexes are copied when the fork first changes them, and the
+    /// changes are recorded so that they can be applied to this engine if
+    /// the path is selected. Cancelling this engine also cancels the fork.
+    fn fork(&self) -> Self {
+        Self {
+            options: self.options.clone(),
+            memory: self.memory.clone(),
+            context: self.context.clone(),
+            vector: self.vector.clone(),
+            parallel: self.parallel.clone(),
+            example: self.example.clone(),
+            nlp: self.nlp.clone(),
+            interop: self.interop.fork(),
+            modify: self.modify.clone(),
+            start_time: self.start_time,
+            instructions: 0,
+            peak_memory: self.peak_memory,
+            path_scores: HashMap::new(),
+            cancellation: self.cancellation.child(),
+            indexes: self.indexes.clone(),
+            shared_indexes: self.indexes.keys().cloned().collect(),
+            index_changes: Some(Vec::new()),
+            index_sources: self.index_sources.clone(),
+            types: self.types.clone(),
+            intents: self.intents.clone(),
+            tools: self.tools.clone(),
+            prompts: self.prompts.clone(),
+            tool_calls: Vec::new(),
+            budget: self.budget.clone(),
+        }
+    }
+    
     /// Register standard library functions
     fn register_stdlib_functions(&mut self) {
         // Create a standard library
         let stdlib = crate::stdlib::StdLib::new();
         
-        // Register the standard library functions in the global context
-        for (name, _) in &stdlib.functions {
+        // Register the standard library and runtime functions in the global context
+        let names = stdlib.names().cloned().chain(RUNTIME_FUNCTIONS.iter().map(|name| name.to_string()));
+        
+        for name in &names.collect::<Vec<String>>() {
             // Create a function node
             let location = crate::utils::SourceLocation::new(0, 0, 0, 0, "stdlib");
             let mut node = crate::parser::ast::Node {
@@ -154,6 +418,158 @@ impl Engine {
         }
     }
     
+    /// Capture a snapshot of a context
+    pub fn snapshot_context(&self, name: &str) -> Result<ContextSnapshot, RuntimeError> {
+        ContextSnapshot::capture(&self.context, &self.memory, name)
+    }
+    
+    /// Restore a context from a snapshot
+    pub fn restore_context(&mut self, snapshot: &ContextSnapshot) {
+        snapshot.restore(&mut self.context, &mut self.memory);
+    }
+    
+    /// Save a context to a snapshot file
+    pub fn save_context(&self, name: &str, path: &Path) -> Result<(), RuntimeError> {
+        self.snapshot_context(name)?.save(path)
+    }
+    
+    /// Load a context from a snapshot file
+    ///
+    /// This replaces the context and the whole memory, like `restore_context`.
+    pub fn load_context(&mut self, path: &Path) -> Result<ContextSnapshot, RuntimeError> {
+        let snapshot = ContextSnapshot::load(path)?;
+        self.restore_context(&snapshot);
+        Ok(snapshot)
+    }
+    
+    /// Register a vector index, replacing any index with the same name
+    pub fn register_index(&mut self, name: &str, index: Box<dyn VectorIndex>) {
+        if let Some(changes) = &mut self.index_changes {
+            changes.push(IndexChange::Register(name.to_string(), index.clone()));
+        }
+        
+        // Entries loaded with the index are found by their document like those added later
+        let mut sources: HashMap<String, BTreeSet<String>> = HashMap::new();
+        
+        for id in index.ids() {
+            if let Some(source) = index.get(&id).and_then(entry_source) {
+                sources.entry(source.to_string()).or_default().insert(id);
+            }
+        }
+        
+        self.index_sources.insert(name.to_string(), sources);
+        self.shared_indexes.remove(name);
+        self.indexes.insert(name.to_string(), Arc::new(RwLock::new(index)));
+    }
+    
+    /// Change a vector index, returning whether it changed
+    ///
+    /// A fork copies an index it still shares with its parent before the
+    /// first change, so paths never see each other's changes. The copy holds
+    /// every entry and graph node of the index, so the first write of each
+    /// path to a large index costs as much memory and time as cloning it.
+    fn change_index(&mut self, change: IndexChange) -> Result<bool, RuntimeError> {
+        let name = match &change {
+            IndexChange::Register(name, index) => {
+                self.register_index(name, index.clone());
+                return Ok(true);
+            }
+            IndexChange::Add(name, _) | IndexChange::Remove(name, _) => name.clone(),
+        };
+        
+        let index = self.indexes.get(&name).cloned().ok_or_else(|| {
+            RuntimeError::new(&format!("Undefined index: '{}'", name), crate::utils::SourceLocation::new(0, 0, 0, 0, ""))
+        })?;
+        
+        let index = if self.shared_indexes.remove(&name) {
+            let copy = index.read().unwrap_or_else(PoisonError::into_inner).clone_index();
+            let copy = Arc::new(RwLock::new(copy));
+            self.indexes.insert(name.clone(), Arc::clone(&copy));
+            copy
+        } else {
+            index
+        };
+        
+        let mut index = index.write().unwrap_or_else(PoisonError::into_inner);
+        let sources = self.index_sources.entry(name).or_default();
+        
+        // The entry that is replaced or removed no longer belongs to its document
+        let id = match &change {
+            IndexChange::Add(_, entry) => &entry.id,
+            IndexChange::Remove(_, id) => id,
+            IndexChange::Register(..) => unreachable!(),
+        };
+        
+        let previous = index.get(id).and_then(entry_source).map(str::to_string);
+        
+        let changed = match &change {
+            IndexChange::Add(_, entry) => {
+                index.add(entry.clone())?;
+                true
+            }
+            IndexChange::Remove(_, id) => index.remove(id),
+            IndexChange::Register(..) => unreachable!(),
+        };
+        
+        if let Some(ids) = previous.and_then(|source| sources.get_mut(&source)) {
+            ids.remove(id);
+        }
+        
+        if let IndexChange::Add(_, entry) = &change {
+            if let Some(source) = entry_source(entry) {
+                sources.entry(source.to_string()).or_default().insert(entry.id.clone());
+            }
+        }
+        
+        drop(index);
+        
+        if let Some(changes) = &mut self.index_changes {
+            changes.push(change);
+        }
+        
+        Ok(changed)
+    }
+    
+    /// Apply the index changes of a parallel path to this engine
+    fn apply_index_changes(&mut self, changes: Vec<IndexChange>) -> Result<(), RuntimeError> {
+        for change in changes {
+            self.change_index(change)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Get a vector index by name
+    pub fn index(&self, name: &str) -> Option<SharedIndex> {
+        self.indexes.get(name).cloned()
+    }
+    
+    /// Set the embedding provider
+    ///
+    /// The provider is used by vector operations, example matching and the
+    /// `embed` standard library function.
+    pub fn set_embedding_provider(&mut self, provider: Arc<dyn EmbeddingProvider>) {
+        self.vector.set_embedding_provider(Arc::clone(&provider));
+        self.example.set_embedding_provider(provider);
+    }
+    
+    /// Cancel the current execution
+    ///
+    /// Execution stops with an error at the next instruction, including any
+    /// running parallel paths. Once that run ends, the engine gets a new token
+    /// so it can run again, and tokens obtained before then no longer affect it.
+    pub fn cancel(&self) {
+        self.cancellation.cancel();
+    }
+    
+    /// Get the token that cancels this engine
+    ///
+    /// Cancelling the token from another thread is equivalent to calling
+    /// `cancel` while the engine is executing.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+    
     /// Execute an AST
     pub fn execute(&mut self, ast: Ast) -> Result<Value, RuntimeError> {
         // Record the start time
@@ -162,18 +578,55 @@ impl Engine {
         // Reset execution statistics
         self.instructions = 0;
         self.peak_memory = 0;
+        self.budget = TokenBudget::new(self.options.max_tokens);
+        self.tool_calls.clear();
+        
+        // Apply the maximum execution time to everything that checks the cancellation token
+        let outer = self.cancellation.clone();
+        
+        if let Some(max_time) = self.options.max_time {
+            self.cancellation = outer.with_deadline(Duration::from_millis(max_time));
+        }
         
         // Execute the AST
-        let result = self.execute_node(&ast.root)?;
+        let result = {
+            let _guard = self.cancellation.enter();
+            let _provider = self.vector.enter();
+            let _budget = self.budget.enter();
+            self.execute_node(&ast.root)
+        };
+        
+        // Only the maximum execution time is reported here, not an outer cancellation
+        let expired = self.cancellation.is_cancelled() && !outer.is_cancelled();
+        self.cancellation = outer;
         
-        // Create the execution result
         let execution_time = self.start_time.unwrap().elapsed().as_millis() as u64;
         
+        // A cancelled engine starts the next run with a new token
+        if self.cancellation.is_cancelled() {
+            self.cancellation = CancellationToken::new();
+        }
+        
+        // Save the responses recorded during the run, even if it failed
+        let saved = self.options.response_cache.as_ref().map_or(Ok(()), |cache| cache.flush());
+        
+        let result = match (result, self.options.max_time) {
+            (Err(error), Some(max_time)) if expired && error.is_interruption() => {
+                Err(RuntimeError::time_limit_exceeded(execution_time, max_time))
+            }
+            (result, _) => result,
+        }?;
+        
+        saved?;
+        
         // Create the execution statistics
         let stats = crate::ExecutionStats {
             execution_time,
             peak_memory: self.peak_memory,
             instructions: self.instructions,
+            tokens: self.budget.used(),
+            token_usage: self.budget.usage(),
+            tool_calls: self.tool_calls.clone(),
         };
         
         // Create the execution result
@@ -185,18 +638,92 @@ impl Engine {
         Ok(result)
     }
     
+    /// Register the declarations of a program without running its statements
+    ///
+    /// Functions, contexts, examples, transformations, types, prompts and
+    /// tools are registered as `execute` registers them. Every other statement
+    /// is skipped, so nothing the program does on its own is run.
+    pub fn declare(&mut self, ast: &Ast) -> Result<(), RuntimeError> {
+        self.execute(Ast::new(Self::declarations(&ast.root))).map(|_| ())
+    }
+    
+    /// Copy a program or context node with only its declarations
+    fn declarations(node: &Node) -> Node {
+        let mut node = node.clone();
+        
+        node.children.retain(|child| matches!(
+            child.kind,
+            NodeKind::Context | NodeKind::Function | NodeKind::Examples | NodeKind::Transform
+                | NodeKind::TypeDeclaration | NodeKind::Prompt | NodeKind::Tools
+        ));
+        
+        for child in &mut node.children {
+            if child.kind == NodeKind::Context {
+                **child = Self::declarations(child);
+            }
+        }
+        
+        node
+    }
+    
+    /// Run every function declared with `fn` on the examples given for it
+    ///
+    /// This is meant to be called after `declare` or `execute`, once the
+    /// functions and their `examples for` blocks have been registered. Functions that are
+    /// only defined by examples are not checked. An error raised by a
+    /// function is reported as the result of its check instead of stopping
+    /// the others.
+    pub fn check_examples(&mut self) -> Vec<ExampleCheck> {
+        let _guard = self.cancellation.enter();
+        let _provider = self.vector.enter();
+        let _budget = self.budget.enter();
+        
+        let location = crate::utils::SourceLocation::new(0, 0, 0, 0, "");
+        let mut checks = Vec::new();
+        
+        let names: Vec<String> = self.example.function_names().into_iter().map(str::to_string).collect();
+        
+        for name in names {
+            let Some(function) = self.context.get_function(&name) else {
+                continue;
+            };
+            
+            // The stubs registered for built-in functions have no body
+            if !function.children.iter().any(|child| child.kind == NodeKind::Block) {
+                continue;
+            }
+            
+            let arity = function.children.iter().filter(|child| child.kind == NodeKind::Parameter).count();
+            
+            for (input, expected) in self.example.get_function_examples(&name).unwrap_or_default() {
+                let arguments = Example::arguments(&input, arity);
+                let frames = self.context.clone();
+                let actual = self.call_function(&name, arguments.clone(), &location);
+                
+                // A failed call leaves its frame behind
+                if actual.is_err() {
+                    self.context = frames;
+                }
+                
+                checks.push(ExampleCheck {
+                    function: name.clone(),
+                    arguments,
+                    expected,
+                    actual: actual.map_err(|error| error.message),
+                });
+            }
+        }
+        
+        checks
+    }
+    
     /// Execute a node
     fn execute_node(&mut self, node: &Node) -> Result<Value, RuntimeError> {
         // Increment the instruction count
         self.instructions += 1;
         
-        // Check if we've exceeded the maximum execution time
-        if let Some(max_time) = self.options.max_time {
-            let elapsed = self.start_time.unwrap().elapsed().as_millis() as u64;
-            if elapsed > max_time {
-                return Err(RuntimeError::time_limit_exceeded(elapsed, max_time));
-            }
-        }
+        // Stop if execution has been cancelled, a deadline has passed or the maximum execution time is exceeded
+        self.cancellation.check(&node.location)?;
         
         // Check if we've exceeded the maximum memory usage
         if let Some(max_memory) = self.options.max_memory {
@@ -229,13 +756,23 @@ impl Engine {
             NodeKind::Return => self.execute_return(node),
             NodeKind::With => self.execute_with(node),
             NodeKind::Within => self.execute_within(node),
+            NodeKind::Deadline => self.execute_deadline(node),
+            NodeKind::Budget => self.execute_budget(node),
             NodeKind::Intent => self.execute_intent(node),
+            NodeKind::IntentHandler => self.execute_intent_handler(node),
+            NodeKind::Tools => self.execute_tools(node),
+            NodeKind::Prompt => self.execute_prompt(node),
+            NodeKind::Render => self.execute_render(node),
+            NodeKind::Stream => self.execute_stream(node),
             NodeKind::Parallel => self.execute_parallel(node),
             NodeKind::Path => Ok(Value::Void), // Paths are handled by the parallel statement
             NodeKind::Apply => self.execute_apply(node),
             NodeKind::Semantic => self.execute_semantic(node),
             NodeKind::Examples => self.execute_examples(node),
             NodeKind::Example => self.execute_example(node),
+            NodeKind::Transform => self.execute_transform(node),
+            NodeKind::TypeDeclaration => self.execute_type_declaration(node),
+            NodeKind::TransformApply => self.execute_transform_apply(node),
             NodeKind::Assignment => self.execute_assignment(node),
             NodeKind::Binary => self.execute_binary(node),
             NodeKind::Unary => self.execute_unary(node),
@@ -422,18 +959,31 @@ impl Engine {
             RuntimeError::missing_attribute("variable", node.location.clone())
         })?;
         
-        // Execute the collection expression
+        // Get the collection expression
         let collection = node.get_child(0).ok_or_else(|| {
             RuntimeError::missing_child(0, node.location.clone())
         })?;
         
-        let collection_value = self.execute_node(collection)?;
-        
         // Get the loop body
         let body = node.get_child(1).ok_or_else(|| {
             RuntimeError::missing_child(1, node.location.clone())
         })?;
         
+        // Iterate over the chunks of a stream as they arrive
+        if collection.kind == NodeKind::Stream {
+            let mut result = Value::Void;
+            
+            for chunk in self.open_stream(collection)? {
+                self.context.register_variable(variable, Value::String(chunk?));
+                result = self.execute_node(body)?;
+            }
+            
+            return Ok(result);
+        }
+        
+        // Execute the collection expression
+        let collection_value = self.execute_node(collection)?.into_most_likely();
+        
         // Iterate over the collection
         match collection_value {
             Value::List(items) => {
@@ -522,6 +1072,88 @@ impl Engine {
         Ok(result)
     }
     
+    /// Execute a deadline node
+    fn execute_deadline(&mut self, node: &Node) -> Result<Value, RuntimeError> {
+        // Get the timeout
+        let timeout = node.get_attribute("timeout").ok_or_else(|| {
+            RuntimeError::missing_attribute("timeout", node.location.clone())
+        })?;
+        
+        let timeout = timeout.parse::<u64>().map_err(|_| {
+            RuntimeError::new(&format!("Invalid timeout: '{}'", timeout), node.location.clone())
+        })?;
+        
+        // Get the body
+        let body = node.get_child(0).ok_or_else(|| {
+            RuntimeError::missing_child(0, node.location.clone())
+        })?;
+        
+        // Execute the body with a token that expires after the timeout
+        let outer = self.cancellation.clone();
+        self.cancellation = outer.with_deadline(Duration::from_millis(timeout));
+        
+        let result = {
+            let _guard = self.cancellation.enter();
+            self.execute_node(body)
+        };
+        
+        // Only this block's deadline is handled here, not an outer cancellation
+        let expired = self.cancellation.is_cancelled() && !outer.is_cancelled();
+        self.cancellation = outer;
+        
+        match result {
+            Err(error) if expired && error.is_interruption() => {
+                // Run the otherwise block instead, if there is one
+                match node.get_child(1) {
+                    Some(otherwise) => self.execute_node(otherwise),
+                    None => Err(RuntimeError::deadline_exceeded(timeout, node.location.clone())),
+                }
+            }
+            result => result,
+        }
+    }
+    
+    /// Execute a budget node
+    fn execute_budget(&mut self, node: &Node) -> Result<Value, RuntimeError> {
+        // Get the number of tokens
+        let tokens = node.get_attribute("tokens").ok_or_else(|| {
+            RuntimeError::missing_attribute("tokens", node.location.clone())
+        })?;
+        
+        let tokens = tokens.parse::<u64>().map_err(|_| {
+            RuntimeError::new(&format!("Invalid token budget: '{}'", tokens), node.location.clone())
+        })?;
+        
+        // Get the body
+        let body = node.get_child(0).ok_or_else(|| {
+            RuntimeError::missing_child(0, node.location.clone())
+        })?;
+        
+        // Execute the body with a budget that is also charged to the enclosing ones
+        let outer = self.budget.clone();
+        self.budget = outer.child(tokens);
+        
+        let result = {
+            let _budget = self.budget.enter();
+            self.execute_node(body)
+        };
+        
+        // Only this block's budget is handled here, not an enclosing one
+        let exhausted = self.budget.is_exhausted();
+        self.budget = outer;
+        
+        match result {
+            Err(error) if exhausted && error.kind == RuntimeErrorKind::TokenBudgetExceeded => {
+                // Run the otherwise block instead, if there is one
+                match node.get_child(1) {
+                    Some(otherwise) => self.execute_node(otherwise),
+                    None => Err(RuntimeError::token_budget_exceeded(tokens, node.location.clone())),
+                }
+            }
+            result => result,
+        }
+    }
+    
     /// Execute an intent node
     fn execute_intent(&mut self, node: &Node) -> Result<Value, RuntimeError> {
         // Check if NLP is enabled
@@ -529,18 +1161,21 @@ impl Engine {
             return Err(RuntimeError::feature_disabled("NLP", node.location.clone()));
         }
         
-        // Execute the intent expression
+        // Get the intent text, which is given as natural language after a colon
         let expression = node.get_child(0).ok_or_else(|| {
             RuntimeError::missing_child(0, node.location.clone())
         })?;
         
-        let expression_value = self.execute_node(expression)?;
+        let expression_value = match expression.get_attribute("value") {
+            Some(text) if expression.kind == NodeKind::NaturalLanguage => Value::String(text.clone()),
+            _ => self.execute_node(expression)?,
+        };
         
         // Process the intent
+        let _site = TokenBudget::enter_call_site(&node.location);
+        
         match expression_value {
-            Value::String(intent) => {
-                self.nlp.process_intent(&intent)
-            }
+            Value::String(intent) => self.handle_intent(&intent, &node.location),
             _ => Err(RuntimeError::invalid_type(
                 "String",
                 &format!("{:?}", expression_value),
@@ -549,81 +1184,664 @@ impl Engine {
         }
     }
     
-    /// Execute a parallel node
-    fn execute_parallel(&mut self, node: &Node) -> Result<Value, RuntimeError> {
-        // Check if parallel execution is enabled
-        if !self.options.parallel {
-            return Err(RuntimeError::feature_disabled("Parallel", node.location.clone()));
+    /// Handle an intent
+    ///
+    /// The intent goes to the handler it is routed to, called with the slot
+    /// values as arguments, or to the fallback handler with the intent as its
+    /// argument, if it takes one. Without handlers, the intent is processed by
+    /// the natural language processor instead.
+    fn handle_intent(&mut self, utterance: &str, location: &crate::utils::SourceLocation) -> Result<Value, RuntimeError> {
+        if self.intents.is_empty() {
+            return self.nlp.process_intent(utterance, self.vector.get_current_vector());
         }
         
-        // Get the selection strategy
-        let strategy = node.get_attribute("strategy").ok_or_else(|| {
-            RuntimeError::missing_attribute("strategy", node.location.clone())
+        let matched = self.intents.route(utterance, self.options.intent_routing, &self.nlp).map_err(|mut error| {
+            error.location = location.clone();
+            error
         })?;
         
-        // Execute each path
-        let mut results = Vec::new();
-        let mut execution_times = Vec::new();
+        if let Some(matched) = matched.filter(|matched| matched.confidence >= self.options.intent_threshold) {
+            // The slots are passed as the parameters of the same name
+            if let Some(arguments) = self.intent_arguments(&matched.function, &matched.slots) {
+                return self.call_function(&matched.function, arguments, location);
+            }
+        }
         
-        for child in &node.children {
-            // Check if it's a path node
-            if child.kind != NodeKind::Path {
-                continue;
+        match self.intents.fallback().map(str::to_string) {
+            Some(fallback) => {
+                let takes_intent = self.context.get_function(&fallback).is_some_and(|function| {
+                    function.children.iter().any(|child| child.kind == NodeKind::Parameter)
+                });
+                
+                let arguments = if takes_intent { vec![Value::String(utterance.to_string())] } else { Vec::new() };
+                self.call_function(&fallback, arguments, location)
             }
+            None => Err(RuntimeError::no_intent_handler(utterance, location.clone())),
+        }
+    }
+    
+    /// Get the arguments of an intent handler from the slot values
+    ///
+    /// Returns `None` if a parameter has no slot or its value cannot be
+    /// converted to the parameter type.
+    fn intent_arguments(&self, function: &str, slots: &[(String, String)]) -> Option<Vec<Value>> {
+        let function = self.context.get_function(function)?;
+        
+        function.children.iter()
+            .filter(|child| child.kind == NodeKind::Parameter)
+            .map(|parameter| {
+                let name = parameter.get_attribute("name")?;
+                let value = slots.iter().find(|(slot, _)| slot == name).map(|(_, value)| value)?;
+                
+                intent::slot_value(value, parameter.get_attribute("type").map_or("Any", |typ| typ))
+            })
+            .collect()
+    }
+    
+    /// Execute an intent handler node
+    fn execute_intent_handler(&mut self, node: &Node) -> Result<Value, RuntimeError> {
+        // Register the handler function
+        let function = node.get_child(0).ok_or_else(|| {
+            RuntimeError::missing_child(0, node.location.clone())
+        })?;
+        
+        let name = function.get_attribute("name").ok_or_else(|| {
+            RuntimeError::missing_attribute("name", function.location.clone())
+        })?;
+        
+        let value = self.execute_node(function)?;
+        
+        // Register the handler
+        if node.get_attribute("fallback").is_some() {
+            self.intents.set_fallback(name);
+        } else {
+            let pattern = node.get_attribute("pattern").ok_or_else(|| {
+                RuntimeError::missing_attribute("pattern", node.location.clone())
+            })?;
             
-            // Get the path name
-            let name = child.get_attribute("name").ok_or_else(|| {
-                RuntimeError::missing_attribute("name", child.location.clone())
+            let pattern = IntentPattern::parse(pattern).map_err(|message| {
+                RuntimeError::new(&message, node.location.clone())
             })?;
             
-            // Get the path body
-            let body = child.get_child(0).ok_or_else(|| {
-                RuntimeError::missing_child(0, child.location.clone())
+            let parameters = function.children.iter()
+                .filter(|child| child.kind == NodeKind::Parameter)
+                .map(|parameter| (
+                    parameter.get_attribute("name").cloned().unwrap_or_default(),
+                    parameter.get_attribute("type").cloned().unwrap_or_else(|| "Any".to_string()),
+                ))
+                .collect();
+            
+            self.intents.add_handler(pattern, name, parameters, self.options.intent_routing).map_err(|mut error| {
+                error.location = node.location.clone();
+                error
+            })?;
+        }
+        
+        Ok(value)
+    }
+    
+    /// Execute a tools node
+    fn execute_tools(&mut self, node: &Node) -> Result<Value, RuntimeError> {
+        for function in &node.children {
+            let name = function.get_attribute("name").ok_or_else(|| {
+                RuntimeError::missing_attribute("name", function.location.clone())
             })?;
             
-            // Execute the path
-            let start_time = Instant::now();
-            let result = self.execute_node(body)?;
-            let execution_time = start_time.elapsed();
+            // Register the function, which can also be called by the program
+            self.execute_node(function)?;
             
-            // Store the result and execution time
-            results.push((name.clone(), result));
-            execution_times.push((name.clone(), execution_time));
+            if !self.tools.contains(name) {
+                self.tools.push(name.clone());
+            }
         }
         
-        // Apply the selection strategy
-        match strategy.as_str() {
-            "fastest" => {
-                // Find the fastest path
-                let fastest = execution_times.iter().min_by_key(|(_, time)| time).ok_or_else(|| {
-                    RuntimeError::no_paths(node.location.clone())
-                })?;
+        Ok(Value::Void)
+    }
+    
+    /// Complete a task with the language model, letting it call the declared tools
+    fn run_agent(&mut self, task: &str, location: &crate::utils::SourceLocation) -> Result<Value, RuntimeError> {
+        let mut tools = Vec::new();
+        
+        for name in &self.tools {
+            if let Some(function) = self.context.get_function(name) {
+                let symbol = Symbol::function(&function).map_err(|error| RuntimeError::new(&error.message, error.location))?;
+                let description = function.get_attribute("description").map_or("", |description| description);
+                
+                tools.push(Tool::from_symbol(&symbol, description, &self.types, &function.location)?);
+            }
+        }
+        
+        let nlp = self.nlp.clone();
+        let mut trace = Vec::new();
+        
+        let result = nlp.run_tools(task, &tools, self.options.max_tool_steps, &mut trace, |tool, arguments| {
+            self.call_function(&tool.name, arguments, location)
+        });
+        
+        self.tool_calls.append(&mut trace);
+        
+        result.map_err(|mut error| {
+            error.location = location.clone();
+            error
+        })
+    }
+    
+    /// Execute a prompt node
+    fn execute_prompt(&mut self, node: &Node) -> Result<Value, RuntimeError> {
+        let prompt = Prompt::from_node(node)?;
+        let name = prompt.name.clone();
+        
+        // A prompt declared again is used from then on, but its earlier versions are kept
+        self.prompts.declare(prompt);
+        
+        Ok(Value::Function(name))
+    }
+    
+    /// Execute a render node
+    fn execute_render(&mut self, node: &Node) -> Result<Value, RuntimeError> {
+        let call = node.get_child(0).ok_or_else(|| {
+            RuntimeError::missing_child(0, node.location.clone())
+        })?;
+        
+        let name = call.get_child(0).and_then(|callee| callee.get_attribute("name")).ok_or_else(|| {
+            RuntimeError::missing_attribute("name", call.location.clone())
+        })?;
+        
+        let mut arguments = Vec::new();
+        
+        for argument in call.children.iter().skip(1) {
+            arguments.push(self.execute_node(argument)?.into_most_likely());
+        }
+        
+        self.render_prompt(name, &arguments, &node.location).map(Value::String)
+    }
+    
+    /// Render a prompt with the examples given for it
+    fn render_prompt(&self, name: &str, arguments: &[Value], location: &crate::utils::SourceLocation) -> Result<String, RuntimeError> {
+        let prompt = self.prompts.get(name).ok_or_else(|| {
+            RuntimeError::new(&format!("Cannot render '{}', which is not a prompt", name), location.clone())
+        })?;
+        
+        if arguments.len() != prompt.parameters.len() {
+            return Err(RuntimeError::invalid_argument_count(
+                name,
+                prompt.parameters.len(),
+                arguments.len(),
+                location.clone(),
+            ));
+        }
+        
+        let examples = self.example.get_function_examples(name).unwrap_or_default();
+        
+        Ok(prompt.render(arguments, &examples))
+    }
+    
+    /// Call a prompt, sending it to the language model
+    ///
+    /// The reply is parsed as the type of the prompt, if it declares one.
+    fn call_prompt(&mut self, name: &str, arguments: &[Value], location: &crate::utils::SourceLocation) -> Result<Value, RuntimeError> {
+        if !self.options.nlp {
+            return Err(RuntimeError::feature_disabled("NLP", location.clone()));
+        }
+        
+        let text = self.render_prompt(name, arguments, location)?;
+        let _site = TokenBudget::enter_call_site(location);
+        
+        let result = match self.prompts.get(name).and_then(|prompt| prompt.return_type.as_ref()) {
+            Some(typ) => Schema::from_type(typ, &self.types)
+                .and_then(|schema| self.nlp.process_structured(&text, &schema)),
+            None => self.nlp.generate_text(&text),
+        };
+        
+        result.map_err(|error| RuntimeError {
+            location: location.clone(),
+            ..error
+        })
+    }
+    
+    /// Execute a stream node, collecting its chunks into a list
+    ///
+    /// Streams that are iterated by a for loop are not collected; see `execute_for`.
+    fn execute_stream(&mut self, node: &Node) -> Result<Value, RuntimeError> {
+        let chunks = self.open_stream(node)?
+            .map(|chunk| chunk.map(Value::String))
+            .collect::<Result<Vec<_>, _>>()?;
+        
+        Ok(Value::List(chunks))
+    }
+    
+    /// Start streaming the completion of the prompt of a stream node
+    ///
+    /// The prompt is natural language, a call to a prompt, which is rendered,
+    /// or an expression that evaluates to a string.
+    fn open_stream(&mut self, node: &Node) -> Result<TextStream, RuntimeError> {
+        if !self.options.nlp {
+            return Err(RuntimeError::feature_disabled("NLP", node.location.clone()));
+        }
+        
+        let prompt = node.get_child(0).ok_or_else(|| {
+            RuntimeError::missing_child(0, node.location.clone())
+        })?;
+        
+        let prompt_name = prompt.get_child(0)
+            .filter(|callee| prompt.kind == NodeKind::Call && callee.kind == NodeKind::Identifier)
+            .and_then(|callee| callee.get_attribute("name"))
+            .filter(|name| self.prompts.contains(name));
+        
+        let text = if prompt.kind == NodeKind::NaturalLanguage {
+            prompt.get_attribute("value").cloned().ok_or_else(|| {
+                RuntimeError::missing_attribute("value", prompt.location.clone())
+            })?
+        } else if let Some(name) = prompt_name {
+            let mut arguments = Vec::new();
+            
+            for argument in prompt.children.iter().skip(1) {
+                arguments.push(self.execute_node(argument)?.into_most_likely());
+            }
+            
+            self.render_prompt(name, &arguments, &prompt.location)?
+        } else {
+            match self.execute_node(prompt)?.into_most_likely() {
+                Value::String(text) => text,
+                value => return Err(RuntimeError::invalid_type(
+                    "String",
+                    &format!("{:?}", value),
+                    prompt.location.clone(),
+                )),
+            }
+        };
+        
+        let timeout = match node.get_attribute("timeout") {
+            Some(timeout) => Some(timeout.parse::<u64>().map_err(|_| {
+                RuntimeError::new(&format!("Invalid timeout: '{}'", timeout), node.location.clone())
+            })?),
+            None => self.options.stream_timeout,
+        };
+        
+        // The stream runs with the current budget and call site, so its tokens are counted here
+        let _site = TokenBudget::enter_call_site(&node.location);
+        
+        Ok(self.nlp.stream_text(&text, self.options.stream_buffer, timeout.map(Duration::from_millis), &node.location))
+    }
+    
+    /// Execute a parallel node
+    fn execute_parallel(&mut self, node: &Node) -> Result<Value, RuntimeError> {
+        // Check if parallel execution is enabled
+        if !self.options.parallel {
+            return Err(RuntimeError::feature_disabled("Parallel", node.location.clone()));
+        }
+        
+        // Get the selection strategy
+        let strategy = node.get_attribute("strategy").ok_or_else(|| {
+            RuntimeError::missing_attribute("strategy", node.location.clone())
+        })?;
+        
+        // Get the optional timeout for each path
+        let timeout = match node.get_attribute("timeout") {
+            Some(timeout) => Some(Duration::from_millis(timeout.parse::<u64>().map_err(|_| {
+                RuntimeError::new(&format!("Invalid timeout: '{}'", timeout), node.location.clone())
+            })?)),
+            None => None,
+        };
+        
+        // Prepare each path to run on its own fork of the engine
+        let mut tasks: Vec<(String, ParallelTask<PathOutcome>)> = Vec::new();
+        let mut tokens = Vec::new();
+        
+        for child in &node.children {
+            // Check if it's a path node
+            if child.kind != NodeKind::Path {
+                continue;
+            }
+            
+            // Get the path name
+            let name = child.get_attribute("name").ok_or_else(|| {
+                RuntimeError::missing_attribute("name", child.location.clone())
+            })?;
+            
+            // Get the path body
+            let body = child.get_child(0).ok_or_else(|| {
+                RuntimeError::missing_child(0, child.location.clone())
+            })?.clone();
+            
+            let mut engine = self.fork();
+            let token = engine.cancellation.clone();
+            tokens.push(token.clone());
+            
+            let path_token = token.clone();
+            let mut run = move || {
+                // Execute the path
+                let _guard = path_token.enter();
+                let _provider = engine.vector.enter();
+                let _budget = engine.budget.enter();
+                let result = engine.execute_node(&body);
                 
-                // Return the result of the fastest path
-                let (name, _) = fastest;
-                let (_, result) = results.iter().find(|(n, _)| n == name).unwrap();
+                PathOutcome {
+                    result,
+                    instructions: engine.instructions,
+                    peak_memory: engine.peak_memory,
+                    index_changes: engine.index_changes.take().unwrap_or_default(),
+                    tool_calls: std::mem::take(&mut engine.tool_calls),
+                }
+            };
+            
+            let task: ParallelTask<PathOutcome> = match timeout {
+                Some(timeout) => {
+                    let parallel = self.parallel.clone();
+                    let path_name = name.clone();
+                    let location = child.location.clone();
+                    
+                    // The path is cancelled when it runs out of time
+                    Box::new(move || {
+                        match parallel.execute_cancellable(move || Ok(run()), &token, timeout) {
+                            Ok(outcome) => outcome,
+                            Err(_) => PathOutcome {
+                                result: Err(RuntimeError::new(
+                                    &format!("Path '{}' timed out after {:?}", path_name, timeout),
+                                    location,
+                                )),
+                                instructions: 0,
+                                peak_memory: 0,
+                                index_changes: Vec::new(),
+                                tool_calls: Vec::new(),
+                            },
+                        }
+                    })
+                }
+                None => Box::new(run),
+            };
+            
+            tasks.push((name.clone(), task));
+        }
+        
+        // Run the paths on worker threads, cancelling the losers once a winner is known
+        let mut winner = None;
+        
+        let mut outcomes = self.parallel.execute_streaming(tasks, |index, _, outcome| {
+            // The fastest path wins even if it failed, so its error is the result of the block
+            let wins = match strategy.as_str() {
+                "fastest" => true,
+                "first_success" => outcome.result.is_ok(),
+                _ => false,
+            };
+            
+            if wins && winner.is_none() {
+                winner = Some(index);
                 
-                Ok(result.clone())
+                for (i, token) in tokens.iter().enumerate() {
+                    if i != index {
+                        token.cancel();
+                    }
+                }
+            }
+        });
+        
+        // Merge the statistics of every path, keeping the tool calls in declaration order
+        for (_, outcome) in &mut outcomes {
+            self.instructions += outcome.instructions;
+            self.peak_memory = std::cmp::max(self.peak_memory, outcome.peak_memory);
+            self.tool_calls.append(&mut outcome.tool_calls);
+        }
+        
+        if outcomes.is_empty() {
+            return Err(RuntimeError::no_paths(node.location.clone()));
+        }
+        
+        // Only the index changes of the selected paths are kept, and only if the block succeeds
+        let mut index_changes: Vec<Vec<IndexChange>> = outcomes.iter_mut()
+            .map(|(_, outcome)| std::mem::take(&mut outcome.index_changes))
+            .collect();
+        
+        // Strategies that stop at the first suitable path
+        match strategy.as_str() {
+            "fastest" | "first_success" => {
+                return match winner {
+                    Some(index) => {
+                        let (_, outcome) = outcomes.into_iter().nth(index).unwrap();
+                        
+                        if outcome.result.is_ok() {
+                            self.apply_index_changes(index_changes.swap_remove(index))?;
+                        }
+                        
+                        outcome.result
+                    }
+                    None => {
+                        // No path succeeded, so report the first failure
+                        let (_, outcome) = outcomes.into_iter().next().unwrap();
+                        outcome.result
+                    }
+                };
             }
+            "vote" => {
+                let (index, value) = self.vote(node, outcomes)?;
+                self.apply_index_changes(index_changes.swap_remove(index))?;
+                return Ok(value);
+            }
+            _ => {}
+        }
+        
+        // The remaining strategies need every path to succeed
+        let mut results = Vec::new();
+        
+        for (name, outcome) in outcomes {
+            results.push((name, outcome.result?));
+        }
+        
+        // Apply the selection strategy
+        match strategy.as_str() {
             "best" => {
-                // In a real implementation, this would use a quality metric
-                // For now, just return the first result
-                if results.is_empty() {
-                    Err(RuntimeError::no_paths(node.location.clone()))
-                } else {
+                // Without a scorer, the first path is the best
+                if node.get_attribute("scorer").is_none() {
+                    self.path_scores.clear();
+                    self.apply_index_changes(index_changes.swap_remove(0))?;
+                    
                     let (_, result) = &results[0];
-                    Ok(result.clone())
+                    return Ok(result.clone());
+                }
+                
+                // Score each path
+                let scores = self.score_paths(node, &results)?;
+                
+                // Find the highest score, preferring earlier paths on ties
+                let mut best = 0;
+                
+                for (i, score) in scores.iter().enumerate() {
+                    if *score > scores[best] {
+                        best = i;
+                    }
                 }
+                
+                // Keep the scores so the program can inspect them
+                self.path_scores = results.iter().map(|(name, _)| name.clone()).zip(scores).collect();
+                self.apply_index_changes(index_changes.swap_remove(best))?;
+                
+                let (_, result) = &results[best];
+                Ok(result.clone())
             }
             "all" => {
+                // Keep the index changes of every path, in declaration order
+                for changes in index_changes {
+                    self.apply_index_changes(changes)?;
+                }
+                
                 // Return all results as a list
                 let values: Vec<Value> = results.into_iter().map(|(_, r)| r).collect();
                 Ok(Value::List(values))
             }
+            "merge" => {
+                // The merge function follows the paths
+                let merger_node = node.children.iter().find(|child| child.kind != NodeKind::Path).ok_or_else(|| {
+                    RuntimeError::missing_child(node.child_count(), node.location.clone())
+                })?;
+                
+                let function = self.evaluate_function(merger_node)?;
+                
+                // Keep the index changes of every path, in declaration order
+                for changes in index_changes {
+                    self.apply_index_changes(changes)?;
+                }
+                
+                // Pass all results to the merge function in declaration order
+                let values: Vec<Value> = results.into_iter().map(|(_, r)| r).collect();
+                self.call_function(&function, vec![Value::List(values)], &merger_node.location)
+            }
             _ => Err(RuntimeError::invalid_strategy(strategy, node.location.clone())),
         }
     }
     
+    /// Select the most common successful result of a parallel block's paths
+    ///
+    /// Returns the result with the first path that returned it. Ties are
+    /// broken in favour of the path declared first. If no path succeeds, the
+    /// first failure is returned.
+    fn vote(&self, node: &Node, outcomes: Vec<(String, PathOutcome)>) -> Result<(usize, Value), RuntimeError> {
+        let mut candidates: Vec<(Value, usize, usize)> = Vec::new();
+        let mut first_error = None;
+        
+        for (path, (_, outcome)) in outcomes.into_iter().enumerate() {
+            match outcome.result {
+                Ok(value) => {
+                    match candidates.iter_mut().find(|(candidate, _, _)| Self::values_equal(candidate, &value)) {
+                        Some((_, votes, _)) => *votes += 1,
+                        None => candidates.push((value, 1, path)),
+                    }
+                }
+                Err(error) => {
+                    if first_error.is_none() {
+                        first_error = Some(error);
+                    }
+                }
+            }
+        }
+        
+        // Find the candidate with the most votes, preferring earlier paths on ties
+        let mut winner: Option<(Value, usize, usize)> = None;
+        
+        for (value, votes, path) in candidates {
+            if winner.as_ref().is_none_or(|(_, best, _)| votes > *best) {
+                winner = Some((value, votes, path));
+            }
+        }
+        
+        match (winner, first_error) {
+            (Some((value, _, path)), _) => Ok((path, value)),
+            (None, Some(error)) => Err(error),
+            (None, None) => Err(RuntimeError::no_paths(node.location.clone())),
+        }
+    }
+    
+    /// Evaluate an expression that must refer to a function, returning its name
+    fn evaluate_function(&mut self, node: &Node) -> Result<String, RuntimeError> {
+        match self.execute_node(node)? {
+            Value::Function(name) => Ok(name),
+            other => Err(RuntimeError::not_callable(
+                &format!("{:?}", other),
+                node.location.clone(),
+            )),
+        }
+    }
+    
+    /// Score the results of a parallel block's paths
+    fn score_paths(&mut self, node: &Node, results: &[(String, Value)]) -> Result<Vec<f64>, RuntimeError> {
+        let scorer = node.get_attribute("scorer").ok_or_else(|| {
+            RuntimeError::missing_attribute("scorer", node.location.clone())
+        })?;
+        
+        let mut scores = Vec::new();
+        
+        match scorer.as_str() {
+            "similarity" => {
+                // Compare each result with the applied vector
+                let target = self.vector.get_current_vector().cloned().ok_or_else(|| {
+                    RuntimeError::new(
+                        "The similarity scorer requires an applied vector",
+                        node.location.clone(),
+                    )
+                })?;
+                
+                for (_, result) in results {
+                    let embedding = self.vector.embed(&crate::stdlib::StdLib::value_to_string(result))?;
+                    scores.push(self.vector.similarity(&embedding, &target));
+                }
+            }
+            "length" => {
+                for (_, result) in results {
+                    let length = match result {
+                        Value::String(s) => s.chars().count(),
+                        Value::List(items) => items.len(),
+                        Value::Map(map) => map.len(),
+                        _ => 0,
+                    };
+                    
+                    scores.push(length as f64);
+                }
+            }
+            "type" => {
+                let semantic_type = node.get_attribute("scorer_type").ok_or_else(|| {
+                    RuntimeError::missing_attribute("scorer_type", node.location.clone())
+                })?;
+                
+                for (_, result) in results {
+                    scores.push(self.nlp.validate_semantic_type(result, semantic_type));
+                }
+            }
+            "judge" => {
+                if !self.options.nlp {
+                    return Err(RuntimeError::feature_disabled("NLP", node.location.clone()));
+                }
+                
+                let criteria = node.get_attribute("criteria").ok_or_else(|| {
+                    RuntimeError::missing_attribute("criteria", node.location.clone())
+                })?;
+                
+                let _site = TokenBudget::enter_call_site(&node.location);
+                
+                for (_, result) in results {
+                    let text = crate::stdlib::StdLib::value_to_string(result);
+                    scores.push(self.nlp.judge(&text, criteria)?);
+                }
+            }
+            "function" => {
+                // The scorer expression follows the paths
+                let scorer_node = node.children.iter().find(|child| child.kind != NodeKind::Path).ok_or_else(|| {
+                    RuntimeError::missing_child(node.child_count(), node.location.clone())
+                })?;
+                
+                let function = self.evaluate_function(scorer_node)?;
+                
+                for (_, result) in results {
+                    let score = match self.call_function(&function, vec![result.clone()], &scorer_node.location)? {
+                        Value::Float(score) => score,
+                        Value::Int(score) => score as f64,
+                        other => return Err(RuntimeError::invalid_type(
+                            "Float",
+                            &format!("{:?}", other),
+                            scorer_node.location.clone(),
+                        )),
+                    };
+                    
+                    scores.push(score);
+                }
+            }
+            _ => {
+                return Err(RuntimeError::new(
+                    &format!("Unknown scorer: '{}'", scorer),
+                    node.location.clone(),
+                ));
+            }
+        }
+        
+        // Other scorers are biased towards results close to the applied vector
+        if scorer != "similarity" && self.vector.get_current_vector().is_some() {
+            for ((_, result), score) in results.iter().zip(scores.iter_mut()) {
+                let embedding = self.vector.embed(&crate::stdlib::StdLib::value_to_string(result))?;
+                *score += self.vector.steering_bias(&embedding);
+            }
+        }
+        
+        Ok(scores)
+    }
+    
     /// Execute an apply node
     fn execute_apply(&mut self, node: &Node) -> Result<Value, RuntimeError> {
         // Check if vector operations are enabled
@@ -641,20 +1859,24 @@ impl Engine {
         // Apply the vector to the body
         match vector_value {
             Value::Vector(vector) => {
-                // Set the current vector
-                self.vector.set_current_vector(vector);
-                
-                // Execute the body
                 let body = node.get_child(1).ok_or_else(|| {
                     RuntimeError::missing_child(1, node.location.clone())
                 })?;
                 
-                let result = self.execute_node(body)?;
+                // Nested vectors are combined with the enclosing ones
+                if let Some(current) = self.vector.get_current_vector() {
+                    Self::check_dimensions(current, &vector, &vector_expr.location)?;
+                }
                 
-                // Clear the current vector
-                self.vector.clear_current_vector();
+                self.vector.apply_vector(vector)?;
                 
-                Ok(result)
+                // Execute the body
+                let result = self.execute_node(body);
+                
+                // Restore the enclosing vector, even if the body failed
+                self.vector.unapply_vector();
+                
+                result
             }
             _ => Err(RuntimeError::invalid_type(
                 "Vector",
@@ -697,8 +1919,8 @@ impl Engine {
                     // Recall the value with the key
                     self.memory.recall(key)
                 } else {
-                    // Recall the most relevant value
-                    self.memory.recall_most_relevant()
+                    // Recall the value closest to the applied vector
+                    self.memory.recall_steered(&self.vector)
                 }
             }
             "@modify" => {
@@ -971,6 +2193,17 @@ impl Engine {
             RuntimeError::missing_child(0, node.location.clone())
         })?;
         
+        // Property access names the property instead of having a right-hand side
+        if operator == "." {
+            let property = node.get_attribute("name").ok_or_else(|| {
+                RuntimeError::missing_attribute("name", node.location.clone())
+            })?;
+            
+            let object = self.execute_node(lhs)?;
+            
+            return Self::property_access(&object, property, node.location.clone());
+        }
+        
         // Get the right-hand side
         let rhs = node.get_child(1).ok_or_else(|| {
             RuntimeError::missing_child(1, node.location.clone())
@@ -979,8 +2212,22 @@ impl Engine {
         // Execute the left-hand side
         let lhs_value = self.execute_node(lhs)?;
         
+        // The right-hand side of `??` is only executed if the left-hand side is not used
+        if operator == "??" {
+            return match lhs_value {
+                Value::Prob { .. } if lhs_value.confidence() >= self.options.confidence_threshold => {
+                    Ok(lhs_value.into_most_likely())
+                }
+                Value::Prob { .. } | Value::Void => self.execute_node(rhs),
+                value => Ok(value),
+            };
+        }
+        
+        // Other operators use the most likely value of a probabilistic value
+        let lhs_value = lhs_value.into_most_likely();
+        
         // Execute the right-hand side
-        let rhs_value = self.execute_node(rhs)?;
+        let rhs_value = self.execute_node(rhs)?.into_most_likely();
         
         // Apply the operator
         match operator.as_str() {
@@ -997,7 +2244,6 @@ impl Engine {
             ">=" => Self::greater_than_or_equal(&lhs_value, &rhs_value, node.location.clone()),
             "and" => Ok(Value::Bool(Self::is_truthy(&lhs_value) && Self::is_truthy(&rhs_value))),
             "or" => Ok(Value::Bool(Self::is_truthy(&lhs_value) || Self::is_truthy(&rhs_value))),
-            "." => Self::property_access(&lhs_value, &rhs_value, node.location.clone()),
             _ => Err(RuntimeError::unknown_operator(operator, node.location.clone())),
         }
     }
@@ -1015,7 +2261,7 @@ impl Engine {
         })?;
         
         // Execute the operand
-        let operand_value = self.execute_node(operand)?;
+        let operand_value = self.execute_node(operand)?.into_most_likely();
         
         // Apply the operator
         match operator.as_str() {
@@ -1032,6 +2278,22 @@ impl Engine {
             RuntimeError::missing_attribute("type", node.location.clone())
         })?;
         
+        // List literals hold their elements as children
+        if typ == "List" {
+            let mut items = Vec::new();
+            
+            for child in &node.children {
+                items.push(self.execute_node(child)?);
+            }
+            
+            return Ok(Value::List(items));
+        }
+        
+        // Null literals have no value
+        if typ == "Null" {
+            return Ok(Value::Void);
+        }
+        
         // Get the value
         let value = node.get_attribute("value").ok_or_else(|| {
             RuntimeError::missing_attribute("value", node.location.clone())
@@ -1054,8 +2316,8 @@ impl Engine {
                 Ok(Value::Float(float))
             }
             "String" => {
-                // Remove the quotes
-                let string = value[1..value.len() - 1].to_string();
+                // Remove the quotes and resolve the escape sequences
+                let string = Self::unescape(&value[1..value.len() - 1]);
                 
                 Ok(Value::String(string))
             }
@@ -1066,11 +2328,38 @@ impl Engine {
                 
                 Ok(Value::Bool(bool))
             }
-            "Null" => Ok(Value::Void),
             _ => Err(RuntimeError::unknown_type(typ, node.location.clone())),
         }
     }
     
+    /// Resolve the escape sequences of a string literal
+    fn unescape(string: &str) -> String {
+        let mut unescaped = String::with_capacity(string.len());
+        let mut chars = string.chars();
+        
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                unescaped.push(c);
+                continue;
+            }
+            
+            match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some('t') => unescaped.push('\t'),
+                Some('r') => unescaped.push('\r'),
+                Some(c @ ('"' | '\\')) => unescaped.push(c),
+                // Unknown escape sequences are kept as written
+                Some(c) => {
+                    unescaped.push('\\');
+                    unescaped.push(c);
+                }
+                None => unescaped.push('\\'),
+            }
+        }
+        
+        unescaped
+    }
+    
     /// Execute an identifier node
     fn execute_identifier(&mut self, node: &Node) -> Result<Value, RuntimeError> {
         // Get the name
@@ -1079,10 +2368,17 @@ impl Engine {
         })?;
         
         // Look up the variable
-        self.context.get_variable(name).ok_or_else(|| {
-            RuntimeError::undefined_variable(name, node.location.clone())
-        })
-    }
+        if let Some(value) = self.context.get_variable(name) {
+            return Ok(value);
+        }
+        
+        // Fall back to a function or prompt with the same name
+        if self.context.get_function(name).is_some() || self.prompts.contains(name) || self.example.has_function(name) {
+            return Ok(Value::Function(name.clone()));
+        }
+        
+        Err(RuntimeError::undefined_variable(name, node.location.clone()))
+    }
     
     /// Execute a call node
     fn execute_call(&mut self, node: &Node) -> Result<Value, RuntimeError> {
@@ -1106,72 +2402,671 @@ impl Engine {
             arguments.push(argument_value);
         }
         
-        // Call the function
+        // Call the function, attributing the language model calls it makes to this call
+        let _site = TokenBudget::enter_call_site(&node.location);
+        
         match callee_value {
-            Value::Function(name) => {
-                // Check if it's a standard library function
-                let stdlib = crate::stdlib::StdLib::new();
-                if let Some(function) = stdlib.get_function(&name) {
-                    // Call the standard library function
-                    let result = function(arguments);
-                    
-                    // Increment the instruction count for stdlib function calls
-                    self.instructions += 1;
-                    
-                    return result;
+            Value::Function(name) => self.call_function(&name, arguments, &node.location),
+            _ => Err(RuntimeError::not_callable(
+                &format!("{:?}", callee_value),
+                node.location.clone(),
+            )),
+        }
+    }
+    
+    /// Call a function by name
+    fn call_function(
+        &mut self,
+        name: &str,
+        arguments: Vec<Value>,
+        location: &crate::utils::SourceLocation,
+    ) -> Result<Value, RuntimeError> {
+        // Natural language functions are sent to the processor of the engine, or the mock backend without NLP
+        let stdlib = match self.options.nlp {
+            true => crate::stdlib::StdLib::with_nlp(self.nlp.clone()),
+            false => crate::stdlib::StdLib::new(),
+        };
+        
+        // Probabilistic arguments are used as their most likely values, except by
+        // functions defined in the program and the functions that combine probabilities
+        let defined_in_program = !RUNTIME_FUNCTIONS.contains(&name)
+            && !stdlib.has_function(name)
+            && self.context.get_function(name).is_some();
+        
+        let arguments = if defined_in_program || crate::stdlib::PROBABILITY_FUNCTIONS.contains(&name) {
+            arguments
+        } else {
+            arguments.into_iter().map(Value::into_most_likely).collect()
+        };
+        
+        // Check if it's a runtime function
+        if let Some(result) = self.call_runtime_function(name, &arguments, location) {
+            self.instructions += 1;
+            
+            return result;
+        }
+        
+        // Check if it's a standard library function
+        if stdlib.has_function(name) {
+            if !self.options.nlp && crate::stdlib::MODEL_FUNCTIONS.contains(&name) {
+                return Err(RuntimeError::feature_disabled("NLP", location.clone()));
+            }
+            
+            // Call the standard library function
+            let result = stdlib.call_function(name, arguments);
+            
+            // Increment the instruction count for stdlib function calls
+            self.instructions += 1;
+            
+            return result;
+        }
+        
+        // Look up the function
+        let function = self.context.get_function(name);
+        
+        // Check if it's a prompt, whose examples do not define a function
+        if function.is_none() && self.prompts.contains(name) {
+            self.instructions += 1;
+            
+            return self.call_prompt(name, &arguments, location);
+        }
+        
+        // Check if it's a function defined only by examples
+        if function.is_none() && self.example.has_function(name) {
+            self.instructions += 1;
+            
+            return self.example.call_function(name, arguments, self.vector.get_current_vector());
+        }
+        
+        let function = function.ok_or_else(|| {
+            RuntimeError::undefined_function(name, location.clone())
+        })?;
+        
+        // Check the argument count
+        let parameter_count = function.children.iter().filter(|child| child.kind == NodeKind::Parameter).count();
+        
+        if arguments.len() != parameter_count {
+            return Err(RuntimeError::invalid_argument_count(
+                name,
+                parameter_count,
+                arguments.len(),
+                location.clone(),
+            ));
+        }
+        
+        // Create a new context for the function
+        self.context.push_frame();
+        
+        // Register the parameters
+        let mut parameter_index = 0;
+        
+        for i in 0..function.child_count() {
+            let child = function.get_child(i).unwrap();
+            
+            if child.kind == NodeKind::Parameter {
+                let parameter_name = child.get_attribute("name").ok_or_else(|| {
+                    RuntimeError::missing_attribute("name", child.location.clone())
+                })?;
+                
+                self.context.register_variable(parameter_name, arguments[parameter_index].clone());
+                parameter_index += 1;
+            }
+        }
+        
+        // Execute the function body
+        let body = function.children.iter().find(|child| child.kind == NodeKind::Block).ok_or_else(|| {
+            RuntimeError::missing_body(name, function.location.clone())
+        })?;
+        
+        let result = self.execute_node(body)?;
+        
+        // Pop the function context
+        self.context.pop_frame();
+        
+        Ok(result)
+    }
+    
+    /// Call a function that is implemented by the engine
+    ///
+    /// Returns `None` if the name is not a runtime function.
+    fn call_runtime_function(
+        &mut self,
+        name: &str,
+        arguments: &[Value],
+        location: &crate::utils::SourceLocation,
+    ) -> Option<Result<Value, RuntimeError>> {
+        let result = match name {
+            "currentContext" => {
+                if !arguments.is_empty() {
+                    return Some(Err(RuntimeError::new(
+                        "currentContext() takes no arguments",
+                        location.clone(),
+                    )));
                 }
                 
-                // Look up the function
-                let function = self.context.get_function(&name).ok_or_else(|| {
-                    RuntimeError::undefined_function(&name, node.location.clone())
-                })?;
+                Ok(Value::Context(self.context.current_context().to_string()))
+            }
+            "saveContext" => {
+                if arguments.len() != 2 {
+                    return Some(Err(RuntimeError::new(
+                        "saveContext() requires exactly two arguments: context and path",
+                        location.clone(),
+                    )));
+                }
+                
+                match (&arguments[0], &arguments[1]) {
+                    (Value::Context(context) | Value::String(context), Value::String(path)) => {
+                        self.save_context(context, Path::new(path)).map(|_| Value::Bool(true))
+                    }
+                    _ => Err(RuntimeError::new(
+                        &format!("saveContext() expects a context and a path, got {:?} and {:?}", arguments[0], arguments[1]),
+                        location.clone(),
+                    )),
+                }
+            }
+            "loadContext" => {
+                if arguments.len() != 1 {
+                    return Some(Err(RuntimeError::new(
+                        "loadContext() requires exactly one argument",
+                        location.clone(),
+                    )));
+                }
                 
-                // Check the argument count
-                let parameter_count = function.children.iter().filter(|child| child.kind == NodeKind::Parameter).count();
+                match &arguments[0] {
+                    Value::String(path) => {
+                        self.load_context(Path::new(path)).map(|snapshot| Value::Context(snapshot.name))
+                    }
+                    other => Err(RuntimeError::new(
+                        &format!("Snapshot path must be a string, got {:?}", other),
+                        location.clone(),
+                    )),
+                }
+            }
+            "pathScores" => {
+                if !arguments.is_empty() {
+                    return Some(Err(RuntimeError::new(
+                        "pathScores() takes no arguments",
+                        location.clone(),
+                    )));
+                }
                 
-                if arguments.len() != parameter_count {
-                    return Err(RuntimeError::invalid_argument_count(
-                        &name,
-                        parameter_count,
-                        arguments.len(),
-                        node.location.clone(),
-                    ));
+                let scores = self.path_scores.iter()
+                    .map(|(name, score)| (name.clone(), Value::Float(*score)))
+                    .collect();
+                
+                Ok(Value::Map(scores))
+            }
+            "classify" => {
+                if !self.options.nlp {
+                    return Some(Err(RuntimeError::feature_disabled("NLP", location.clone())));
+                }
+                
+                if arguments.len() != 2 {
+                    return Some(Err(RuntimeError::new(
+                        "classify() requires exactly two arguments: text and categories",
+                        location.clone(),
+                    )));
+                }
+                
+                match (&arguments[0], &arguments[1]) {
+                    (Value::String(text), Value::List(categories)) => {
+                        let categories: Vec<String> = categories.iter().map(crate::stdlib::StdLib::value_to_string).collect();
+                        self.nlp.classify_text(text, &categories)
+                    }
+                    _ => Err(RuntimeError::new(
+                        &format!("classify() expects a text and a list of categories, got {:?} and {:?}", arguments[0], arguments[1]),
+                        location.clone(),
+                    )),
+                }
+            }
+            "routeIntent" => {
+                if !self.options.nlp {
+                    return Some(Err(RuntimeError::feature_disabled("NLP", location.clone())));
+                }
+                
+                match arguments {
+                    [Value::String(text)] => self.handle_intent(text, location),
+                    _ => Err(RuntimeError::new(
+                        "routeIntent() requires exactly one argument: text",
+                        location.clone(),
+                    )),
+                }
+            }
+            "agent" => {
+                if !self.options.nlp {
+                    return Some(Err(RuntimeError::feature_disabled("NLP", location.clone())));
                 }
                 
-                // Create a new context for the function
-                self.context.push_frame();
+                match arguments {
+                    [Value::String(task)] => self.run_agent(task, location),
+                    _ => Err(RuntimeError::new(
+                        "agent() requires exactly one argument: task",
+                        location.clone(),
+                    )),
+                }
+            }
+            "promptVersion" => match arguments {
+                [Value::String(name)] | [Value::Function(name)] => match self.prompts.get(name) {
+                    Some(prompt) => Ok(Value::String(prompt.version.clone())),
+                    None => Err(RuntimeError::new(&format!("Undefined prompt: '{}'", name), location.clone())),
+                },
+                _ => Err(RuntimeError::new(
+                    "promptVersion() requires exactly one argument: the prompt name",
+                    location.clone(),
+                )),
+            },
+            "usePromptVersion" => match arguments {
+                [Value::String(name) | Value::Function(name), Value::String(version)] => {
+                    self.prompts.select(name, version, location).map(|_| Value::Void)
+                }
+                _ => Err(RuntimeError::new(
+                    "usePromptVersion() requires exactly two arguments: the prompt name and version",
+                    location.clone(),
+                )),
+            },
+            "diffContexts" => {
+                if arguments.len() != 2 {
+                    return Some(Err(RuntimeError::new(
+                        "diffContexts() requires exactly two arguments: before and after",
+                        location.clone(),
+                    )));
+                }
                 
-                // Register the parameters
-                let mut parameter_index = 0;
+                // Contexts are compared live, strings are treated as snapshot files
+                let mut snapshots = Vec::new();
                 
-                for i in 0..function.child_count() {
-                    let child = function.get_child(i).unwrap();
+                for argument in arguments {
+                    let snapshot = match argument {
+                        Value::Context(name) => self.snapshot_context(name),
+                        Value::String(path) => ContextSnapshot::load(Path::new(path)),
+                        other => Err(RuntimeError::new(
+                            &format!("diffContexts() expects contexts or snapshot paths, got {:?}", other),
+                            location.clone(),
+                        )),
+                    };
                     
-                    if child.kind == NodeKind::Parameter {
-                        let parameter_name = child.get_attribute("name").ok_or_else(|| {
-                            RuntimeError::missing_attribute("name", child.location.clone())
-                        })?;
-                        
-                        self.context.register_variable(parameter_name, arguments[parameter_index].clone());
-                        parameter_index += 1;
+                    match snapshot {
+                        Ok(snapshot) => snapshots.push(snapshot),
+                        Err(error) => return Some(Err(error)),
                     }
                 }
                 
-                // Execute the function body
-                let body = function.children.iter().find(|child| child.kind == NodeKind::Block).ok_or_else(|| {
-                    RuntimeError::missing_body(&name, function.location.clone())
+                Ok(snapshots[0].diff(&snapshots[1]).to_value())
+            }
+            "indexCreate" | "indexAdd" | "indexSearch" | "indexSearchWhere" | "indexRemove" | "indexSize"
+            | "indexSave" | "indexLoad" => self.call_index_function(name, arguments, location),
+            "chunkByTokens" | "indexAddChunks" | "ragRetrieve" | "ragAnswer" => self.call_rag_function(name, arguments, location),
+            "pythonEval" | "pythonExec" | "pythonImport" | "pythonCall" => self.call_python_function(name, arguments, location),
+            _ => return None,
+        };
+        
+        Some(result)
+    }
+    
+    /// Call a vector index function
+    fn call_index_function(
+        &mut self,
+        name: &str,
+        arguments: &[Value],
+        location: &crate::utils::SourceLocation,
+    ) -> Result<Value, RuntimeError> {
+        let (arity, usage) = match name {
+            "indexCreate" => (2, "two arguments: name and kind"),
+            "indexAdd" => (3, "three arguments: index, id and item"),
+            "indexSearch" => (3, "three arguments: index, query and count"),
+            "indexSearchWhere" => (4, "four arguments: index, query, count and filter"),
+            "indexRemove" => (2, "two arguments: index and id"),
+            "indexSave" => (2, "two arguments: index and path"),
+            "indexLoad" => (2, "two arguments: name and path"),
+            _ => (1, "one argument"),
+        };
+        
+        if arguments.len() != arity {
+            return Err(RuntimeError::new(
+                &format!("{}() requires exactly {}", name, usage),
+                location.clone(),
+            ));
+        }
+        
+        let index_name = Self::string_argument(name, arguments, 0, location)?;
+        
+        match name {
+            "indexCreate" => {
+                let index = vector_index::create_index(Self::string_argument(name, arguments, 1, location)?)?;
+                self.register_index(index_name, index);
+                Ok(Value::String(index_name.to_string()))
+            }
+            "indexLoad" => {
+                let index = vector_index::load_index(Path::new(Self::string_argument(name, arguments, 1, location)?))?;
+                self.register_index(index_name, index);
+                Ok(Value::String(index_name.to_string()))
+            }
+            _ => {
+                let index = self.index(index_name).ok_or_else(|| {
+                    RuntimeError::new(&format!("Undefined index: '{}'", index_name), location.clone())
                 })?;
                 
-                let result = self.execute_node(body)?;
+                match name {
+                    "indexAdd" => {
+                        let id = Self::string_argument(name, arguments, 1, location)?;
+                        let (vector, metadata) = self.index_item(&arguments[2], location)?;
+                        
+                        self.change_index(IndexChange::Add(index_name.to_string(), IndexEntry {
+                            id: id.to_string(),
+                            vector,
+                            metadata,
+                        }))?;
+                        
+                        Ok(Value::Bool(true))
+                    }
+                    "indexSearch" | "indexSearchWhere" => {
+                        let query = match &arguments[1] {
+                            Value::String(text) => self.vector.embed(text)?,
+                            Value::Vector(vector) => vector.clone(),
+                            other => return Err(RuntimeError::new(
+                                &format!("{}() expects a string or vector query, got {:?}", name, other),
+                                location.clone(),
+                            )),
+                        };
+                        
+                        let count = match &arguments[2] {
+                            Value::Int(count) if *count >= 0 => *count as usize,
+                            other => return Err(RuntimeError::new(
+                                &format!("{}() expects a non-negative count, got {:?}", name, other),
+                                location.clone(),
+                            )),
+                        };
+                        
+                        // Filters match entries whose metadata has all of the given values
+                        let conditions = match arguments.get(3) {
+                            Some(Value::Map(conditions)) => conditions.clone(),
+                            Some(other) => return Err(RuntimeError::new(
+                                &format!("indexSearchWhere() expects a map of metadata values, got {:?}", other),
+                                location.clone(),
+                            )),
+                            None => HashMap::new(),
+                        };
+                        let mut filter = |entry: &IndexEntry| {
+                            conditions.iter().all(|(key, value)| entry.metadata.get(key) == Some(value))
+                        };
+                        
+                        let results = index.read().unwrap_or_else(PoisonError::into_inner).search(&query, count, Some(&mut filter))?;
+                        
+                        Ok(Value::List(results.into_iter().map(|result| {
+                            let mut entry = HashMap::new();
+                            entry.insert("id".to_string(), Value::String(result.id));
+                            entry.insert("score".to_string(), Value::Float(result.score));
+                            entry.insert("metadata".to_string(), Value::Map(result.metadata));
+                            Value::Map(entry)
+                        }).collect()))
+                    }
+                    "indexRemove" => {
+                        let id = Self::string_argument(name, arguments, 1, location)?;
+                        Ok(Value::Bool(self.change_index(IndexChange::Remove(index_name.to_string(), id.to_string()))?))
+                    }
+                    "indexSave" => {
+                        let path = Self::string_argument(name, arguments, 1, location)?;
+                        index.read().unwrap_or_else(PoisonError::into_inner).save(Path::new(path))?;
+                        Ok(Value::Bool(true))
+                    }
+                    _ => Ok(Value::Int(index.read().unwrap_or_else(PoisonError::into_inner).len() as i64)),
+                }
+            }
+        }
+    }
+    
+    /// Call a retrieval-augmented generation function
+    fn call_rag_function(
+        &mut self,
+        name: &str,
+        arguments: &[Value],
+        location: &crate::utils::SourceLocation,
+    ) -> Result<Value, RuntimeError> {
+        let (arity, usage) = match name {
+            "chunkByTokens" => (3, "three arguments: text, maxTokens and overlap"),
+            "indexAddChunks" => (3, "three arguments: index, source and chunks"),
+            "ragRetrieve" => (3, "three arguments: index, question and count"),
+            _ => (2, "two arguments: question and index"),
+        };
+        
+        if arguments.len() != arity {
+            return Err(RuntimeError::new(
+                &format!("{}() requires exactly {}", name, usage),
+                location.clone(),
+            ));
+        }
+        
+        let count_argument = |position: usize| match &arguments[position] {
+            Value::Int(count) if *count >= 0 => Ok(*count as usize),
+            other => Err(RuntimeError::new(
+                &format!("Argument {} of {}() must be a non-negative integer, got {:?}", position + 1, name, other),
+                location.clone(),
+            )),
+        };
+        
+        match name {
+            "chunkByTokens" => {
+                let text = Self::string_argument(name, arguments, 0, location)?;
+                let counter = self.options.token_counter.clone().unwrap_or_default();
+                let chunks = rag::chunk_by_tokens(text, count_argument(1)?, count_argument(2)?, &counter)
+                    .map_err(|message| RuntimeError::new(&message, location.clone()))?;
                 
-                // Pop the function context
-                self.context.pop_frame();
+                Ok(Value::List(chunks.iter().map(Chunk::to_value).collect()))
+            }
+            "indexAddChunks" => {
+                let index_name = Self::string_argument(name, arguments, 0, location)?;
+                let source = Self::string_argument(name, arguments, 1, location)?;
                 
-                Ok(result)
+                if self.index(index_name).is_none() {
+                    return Err(RuntimeError::new(&format!("Undefined index: '{}'", index_name), location.clone()));
+                }
+                
+                let chunks = match &arguments[2] {
+                    Value::List(chunks) => chunks.iter().map(|chunk| Chunk::from_value(chunk).ok_or_else(|| {
+                        RuntimeError::new(
+                            &format!("indexAddChunks() expects chunks or strings, got {:?}", chunk),
+                            location.clone(),
+                        )
+                    })).collect::<Result<Vec<Chunk>, RuntimeError>>()?,
+                    other => return Err(RuntimeError::new(
+                        &format!("indexAddChunks() expects a list of chunks, got {:?}", other),
+                        location.clone(),
+                    )),
+                };
+                
+                let texts: Vec<&str> = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+                let vectors = self.vector.embed_batch(&texts)?;
+                
+                // Re-adding a document replaces all of its chunks, including those whose offsets changed
+                let stale: Vec<String> = self.index_sources.get(index_name)
+                    .and_then(|sources| sources.get(source))
+                    .map(|ids| ids.iter().cloned().collect())
+                    .unwrap_or_default();
+                
+                for id in stale {
+                    self.change_index(IndexChange::Remove(index_name.to_string(), id))?;
+                }
+                
+                // Chunks are identified by their source and offsets
+                for (chunk, vector) in chunks.iter().zip(vectors) {
+                    let mut metadata = chunk.to_map();
+                    metadata.insert("source".to_string(), Value::String(source.to_string()));
+                    
+                    self.change_index(IndexChange::Add(index_name.to_string(), IndexEntry {
+                        id: format!("{}:{}-{}", source, chunk.start, chunk.end),
+                        vector,
+                        metadata,
+                    }))?;
+                }
+                
+                Ok(Value::Int(chunks.len() as i64))
             }
-            _ => Err(RuntimeError::not_callable(
-                &format!("{:?}", callee_value),
-                node.location.clone(),
+            "ragRetrieve" => {
+                let index_name = Self::string_argument(name, arguments, 0, location)?;
+                let question = Self::string_argument(name, arguments, 1, location)?;
+                let passages = self.retrieve(index_name, question, count_argument(2)?, location)?;
+                
+                Ok(Value::List(passages.into_iter().map(Value::Map).collect()))
+            }
+            _ => {
+                if !self.options.nlp {
+                    return Err(RuntimeError::feature_disabled("NLP", location.clone()));
+                }
+                
+                let question = Self::string_argument(name, arguments, 0, location)?;
+                let index_name = Self::string_argument(name, arguments, 1, location)?;
+                let passages = self.retrieve(index_name, question, DEFAULT_RAG_SOURCES, location)?;
+                
+                let sources: Vec<String> = passages.iter()
+                    .map(|passage| passage.get("text").map(crate::stdlib::StdLib::value_to_string).unwrap_or_default())
+                    .collect();
+                let answer = self.nlp.answer_from_sources(question, &sources)?;
+                
+                let citations = rag::cited_sources(&answer, passages.len()).into_iter().map(|number| {
+                    let mut citation = passages[number - 1].clone();
+                    citation.insert("number".to_string(), Value::Int(number as i64));
+                    Value::Map(citation)
+                }).collect();
+                
+                let mut result = HashMap::new();
+                result.insert("answer".to_string(), Value::String(answer));
+                result.insert("citations".to_string(), Value::List(citations));
+                
+                Ok(Value::Map(result))
+            }
+        }
+    }
+    
+    /// Call a Python interoperability function
+    ///
+    /// `pythonCall` takes its positional arguments as a list and its keyword
+    /// arguments as a map, either of which can be `null` or omitted.
+    fn call_python_function(
+        &mut self,
+        name: &str,
+        arguments: &[Value],
+        location: &crate::utils::SourceLocation,
+    ) -> Result<Value, RuntimeError> {
+        let (arity, usage) = match name {
+            "pythonEval" | "pythonExec" => (1..=1, "exactly one argument: code"),
+            "pythonImport" => (1..=1, "exactly one argument: moduleName"),
+            _ => (1..=3, "one to three arguments: function, args and kwargs"),
+        };
+        
+        if !arity.contains(&arguments.len()) {
+            return Err(RuntimeError::new(
+                &format!("{}() requires {}", name, usage),
+                location.clone(),
+            ));
+        }
+        
+        let result = match name {
+            "pythonEval" => self.interop.python_eval(Self::string_argument(name, arguments, 0, location)?),
+            "pythonExec" => self.interop.python_exec(Self::string_argument(name, arguments, 0, location)?),
+            "pythonImport" => self.interop.python_import(Self::string_argument(name, arguments, 0, location)?),
+            _ => {
+                let function = Self::string_argument(name, arguments, 0, location)?;
+                
+                let positional = match arguments.get(1).unwrap_or(&Value::Void) {
+                    Value::List(values) => values.clone(),
+                    Value::Void => Vec::new(),
+                    other => return Err(RuntimeError::new(
+                        &format!("Argument 2 of pythonCall() must be a list or null, got {:?}", other),
+                        location.clone(),
+                    )),
+                };
+                
+                let keywords = match arguments.get(2).unwrap_or(&Value::Void) {
+                    Value::Map(values) => values.clone(),
+                    Value::Void => HashMap::new(),
+                    other => return Err(RuntimeError::new(
+                        &format!("Argument 3 of pythonCall() must be a map or null, got {:?}", other),
+                        location.clone(),
+                    )),
+                };
+                
+                self.interop.python_call(function, positional, keywords)
+            }
+        };
+        
+        result.map_err(|mut error| {
+            error.location = location.clone();
+            error
+        })
+    }
+    
+    /// Retrieve the passages of an index that are most similar to a question
+    ///
+    /// Each passage is the metadata of its entry, with its `id` and `score`.
+    fn retrieve(
+        &self,
+        index_name: &str,
+        question: &str,
+        count: usize,
+        location: &crate::utils::SourceLocation,
+    ) -> Result<Vec<HashMap<String, Value>>, RuntimeError> {
+        let index = self.index(index_name).ok_or_else(|| {
+            RuntimeError::new(&format!("Undefined index: '{}'", index_name), location.clone())
+        })?;
+        
+        let query = self.vector.embed(question)?;
+        let results = index.read().unwrap_or_else(PoisonError::into_inner).search(&query, count, None)?;
+        
+        Ok(results.into_iter().map(|result| {
+            let mut passage = result.metadata;
+            passage.insert("id".to_string(), Value::String(result.id));
+            passage.insert("score".to_string(), Value::Float(result.score));
+            passage
+        }).collect())
+    }
+    
+    /// Get a string argument of a runtime function
+    fn string_argument<'a>(
+        name: &str,
+        arguments: &'a [Value],
+        position: usize,
+        location: &crate::utils::SourceLocation,
+    ) -> Result<&'a str, RuntimeError> {
+        match &arguments[position] {
+            Value::String(value) => Ok(value),
+            other => Err(RuntimeError::new(
+                &format!("Argument {} of {}() must be a string, got {:?}", position + 1, name, other),
+                location.clone(),
+            )),
+        }
+    }
+    
+    /// Convert an item added to a vector index into its embedding and metadata
+    ///
+    /// Strings are embedded and kept as the `text` metadata entry. Maps are
+    /// stored as metadata and embedded from their `vector` or `text` entry.
+    fn index_item(
+        &self,
+        item: &Value,
+        location: &crate::utils::SourceLocation,
+    ) -> Result<(Vec<f64>, HashMap<String, Value>), RuntimeError> {
+        match item {
+            Value::String(text) => Ok((
+                self.vector.embed(text)?,
+                HashMap::from([("text".to_string(), item.clone())]),
+            )),
+            Value::Vector(vector) => Ok((vector.clone(), HashMap::new())),
+            Value::Map(map) => {
+                let mut metadata = map.clone();
+                let vector = match (metadata.remove("vector"), metadata.get("text")) {
+                    (Some(Value::Vector(vector)), _) => vector,
+                    (None, Some(Value::String(text))) => self.vector.embed(text)?,
+                    _ => return Err(RuntimeError::new(
+                        "indexAdd() expects a map with a 'vector' or 'text' entry",
+                        location.clone(),
+                    )),
+                };
+                
+                Ok((vector, metadata))
+            }
+            other => Err(RuntimeError::new(
+                &format!("indexAdd() expects a string, vector or map item, got {:?}", other),
+                location.clone(),
             )),
         }
     }
@@ -1188,8 +3083,19 @@ impl Engine {
             RuntimeError::missing_attribute("value", node.location.clone())
         })?;
         
-        // Process the natural language
-        self.nlp.process_natural_language(value)
+        // Process the natural language, into a value of the output type if there is one
+        let _site = TokenBudget::enter_call_site(&node.location);
+        
+        let result = match node.get_attribute("type") {
+            Some(typ) => Schema::from_type(typ, &self.types)
+                .and_then(|schema| self.nlp.process_structured(value, &schema)),
+            None => self.nlp.process_natural_language(value, self.vector.get_current_vector()),
+        };
+        
+        result.map_err(|error| RuntimeError {
+            location: node.location.clone(),
+            ..error
+        })
     }
     
     /// Execute a vector node
@@ -1248,10 +3154,19 @@ impl Engine {
         
         // Create a function from the examples
         let mut examples = Vec::new();
+        let mut arity = None;
         
         // Process each example
         for child in &node.children {
             if child.kind == NodeKind::Example {
+                // Every example gives the same number of arguments
+                let example_arity = Self::example_arity(child);
+                let expected = *arity.get_or_insert(example_arity);
+                
+                if example_arity != expected {
+                    return Err(RuntimeError::example_arity_mismatch(function_name, expected, example_arity, child.location.clone()));
+                }
+                
                 // Get the input and output
                 let input = child.get_child(0).ok_or_else(|| {
                     RuntimeError::missing_child(0, child.location.clone())
@@ -1271,12 +3186,96 @@ impl Engine {
         }
         
         // Register the function with the example executor
-        self.example.register_function(function_name, examples);
+        self.example.register_function(function_name, arity.unwrap_or(1), examples)
+            .map_err(|mut error| {
+                error.location = node.location.clone();
+                error
+            })?;
         
         // Return the function
         Ok(Value::Function(function_name.clone()))
     }
     
+    /// Get the number of arguments the input of an example or a transformation gives
+    fn example_arity(node: &Node) -> usize {
+        node.get_attribute("arity").and_then(|arity| arity.parse().ok()).unwrap_or(1)
+    }
+    
+    /// Execute a transform node
+    fn execute_transform(&mut self, node: &Node) -> Result<Value, RuntimeError> {
+        // Get the input and output
+        let input = node.get_child(0).ok_or_else(|| {
+            RuntimeError::missing_child(0, node.location.clone())
+        })?;
+        
+        let output = node.get_child(1).ok_or_else(|| {
+            RuntimeError::missing_child(1, node.location.clone())
+        })?;
+        
+        // Execute the input and output
+        let input_value = self.execute_node(input)?;
+        let output_value = self.execute_node(output)?;
+        
+        // Add the transformation to its transformer, which cannot share its name with a declared function
+        let transformer = node.get_attribute("name").map(String::as_str);
+        
+        if let Some(name) = transformer {
+            if self.context.get_function(name).is_some() {
+                return Err(RuntimeError::redefined_function(name, node.location.clone()));
+            }
+        }
+        
+        self.example.register_transformation(transformer, Self::example_arity(node), input_value, output_value)
+            .map_err(|mut error| {
+                error.location = node.location.clone();
+                error
+            })?;
+        
+        Ok(Value::Void)
+    }
+    
+    /// Execute a type declaration node
+    fn execute_type_declaration(&mut self, node: &Node) -> Result<Value, RuntimeError> {
+        let name = node.get_attribute("name").ok_or_else(|| {
+            RuntimeError::missing_attribute("name", node.location.clone())
+        })?;
+        
+        // The fields are parameter nodes
+        let mut fields = Vec::new();
+        
+        for field in &node.children {
+            let field_name = field.get_attribute("name").ok_or_else(|| {
+                RuntimeError::missing_attribute("name", field.location.clone())
+            })?;
+            
+            let field_type = field.get_attribute("type").ok_or_else(|| {
+                RuntimeError::missing_attribute("type", field.location.clone())
+            })?;
+            
+            fields.push((field_name.clone(), field_type.clone()));
+        }
+        
+        self.types.insert(name.clone(), fields);
+        
+        Ok(Value::Void)
+    }
+    
+    /// Execute a transform application node
+    fn execute_transform_apply(&mut self, node: &Node) -> Result<Value, RuntimeError> {
+        // Execute the value
+        let value = node.get_child(0).ok_or_else(|| {
+            RuntimeError::missing_child(0, node.location.clone())
+        })?;
+        
+        let value = self.execute_node(value)?;
+        
+        // Apply the transformer
+        let transformer = node.get_attribute("name").map(String::as_str);
+        self.instructions += 1;
+        
+        self.example.transform(transformer, value, self.vector.get_current_vector())
+    }
+    
     /// Execute an example node
     fn execute_example(&mut self, node: &Node) -> Result<Value, RuntimeError> {
         // Examples are handled by the examples statement
@@ -1295,6 +3294,7 @@ impl Engine {
             Value::Vector(_) => true,
             Value::Function(_) => true,
             Value::Context(_) => true,
+            Value::Prob { value, .. } => Self::is_truthy(value),
             Value::Void => false,
         }
     }
@@ -1302,6 +3302,7 @@ impl Engine {
     /// Check if two values are equal
     fn values_equal(a: &Value, b: &Value) -> bool {
         match (a, b) {
+            (Value::Prob { .. }, _) | (_, Value::Prob { .. }) => Self::values_equal(a.most_likely(), b.most_likely()),
             (Value::Bool(a), Value::Bool(b)) => a == b,
             (Value::Int(a), Value::Int(b)) => a == b,
             (Value::Float(a), Value::Float(b)) => a == b,
@@ -1358,13 +3359,8 @@ impl Engine {
                 Ok(Value::List(result))
             }
             (Value::Vector(a), Value::Vector(b)) => {
-                let mut result = a.clone();
-                for (i, val) in b.iter().enumerate() {
-                    if i < result.len() {
-                        result[i] += val;
-                    }
-                }
-                Ok(Value::Vector(result))
+                Self::check_dimensions(a, b, &location)?;
+                Ok(Value::Vector(a.iter().zip(b).map(|(x, y)| x + y).collect()))
             }
             _ => Err(RuntimeError::invalid_operation(
                 "+",
@@ -1383,13 +3379,8 @@ impl Engine {
             (Value::Int(a), Value::Float(b)) => Ok(Value::Float(*a as f64 - b)),
             (Value::Float(a), Value::Int(b)) => Ok(Value::Float(a - *b as f64)),
             (Value::Vector(a), Value::Vector(b)) => {
-                let mut result = a.clone();
-                for (i, val) in b.iter().enumerate() {
-                    if i < result.len() {
-                        result[i] -= val;
-                    }
-                }
-                Ok(Value::Vector(result))
+                Self::check_dimensions(a, b, &location)?;
+                Ok(Value::Vector(a.iter().zip(b).map(|(x, y)| x - y).collect()))
             }
             _ => Err(RuntimeError::invalid_operation(
                 "-",
@@ -1436,12 +3427,18 @@ impl Engine {
                 
                 Ok(Value::List(result))
             }
-            (Value::Vector(a), Value::Float(b)) => {
-                let mut result = a.clone();
-                for val in &mut result {
-                    *val *= *b;
-                }
-                Ok(Value::Vector(result))
+            (Value::Vector(a), Value::Float(_) | Value::Int(_)) => {
+                let scale = Self::scalar(b);
+                Ok(Value::Vector(a.iter().map(|x| x * scale).collect()))
+            }
+            (Value::Float(_) | Value::Int(_), Value::Vector(b)) => {
+                let scale = Self::scalar(a);
+                Ok(Value::Vector(b.iter().map(|x| x * scale).collect()))
+            }
+            (Value::Vector(a), Value::Vector(b)) => {
+                // The product of two vectors is their dot product
+                Self::check_dimensions(a, b, &location)?;
+                Ok(Value::Float(Vector::dot(a, b)))
             }
             _ => Err(RuntimeError::invalid_operation(
                 "*",
@@ -1483,16 +3480,14 @@ impl Engine {
                 
                 Ok(Value::Float(a / *b as f64))
             }
-            (Value::Vector(a), Value::Float(b)) => {
-                if *b == 0.0 {
+            (Value::Vector(a), Value::Float(_) | Value::Int(_)) => {
+                let divisor = Self::scalar(b);
+                
+                if divisor == 0.0 {
                     return Err(RuntimeError::division_by_zero(location));
                 }
                 
-                let mut result = a.clone();
-                for val in &mut result {
-                    *val /= *b;
-                }
-                Ok(Value::Vector(result))
+                Ok(Value::Vector(a.iter().map(|x| x / divisor).collect()))
             }
             _ => Err(RuntimeError::invalid_operation(
                 "/",
@@ -1612,24 +3607,60 @@ impl Engine {
     }
     
     /// Access a property of a value
-    fn property_access(a: &Value, b: &Value, location: crate::utils::SourceLocation) -> Result<Value, RuntimeError> {
-        match (a, b) {
-            (Value::Map(map), Value::String(key)) => {
+    fn property_access(a: &Value, property: &str, location: crate::utils::SourceLocation) -> Result<Value, RuntimeError> {
+        match (a, property) {
+            (Value::Map(map), key) => {
                 map.get(key).cloned().ok_or_else(|| {
                     RuntimeError::undefined_property(key, location)
                 })
             }
+            (Value::Vector(v), "normalized") => Ok(Value::Vector(Vector::normalize(v))),
+            (Value::Vector(v), "magnitude") => Ok(Value::Float(Vector::magnitude(v))),
+            (Value::Vector(v), "dimension") => Ok(Value::Int(v.len() as i64)),
+            (Value::Vector(_), _) => Err(RuntimeError::undefined_property(property, location)),
+            (Value::Prob { .. }, "confidence") => Ok(Value::Float(a.confidence())),
+            (Value::Prob { .. }, "value") => Ok(a.most_likely().clone()),
+            (Value::Prob { alternatives, .. }, "alternatives") => {
+                let alternatives = alternatives.iter().map(|(value, confidence)| {
+                    Value::Map(HashMap::from([
+                        ("value".to_string(), value.clone()),
+                        ("confidence".to_string(), Value::Float(*confidence)),
+                    ]))
+                }).collect();
+                
+                Ok(Value::List(alternatives))
+            }
+            (Value::Prob { value, .. }, _) => Self::property_access(value, property, location),
+            (_, "confidence") => Ok(Value::Float(1.0)),
             _ => Err(RuntimeError::invalid_operation(
                 ".",
                 &format!("{:?}", a),
-                &format!("{:?}", b),
+                property,
                 location,
             )),
         }
     }
     
-    /// Negate a value
-    fn negate(a: &Value, location: crate::utils::SourceLocation) -> Result<Value, RuntimeError> {
+    /// Check that two vectors have the same dimension
+    fn check_dimensions(a: &[f64], b: &[f64], location: &crate::utils::SourceLocation) -> Result<(), RuntimeError> {
+        if a.len() != b.len() {
+            return Err(RuntimeError::dimension_mismatch(a.len(), b.len(), location.clone()));
+        }
+        
+        Ok(())
+    }
+    
+    /// Convert a number into the scale factor used by vector arithmetic
+    fn scalar(value: &Value) -> f64 {
+        match value {
+            Value::Int(n) => *n as f64,
+            Value::Float(f) => *f,
+            _ => 1.0,
+        }
+    }
+    
+    /// Negate a value
+    fn negate(a: &Value, location: crate::utils::SourceLocation) -> Result<Value, RuntimeError> {
         match a {
             Value::Int(a) => Ok(Value::Int(-a)),
             Value::Float(a) => Ok(Value::Float(-a)),
@@ -1653,6 +3684,9 @@ impl Engine {
 mod tests {
     use super::*;
     use crate::parser::ast::{Ast, Node, NodeKind};
+    use crate::runtime::cache::CacheMode;
+    use crate::runtime::error::{RuntimeErrorKind, SchemaMismatch};
+    use crate::runtime::testing::TestBackend;
     use crate::utils::SourceLocation;
     
     #[test]
@@ -1689,6 +3723,12 @@ mod tests {
         let result = engine.execute_literal(&node).unwrap();
         assert_eq!(result, Value::String("hello".to_string()));
         
+        // Test a string literal with escape sequences
+        node.attributes.insert("value".to_string(), r#""say \"hi\"\n\\ \d""#.to_string());
+        
+        let result = engine.execute_literal(&node).unwrap();
+        assert_eq!(result, Value::String("say \"hi\"\n\\ \\d".to_string()));
+        
         // Test a boolean literal
         node.attributes.insert("type".to_string(), "Bool".to_string());
         node.attributes.insert("value".to_string(), "true".to_string());
@@ -1757,4 +3797,1175 @@ mod tests {
         assert!(Engine::values_equal(&Value::Void, &Value::Void));
         assert!(!Engine::values_equal(&Value::Int(42), &Value::String("42".to_string())));
     }
+    
+    /// Parse and execute source code on an engine
+    fn run(engine: &mut Engine, source: &str) -> Result<Value, RuntimeError> {
+        let tokens = crate::Lexer::new(source).tokenize().unwrap();
+        let ast = crate::Parser::new(tokens).parse().unwrap();
+        
+        engine.execute(ast)
+    }
+    
+    #[test]
+    fn test_execute_parallel_isolates_paths() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        let result = run(&mut engine, r#"
+            var base = 1;
+            var r = parallel {
+                a: { var x = base + 1; x; }
+                b: { var x = base * 10; x; }
+                c: { "three"; }
+            } select all;
+        "#).unwrap();
+        
+        // Results are merged in declaration order
+        assert_eq!(result, Value::List(vec![
+            Value::Int(2),
+            Value::Int(10),
+            Value::String("three".to_string()),
+        ]));
+        
+        // Variables declared inside a path do not leak into the engine
+        assert_eq!(engine.context.get_variable("x"), None);
+    }
+    
+    #[test]
+    fn test_execute_parallel_select_best_by() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        let result = run(&mut engine, r#"
+            context Scoring {
+                fn rank(answer: String) -> Float {
+                    return length(answer) * 1.0;
+                }
+            }
+            var r = parallel {
+                short: { "hi"; }
+                long: { "hello there"; }
+            } select best by rank;
+        "#).unwrap();
+        
+        assert_eq!(result, Value::String("hello there".to_string()));
+        assert_eq!(engine.path_scores.get("short"), Some(&2.0));
+        assert_eq!(engine.path_scores.get("long"), Some(&11.0));
+        
+        let result = run(&mut engine, r#"
+            var r = parallel {
+                a: { "not an email"; }
+                b: { "bob@example.com"; }
+            } select best by ~EmailAddress~;
+        "#).unwrap();
+        
+        assert_eq!(result, Value::String("bob@example.com".to_string()));
+        assert_eq!(engine.path_scores.get("a"), Some(&0.0));
+    }
+    
+    #[test]
+    fn test_execute_parallel_strategies() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        // The failing path is skipped
+        let result = run(&mut engine, r#"
+            var r = parallel {
+                broken: { missing; }
+                working: { "ok"; }
+            } select first_success;
+        "#).unwrap();
+        
+        assert_eq!(result, Value::String("ok".to_string()));
+        
+        // The most common result wins
+        let result = run(&mut engine, r#"
+            var r = parallel {
+                a: { "no"; }
+                b: { "yes"; }
+                c: { "yes"; }
+                d: { missing; }
+            } select vote;
+        "#).unwrap();
+        
+        assert_eq!(result, Value::String("yes".to_string()));
+        
+        // The merge function receives every result
+        let result = run(&mut engine, r#"
+            context Merging {
+                fn count(results: List) -> Int {
+                    return length(results);
+                }
+            }
+            var r = parallel {
+                a: { 1; }
+                b: { 2; }
+                c: { 3; }
+            } select merge with count;
+        "#).unwrap();
+        
+        assert_eq!(result, Value::Int(3));
+    }
+    
+    #[test]
+    fn test_execute_parallel_fastest_failure() {
+        // Two threads, so the failing path runs while the other path is still going
+        let mut engine = Engine::new(EngineOptions { max_threads: 2, ..EngineOptions::default() });
+        engine.context.register_variable("items", Value::List(vec![Value::Int(1); 100_000]));
+        
+        // The endless path only stops when it is cancelled, so the failing path always finishes first
+        let error = run(&mut engine, r#"
+            var r = parallel {
+                endless: { for a in items { for b in items { b; } } "ok"; }
+                broken: { missing; }
+            } select fastest;
+        "#).unwrap_err();
+        assert_eq!(error.message, "Undefined variable: 'missing'");
+        
+        // Failures are skipped until a path succeeds, whichever finishes first
+        let result = run(&mut engine, r#"
+            var r = parallel {
+                slow: { for item in items { item; } "ok"; }
+                broken: { missing; }
+            } select first_success;
+        "#).unwrap();
+        assert_eq!(result, Value::String("ok".to_string()));
+    }
+    
+    #[test]
+    fn test_execute_cancelled() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        // Cancel the engine from another thread
+        let token = engine.cancellation_token();
+        std::thread::spawn(move || token.cancel()).join().unwrap();
+        
+        let error = run(&mut engine, "var x = 1;").unwrap_err();
+        
+        assert_eq!(error.message, "Execution was cancelled");
+        
+        // The next run is not cancelled
+        let result = run(&mut engine, "var x = 1;");
+        assert!(result.is_ok());
+    }
+    
+    #[test]
+    fn test_execute_max_time() {
+        let mut engine = Engine::new(EngineOptions { max_time: Some(0), ..EngineOptions::default() });
+        
+        let error = run(&mut engine, "var x = 1;").unwrap_err();
+        assert!(error.message.starts_with("Time limit exceeded"), "{}", error.message);
+        
+        // A deadline block inside the time limit reports its own deadline
+        let mut engine = Engine::new(EngineOptions { max_time: Some(60_000), ..EngineOptions::default() });
+        
+        let error = run(&mut engine, "deadline 0ms { \"done\"; }").unwrap_err();
+        assert_eq!(error.message, RuntimeError::deadline_exceeded(0, error.location.clone()).message);
+    }
+    
+    #[test]
+    fn test_execute_deadline() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        let result = run(&mut engine, "deadline 2s { \"done\"; }").unwrap();
+        assert_eq!(result, Value::String("done".to_string()));
+        
+        // An expired deadline runs the otherwise block
+        let result = run(&mut engine, "deadline 0ms { \"done\"; } otherwise { \"late\"; }").unwrap();
+        assert_eq!(result, Value::String("late".to_string()));
+        
+        let error = run(&mut engine, "deadline 0ms { \"done\"; }").unwrap_err();
+        assert_eq!(error.message, "Deadline of 0ms exceeded");
+        
+        // The engine is usable after a deadline has passed
+        let result = run(&mut engine, "var x = 1;");
+        assert!(result.is_ok());
+        
+        // A failure is reported as it is, even if the deadline passed meanwhile
+        let mut engine = Engine::new(EngineOptions {
+            llm_backend: Some(Arc::new(TestBackend::new().on_chat(|_| {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                Err(RuntimeError::new("The backend failed", SourceLocation::new(0, 0, 0, 0, "")))
+            }))),
+            ..Default::default()
+        });
+        
+        let error = run(&mut engine, r##"deadline 10ms { #"find premium users"#; } otherwise { "late"; }"##).unwrap_err();
+        assert_eq!(error.message, "The backend failed");
+    }
+    
+    #[test]
+    fn test_execute_budget() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        let result = run(&mut engine, r##"budget tokens 10_000 { #"find premium users"#; "done"; } otherwise { "over"; }"##).unwrap();
+        assert_eq!(result, Value::String("done".to_string()));
+        
+        // A call that does not fit in the budget is not made
+        let result = run(&mut engine, r##"budget tokens 5 { #"find premium users"#; "done"; } otherwise { "over"; }"##).unwrap();
+        assert_eq!(result, Value::String("over".to_string()));
+        assert_eq!(engine.get_stats().tokens, 0);
+        
+        let error = run(&mut engine, r##"budget tokens 5 { #"find premium users"#; }"##).unwrap_err();
+        assert_eq!(error.message, "Token budget of 5 tokens exceeded");
+        
+        // Only the budget that ran out handles it
+        let result = run(&mut engine, r##"
+            budget tokens 5 {
+                budget tokens 10_000 { #"find premium users"#; } otherwise { "inner"; }
+            } otherwise {
+                "outer";
+            }
+        "##).unwrap();
+        assert_eq!(result, Value::String("outer".to_string()));
+        
+        // Calls that are not made in a budget block are not limited by it
+        let result = run(&mut engine, r##"budget tokens 5 { 1; } #"find premium users"#; "done";"##).unwrap();
+        assert_eq!(result, Value::String("done".to_string()));
+    }
+    
+    #[test]
+    fn test_execute_intent() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        // Without handlers, intents are processed by the natural language processor
+        let result = run(&mut engine, "intent: recommend products to users;").unwrap();
+        match result {
+            Value::Map(response) => assert_eq!(response.get("intent_type"), Some(&Value::String("recommendation".to_string()))),
+            other => panic!("Expected a map, got {:?}", other),
+        }
+        
+        let handlers = r#"
+            on intent "refund order {order_id}" fn handleRefund(order_id: Int) -> Int { return order_id * 2; }
+            on intent "track order {order_id}" fn handleTracking(order_id: String) -> String { return "tracking " + order_id; }
+        "#;
+        
+        let result = run(&mut engine, &format!("{} intent \"Refund order 21.\";", handlers)).unwrap();
+        assert_eq!(result, Value::Int(42));
+        
+        let result = run(&mut engine, &format!("{} intent: track order A7;", handlers)).unwrap();
+        assert_eq!(result, Value::String("tracking A7".to_string()));
+        
+        // Slots of utterances that do not match exactly are bounded by the parameter types
+        let result = run(&mut engine, &format!("{} routeIntent(\"I'd like a refund for order 42, please\");", handlers)).unwrap();
+        assert_eq!(result, Value::Int(84));
+        
+        let error = run(&mut engine, &format!("{} routeIntent(\"hello there\");", handlers)).unwrap_err();
+        assert_eq!(error.message, "No intent handler matches 'hello there'");
+        
+        // Utterances go to the fallback handler unless they are routed with enough confidence
+        let handlers = format!("{} on intent otherwise fn handleUnknown(text: String) -> String {{ return \"unknown: \" + text; }}", handlers);
+        
+        let result = run(&mut engine, &format!("{} intent \"hello there\";", handlers)).unwrap();
+        assert_eq!(result, Value::String("unknown: hello there".to_string()));
+        
+        // A slot value that cannot be passed as its parameter does not match
+        let result = run(&mut engine, &format!("{} intent \"refund order forty\";", handlers)).unwrap();
+        assert_eq!(result, Value::String("unknown: refund order forty".to_string()));
+        
+        let mut engine = Engine::new(EngineOptions {
+            intent_routing: IntentRouting::Model,
+            intent_threshold: 0.9,
+            ..EngineOptions::default()
+        });
+        
+        let result = run(&mut engine, &format!("{} routeIntent(\"I want a refund for order 8\");", handlers)).unwrap();
+        assert_eq!(result, Value::Int(16));
+        
+        let result = run(&mut engine, &format!("{} routeIntent(\"where is my order 8\");", handlers)).unwrap();
+        assert_eq!(result, Value::String("unknown: where is my order 8".to_string()));
+    }
+    
+    #[test]
+    fn test_execute_prompt() {
+        const PROMPTS: &str = r##"
+            type Label { name: String, confidence: Float }
+            prompt Summarize(text: String, max_words: Int) { #"Summarize ${text} in ${max_words} words."# }
+            prompt Classify(ticket: String) -> Label version "2" { #"Classify the ticket.
+                ${examples}
+                ticket: ${ticket}"# }
+            examples for Classify {
+                "I was charged twice" -> "billing";
+            }
+        "##;
+        
+        // Examples are added to those of earlier runs, so every run has its own engine
+        let run_prompts = |source: &str| {
+            let mut engine = Engine::new(EngineOptions {
+                llm_backend: Some(Arc::new(TestBackend::new()
+                    .on_complete(|prompt| Ok(format!("reply to: {}", prompt)))
+                    .on_chat(|_| Ok("{\"name\": \"billing\", \"confidence\": 0.9}".to_string())))),
+                ..Default::default()
+            });
+            
+            let result = run(&mut engine, &format!("{} {}", PROMPTS, source));
+            (result, engine.get_stats())
+        };
+        
+        // Rendering does not call the model
+        let (result, stats) = run_prompts("render Summarize(\"the report\", 10);");
+        assert_eq!(result.unwrap(), Value::String("Summarize the report in 10 words.".to_string()));
+        assert_eq!(stats.tokens, 0);
+        
+        let (result, _) = run_prompts("render Classify(\"Where is my invoice?\");");
+        assert_eq!(result.unwrap(), Value::String("Classify the ticket.\nticket: I was charged twice\nOutput: billing\nticket: Where is my invoice?".to_string()));
+        
+        // Calling a prompt sends it to the model, and parses the reply as its type
+        let (result, stats) = run_prompts("Summarize(\"the report\", 10);");
+        assert_eq!(result.unwrap(), Value::String("reply to: Summarize the report in 10 words.".to_string()));
+        assert!(stats.tokens > 0);
+        
+        let (result, _) = run_prompts("Classify(\"Where is my invoice?\");");
+        match result.unwrap() {
+            Value::Map(label) => assert_eq!(label.get("name"), Some(&Value::String("billing".to_string()))),
+            other => panic!("Expected a label, got {:?}", other),
+        }
+        
+        let (result, _) = run_prompts("promptVersion(\"Classify\");");
+        assert_eq!(result.unwrap(), Value::String("2".to_string()));
+        
+        let (result, _) = run_prompts("promptVersion(\"Summarize\");");
+        assert_eq!(result.unwrap(), Value::String(crate::parser::template::template_version("Summarize ${text} in ${max_words} words.")));
+        
+        // A prompt declared again is used from then on, but earlier versions can be selected
+        let (result, _) = run_prompts(r##"prompt Classify(ticket: String) -> Label version "3" { #"Label ${ticket}"# } promptVersion("Classify");"##);
+        assert_eq!(result.unwrap(), Value::String("3".to_string()));
+        
+        let (result, _) = run_prompts(r##"prompt Classify(ticket: String) -> Label version "3" { #"Label ${ticket}"# } usePromptVersion("Classify", "2"); render Classify("Where is my invoice?");"##);
+        assert_eq!(result.unwrap(), Value::String("Classify the ticket.\nticket: I was charged twice\nOutput: billing\nticket: Where is my invoice?".to_string()));
+        
+        let (result, _) = run_prompts("usePromptVersion(\"Classify\", \"1\");");
+        assert_eq!(result.unwrap_err().message, "Prompt 'Classify' has no version '1'");
+    }
+    
+    #[test]
+    fn test_execute_stream() {
+        let mut engine = Engine::new(EngineOptions {
+            stream_buffer: 1,
+            ..Default::default()
+        });
+        
+        // The loop body runs once per chunk, as the chunks arrive
+        let result = run(&mut engine, r##"
+            var story = "";
+            var count = 0;
+            for chunk in stream #"Once upon a time"# {
+                story = story + chunk;
+                count = count + 1;
+            }
+            [story, count];
+        "##).unwrap();
+        
+        let story = MockBackend.complete("Once upon a time").unwrap();
+        assert_eq!(result, Value::List(vec![
+            Value::String(story.clone()),
+            Value::Int(story.split_inclusive(' ').count() as i64),
+        ]));
+        assert!(engine.get_stats().tokens > 0);
+        
+        // Prompts are rendered before they are streamed, and a stream outside of a loop is a list of its chunks
+        let result = run(&mut engine, r##"
+            prompt Tell(opening: String) { #"${opening} upon a time"# }
+            stream Tell("Once");
+        "##).unwrap();
+        
+        match result {
+            Value::List(chunks) => {
+                assert_eq!(chunks[0], Value::String("Once ".to_string()));
+                assert_eq!(chunks.len(), story.split_inclusive(' ').count());
+            }
+            other => panic!("Expected a list of chunks, got {:?}", other),
+        }
+        
+        // Streams stop at the deadline of the block they run in
+        let error = run(&mut engine, r##"deadline 0ms { for chunk in stream "Once upon a time" { chunk; } }"##).unwrap_err();
+        assert_eq!(error.message, "Deadline of 0ms exceeded");
+        
+        let error = run(&mut engine, "var count = 3; for chunk in stream count { chunk; }").unwrap_err();
+        assert_eq!(error.message, "Invalid type: expected String, got Int(3)");
+    }
+    
+    #[test]
+    fn test_execute_agent() {
+        let tools = r#"
+            tools {
+                "Look up the total of an order by its id"
+                fn lookupOrder(id: Int) -> Int { return id * 2; }
+                "Cancel an order"
+                fn cancelOrder(id: Int, reason: String) -> Bool { return 1 / 0; }
+            }
+        "#;
+        
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        // The model calls the tool and answers with its result
+        let result = run(&mut engine, &format!("{} agent(\"What is the total of order 21?\");", tools)).unwrap();
+        assert_eq!(result, Value::String("42".to_string()));
+        
+        let calls = engine.get_stats().tool_calls;
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].tool, "lookupOrder");
+        assert_eq!(calls[0].arguments, Value::Map([("id".to_string(), Value::Int(21))].into_iter().collect()));
+        assert_eq!(calls[0].result, Ok(Value::Int(42)));
+        
+        // Errors of tools are sent back to the model instead of failing the run
+        let result = run(&mut engine, &format!("{} agent(\"Cancel order 7, it is late\");", tools)).unwrap();
+        assert!(matches!(result, Value::String(answer) if answer.starts_with("The tool cancelOrder")));
+        
+        let calls = engine.get_stats().tool_calls;
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].tool, "cancelOrder");
+        assert!(calls[0].result.is_err());
+        
+        // Interruptions stop the agent instead of being sent back to the model
+        let budgeted = r##"tools { fn lookupOrder(id: Int) -> Int { budget tokens 1 { #"find premium users"#; } return id; } }"##;
+        let error = run(&mut engine, &format!("{} agent(\"What is the total of order 21?\");", budgeted)).unwrap_err();
+        assert_eq!(error.message, "Token budget of 1 tokens exceeded");
+        
+        // The model must answer within the configured number of tool calls
+        let mut engine = Engine::new(EngineOptions {
+            max_tool_steps: 0,
+            ..EngineOptions::default()
+        });
+        
+        let error = run(&mut engine, &format!("{} agent(\"What is the total of order 21?\");", tools)).unwrap_err();
+        assert_eq!(error.message, "The language model made 0 tool calls without answering");
+        
+        // The tool calls of every parallel path are traced, in declaration order
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        let result = run(&mut engine, &format!(r#"{}
+            var r = parallel {{
+                first: {{ agent("What is the total of order 21?"); }}
+                second: {{ agent("What is the total of order 5?"); }}
+            }} select all;
+        "#, tools)).unwrap();
+        assert_eq!(result, Value::List(vec![Value::String("42".to_string()), Value::String("10".to_string())]));
+        
+        let calls = engine.get_stats().tool_calls;
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].result, Ok(Value::Int(42)));
+        assert_eq!(calls[1].result, Ok(Value::Int(10)));
+    }
+    
+    #[test]
+    fn test_execute_token_usage() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        let result = run(&mut engine, r##"
+            var users = #"find premium users"#;
+            fn label(text: String) -> String {
+                return classify(text, ["sports", "politics"]);
+            }
+            var a = label("the team won the game");
+            var b = label("the election results are in");
+        "##);
+        assert!(result.is_ok(), "{:?}", result);
+        
+        let stats = engine.get_stats();
+        assert_eq!(stats.token_usage.len(), 2);
+        assert_eq!(stats.tokens, stats.token_usage.iter().map(|usage| usage.total_tokens()).sum::<u64>());
+        
+        // The calls are attributed to the innermost expression that made them
+        assert_eq!(stats.token_usage[0].calls, 1);
+        assert!(stats.token_usage[0].completion_tokens > 0);
+        assert_eq!(stats.token_usage[1].calls, 2);
+        assert_eq!(stats.token_usage[1].prompt_tokens, 2 * 2 + 5 + 5);
+        
+        // The usage is reset for every run
+        run(&mut engine, "var x = 1;").unwrap();
+        assert_eq!(engine.get_stats().tokens, 0);
+        assert!(engine.get_stats().token_usage.is_empty());
+    }
+    
+    #[test]
+    fn test_engine_options_max_tokens() {
+        let options = EngineOptions {
+            max_tokens: Some(10),
+            ..Default::default()
+        };
+        
+        let mut engine = Engine::new(options);
+        
+        let result = run(&mut engine, r#"var label = classify("the team won the game", ["sports", "politics"]);"#);
+        assert!(result.is_ok(), "{:?}", result);
+        
+        let error = run(&mut engine, r##"#"find premium users"#;"##).unwrap_err();
+        assert_eq!(error.message, "Token budget of 10 tokens exceeded");
+        
+        // The run budget is not handled by budget blocks
+        let error = run(&mut engine, r##"budget tokens 1_000 { #"find premium users"#; } otherwise { "over"; }"##).unwrap_err();
+        assert_eq!(error.message, "Token budget of 10 tokens exceeded");
+    }
+    
+    #[test]
+    fn test_engine_options_embedding_dimension() {
+        let options = EngineOptions {
+            embedding_dimension: 16,
+            ..Default::default()
+        };
+        
+        let mut engine = Engine::new(options);
+        
+        // The standard library uses the engine's provider
+        let result = run(&mut engine, "var v = embed(\"hello\");").unwrap();
+        
+        assert_eq!(engine.vector.dimension(), 16);
+        assert!(matches!(result, Value::Vector(v) if v.len() == 16));
+    }
+    
+    #[test]
+    fn test_engine_options_max_threads() {
+        let options = EngineOptions {
+            max_threads: 2,
+            ..Default::default()
+        };
+        
+        let engine = Engine::new(options);
+        
+        assert_eq!(engine.parallel.get_max_threads(), 2);
+    }
+    
+    #[test]
+    fn test_engine_options_llm_backend() {
+        let options = EngineOptions {
+            llm_backend: Some(Arc::new(TestBackend::new().preferring_last_category())),
+            ..Default::default()
+        };
+        
+        let mut engine = Engine::new(options);
+        
+        let result = run(&mut engine, r#"var label = classify("the team won the game", ["sports", "politics"]);"#);
+        
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(
+            engine.context.get_variable("label").map(Value::into_most_likely),
+            Some(Value::String("politics".to_string()))
+        );
+    }
+    
+    #[test]
+    fn test_execute_text_analysis() {
+        let tokenize = |nlp| {
+            let mut engine = Engine::new(EngineOptions {
+                llm_backend: Some(Arc::new(TestBackend::new().on_chat(|_| Ok("[\"from\", \"backend\"]".to_string())))),
+                nlp,
+                ..Default::default()
+            });
+            
+            run(&mut engine, "tokenize(\"Hello, world\");").unwrap()
+        };
+        
+        let strings = |items: &[&str]| Value::List(items.iter().map(|item| Value::String(item.to_string())).collect());
+        
+        // The standard library uses the backend of the engine, or the local implementation without NLP
+        assert_eq!(tokenize(true), strings(&["from", "backend"]));
+        assert_eq!(tokenize(false), strings(&["Hello", ",", "world"]));
+        
+        // Functions that need a language model are disabled with NLP
+        let mut engine = Engine::new(EngineOptions { nlp: false, ..Default::default() });
+        let error = run(&mut engine, "translateText(\"Hello\", \"fr\");").unwrap_err();
+        assert_eq!(error.message, "Feature disabled: 'NLP'");
+        assert_eq!(error.location.start_line, 1);
+    }
+    
+    #[test]
+    fn test_engine_options_response_cache() {
+        let path = std::env::temp_dir().join(format!("llm_lang_engine_cache_{}.json", std::process::id()));
+        let source = r##"
+            var users = #"find premium users"#;
+            var label = classify("the team won the game", ["sports", "politics"]);
+            var v = embed("hello");
+        "##;
+        
+        let engine_with = |mode| {
+            Engine::new(EngineOptions {
+                response_cache: Some(Arc::new(ResponseCache::open(&path, mode).unwrap())),
+                ..Default::default()
+            })
+        };
+        
+        let mut recording = engine_with(CacheMode::Record);
+        let result = run(&mut recording, source);
+        assert!(result.is_ok(), "{:?}", result);
+        
+        // The replay gives the same values without calling the backends
+        let mut replaying = engine_with(CacheMode::Replay);
+        let result = run(&mut replaying, source);
+        assert!(result.is_ok(), "{:?}", result);
+        
+        for name in ["users", "label", "v"] {
+            assert_eq!(replaying.context.get_variable(name), recording.context.get_variable(name));
+        }
+        
+        let error = run(&mut replaying, "var v = embed(\"goodbye\");").unwrap_err();
+        assert!(error.message.contains("replay mode"), "{}", error.message);
+        
+        std::fs::remove_file(&path).unwrap();
+    }
+    
+    #[test]
+    fn test_execute_structured_natural_language() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        let result = run(&mut engine, r##"
+            type User { name: String, email: ~EmailAddress~ }
+            var users = #"find premium users"# as List<User>;
+            var count = #"how many premium users are there"# as Int;
+        "##);
+        
+        assert!(result.is_ok(), "{:?}", result);
+        
+        // Only the declared fields are kept
+        let mut alice = HashMap::new();
+        alice.insert("name".to_string(), Value::String("Alice Smith".to_string()));
+        alice.insert("email".to_string(), Value::String("alice@example.com".to_string()));
+        
+        match engine.context.get_variable("users") {
+            Some(Value::List(users)) => {
+                assert_eq!(users.len(), 2);
+                assert_eq!(users[0], Value::Map(alice));
+            }
+            other => panic!("Expected a list of users, got {:?}", other),
+        }
+        
+        assert_eq!(engine.context.get_variable("count"), Some(Value::Int(2)));
+        
+        let error = run(&mut engine, "var orders = #\"find recent orders\"# as List<Order>;").unwrap_err();
+        assert_eq!(error.message, "Unknown type: 'Order'");
+    }
+    
+    #[test]
+    fn test_execute_structured_natural_language_mismatch() {
+        // The backend never returns a valid email address
+        let backend = Arc::new(TestBackend::new().on_chat(|messages| {
+            // Retries explain what was wrong with the previous reply
+            if messages.len() > 2 {
+                let feedback = &messages[messages.len() - 1].content;
+                assert!(feedback.contains("$[0].email: expected ~EmailAddress~"), "{}", feedback);
+            }
+            
+            Ok(r#"[{"name": "Alice", "email": "alice at example"}]"#.to_string())
+        }));
+        let options = EngineOptions {
+            llm_backend: Some(backend.clone()),
+            ..Default::default()
+        };
+        
+        let mut engine = Engine::new(options);
+        
+        let error = run(&mut engine, r##"
+            type User { name: String, email: ~EmailAddress~ }
+            var users = #"find premium users"# as List<User>;
+        "##).unwrap_err();
+        
+        assert_eq!(backend.calls(), 1 + crate::runtime::nlp::STRUCTURED_OUTPUT_RETRIES);
+        assert_eq!(
+            error.kind,
+            RuntimeErrorKind::SchemaMismatch(Box::new(SchemaMismatch {
+                expected: "List<User>".to_string(),
+                reason: "$[0].email: expected ~EmailAddress~, got \"alice at example\"".to_string(),
+                response: r#"[{"name": "Alice", "email": "alice at example"}]"#.to_string(),
+            }))
+        );
+        
+        // The error points at the expression rather than the language model
+        assert_ne!(error.location.start_line, 0);
+    }
+    
+    #[test]
+    fn test_execute_vector_arithmetic() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        engine.context.register_variable("a", Value::Vector(vec![1.0, 2.0]));
+        engine.context.register_variable("b", Value::Vector(vec![3.0, 4.0]));
+        engine.context.register_variable("c", Value::Vector(vec![1.0]));
+        
+        let mut evaluate = |source: &str| run(&mut engine, source);
+        
+        assert_eq!(evaluate("a + b;").unwrap(), Value::Vector(vec![4.0, 6.0]));
+        assert_eq!(evaluate("b - a;").unwrap(), Value::Vector(vec![2.0, 2.0]));
+        assert_eq!(evaluate("a * 0.5 + 2 * b;").unwrap(), Value::Vector(vec![6.5, 9.0]));
+        assert_eq!(evaluate("b / 2;").unwrap(), Value::Vector(vec![1.5, 2.0]));
+        assert_eq!(evaluate("a * b;").unwrap(), Value::Float(11.0));
+        assert_eq!(evaluate("b.normalized;").unwrap(), Value::Vector(vec![0.6, 0.8]));
+        assert_eq!(evaluate("b.magnitude;").unwrap(), Value::Float(5.0));
+        assert_eq!(evaluate("a.dimension;").unwrap(), Value::Int(2));
+        
+        let error = evaluate("a + c;").unwrap_err();
+        assert_eq!(error.message, "Vector dimension mismatch: expected 2, got 1");
+        
+        let error = evaluate("a / 0;").unwrap_err();
+        assert_eq!(error.message, "Division by zero");
+    }
+    
+    #[test]
+    fn test_execute_apply_steering() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        // Equal lengths, so only the applied vector decides
+        let result = run(&mut engine, r#"
+            @remember pet = "a small cat";
+            @remember finance = "the stock market";
+            apply embed("cat") to {
+                apply embed("stock market") * 2 to {
+                    var winner = parallel {
+                        a: { "a cat"; }
+                        b: { "stock"; }
+                    } select best by length;
+                    var recalled = @recall;
+                }
+            }
+        "#);
+        
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(engine.context.get_variable("winner"), Some(Value::String("stock".to_string())));
+        assert_eq!(
+            engine.context.get_variable("recalled").map(Value::into_most_likely),
+            Some(Value::String("the stock market".to_string()))
+        );
+        assert!(engine.vector.get_current_vector().is_none());
+        
+        // A nested vector is weighted like an outer one, so a small weight leaves the outer vector deciding
+        let result = run(&mut engine, r#"
+            apply embed("cat") to {
+                apply embed("stock market") * 0.1 to {
+                    var recalled = @recall;
+                }
+            }
+        "#);
+        
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(
+            engine.context.get_variable("recalled").map(Value::into_most_likely),
+            Some(Value::String("a small cat".to_string()))
+        );
+        
+        // Example-driven functions prefer the example closest to the applied vector
+        let result = run(&mut engine, r#"
+            examples for describe {
+                1 -> "a happy cat";
+                1 -> "stock market report";
+            }
+            apply embed("stock market") to { describe(1); }
+        "#).unwrap();
+        assert_eq!(result.into_most_likely(), Value::String("stock market report".to_string()));
+        assert_eq!(run(&mut engine, "describe(1);").unwrap().into_most_likely(), Value::String("a happy cat".to_string()));
+        
+        // The enclosing vector is restored when the body fails
+        let error = run(&mut engine, "apply embed(\"cat\") to { undefinedFunction(); }").unwrap_err();
+        assert_eq!(error.message, "Undefined variable: 'undefinedFunction'");
+        assert!(engine.vector.get_current_vector().is_none());
+    }
+    
+    #[test]
+    fn test_execute_example_functions() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        let result = run(&mut engine, r#"
+            examples for capitalize {
+                "hello" -> "Hello";
+                "world" -> "World";
+            }
+            examples for add {
+                (1, 2) -> 3;
+                (2, 2) -> 4;
+            }
+            var greeting = capitalize("world");
+            var sum = add(2, 2);
+        "#);
+        
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(engine.context.get_variable("greeting").map(Value::into_most_likely), Some(Value::String("World".to_string())));
+        assert_eq!(engine.context.get_variable("sum").map(Value::into_most_likely), Some(Value::Int(4)));
+        
+        // A second block does not replace the examples of the first
+        assert_eq!(run(&mut engine, "capitalize(\"hello\");").unwrap().into_most_likely(), Value::String("Hello".to_string()));
+        
+        let error = run(&mut engine, "capitalize(\"hello\", \"world\");").unwrap_err();
+        assert_eq!(error.message, "Invalid argument count for function 'capitalize': expected 1, got 2");
+        
+        // The examples are generalized by a synthesized program
+        assert_eq!(run(&mut engine, "add(7, 9);").unwrap().into_most_likely(), Value::Int(16));
+        
+        let error = run(&mut engine, r#"
+            examples for name {
+                1 -> "one";
+                2 -> "two";
+            }
+            name(7);
+        "#).unwrap_err();
+        assert_eq!(error.message, "No example for 'name' is close enough to the arguments");
+        
+        // A list is a single argument, whatever its length
+        let result = run(&mut engine, r#"
+            examples for rev {
+                [1, 2, 3] -> [3, 2, 1];
+                [4, 5, 6] -> [6, 5, 4];
+            }
+            rev([7, 8, 9]);
+        "#).unwrap();
+        assert_eq!(result.into_most_likely(), Value::List(vec![Value::Int(9), Value::Int(8), Value::Int(7)]));
+        
+        let error = run(&mut engine, "examples for pair { (1, 2) -> 3; 4 -> 4; }").unwrap_err();
+        assert_eq!(error.message, "Examples for 'pair' give 2 arguments, but this one gives 1");
+        
+        let error = run(&mut engine, "examples for add { 1 -> 2; }").unwrap_err();
+        assert_eq!(error.message, "Examples for 'add' give 2 arguments, but this one gives 1");
+    }
+    
+    #[test]
+    fn test_execute_transform() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        let result = run(&mut engine, r#"
+            transform "hello" into "Hello";
+            transform plural: "apple" into "apples";
+            transform plural: "language" into "languages";
+            var greeting = transform "world";
+            var fruits = transform "pear" with plural;
+            var words = plural("word");
+        "#);
+        
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(engine.context.get_variable("greeting").map(Value::into_most_likely), Some(Value::String("World".to_string())));
+        assert_eq!(engine.context.get_variable("fruits").map(Value::into_most_likely), Some(Value::String("pears".to_string())));
+        assert_eq!(engine.context.get_variable("words").map(Value::into_most_likely), Some(Value::String("words".to_string())));
+        
+        let error = run(&mut engine, "var x = transform \"box\" with missing;").unwrap_err();
+        assert_eq!(error.message, "Undefined transformer: 'missing'");
+        
+        // A transformation in a loop is only added once
+        let result = run(&mut engine, r#"
+            for (i in [1, 2, 3]) {
+                transform shout: "hi" into "HI";
+            }
+        "#);
+        
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(engine.example.get_function_examples("shout").map(|examples| examples.len()), Some(1));
+        
+        // A named transformer cannot share its name with a declared function
+        let error = run(&mut engine, r#"
+            fn double(x: Int) -> Int { return x * 2; }
+            transform double: 2 into 4;
+        "#).unwrap_err();
+        assert_eq!(error.message, "Redefined function: 'double'");
+    }
+    
+    #[test]
+    fn test_execute_probabilities() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        let result = run(&mut engine, r#"
+            context Checks {
+                fn certainty(value: String) -> Float {
+                    return value.confidence;
+                }
+            }
+            examples for name {
+                1 -> "one";
+                2 -> "two";
+            }
+            var exact = name(2);
+            var known = exact ?? "unknown";
+            var unsure = prob("maybe", 0.2) ?? "fallback";
+            var plain = 5 ?? 0;
+            var certain = certainty(exact);
+            var combined = probOr([prob("cat", 0.6), prob("cat", 0.5), prob("dog", 0.7)]);
+            var likeliest = combined.value;
+            var runnerUp = first(combined.alternatives).value;
+            var label = classify("the team won the game", ["politics", "sports"]);
+            var shout = toUpperCase(label) + "!";
+            var isSports = label == "sports";
+        "#);
+        
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(engine.context.get_variable("known"), Some(Value::String("two".to_string())));
+        assert_eq!(engine.context.get_variable("unsure"), Some(Value::String("fallback".to_string())));
+        assert_eq!(engine.context.get_variable("plain"), Some(Value::Int(5)));
+        assert_eq!(engine.context.get_variable("certain"), Some(Value::Float(1.0)));
+        assert_eq!(engine.context.get_variable("likeliest"), Some(Value::String("cat".to_string())));
+        assert_eq!(engine.context.get_variable("runnerUp"), Some(Value::String("dog".to_string())));
+        assert_eq!(engine.context.get_variable("shout"), Some(Value::String("SPORTS!".to_string())));
+        assert_eq!(engine.context.get_variable("isSports"), Some(Value::Bool(true)));
+        
+        // The threshold of `??` is configurable
+        let mut engine = Engine::new(EngineOptions {
+            confidence_threshold: 0.9,
+            ..Default::default()
+        });
+        
+        let result = run(&mut engine, "prob(\"likely\", 0.8) ?? \"fallback\";").unwrap();
+        assert_eq!(result, Value::String("fallback".to_string()));
+    }
+    
+    #[test]
+    fn test_check_examples() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        let result = run(&mut engine, r#"
+            context Text {
+                fn capitalize(word: String) -> String {
+                    return toUpperCase(substring(word, 0, 1)) + substring(word, 1, length(word));
+                }
+                fn add(a: Int, b: Int) -> Int {
+                    return a + b + 1;
+                }
+                fn broken(x: Int) -> Int {
+                    return x / 0;
+                }
+            }
+            examples for capitalize {
+                "hello" -> "Hello";
+            }
+            examples for add {
+                (1, 2) -> 3;
+            }
+            examples for broken {
+                1 -> 1;
+            }
+            examples for double {
+                2 -> 4;
+            }
+        "#);
+        
+        assert!(result.is_ok(), "{:?}", result);
+        
+        // Functions defined only by examples are not checked
+        let checks = engine.check_examples();
+        let calls: Vec<String> = checks.iter().map(ExampleCheck::call).collect();
+        assert_eq!(calls, vec!["add(1, 2)", "broken(1)", "capitalize(\"hello\")"]);
+        
+        assert!(!checks[0].passed());
+        assert_eq!(checks[0].diff(), "- 3\n+ 4");
+        assert_eq!(checks[1].actual, Err("Division by zero".to_string()));
+        assert!(checks[2].passed());
+        
+        // A failed check does not affect the program afterwards
+        assert_eq!(run(&mut engine, "capitalize(\"world\");").unwrap(), Value::String("World".to_string()));
+        
+        // Only the declarations are needed, not the statements of the program
+        let mut engine = Engine::new(EngineOptions::default());
+        let tokens = crate::Lexer::new(r#"
+            var broken = 1 / 0;
+            context Math {
+                fn half(x: Float) -> Float {
+                    return x / 3.0 * 1.5;
+                }
+                var alsoBroken = 1 / 0;
+            }
+            examples for half {
+                0.7 -> 0.35;
+            }
+        "#).tokenize().unwrap();
+        let ast = crate::Parser::new(tokens).parse().unwrap();
+        
+        engine.declare(&ast).unwrap();
+        
+        // Floats are compared with a tolerance
+        let checks = engine.check_examples();
+        assert_eq!(checks.len(), 1);
+        assert!(checks[0].passed(), "{}", checks[0].diff());
+    }
+    
+    #[test]
+    fn test_execute_index_functions() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        let result = run(&mut engine, r#"
+            indexCreate("docs", "hnsw");
+            indexAdd("docs", "a", "the cat sat on the mat");
+            indexAdd("docs", "b", "stock markets fell sharply");
+            indexAdd("docs", "c", "a kitten sleeps on the rug");
+            indexRemove("docs", "c");
+            var hits = indexSearch("docs", "the cat sat on the mat", 1);
+        "#).unwrap();
+        
+        let Value::List(hits) = result else { panic!("expected a list, got {:?}", result) };
+        let Value::Map(hit) = &hits[0] else { panic!("expected a map, got {:?}", hits[0]) };
+        
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hit["id"], Value::String("a".to_string()));
+        assert!(matches!(hit["score"], Value::Float(score) if score > 0.99));
+        assert_eq!(engine.index("docs").unwrap().read().unwrap().len(), 2);
+        
+        let error = run(&mut engine, "indexSearch(\"missing\", \"cat\", 1);").unwrap_err();
+        assert_eq!(error.message, "Undefined index: 'missing'");
+    }
+    
+    #[test]
+    fn test_execute_parallel_index_isolation() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        // Each path sees only its own changes, and the join applies every path's changes in order
+        let result = run(&mut engine, r#"
+            indexCreate("docs", "flat");
+            indexAdd("docs", "shared", "the cat sat on the mat");
+            var r = parallel {
+                a: { indexAdd("docs", "a", "stock markets fell"); indexRemove("docs", "shared"); indexSize("docs"); }
+                b: { indexAdd("docs", "b", "a kitten sleeps"); indexSize("docs"); }
+            } select all;
+        "#).unwrap();
+        
+        assert_eq!(result, Value::List(vec![Value::Int(1), Value::Int(2)]));
+        
+        let ids = |engine: &Engine| {
+            let index = engine.index("docs").unwrap();
+            let index = index.read().unwrap();
+            ["shared", "a", "b", "c", "d", "e"].into_iter().filter(|id| index.get(id).is_some()).collect::<Vec<&str>>()
+        };
+        assert_eq!(ids(&engine), vec!["a", "b"]);
+        
+        // Only the selected path's changes are kept
+        run(&mut engine, r#"
+            var r = parallel {
+                c: { indexAdd("docs", "c", "rain is expected"); "c"; }
+                d: { indexAdd("docs", "d", "the rug is red"); "d"; }
+            } select best;
+            var s = parallel {
+                broken: { indexAdd("docs", "e", "cats chase mice"); missing; }
+                working: { "ok"; }
+            } select first_success;
+        "#).unwrap();
+        
+        assert_eq!(ids(&engine), vec!["a", "b", "c"]);
+    }
+    
+    #[test]
+    fn test_execute_rag() {
+        let mut engine = Engine::new(EngineOptions::default());
+        let document = "# Paris\nParis is the capital of France. The Eiffel Tower is in Paris.\n\n# Rome\nRome is the capital of Italy.";
+        engine.context.register_variable("document", Value::String(document.to_string()));
+        
+        let result = run(&mut engine, r#"
+            indexCreate("docs", "flat");
+            indexAddChunks("docs", "guide.md", chunkBySentence(document, 1));
+            var result = ragAnswer("Where is the Eiffel Tower?", "docs");
+        "#).unwrap();
+        
+        let Value::Map(result) = result else { panic!("expected a map, got {:?}", result) };
+        let Value::List(citations) = &result["citations"] else { panic!("expected a list, got {:?}", result["citations"]) };
+        let Value::Map(citation) = &citations[0] else { panic!("expected a map, got {:?}", citations[0]) };
+        
+        assert_eq!(result["answer"], Value::String("The Eiffel Tower is in Paris. [1]".to_string()));
+        assert_eq!(citations.len(), 1);
+        assert_eq!(citation["source"], Value::String("guide.md".to_string()));
+        assert_eq!(citation["number"], Value::Int(1));
+        
+        // Citations point back to the cited text in the document
+        let (Value::Int(start), Value::Int(end)) = (&citation["start"], &citation["end"]) else { panic!("expected offsets") };
+        assert_eq!(&document[*start as usize..*end as usize], "The Eiffel Tower is in Paris.");
+        assert_eq!(citation["id"], Value::String(format!("guide.md:{}-{}", start, end)));
+        
+        // Adding an edited document removes the chunks it no longer has, but keeps other sources
+        let edited = "# Paris\nParis is the capital of France.\n\n# Rome\nRome is the capital of Italy. The Colosseum is in Rome.";
+        engine.context.register_variable("edited", Value::String(edited.to_string()));
+        
+        let result = run(&mut engine, r#"
+            indexAddChunks("docs", "notes.md", ["Rome has many fountains."]);
+            var added = indexAddChunks("docs", "guide.md", chunkBySentence(edited, 1));
+            indexSize("docs") - added;
+        "#).unwrap();
+        
+        assert_eq!(result, Value::Int(1));
+        assert!(engine.index("docs").unwrap().read().unwrap().get(&format!("guide.md:{}-{}", start, end)).is_none());
+        
+        // A parallel path replaces the chunks of a document the engine added before it forked
+        let result = run(&mut engine, r#"
+            var before = indexSize("docs");
+            var outcome = parallel {
+                rewrite: { indexAddChunks("docs", "notes.md", ["Rome has old fountains. And new ones."]); "rewrite"; }
+            } select first_success;
+            indexSize("docs") - before;
+        "#).unwrap();
+        
+        assert_eq!(result, Value::Int(0));
+        
+        let chunks = run(&mut engine, "chunkByTokens(\"one two three four\", 3, 1);").unwrap();
+        let Value::List(chunks) = chunks else { panic!("expected a list, got {:?}", chunks) };
+        assert_eq!(chunks.len(), 2);
+        
+        let error = run(&mut engine, "ragRetrieve(\"missing\", \"Paris\", 2);").unwrap_err();
+        assert_eq!(error.message, "Undefined index: 'missing'");
+    }
+    
+    #[test]
+    #[cfg(feature = "python_interop")]
+    fn test_execute_python() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        let result = run(&mut engine, r#"
+            pythonImport("json");
+            pythonExec("def scale(values, factor=1): return [value * factor for value in values]");
+            var options = pythonEval("{'factor': 3}");
+            pythonCall("scale", [[1, 2]], options);
+        "#).unwrap();
+        assert_eq!(result, Value::List(vec![Value::Int(3), Value::Int(6)]));
+        
+        let result = run(&mut engine, "pythonCall(\"json.dumps\", [[1]], null);").unwrap();
+        assert_eq!(result, Value::String("[1]".to_string()));
+        
+        // Omitted arguments are null
+        let result = run(&mut engine, "pythonCall(\"scale\", [[1, 2]]);").unwrap();
+        assert_eq!(result, Value::List(vec![Value::Int(1), Value::Int(2)]));
+        
+        let result = run(&mut engine, r#"
+            pythonExec("def pair(first=1, second=2): return [first, second]");
+            pythonCall("pair");
+        "#).unwrap();
+        assert_eq!(result, Value::List(vec![Value::Int(1), Value::Int(2)]));
+        
+        let error = run(&mut engine, "pythonEval(\"1 / 0\");").unwrap_err();
+        assert_eq!(error.message, "Python ZeroDivisionError: division by zero");
+        assert_eq!(error.location.start_line, 1);
+        
+        // Parallel paths have their own globals
+        let result = run(&mut engine, r#"
+            pythonExec("x = 1");
+            var outcome = parallel {
+                rewrite: { pythonExec("x = 2"); pythonEval("x"); }
+            } select first_success;
+            [outcome, pythonEval("x")];
+        "#).unwrap();
+        assert_eq!(result, Value::List(vec![Value::Int(2), Value::Int(1)]));
+        
+        let error = run(&mut engine, "deadline 50ms { pythonExec(\"while True: pass\"); }").unwrap_err();
+        assert_eq!(error.message, "Deadline of 50ms exceeded");
+    }
+    
+    #[test]
+    #[cfg(not(feature = "python_interop"))]
+    fn test_execute_python_without_feature() {
+        let mut engine = Engine::new(EngineOptions::default());
+        
+        let error = run(&mut engine, "pythonEval(\"1 + 1\");").unwrap_err();
+        assert_eq!(error.message, "Feature disabled: 'python_interop'");
+        
+        let error = run(&mut engine, "pythonCall(\"len\", 1, null);").unwrap_err();
+        assert_eq!(error.message, "Argument 2 of pythonCall() must be a list or null, got Int(1)");
+        
+        let error = run(&mut engine, "pythonCall(\"len\", 1);").unwrap_err();
+        assert_eq!(error.message, "Argument 2 of pythonCall() must be a list or null, got Int(1)");
+        
+        let error = run(&mut engine, "pythonCall();").unwrap_err();
+        assert_eq!(error.message, "pythonCall() requires one to three arguments: function, args and kwargs");
+    }
+    
+    #[test]
+    fn test_save_and_load_context() {
+        let mut engine = Engine::new(EngineOptions::default());
+        let path = std::env::temp_dir().join(format!("llm_lang_engine_snapshot_{}.json", std::process::id()));
+        
+        engine.context.register_variable("x", Value::Int(1));
+        engine.save_context("global", &path).unwrap();
+        
+        engine.context.assign_variable("x", Value::Int(2)).unwrap();
+        let snapshot = engine.load_context(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        
+        assert_eq!(snapshot.name, "global");
+        assert_eq!(engine.context.get_variable("x"), Some(Value::Int(1)));
+        
+        // Standard library functions survive a restore
+        assert!(engine.context.get_function("print").is_some());
+        assert!(!snapshot.functions.contains_key("print"));
+    }
 }
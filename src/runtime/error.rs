@@ -5,6 +5,58 @@
 use crate::utils::SourceLocation;
 use std::fmt;
 
+/// The kind of a runtime error, for errors that carry more than a message
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum RuntimeErrorKind {
+    /// An error described by its message only
+    #[default]
+    General,
+    
+    /// A language model reply that does not match the requested type
+    SchemaMismatch(Box<SchemaMismatch>),
+    
+    /// An exception raised by Python code
+    PythonException(Box<PythonException>),
+    
+    /// Execution was cancelled
+    Cancelled,
+    
+    /// The deadline of a `deadline` block passed
+    DeadlineExceeded,
+    
+    /// The maximum execution time of a run passed
+    TimeLimitExceeded,
+    
+    /// The token budget of a `budget` block ran out
+    TokenBudgetExceeded,
+}
+
+/// The details of a language model reply that does not match the requested type
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaMismatch {
+    /// The requested type
+    pub expected: String,
+    
+    /// Why the reply does not match
+    pub reason: String,
+    
+    /// The last reply of the language model
+    pub response: String,
+}
+
+/// The details of an exception raised by Python code
+#[derive(Debug, Clone, PartialEq)]
+pub struct PythonException {
+    /// The name of the exception type, such as `ValueError`
+    pub exception_type: String,
+    
+    /// The exception message
+    pub message: String,
+    
+    /// The formatted traceback, empty if the exception has none
+    pub traceback: String,
+}
+
 /// An error that can occur during runtime
 #[derive(Debug, Clone)]
 pub struct RuntimeError {
@@ -13,6 +65,9 @@ pub struct RuntimeError {
     
     /// The error location
     pub location: SourceLocation,
+    
+    /// The error kind
+    pub kind: RuntimeErrorKind,
 }
 
 impl RuntimeError {
@@ -21,6 +76,7 @@ impl RuntimeError {
         Self {
             message: message.to_string(),
             location,
+            kind: RuntimeErrorKind::General,
         }
     }
     
@@ -29,6 +85,7 @@ impl RuntimeError {
         Self {
             message: format!("{}", format),
             location,
+            kind: RuntimeErrorKind::General,
         }
     }
     
@@ -52,6 +109,11 @@ impl RuntimeError {
         Self::new(&format!("Undefined function: '{}'", name), location)
     }
     
+    /// Create a new "redefined function" error
+    pub fn redefined_function(name: &str, location: SourceLocation) -> Self {
+        Self::new(&format!("Redefined function: '{}'", name), location)
+    }
+    
     /// Create a new "undefined context" error
     pub fn undefined_context(name: &str, location: SourceLocation) -> Self {
         Self::new(&format!("Undefined context: '{}'", name), location)
@@ -153,6 +215,14 @@ impl RuntimeError {
         Self::new("Division by zero", location)
     }
     
+    /// Create a new "dimension mismatch" error
+    pub fn dimension_mismatch(expected: usize, actual: usize, location: SourceLocation) -> Self {
+        Self::new(
+            &format!("Vector dimension mismatch: expected {}, got {}", expected, actual),
+            location,
+        )
+    }
+    
     /// Create a new "not callable" error
     pub fn not_callable(value: &str, location: SourceLocation) -> Self {
         Self::new(&format!("Not callable: {}", value), location)
@@ -165,13 +235,16 @@ impl RuntimeError {
     
     /// Create a new "time limit exceeded" error
     pub fn time_limit_exceeded(elapsed: u64, limit: u64) -> Self {
-        Self::new(
-            &format!(
-                "Time limit exceeded: {} ms (limit: {} ms)",
-                elapsed, limit
-            ),
-            SourceLocation::new(0, 0, 0, 0, ""),
-        )
+        Self {
+            kind: RuntimeErrorKind::TimeLimitExceeded,
+            ..Self::new(
+                &format!(
+                    "Time limit exceeded: {} ms (limit: {} ms)",
+                    elapsed, limit
+                ),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )
+        }
     }
     
     /// Create a new "memory limit exceeded" error
@@ -199,6 +272,115 @@ impl RuntimeError {
     pub fn no_paths(location: SourceLocation) -> Self {
         Self::new("No paths in parallel statement", location)
     }
+    
+    /// Create a new "cancelled" error
+    pub fn cancelled(location: SourceLocation) -> Self {
+        Self {
+            kind: RuntimeErrorKind::Cancelled,
+            ..Self::new("Execution was cancelled", location)
+        }
+    }
+    
+    /// Create a new "deadline exceeded" error
+    pub fn deadline_exceeded(timeout: u64, location: SourceLocation) -> Self {
+        Self {
+            kind: RuntimeErrorKind::DeadlineExceeded,
+            ..Self::new(&format!("Deadline of {}ms exceeded", timeout), location)
+        }
+    }
+    
+    /// Create a new "stream timeout" error
+    pub fn stream_timeout(timeout: u64, location: SourceLocation) -> Self {
+        Self::new(&format!("No stream chunk received within {}ms", timeout), location)
+    }
+    
+    /// Create a new "token budget exceeded" error
+    pub fn token_budget_exceeded(limit: u64, location: SourceLocation) -> Self {
+        Self {
+            kind: RuntimeErrorKind::TokenBudgetExceeded,
+            ..Self::new(&format!("Token budget of {} tokens exceeded", limit), location)
+        }
+    }
+    
+    /// Create a new "no intent handler" error
+    pub fn no_intent_handler(utterance: &str, location: SourceLocation) -> Self {
+        Self::new(&format!("No intent handler matches '{}'", utterance), location)
+    }
+    
+    /// Create a new "undefined transformer" error
+    ///
+    /// A transformer without a name is the anonymous transformer.
+    pub fn undefined_transformer(name: Option<&str>, location: SourceLocation) -> Self {
+        match name {
+            Some(name) => Self::new(&format!("Undefined transformer: '{}'", name), location),
+            None => Self::new("No transformations have been defined", location),
+        }
+    }
+    
+    /// Create a new "schema mismatch" error
+    ///
+    /// The error keeps the raw response of the language model, since it is
+    /// usually needed to see what went wrong.
+    pub fn schema_mismatch(expected: &str, reason: &str, response: &str, location: SourceLocation) -> Self {
+        Self {
+            message: format!("Language model output does not match {}: {}", expected, reason),
+            location,
+            kind: RuntimeErrorKind::SchemaMismatch(Box::new(SchemaMismatch {
+                expected: expected.to_string(),
+                reason: reason.to_string(),
+                response: response.to_string(),
+            })),
+        }
+    }
+    
+    /// Create a new "Python exception" error
+    ///
+    /// The error keeps the traceback of the exception, which is shown after
+    /// the error message.
+    pub fn python_exception(exception_type: &str, message: &str, traceback: &str, location: SourceLocation) -> Self {
+        Self {
+            message: format!("Python {}: {}", exception_type, message),
+            location,
+            kind: RuntimeErrorKind::PythonException(Box::new(PythonException {
+                exception_type: exception_type.to_string(),
+                message: message.to_string(),
+                traceback: traceback.to_string(),
+            })),
+        }
+    }
+    
+    /// Check if the error stopped execution from outside, rather than reporting a failure
+    ///
+    /// Such errors are passed on as they are, never handled like the failure
+    /// of the code that was running.
+    pub fn is_interruption(&self) -> bool {
+        matches!(
+            self.kind,
+            RuntimeErrorKind::Cancelled
+                | RuntimeErrorKind::DeadlineExceeded
+                | RuntimeErrorKind::TimeLimitExceeded
+                | RuntimeErrorKind::TokenBudgetExceeded
+        )
+    }
+    
+    /// Create a new "no close example" error
+    pub fn no_close_example(function: &str, location: SourceLocation) -> Self {
+        Self::new(
+            &format!("No example for '{}' is close enough to the arguments", function),
+            location,
+        )
+    }
+    
+    /// Create a new "example arity mismatch" error
+    pub fn example_arity_mismatch(function: &str, expected: usize, actual: usize, location: SourceLocation) -> Self {
+        Self::new(
+            &format!(
+                "Examples for '{}' give {} arguments, but this one gives {}",
+                function, expected, actual
+            ),
+            location,
+        )
+    }
 }
 
 impl std::error::Error for RuntimeError {}
@@ -212,7 +394,14 @@ impl fmt::Display for RuntimeError {
             self.location.file,
             self.location.start_line,
             self.location.start_column
-        )
+        )?;
+        
+        match &self.kind {
+            RuntimeErrorKind::PythonException(exception) if !exception.traceback.is_empty() => {
+                write!(f, "\n{}", exception.traceback.trim_end())
+            }
+            _ => Ok(()),
+        }
     }
 }
 
@@ -274,6 +463,15 @@ mod tests {
         assert_eq!(error.location, location);
     }
     
+    #[test]
+    fn test_runtime_error_redefined_function() {
+        let location = SourceLocation::new(1, 1, 1, 1, "test.llm");
+        let error = RuntimeError::redefined_function("foo", location.clone());
+        
+        assert_eq!(error.message, "Redefined function: 'foo'");
+        assert_eq!(error.location, location);
+    }
+    
     #[test]
     fn test_runtime_error_undefined_context() {
         let location = SourceLocation::new(1, 1, 1, 1, "test.llm");
@@ -408,6 +606,7 @@ mod tests {
         let error = RuntimeError::time_limit_exceeded(1000, 500);
         
         assert_eq!(error.message, "Time limit exceeded: 1000 ms (limit: 500 ms)");
+        assert!(error.is_interruption());
     }
     
     #[test]
@@ -444,6 +643,76 @@ mod tests {
         assert_eq!(error.location, location);
     }
     
+    #[test]
+    fn test_runtime_error_cancelled() {
+        let location = SourceLocation::new(1, 1, 1, 1, "test.llm");
+        let error = RuntimeError::cancelled(location.clone());
+        
+        assert_eq!(error.message, "Execution was cancelled");
+        assert_eq!(error.location, location);
+        assert!(error.is_interruption());
+        assert!(!RuntimeError::no_paths(location).is_interruption());
+    }
+    
+    #[test]
+    fn test_runtime_error_token_budget_exceeded() {
+        let location = SourceLocation::new(1, 1, 1, 1, "test.llm");
+        let error = RuntimeError::token_budget_exceeded(100, location.clone());
+        
+        assert_eq!(error.message, "Token budget of 100 tokens exceeded");
+        assert_eq!(error.location, location);
+        assert_eq!(error.kind, RuntimeErrorKind::TokenBudgetExceeded);
+        assert!(error.is_interruption());
+    }
+    
+    #[test]
+    fn test_runtime_error_deadline_exceeded() {
+        let location = SourceLocation::new(1, 1, 1, 1, "test.llm");
+        let error = RuntimeError::deadline_exceeded(2000, location.clone());
+        
+        assert_eq!(error.message, "Deadline of 2000ms exceeded");
+        assert_eq!(error.location, location);
+    }
+    
+    #[test]
+    fn test_runtime_error_stream_timeout() {
+        let location = SourceLocation::new(1, 1, 1, 1, "test.llm");
+        let error = RuntimeError::stream_timeout(500, location.clone());
+        
+        assert_eq!(error.message, "No stream chunk received within 500ms");
+        assert_eq!(error.location, location);
+    }
+    
+    #[test]
+    fn test_runtime_error_dimension_mismatch() {
+        let location = SourceLocation::new(1, 1, 1, 1, "test.llm");
+        let error = RuntimeError::dimension_mismatch(32, 16, location.clone());
+        
+        assert_eq!(error.message, "Vector dimension mismatch: expected 32, got 16");
+        assert_eq!(error.location, location);
+    }
+    
+    #[test]
+    fn test_runtime_error_undefined_transformer() {
+        let location = SourceLocation::new(1, 1, 1, 1, "test.llm");
+        
+        let error = RuntimeError::undefined_transformer(Some("plural"), location.clone());
+        assert_eq!(error.message, "Undefined transformer: 'plural'");
+        
+        let error = RuntimeError::undefined_transformer(None, location.clone());
+        assert_eq!(error.message, "No transformations have been defined");
+        assert_eq!(error.location, location);
+    }
+    
+    #[test]
+    fn test_runtime_error_no_close_example() {
+        let location = SourceLocation::new(1, 1, 1, 1, "test.llm");
+        let error = RuntimeError::no_close_example("capitalize", location.clone());
+        
+        assert_eq!(error.message, "No example for 'capitalize' is close enough to the arguments");
+        assert_eq!(error.location, location);
+    }
+    
     #[test]
     fn test_runtime_error_display() {
         let location = SourceLocation::new(1, 1, 1, 1, "test.llm");
@@ -454,4 +723,34 @@ mod tests {
             "Runtime error: Test error at test.llm:1:1"
         );
     }
+    
+    #[test]
+    fn test_runtime_error_schema_mismatch() {
+        let location = SourceLocation::new(1, 1, 1, 1, "test.llm");
+        let error = RuntimeError::schema_mismatch("List<Int>", "$[0]: expected Int, got \"one\"", "[\"one\"]", location);
+        
+        assert_eq!(error.message, "Language model output does not match List<Int>: $[0]: expected Int, got \"one\"");
+        assert_eq!(
+            error.kind,
+            RuntimeErrorKind::SchemaMismatch(Box::new(SchemaMismatch {
+                expected: "List<Int>".to_string(),
+                reason: "$[0]: expected Int, got \"one\"".to_string(),
+                response: "[\"one\"]".to_string(),
+            }))
+        );
+        assert_eq!(RuntimeError::new("Test error", SourceLocation::new(1, 1, 1, 1, "")).kind, RuntimeErrorKind::General);
+    }
+    
+    #[test]
+    fn test_runtime_error_python_exception() {
+        let location = SourceLocation::new(1, 1, 1, 1, "test.llm");
+        let traceback = "Traceback (most recent call last):\n  File \"<string>\", line 1, in <module>\n";
+        let error = RuntimeError::python_exception("ValueError", "bad input", traceback, location);
+        
+        assert_eq!(error.message, "Python ValueError: bad input");
+        assert_eq!(
+            error.to_string(),
+            "Runtime error: Python ValueError: bad input at test.llm:1:1\nTraceback (most recent call last):\n  File \"<string>\", line 1, in <module>"
+        );
+    }
 }
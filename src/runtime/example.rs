@@ -4,9 +4,26 @@
 //! which learns from examples and generalizes to new inputs.
 
 use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use crate::parser::ast::Ast;
 use crate::Value;
 use super::error::RuntimeError;
-use super::vector::Vector;
+use super::synthesis::{synthesize, Program};
+use super::vector::{EmbeddingProvider, Vector, VectorEmbedding, STEERING_WEIGHT};
+
+/// The similarity an input must have to the closest example of a function
+pub const MIN_SIMILARITY: f64 = 0.5;
+
+/// The number of alternative outputs kept by a call to a function defined by examples
+pub const MAX_ALTERNATIVES: usize = 3;
+
+/// How far apart a float returned by a function can be from the output of its example
+pub const FLOAT_TOLERANCE: f64 = 1e-9;
+
+/// The name under which transformations without a transformer name are kept
+///
+/// This is a keyword, so it cannot clash with the name of a function.
+pub const ANONYMOUS_TRANSFORMER: &str = "transform";
 
 /// An example data
 #[derive(Debug, Clone)]
@@ -18,11 +35,37 @@ struct ExampleData {
     output: Value,
 }
 
+/// The result of running a function declared with `fn` on one of its examples
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExampleCheck {
+    /// The name of the function
+    pub function: String,
+    
+    /// The arguments the input of the example was split into
+    pub arguments: Vec<Value>,
+    
+    /// The output of the example
+    pub expected: Value,
+    
+    /// The value returned by the function, or the message of the error it raised
+    pub actual: Result<Value, String>,
+}
+
 /// An example executor
+#[derive(Debug, Clone)]
 pub struct Example {
     /// The examples
     examples: Vec<ExampleData>,
     
+    /// The examples of each function defined by examples
+    functions: HashMap<String, Vec<ExampleData>>,
+    
+    /// The number of arguments each function defined by examples takes, as its examples give them
+    arities: HashMap<String, usize>,
+    
+    /// The programs synthesized from the examples of each function, once the function is first used
+    programs: HashMap<String, OnceLock<Option<Program>>>,
+    
     /// The vector engine
     vector: Vector,
 }
@@ -32,10 +75,18 @@ impl Example {
     pub fn new() -> Self {
         Self {
             examples: Vec::new(),
+            functions: HashMap::new(),
+            arities: HashMap::new(),
+            programs: HashMap::new(),
             vector: Vector::new(),
         }
     }
     
+    /// Set the embedding provider used to compare string inputs
+    pub fn set_embedding_provider(&mut self, provider: Arc<dyn EmbeddingProvider>) {
+        self.vector.set_embedding_provider(provider);
+    }
+    
     /// Add an example
     pub fn add_example(&mut self, input: Value, output: Value) {
         self.examples.push(ExampleData { input, output });
@@ -54,28 +105,53 @@ impl Example {
     /// Execute an example
     pub fn execute(&self, input: Value) -> Result<Value, RuntimeError> {
         // If there are no examples, return an error
-        if self.examples.is_empty() {
+        let Some((best_example, _)) = self.closest_example(&self.examples, &input, None)? else {
             return Err(RuntimeError::new(
                 "No examples to learn from",
                 crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
             ));
-        }
+        };
         
-        // Find the most similar example
-        let mut best_example = &self.examples[0];
-        let mut best_similarity = self.similarity(&input, &best_example.input);
+        // Return the output of the most similar example
+        Ok(best_example.output.clone())
+    }
+    
+    /// Find the example most similar to an input
+    ///
+    /// Returns the example along with the similarity of its input, without the
+    /// steering bias.
+    fn closest_example<'a>(
+        &self,
+        examples: &'a [ExampleData],
+        input: &Value,
+        steering: Option<&VectorEmbedding>,
+    ) -> Result<Option<(&'a ExampleData, f64)>, RuntimeError> {
+        let mut best: Option<(&ExampleData, f64, f64)> = None;
         
-        for example in &self.examples[1..] {
-            let similarity = self.similarity(&input, &example.input);
+        for example in examples {
+            let similarity = self.similarity(input, &example.input);
+            let score = similarity + self.steering_bias(&example.output, steering)?;
             
-            if similarity > best_similarity {
-                best_example = example;
-                best_similarity = similarity;
+            if best.is_none_or(|(_, _, best_score)| score > best_score) {
+                best = Some((example, similarity, score));
             }
         }
         
-        // Return the output of the most similar example
-        Ok(best_example.output.clone())
+        Ok(best.map(|(example, similarity, _)| (example, similarity)))
+    }
+    
+    /// Calculate the bias a steering vector gives to an example output
+    fn steering_bias(&self, output: &Value, steering: Option<&VectorEmbedding>) -> Result<f64, RuntimeError> {
+        let Some(steering) = steering else {
+            return Ok(0.0);
+        };
+        
+        let embedding = match output {
+            Value::Vector(v) => v.clone(),
+            other => self.vector.embed(&crate::stdlib::StdLib::value_to_string(other))?,
+        };
+        
+        Ok(STEERING_WEIGHT * self.vector.similarity(&embedding, steering))
     }
     
     /// Calculate the similarity between two values
@@ -145,12 +221,222 @@ impl Example {
     }
     
     /// Register a function with examples
-    pub fn register_function(&mut self, function_name: &str, examples: Vec<(Value, Value)>) {
-        // Clear any existing examples
-        self.clear_examples();
+    ///
+    /// The input of each example is its argument, or the list of its arguments
+    /// if the function takes several. Registering more examples for the same
+    /// function adds the ones it does not have yet, such as those of a
+    /// statement run in a loop, and is an error if they give another number of
+    /// arguments. A program consistent with all examples is synthesized for the
+    /// function, if there is one, when it is first called.
+    pub fn register_function(&mut self, function_name: &str, arity: usize, examples: Vec<(Value, Value)>) -> Result<(), RuntimeError> {
+        let expected = *self.arities.entry(function_name.to_string()).or_insert(arity);
         
-        // Add the new examples
-        self.learn(examples);
+        if expected != arity {
+            return Err(RuntimeError::example_arity_mismatch(
+                function_name,
+                expected,
+                arity,
+                crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        let function = self.functions.entry(function_name.to_string()).or_default();
+        let known = function.len();
+        
+        for (input, output) in examples {
+            if !function.iter().any(|example| example.input == input && example.output == output) {
+                function.push(ExampleData { input, output });
+            }
+        }
+        
+        // The program is synthesized again from all examples when it is next needed
+        if function.len() > known || !self.programs.contains_key(function_name) {
+            self.programs.insert(function_name.to_string(), OnceLock::new());
+        }
+        
+        Ok(())
+    }
+    
+    /// Add a transformation to a transformer
+    ///
+    /// Transformers are functions defined by examples, so a named transformer
+    /// can also be called like a function.
+    pub fn register_transformation(&mut self, transformer: Option<&str>, arity: usize, input: Value, output: Value) -> Result<(), RuntimeError> {
+        self.register_function(transformer.unwrap_or(ANONYMOUS_TRANSFORMER), arity, vec![(input, output)])
+    }
+    
+    /// Apply a transformer to a value
+    ///
+    /// A list value is split into arguments if the transformer takes several.
+    pub fn transform(
+        &self,
+        transformer: Option<&str>,
+        input: Value,
+        steering: Option<&VectorEmbedding>,
+    ) -> Result<Value, RuntimeError> {
+        let name = transformer.unwrap_or(ANONYMOUS_TRANSFORMER);
+        
+        let Some(arity) = self.function_arity(name) else {
+            return Err(RuntimeError::undefined_transformer(
+                transformer,
+                crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        };
+        
+        self.call_function(name, Self::arguments(&input, arity), steering)
+    }
+    
+    /// Split the input of an example into the arguments of its function
+    pub fn arguments(input: &Value, arity: usize) -> Vec<Value> {
+        match input {
+            Value::List(items) if arity > 1 => items.clone(),
+            _ => vec![input.clone()],
+        }
+    }
+    
+    /// Get the program synthesized for a function, synthesizing it on first use
+    ///
+    /// Returns an error if the synthesis is cancelled or runs out of time, in
+    /// which case it is tried again when the program is next needed.
+    pub fn get_function_program(&self, function_name: &str) -> Result<Option<&Program>, RuntimeError> {
+        let Some(program) = self.programs.get(function_name) else {
+            return Ok(None);
+        };
+        
+        if let Some(program) = program.get() {
+            return Ok(program.as_ref());
+        }
+        
+        let arity = self.function_arity(function_name).unwrap_or(1);
+        let examples: Vec<(Vec<Value>, Value)> = self.functions.get(function_name).into_iter().flatten()
+            .map(|example| (Self::arguments(&example.input, arity), example.output.clone()))
+            .collect();
+        
+        let synthesized = synthesize(&examples)?;
+        
+        Ok(program.get_or_init(|| synthesized).as_ref())
+    }
+    
+    /// Get the program synthesized for a function as the AST of a function declaration
+    ///
+    /// The source of the program can be generated with `Modify::generate_source`.
+    pub fn get_function_ast(&self, function_name: &str) -> Result<Option<Ast>, RuntimeError> {
+        let Some(program) = self.get_function_program(function_name)? else {
+            return Ok(None);
+        };
+        
+        let (Some(example), Some(arity)) = (
+            self.functions.get(function_name).and_then(|examples| examples.first()),
+            self.function_arity(function_name),
+        ) else {
+            return Ok(None);
+        };
+        
+        Ok(Some(program.to_ast(function_name, &Self::arguments(&example.input, arity), &example.output)))
+    }
+    
+    /// Get the names of the functions that have examples, in alphabetical order
+    pub fn function_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.functions.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+    
+    /// Check if a function is defined by examples
+    pub fn has_function(&self, function_name: &str) -> bool {
+        self.functions.contains_key(function_name)
+    }
+    
+    /// Get the examples of a function
+    pub fn get_function_examples(&self, function_name: &str) -> Option<Vec<(Value, Value)>> {
+        self.functions.get(function_name).map(|examples| {
+            examples.iter().map(|e| (e.input.clone(), e.output.clone())).collect()
+        })
+    }
+    
+    /// Get the number of arguments a function defined by examples takes
+    ///
+    /// This is the number its examples give, in parentheses, and never depends
+    /// on the values of the examples.
+    pub fn function_arity(&self, function_name: &str) -> Option<usize> {
+        self.arities.get(function_name).copied()
+    }
+    
+    /// Call a function defined by examples
+    ///
+    /// Runs the program synthesized for the function. If there is none, or it
+    /// fails for the arguments, returns the output of the example closest to
+    /// the arguments, or an error if even the closest example is less similar
+    /// than `MIN_SIMILARITY`. A cancelled or expired synthesis or program is
+    /// an error rather than a reason to fall back to the examples.
+    ///
+    /// The output is a probabilistic value. The output of a program is certain
+    /// for the inputs of the examples, and otherwise has the probability
+    /// `(n + 1) / (n + 2)` that Laplace's rule of succession gives after `n`
+    /// examples. The output of the closest
+    /// example is as likely as the example is similar, and the outputs of the
+    /// other examples are its alternatives.
+    pub fn call_function(
+        &self,
+        function_name: &str,
+        arguments: Vec<Value>,
+        steering: Option<&VectorEmbedding>,
+    ) -> Result<Value, RuntimeError> {
+        let location = crate::utils::SourceLocation::new(0, 0, 0, 0, "");
+        
+        let (Some(examples), Some(arity)) = (self.functions.get(function_name), self.function_arity(function_name)) else {
+            return Err(RuntimeError::undefined_function(function_name, location));
+        };
+        
+        if arguments.len() != arity {
+            return Err(RuntimeError::invalid_argument_count(function_name, arity, arguments.len(), location));
+        }
+        
+        let input = if arity == 1 {
+            arguments[0].clone()
+        } else {
+            Value::List(arguments.clone())
+        };
+        
+        if let Some(program) = self.get_function_program(function_name)? {
+            match program.evaluate(&arguments) {
+                Ok(output) => {
+                    let confidence = if examples.iter().any(|example| example.input == input) {
+                        1.0
+                    } else {
+                        (examples.len() + 1) as f64 / (examples.len() + 2) as f64
+                    };
+                    
+                    return Ok(Value::prob(output, confidence, Vec::new()));
+                }
+                // Cancellation and time limits stop the call rather than fall back
+                Err(error) if error.is_interruption() => return Err(error),
+                // The program does not apply to the arguments
+                Err(_) => {}
+            }
+        }
+        
+        let (example, similarity) = match self.closest_example(examples, &input, steering)? {
+            Some((example, similarity)) if similarity >= MIN_SIMILARITY => (example, similarity),
+            _ => return Err(RuntimeError::no_close_example(function_name, location)),
+        };
+        
+        // Every other output is an alternative, as likely as its closest example
+        let mut alternatives: Vec<(Value, f64)> = Vec::new();
+        
+        for other in examples.iter().filter(|other| other.output != example.output) {
+            let other_similarity = self.similarity(&input, &other.input);
+            
+            match alternatives.iter_mut().find(|(output, _)| *output == other.output) {
+                Some(alternative) => alternative.1 = alternative.1.max(other_similarity),
+                None => alternatives.push((other.output.clone(), other_similarity)),
+            }
+        }
+        
+        alternatives.sort_by(|a, b| b.1.total_cmp(&a.1));
+        alternatives.truncate(MAX_ALTERNATIVES);
+        
+        Ok(Value::prob(example.output.clone(), similarity, alternatives))
     }
     
     /// Generalize from examples
@@ -165,6 +451,126 @@ impl Example {
     }
 }
 
+impl ExampleCheck {
+    /// Format the call the check made
+    pub fn call(&self) -> String {
+        let arguments: Vec<String> = self.arguments.iter().map(Self::literal).collect();
+        format!("{}({})", self.function, arguments.join(", "))
+    }
+    
+    /// Check if the function returned the output of the example
+    ///
+    /// Floats are equal if they differ by at most `FLOAT_TOLERANCE`, relative
+    /// to the larger of them when it is above 1.
+    pub fn passed(&self) -> bool {
+        matches!(&self.actual, Ok(actual) if Self::matches(&self.expected, actual.most_likely()))
+    }
+    
+    /// Check if two values are equal, allowing floats to differ by the tolerance
+    fn matches(expected: &Value, actual: &Value) -> bool {
+        match (expected, actual) {
+            (Value::Float(expected), Value::Float(actual)) => {
+                (expected - actual).abs() <= FLOAT_TOLERANCE * expected.abs().max(actual.abs()).max(1.0)
+            }
+            (Value::List(expected), Value::List(actual)) => {
+                expected.len() == actual.len()
+                    && expected.iter().zip(actual).all(|(expected, actual)| Self::matches(expected, actual))
+            }
+            (Value::Map(expected), Value::Map(actual)) => {
+                expected.len() == actual.len()
+                    && expected.iter().all(|(key, expected)| actual.get(key).is_some_and(|actual| Self::matches(expected, actual)))
+            }
+            _ => expected == actual,
+        }
+    }
+    
+    /// Describe how the value returned by the function differs from the output of the example
+    ///
+    /// Lines starting with `-` show what the example expects and lines starting
+    /// with `+` what the function returned. Lists and maps are compared item by
+    /// item, so only the items that differ are shown. Returns an empty string
+    /// if the check passed.
+    pub fn diff(&self) -> String {
+        let mut lines = Vec::new();
+        
+        match &self.actual {
+            Ok(actual) => Self::differences("", &self.expected, actual.most_likely(), &mut lines),
+            Err(message) => {
+                lines.push(format!("- {}", Self::literal(&self.expected)));
+                lines.push(format!("+ error: {}", message));
+            }
+        }
+        
+        lines.join("\n")
+    }
+    
+    /// Collect the differences between two values, prefixing each with the path to it
+    fn differences(path: &str, expected: &Value, actual: &Value, lines: &mut Vec<String>) {
+        if Self::matches(expected, actual) {
+            return;
+        }
+        
+        match (expected, actual) {
+            (Value::List(expected), Value::List(actual)) => {
+                for i in 0..expected.len().max(actual.len()) {
+                    let path = format!("{}[{}]", path, i);
+                    
+                    match (expected.get(i), actual.get(i)) {
+                        (Some(expected), Some(actual)) => Self::differences(&path, expected, actual, lines),
+                        (Some(expected), None) => lines.push(format!("- {}: {}", path, Self::literal(expected))),
+                        (None, Some(actual)) => lines.push(format!("+ {}: {}", path, Self::literal(actual))),
+                        (None, None) => {}
+                    }
+                }
+            }
+            (Value::Map(expected), Value::Map(actual)) => {
+                let mut keys: Vec<&String> = expected.keys().chain(actual.keys()).collect();
+                keys.sort();
+                keys.dedup();
+                
+                for key in keys {
+                    let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                    
+                    match (expected.get(key), actual.get(key)) {
+                        (Some(expected), Some(actual)) => Self::differences(&path, expected, actual, lines),
+                        (Some(expected), None) => lines.push(format!("- {}: {}", path, Self::literal(expected))),
+                        (None, Some(actual)) => lines.push(format!("+ {}: {}", path, Self::literal(actual))),
+                        (None, None) => {}
+                    }
+                }
+            }
+            _ => {
+                let prefix = if path.is_empty() { String::new() } else { format!("{}: ", path) };
+                
+                lines.push(format!("- {}{}", prefix, Self::literal(expected)));
+                lines.push(format!("+ {}{}", prefix, Self::literal(actual)));
+            }
+        }
+    }
+    
+    /// Format a value the way it would be written in a program
+    fn literal(value: &Value) -> String {
+        match value {
+            Value::String(s) => format!("{:?}", s),
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().map(Self::literal).collect();
+                format!("[{}]", items.join(", "))
+            }
+            Value::Map(map) => {
+                let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+                entries.sort_by_key(|(key, _)| *key);
+                
+                let entries: Vec<String> = entries.iter()
+                    .map(|(key, value)| format!("{:?}: {}", key, Self::literal(value)))
+                    .collect();
+                format!("{{{}}}", entries.join(", "))
+            }
+            Value::Prob { value, .. } => Self::literal(value),
+            other => crate::stdlib::StdLib::value_to_string(other),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -213,6 +619,189 @@ mod tests {
         assert_eq!(result, Value::Int(4));
     }
     
+    #[test]
+    fn test_example_call_function_steered() {
+        let mut example = Example::new();
+        let vector = Vector::new();
+        
+        // Both examples match the input equally well
+        example.register_function("describe", 1, vec![
+            (Value::Int(1), Value::String("a happy cat".to_string())),
+            (Value::Int(1), Value::String("stock market report".to_string())),
+        ]).unwrap();
+        
+        let steering = vector.embed("stock market").unwrap();
+        let result = example.call_function("describe", vec![Value::Int(1)], Some(&steering)).unwrap();
+        
+        assert_eq!(result.into_most_likely(), Value::String("stock market report".to_string()));
+        
+        let result = example.call_function("describe", vec![Value::Int(1)], None).unwrap();
+        assert_eq!(result.into_most_likely(), Value::String("a happy cat".to_string()));
+    }
+    
+    #[test]
+    fn test_example_register_function() {
+        let mut example = Example::new();
+        
+        example.register_function("capitalize", 1, vec![
+            (Value::String("hello".to_string()), Value::String("Hello".to_string())),
+        ]).unwrap();
+        example.register_function("double", 1, vec![
+            (Value::Int(2), Value::Int(4)),
+        ]).unwrap();
+        example.register_function("capitalize", 1, vec![
+            (Value::String("world".to_string()), Value::String("World".to_string())),
+        ]).unwrap();
+        
+        // Registering one function keeps the examples of the others
+        assert!(example.has_function("double"));
+        assert!(!example.has_function("add"));
+        assert_eq!(example.get_function_examples("capitalize").unwrap().len(), 2);
+        
+        let result = example.call_function("capitalize", vec![Value::String("world".to_string())], None).unwrap();
+        assert_eq!(result.most_likely(), &Value::String("World".to_string()));
+        
+        let result = example.call_function("double", vec![Value::Int(2)], None).unwrap();
+        assert_eq!(result.most_likely(), &Value::Int(4));
+    }
+    
+    #[test]
+    fn test_example_call_function_synthesized() {
+        let mut example = Example::new();
+        
+        example.register_function("capitalize", 1, vec![
+            (Value::String("hello".to_string()), Value::String("Hello".to_string())),
+        ]).unwrap();
+        
+        // The program is only synthesized when the function is first used
+        assert!(example.programs["capitalize"].get().is_none());
+        
+        // The synthesized program generalizes beyond the examples
+        let result = example.call_function("capitalize", vec![Value::String("world".to_string())], None).unwrap();
+        assert_eq!(result.most_likely(), &Value::String("World".to_string()));
+        assert_eq!(result.confidence(), 2.0 / 3.0);
+        
+        let result = example.call_function("capitalize", vec![Value::String("hello".to_string())], None).unwrap();
+        assert_eq!(result.confidence(), 1.0);
+        
+        let ast = example.get_function_ast("capitalize").unwrap().unwrap();
+        let source = super::super::modify::Modify::new().generate_source(&ast).unwrap();
+        assert!(source.starts_with("fn capitalize(input: String) -> String {"));
+        
+        // Without a program, the closest example is used
+        example.register_function("name", 1, vec![
+            (Value::Int(1), Value::String("one".to_string())),
+            (Value::Int(2), Value::String("two".to_string())),
+        ]).unwrap();
+        
+        assert!(example.get_function_program("name").unwrap().is_none());
+        let result = example.call_function("name", vec![Value::Int(2)], None).unwrap();
+        assert_eq!(result.most_likely(), &Value::String("two".to_string()));
+        
+        match result {
+            Value::Prob { confidence, alternatives, .. } => {
+                assert_eq!(confidence, 1.0);
+                assert_eq!(alternatives.len(), 1);
+                assert_eq!(alternatives[0].0, Value::String("one".to_string()));
+                assert!(alternatives[0].1 < confidence);
+            }
+            other => panic!("Expected a probabilistic value, got {:?}", other),
+        }
+        
+        let error = example.call_function("name", vec![Value::Int(7)], None).unwrap_err();
+        assert_eq!(error.message, "No example for 'name' is close enough to the arguments");
+    }
+    
+    #[test]
+    fn test_example_call_function_cancelled() {
+        let mut example = Example::new();
+        
+        example.register_function("capitalize", 1, vec![
+            (Value::String("hello".to_string()), Value::String("Hello".to_string())),
+        ]).unwrap();
+        
+        // A cancelled synthesis is an error and is not remembered
+        {
+            let token = super::super::cancel::CancellationToken::new();
+            let _token = token.enter();
+            token.cancel();
+            
+            let error = example.call_function("capitalize", vec![Value::String("world".to_string())], None).unwrap_err();
+            assert!(error.is_interruption());
+            assert!(example.programs["capitalize"].get().is_none());
+        }
+        
+        let result = example.call_function("capitalize", vec![Value::String("world".to_string())], None).unwrap();
+        assert_eq!(result.most_likely(), &Value::String("World".to_string()));
+    }
+    
+    #[test]
+    fn test_example_function_arity() {
+        let mut example = Example::new();
+        
+        example.register_function("add", 2, vec![
+            (Value::List(vec![Value::Int(1), Value::Int(2)]), Value::Int(3)),
+            (Value::List(vec![Value::Int(2), Value::Int(2)]), Value::Int(4)),
+        ]).unwrap();
+        example.register_function("sum", 1, vec![
+            (Value::List(vec![Value::Int(1), Value::Int(2)]), Value::Int(3)),
+            (Value::List(vec![Value::Int(1)]), Value::Int(1)),
+        ]).unwrap();
+        
+        assert_eq!(example.function_arity("add"), Some(2));
+        assert_eq!(example.function_arity("sum"), Some(1));
+        assert_eq!(example.function_arity("missing"), None);
+        
+        let result = example.call_function("add", vec![Value::Int(2), Value::Int(2)], None).unwrap();
+        assert_eq!(result.most_likely(), &Value::Int(4));
+        
+        let error = example.call_function("add", vec![Value::Int(2)], None).unwrap_err();
+        assert_eq!(error.message, "Invalid argument count for function 'add': expected 2, got 1");
+        
+        // Lists of the same length are single arguments when the examples say so
+        example.register_function("rev", 1, vec![
+            (Value::List(vec![Value::Int(1), Value::Int(2), Value::Int(3)]), Value::List(vec![Value::Int(3), Value::Int(2), Value::Int(1)])),
+            (Value::List(vec![Value::Int(4), Value::Int(5), Value::Int(6)]), Value::List(vec![Value::Int(6), Value::Int(5), Value::Int(4)])),
+        ]).unwrap();
+        assert_eq!(example.function_arity("rev"), Some(1));
+        
+        let error = example.register_function("add", 1, vec![(Value::Int(1), Value::Int(1))]).unwrap_err();
+        assert_eq!(error.message, "Examples for 'add' give 2 arguments, but this one gives 1");
+        assert_eq!(example.get_function_examples("add").unwrap().len(), 2);
+    }
+    
+    #[test]
+    fn test_example_transform() {
+        let mut example = Example::new();
+        
+        assert_eq!(
+            example.transform(None, Value::String("world".to_string()), None).unwrap_err().message,
+            "No transformations have been defined"
+        );
+        
+        example.register_transformation(None, 1, Value::String("hello".to_string()), Value::String("Hello".to_string())).unwrap();
+        example.register_transformation(Some("add"), 2, Value::List(vec![Value::Int(1), Value::Int(2)]), Value::Int(3)).unwrap();
+        example.register_transformation(Some("add"), 2, Value::List(vec![Value::Int(2), Value::Int(5)]), Value::Int(7)).unwrap();
+        
+        let result = example.transform(None, Value::String("world".to_string()), None).unwrap();
+        assert_eq!(result.most_likely(), &Value::String("World".to_string()));
+        
+        // The list is split into the arguments of the transformer
+        let result = example.transform(Some("add"), Value::List(vec![Value::Int(4), Value::Int(4)]), None).unwrap();
+        assert_eq!(result.most_likely(), &Value::Int(8));
+        
+        assert!(example.has_function("add"));
+        
+        // A transformation that is already known is not added again
+        example.register_transformation(Some("add"), 2, Value::List(vec![Value::Int(1), Value::Int(2)]), Value::Int(3)).unwrap();
+        assert_eq!(example.get_function_examples("add").unwrap().len(), 2);
+        
+        assert_eq!(
+            example.transform(Some("plural"), Value::String("box".to_string()), None).unwrap_err().message,
+            "Undefined transformer: 'plural'"
+        );
+    }
+    
     #[test]
     fn test_example_execute_no_examples() {
         let example = Example::new();
@@ -310,4 +899,51 @@ mod tests {
         assert_eq!(result[0], Value::Int(2));
         assert_eq!(result[1], Value::Int(4));
     }
+    
+    #[test]
+    fn test_example_check_diff() {
+        let check = ExampleCheck {
+            function: "stats".to_string(),
+            arguments: vec![Value::Int(1), Value::Int(2)],
+            expected: Value::Map(HashMap::from([
+                ("items".to_string(), Value::List(vec![Value::Int(1), Value::Int(2)])),
+                ("label".to_string(), Value::String("small".to_string())),
+            ])),
+            actual: Ok(Value::Map(HashMap::from([
+                ("items".to_string(), Value::List(vec![Value::Int(1), Value::Int(3), Value::Int(4)])),
+                ("label".to_string(), Value::String("small".to_string())),
+            ]))),
+        };
+        
+        assert!(!check.passed());
+        assert_eq!(check.call(), "stats(1, 2)");
+        assert_eq!(check.diff(), "- items[1]: 2\n+ items[1]: 3\n+ items[2]: 4");
+        
+        let check = ExampleCheck {
+            function: "capitalize".to_string(),
+            arguments: vec![Value::String("hello".to_string())],
+            expected: Value::String("Hello".to_string()),
+            actual: Err("Undefined variable: 'x'".to_string()),
+        };
+        
+        assert_eq!(check.diff(), "- \"Hello\"\n+ error: Undefined variable: 'x'");
+        
+        let check = ExampleCheck {
+            actual: Ok(Value::prob(Value::String("Hello".to_string()), 0.9, Vec::new())),
+            ..check
+        };
+        
+        assert!(check.passed());
+        assert_eq!(check.diff(), "");
+        
+        // Floats only differ beyond the tolerance
+        let check = ExampleCheck {
+            expected: Value::List(vec![Value::Float(0.3), Value::Float(1.0)]),
+            actual: Ok(Value::List(vec![Value::Float(0.1 + 0.2), Value::Float(1.5)])),
+            ..check
+        };
+        
+        assert!(!check.passed());
+        assert_eq!(check.diff(), "- [1]: 1\n+ [1]: 1.5");
+    }
 }
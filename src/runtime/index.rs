@@ -0,0 +1,838 @@
+//! Index module for the LLM.lang runtime
+//!
+//! This module provides vector indexes for the LLM.lang runtime, which store
+//! embeddings with metadata and find the entries nearest to a query. The flat
+//! index searches exhaustively; the HNSW index trades a little recall for
+//! search times that stay low with tens of thousands of entries.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Value;
+use super::error::RuntimeError;
+use super::vector::VectorEmbedding;
+
+/// An entry stored in a vector index
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// The entry identifier
+    pub id: String,
+
+    /// The entry embedding
+    pub vector: VectorEmbedding,
+
+    /// The metadata stored with the entry
+    pub metadata: HashMap<String, Value>,
+}
+
+/// An entry returned by a search
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    /// The entry identifier
+    pub id: String,
+
+    /// The similarity between the query and the entry, between 0.0 and 1.0
+    pub score: f64,
+
+    /// The metadata stored with the entry
+    pub metadata: HashMap<String, Value>,
+}
+
+/// A predicate deciding which entries a search may return
+pub type IndexFilter<'a> = &'a mut dyn FnMut(&IndexEntry) -> bool;
+
+/// A vector index
+pub trait VectorIndex: fmt::Debug + Send + Sync {
+    /// Get the kind of the index, as accepted by `create_index`
+    fn kind(&self) -> &'static str;
+
+    /// Get the dimension of the stored vectors, once the first entry is added
+    fn dimension(&self) -> Option<usize>;
+
+    /// Get the number of entries in the index
+    fn len(&self) -> usize;
+
+    /// Check if the index has no entries
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add an entry, replacing any entry with the same identifier
+    fn add(&mut self, entry: IndexEntry) -> Result<(), RuntimeError>;
+
+    /// Remove an entry, returning whether it existed
+    fn remove(&mut self, id: &str) -> bool;
+
+    /// Get an entry by identifier
+    fn get(&self, id: &str) -> Option<&IndexEntry>;
+
+    /// Get the identifiers of the entries
+    fn ids(&self) -> Vec<String>;
+
+    /// Find the `k` entries most similar to the query that pass the filter, most similar first
+    fn search(&self, query: &[f64], k: usize, filter: Option<IndexFilter>) -> Result<Vec<SearchResult>, RuntimeError>;
+
+    /// Save the index to a file
+    fn save(&self, path: &Path) -> Result<(), RuntimeError>;
+
+    /// Copy the index with all of its entries
+    fn clone_index(&self) -> Box<dyn VectorIndex>;
+}
+
+impl Clone for Box<dyn VectorIndex> {
+    fn clone(&self) -> Self {
+        self.clone_index()
+    }
+}
+
+/// Create an empty index of the given kind (`flat` or `hnsw`)
+pub fn create_index(kind: &str) -> Result<Box<dyn VectorIndex>, RuntimeError> {
+    match kind {
+        "flat" => Ok(Box::new(FlatIndex::new())),
+        "hnsw" => Ok(Box::new(HnswIndex::default())),
+        _ => Err(RuntimeError::new(
+            &format!("Unknown index kind: '{}' (expected 'flat' or 'hnsw')", kind),
+            crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+        )),
+    }
+}
+
+/// Load an index saved by `VectorIndex::save`
+pub fn load_index(path: &Path) -> Result<Box<dyn VectorIndex>, RuntimeError> {
+    let json = std::fs::read_to_string(path).map_err(|e| {
+        RuntimeError::new(
+            &format!("Failed to load index from '{}': {}", path.display(), e),
+            crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+        )
+    })?;
+
+    let file: IndexFile = serde_json::from_str(&json).map_err(|e| {
+        RuntimeError::new(
+            &format!("Invalid index file '{}': {}", path.display(), e),
+            crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+        )
+    })?;
+
+    Ok(match file {
+        IndexFile::Flat(index) => Box::new(index),
+        IndexFile::Hnsw(index) => Box::new(index),
+    })
+}
+
+/// Select the `k` highest scores, highest first
+///
+/// This keeps a heap of at most `k` items instead of sorting every score.
+/// Equal scores keep their input order.
+pub fn top_k<I: IntoIterator<Item = (usize, f64)>>(scores: I, k: usize) -> Vec<(usize, f64)> {
+    if k == 0 {
+        return Vec::new();
+    }
+
+    let mut heap = BinaryHeap::new();
+
+    for (node, score) in scores {
+        heap.push(Candidate { distance: -score, node });
+
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|candidate| (candidate.node, -candidate.distance))
+        .collect()
+}
+
+/// An index file, tagged with the kind of index it contains
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum IndexFile {
+    Flat(FlatIndex),
+    Hnsw(HnswIndex),
+}
+
+/// A borrowed index file, used when saving
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum IndexFileRef<'a> {
+    Flat(&'a FlatIndex),
+    Hnsw(&'a HnswIndex),
+}
+
+impl IndexFileRef<'_> {
+    /// Write the index file
+    fn save(&self, path: &Path) -> Result<(), RuntimeError> {
+        let json = serde_json::to_string(self).map_err(|e| {
+            RuntimeError::new(
+                &format!("Failed to serialize index: {}", e),
+                crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+            )
+        })?;
+
+        std::fs::write(path, json).map_err(|e| {
+            RuntimeError::new(
+                &format!("Failed to save index to '{}': {}", path.display(), e),
+                crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+            )
+        })
+    }
+}
+
+/// A node paired with its distance to a query, ordered by distance
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Candidate {
+    /// The distance to the query
+    distance: f64,
+
+    /// The node position
+    node: usize,
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then_with(|| self.node.cmp(&other.node))
+    }
+}
+
+/// Calculate the cosine similarity between two vectors, between -1.0 and 1.0
+fn cosine(a: &[f64], b: &[f64]) -> f64 {
+    let mut dot_product = 0.0;
+    let mut magnitude_a = 0.0;
+    let mut magnitude_b = 0.0;
+
+    for (x, y) in a.iter().zip(b) {
+        dot_product += x * y;
+        magnitude_a += x * x;
+        magnitude_b += y * y;
+    }
+
+    if magnitude_a > 0.0 && magnitude_b > 0.0 {
+        dot_product / (magnitude_a.sqrt() * magnitude_b.sqrt())
+    } else {
+        0.0
+    }
+}
+
+/// Calculate the distance between two vectors, between 0.0 and 2.0
+fn distance(a: &[f64], b: &[f64]) -> f64 {
+    1.0 - cosine(a, b)
+}
+
+/// Convert a distance into a score on the same scale as `Vector::similarity`
+fn score(distance: f64) -> f64 {
+    (2.0 - distance) / 2.0
+}
+
+/// Check that a vector has the dimension of the index
+fn check_dimension(expected: Option<usize>, vector: &[f64]) -> Result<(), RuntimeError> {
+    match expected {
+        Some(dimension) if dimension != vector.len() => Err(RuntimeError::dimension_mismatch(
+            dimension,
+            vector.len(),
+            crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// An index that compares the query with every entry
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlatIndex {
+    /// The entries
+    entries: Vec<IndexEntry>,
+
+    /// The position of each entry by identifier
+    positions: HashMap<String, usize>,
+
+    /// The dimension of the stored vectors
+    dimension: Option<usize>,
+}
+
+impl FlatIndex {
+    /// Create a new flat index
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl VectorIndex for FlatIndex {
+    fn kind(&self) -> &'static str {
+        "flat"
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn add(&mut self, entry: IndexEntry) -> Result<(), RuntimeError> {
+        check_dimension(self.dimension, &entry.vector)?;
+        self.dimension = Some(entry.vector.len());
+
+        match self.positions.get(&entry.id) {
+            Some(&position) => self.entries[position] = entry,
+            None => {
+                self.positions.insert(entry.id.clone(), self.entries.len());
+                self.entries.push(entry);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn remove(&mut self, id: &str) -> bool {
+        let Some(position) = self.positions.remove(id) else {
+            return false;
+        };
+
+        self.entries.swap_remove(position);
+
+        // The last entry moved into the freed position
+        if let Some(moved) = self.entries.get(position) {
+            self.positions.insert(moved.id.clone(), position);
+        }
+
+        true
+    }
+
+    fn get(&self, id: &str) -> Option<&IndexEntry> {
+        self.positions.get(id).map(|&position| &self.entries[position])
+    }
+
+    fn ids(&self) -> Vec<String> {
+        self.entries.iter().map(|entry| entry.id.clone()).collect()
+    }
+
+    fn search(&self, query: &[f64], k: usize, mut filter: Option<IndexFilter>) -> Result<Vec<SearchResult>, RuntimeError> {
+        check_dimension(self.dimension, query)?;
+
+        let scores = self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| filter.as_mut().is_none_or(|filter| filter(entry)))
+            .map(|(position, entry)| (position, cosine(query, &entry.vector)));
+
+        Ok(top_k(scores, k)
+            .into_iter()
+            .map(|(position, similarity)| {
+                let entry = &self.entries[position];
+                SearchResult {
+                    id: entry.id.clone(),
+                    score: score(1.0 - similarity),
+                    metadata: entry.metadata.clone(),
+                }
+            })
+            .collect())
+    }
+
+    fn save(&self, path: &Path) -> Result<(), RuntimeError> {
+        IndexFileRef::Flat(self).save(path)
+    }
+
+    fn clone_index(&self) -> Box<dyn VectorIndex> {
+        Box::new(self.clone())
+    }
+}
+
+/// A node in the HNSW graph
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HnswNode {
+    /// The entry stored at the node
+    entry: IndexEntry,
+
+    /// The neighbors of the node on each layer it belongs to
+    neighbors: Vec<Vec<usize>>,
+
+    /// Whether the entry has been removed
+    ///
+    /// Removed nodes stay in the graph so that it remains connected, but are
+    /// never returned by searches. The graph is rebuilt without them once they
+    /// make up half of its nodes.
+    deleted: bool,
+}
+
+/// An approximate index based on hierarchical navigable small world graphs
+///
+/// Each entry is linked to its nearest neighbors on layer 0 and, with
+/// exponentially decreasing probability, on higher layers. Searches descend
+/// greedily from the sparse top layer and explore layer 0 with a candidate
+/// list of `ef_search` nodes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HnswIndex {
+    /// The number of neighbors linked on each layer (twice as many on layer 0)
+    m: usize,
+
+    /// The candidate list size used when inserting
+    ef_construction: usize,
+
+    /// The candidate list size used when searching
+    ef_search: usize,
+
+    /// The graph nodes
+    nodes: Vec<HnswNode>,
+
+    /// The node of each live entry by identifier
+    positions: HashMap<String, usize>,
+
+    /// The node searches start from
+    entry_point: Option<usize>,
+
+    /// The dimension of the stored vectors
+    dimension: Option<usize>,
+
+    /// The state of the generator used to pick node layers
+    rng_state: u64,
+}
+
+impl Default for HnswIndex {
+    fn default() -> Self {
+        Self::new(16, 200, 64)
+    }
+}
+
+impl HnswIndex {
+    /// Create a new HNSW index
+    pub fn new(m: usize, ef_construction: usize, ef_search: usize) -> Self {
+        Self {
+            m: m.max(2),
+            ef_construction: ef_construction.max(1),
+            ef_search: ef_search.max(1),
+            nodes: Vec::new(),
+            positions: HashMap::new(),
+            entry_point: None,
+            dimension: None,
+            rng_state: 0x2545_F491_4F6C_DD1D,
+        }
+    }
+
+    /// Set the candidate list size used when searching
+    pub fn set_ef_search(&mut self, ef_search: usize) {
+        self.ef_search = ef_search.max(1);
+    }
+
+    /// Pick the top layer of a new node
+    ///
+    /// The generator is seeded deterministically so that building the same
+    /// index twice produces the same graph.
+    fn random_level(&mut self) -> usize {
+        let mut x = self.rng_state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng_state = x;
+
+        let uniform = ((x >> 11) as f64 / (1u64 << 53) as f64).max(f64::MIN_POSITIVE);
+        (-uniform.ln() / (self.m as f64).ln()).floor() as usize
+    }
+
+    /// Get the top layer of the graph
+    fn top_level(&self) -> usize {
+        self.entry_point.map_or(0, |node| self.nodes[node].neighbors.len() - 1)
+    }
+
+    /// Find the `ef` nodes nearest to the query on a layer, nearest first
+    fn search_layer(&self, query: &[f64], entry_points: &[usize], ef: usize, level: usize) -> Vec<Candidate> {
+        let mut visited: HashSet<usize> = entry_points.iter().copied().collect();
+        let mut candidates = BinaryHeap::new();
+        let mut nearest = BinaryHeap::new();
+
+        for &node in entry_points {
+            let candidate = Candidate { distance: distance(query, &self.nodes[node].entry.vector), node };
+            candidates.push(Reverse(candidate));
+            nearest.push(candidate);
+        }
+
+        while let Some(Reverse(current)) = candidates.pop() {
+            let furthest = nearest.peek().map_or(f64::INFINITY, |candidate: &Candidate| candidate.distance);
+            if current.distance > furthest && nearest.len() >= ef {
+                break;
+            }
+
+            let Some(neighbors) = self.nodes[current.node].neighbors.get(level) else {
+                continue;
+            };
+
+            for &neighbor in neighbors {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+
+                let candidate = Candidate { distance: distance(query, &self.nodes[neighbor].entry.vector), node: neighbor };
+                let furthest = nearest.peek().map_or(f64::INFINITY, |candidate: &Candidate| candidate.distance);
+
+                if nearest.len() < ef || candidate.distance < furthest {
+                    candidates.push(Reverse(candidate));
+                    nearest.push(candidate);
+
+                    if nearest.len() > ef {
+                        nearest.pop();
+                    }
+                }
+            }
+        }
+
+        nearest.into_sorted_vec()
+    }
+
+    /// Descend greedily from the top layer to the layer above `level`
+    fn descend(&self, query: &[f64], level: usize) -> Vec<usize> {
+        let Some(entry_point) = self.entry_point else {
+            return Vec::new();
+        };
+
+        let mut entry_points = vec![entry_point];
+
+        for layer in (level + 1..=self.top_level()).rev() {
+            entry_points = vec![self.search_layer(query, &entry_points, 1, layer)[0].node];
+        }
+
+        entry_points
+    }
+
+    /// Insert an entry as a new node, linking it to its nearest neighbors
+    fn insert(&mut self, entry: IndexEntry) {
+        let level = self.random_level();
+        let node = self.nodes.len();
+        let query = entry.vector.clone();
+        let top_level = self.top_level();
+        let entry_points = self.descend(&query, level);
+
+        self.positions.insert(entry.id.clone(), node);
+        self.nodes.push(HnswNode {
+            entry,
+            neighbors: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+
+        if entry_points.is_empty() {
+            self.entry_point = Some(node);
+            return;
+        }
+
+        let mut entry_points = entry_points;
+
+        for layer in (0..=level.min(top_level)).rev() {
+            let nearest = self.search_layer(&query, &entry_points, self.ef_construction, layer);
+            let max = if layer == 0 { self.m * 2 } else { self.m };
+
+            let selected: Vec<usize> = nearest
+                .iter()
+                .filter(|candidate| candidate.node != node)
+                .take(self.m)
+                .map(|candidate| candidate.node)
+                .collect();
+
+            for &neighbor in &selected {
+                self.nodes[neighbor].neighbors[layer].push(node);
+
+                if self.nodes[neighbor].neighbors[layer].len() > max {
+                    self.prune(neighbor, layer, max);
+                }
+            }
+
+            self.nodes[node].neighbors[layer] = selected;
+            entry_points = nearest.iter().map(|candidate| candidate.node).collect();
+        }
+
+        if level > top_level {
+            self.entry_point = Some(node);
+        }
+    }
+
+    /// Rebuild the graph from its live entries, dropping the removed nodes
+    fn compact(&mut self) {
+        let nodes = std::mem::take(&mut self.nodes);
+        self.positions.clear();
+        self.entry_point = None;
+
+        for node in nodes.into_iter().filter(|node| !node.deleted) {
+            self.insert(node.entry);
+        }
+    }
+
+    /// Keep only the nearest neighbors of a node on a layer
+    fn prune(&mut self, node: usize, level: usize, max: usize) {
+        let vector = &self.nodes[node].entry.vector;
+        let mut neighbors: Vec<Candidate> = self.nodes[node].neighbors[level]
+            .iter()
+            .map(|&neighbor| Candidate { distance: distance(vector, &self.nodes[neighbor].entry.vector), node: neighbor })
+            .collect();
+
+        neighbors.sort();
+        neighbors.truncate(max);
+
+        self.nodes[node].neighbors[level] = neighbors.into_iter().map(|candidate| candidate.node).collect();
+    }
+}
+
+impl VectorIndex for HnswIndex {
+    fn kind(&self) -> &'static str {
+        "hnsw"
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+
+    fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    fn add(&mut self, entry: IndexEntry) -> Result<(), RuntimeError> {
+        check_dimension(self.dimension, &entry.vector)?;
+        self.dimension = Some(entry.vector.len());
+
+        // Replacing an entry removes the old node and inserts a new one
+        self.remove(&entry.id);
+        self.insert(entry);
+
+        Ok(())
+    }
+
+    fn remove(&mut self, id: &str) -> bool {
+        match self.positions.remove(id) {
+            Some(node) => {
+                self.nodes[node].deleted = true;
+
+                // Rebuild the graph once removed nodes make up half of it
+                if self.positions.len() * 2 <= self.nodes.len() {
+                    self.compact();
+                }
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn get(&self, id: &str) -> Option<&IndexEntry> {
+        self.positions.get(id).map(|&node| &self.nodes[node].entry)
+    }
+
+    fn ids(&self) -> Vec<String> {
+        self.nodes.iter().filter(|node| !node.deleted).map(|node| node.entry.id.clone()).collect()
+    }
+
+    fn search(&self, query: &[f64], k: usize, mut filter: Option<IndexFilter>) -> Result<Vec<SearchResult>, RuntimeError> {
+        check_dimension(self.dimension, query)?;
+
+        if k == 0 || self.positions.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let entry_points = self.descend(query, 0);
+        let mut ef = self.ef_search.max(k);
+
+        // Removed and filtered-out nodes take up candidate slots, so widen the
+        // search until enough entries pass or the whole graph has been seen
+        loop {
+            let mut results = Vec::new();
+
+            for candidate in self.search_layer(query, &entry_points, ef, 0) {
+                let node = &self.nodes[candidate.node];
+
+                if node.deleted || !filter.as_mut().is_none_or(|filter| filter(&node.entry)) {
+                    continue;
+                }
+
+                results.push(SearchResult {
+                    id: node.entry.id.clone(),
+                    score: score(candidate.distance),
+                    metadata: node.entry.metadata.clone(),
+                });
+
+                if results.len() == k {
+                    break;
+                }
+            }
+
+            if results.len() == k || ef >= self.nodes.len() {
+                return Ok(results);
+            }
+
+            ef = (ef * 2).min(self.nodes.len());
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<(), RuntimeError> {
+        IndexFileRef::Hnsw(self).save(path)
+    }
+
+    fn clone_index(&self) -> Box<dyn VectorIndex> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create a deterministic pseudo-random vector
+    fn vector(seed: u64, dimension: usize) -> VectorEmbedding {
+        let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+        (0..dimension)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+            })
+            .collect()
+    }
+
+    fn entry(id: &str, vector: VectorEmbedding, group: i64) -> IndexEntry {
+        IndexEntry {
+            id: id.to_string(),
+            vector,
+            metadata: HashMap::from([("group".to_string(), Value::Int(group))]),
+        }
+    }
+
+    #[test]
+    fn test_top_k() {
+        let scores = vec![(0, 0.2), (1, 0.9), (2, 0.5), (3, 0.9)];
+
+        assert_eq!(top_k(scores.clone(), 2), vec![(1, 0.9), (3, 0.9)]);
+        assert_eq!(top_k(scores.clone(), 10).len(), 4);
+        assert!(top_k(scores, 0).is_empty());
+    }
+
+    #[test]
+    fn test_flat_index() {
+        let mut index = FlatIndex::new();
+
+        index.add(entry("a", vec![1.0, 0.0], 1)).unwrap();
+        index.add(entry("b", vec![0.0, 1.0], 1)).unwrap();
+        index.add(entry("c", vec![-1.0, 0.0], 2)).unwrap();
+
+        let results = index.search(&[1.0, 0.1], 2, None).unwrap();
+        assert_eq!(results.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+        assert!(results[0].score > 0.99);
+
+        let error = index.add(entry("d", vec![1.0], 1)).unwrap_err();
+        assert_eq!(error.message, "Vector dimension mismatch: expected 2, got 1");
+
+        // Replacing keeps a single entry per identifier
+        index.add(entry("a", vec![0.0, -1.0], 3)).unwrap();
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.get("a").unwrap().vector, vec![0.0, -1.0]);
+
+        assert!(index.remove("b"));
+        assert!(!index.remove("b"));
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.get("c").unwrap().id, "c");
+    }
+
+    #[test]
+    fn test_index_search_with_filter() {
+        for kind in ["flat", "hnsw"] {
+            let mut index = create_index(kind).unwrap();
+
+            for i in 0..200 {
+                index.add(entry(&format!("doc{}", i), vector(i, 16), (i % 10) as i64)).unwrap();
+            }
+
+            let mut filter = |entry: &IndexEntry| entry.metadata.get("group") == Some(&Value::Int(3));
+            let results = index.search(&vector(1000, 16), 5, Some(&mut filter)).unwrap();
+
+            assert_eq!(results.len(), 5, "{}", kind);
+            assert!(results.iter().all(|r| r.metadata["group"] == Value::Int(3)));
+            assert!(results.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        }
+    }
+
+    #[test]
+    fn test_hnsw_index_recall() {
+        let mut flat = FlatIndex::new();
+        let mut hnsw = HnswIndex::new(16, 100, 64);
+
+        for i in 0..1000 {
+            let entry = entry(&format!("doc{}", i), vector(i, 32), 0);
+            flat.add(entry.clone()).unwrap();
+            hnsw.add(entry).unwrap();
+        }
+
+        let mut found = 0;
+        for q in 0..20 {
+            let query = vector(10_000 + q, 32);
+            let exact = flat.search(&query, 10, None).unwrap();
+            let approximate = hnsw.search(&query, 10, None).unwrap();
+
+            found += approximate.iter().filter(|r| exact.iter().any(|e| e.id == r.id)).count();
+        }
+
+        assert!(found >= 180, "recall too low: {}/200", found);
+    }
+
+    #[test]
+    fn test_hnsw_index_remove() {
+        let mut index = HnswIndex::default();
+
+        for i in 0..50 {
+            index.add(entry(&format!("doc{}", i), vector(i, 8), 0)).unwrap();
+        }
+
+        let query = vector(7, 8);
+        assert_eq!(index.search(&query, 1, None).unwrap()[0].id, "doc7");
+
+        assert!(index.remove("doc7"));
+        assert_eq!(index.len(), 49);
+        assert!(index.get("doc7").is_none());
+
+        let results = index.search(&query, 49, None).unwrap();
+        assert_eq!(results.len(), 49);
+        assert!(results.iter().all(|r| r.id != "doc7"));
+
+        // Removing half of the entries drops the removed nodes from the graph
+        for i in 0..25 {
+            index.remove(&format!("doc{}", i));
+        }
+
+        assert_eq!(index.len(), 25);
+        assert_eq!(index.nodes.len(), 25);
+        assert_eq!(index.search(&vector(30, 8), 1, None).unwrap()[0].id, "doc30");
+        assert_eq!(index.search(&query, 25, None).unwrap().len(), 25);
+    }
+
+    #[test]
+    fn test_index_save_and_load() {
+        for kind in ["flat", "hnsw"] {
+            let mut index = create_index(kind).unwrap();
+
+            for i in 0..20 {
+                index.add(entry(&format!("doc{}", i), vector(i, 8), i as i64)).unwrap();
+            }
+
+            let path = std::env::temp_dir().join(format!("llm_lang_index_{}_{}.json", kind, std::process::id()));
+            index.save(&path).unwrap();
+            let loaded = load_index(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            let query = vector(99, 8);
+            assert_eq!(loaded.kind(), kind);
+            assert_eq!(loaded.len(), 20);
+            let ids = |results: Vec<SearchResult>| results.into_iter().map(|r| r.id).collect::<Vec<_>>();
+            assert_eq!(ids(loaded.search(&query, 3, None).unwrap()), ids(index.search(&query, 3, None).unwrap()));
+        }
+
+        assert_eq!(create_index("tree").unwrap_err().message, "Unknown index kind: 'tree' (expected 'flat' or 'hnsw')");
+    }
+}
@@ -0,0 +1,319 @@
+//! Intent module for the LLM.lang runtime
+//!
+//! This module routes utterances to the handlers declared with `on intent`.
+//! A handler has a pattern such as `refund order {order_id}`, whose slots name
+//! the parameters of the handler function. Utterances that match a pattern
+//! word for word go to its handler. Others are routed by embedding similarity
+//! or by the language model backend, and go to the fallback handler, if any,
+//! when no handler is matched with enough confidence.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::parser::template::IntentPattern;
+use crate::Value;
+use super::error::RuntimeError;
+use super::nlp::NLP;
+use super::vector::{Vector, VectorEmbedding};
+
+/// The confidence a routed utterance needs to go to a handler, unless configured otherwise
+pub const DEFAULT_INTENT_THRESHOLD: f64 = 0.75;
+
+/// How utterances that do not match a pattern exactly are routed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntentRouting {
+    /// Route to the handler whose pattern is the most similar to the utterance
+    #[default]
+    Embedding,
+
+    /// Ask the language model backend to choose the handler and fill in its slots
+    Model,
+}
+
+impl FromStr for IntentRouting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "embedding" => Ok(IntentRouting::Embedding),
+            "model" => Ok(IntentRouting::Model),
+            _ => Err(format!("Unknown intent routing '{}': expected embedding or model", s)),
+        }
+    }
+}
+
+impl fmt::Display for IntentRouting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntentRouting::Embedding => write!(f, "embedding"),
+            IntentRouting::Model => write!(f, "model"),
+        }
+    }
+}
+
+/// Convert a slot value to the type of the parameter it is passed as
+///
+/// Returns `None` if the value cannot be converted.
+pub fn slot_value(value: &str, typ: &str) -> Option<Value> {
+    match typ {
+        "Int" => value.replace(',', "").parse().ok().map(Value::Int),
+        "Float" => value.replace(',', "").parse().ok().map(Value::Float),
+        "Bool" => match value.to_lowercase().as_str() {
+            "true" | "yes" => Some(Value::Bool(true)),
+            "false" | "no" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        _ => Some(Value::String(value.to_string())),
+    }
+}
+
+/// A handler declared with `on intent`
+#[derive(Debug, Clone)]
+pub struct IntentHandler {
+    /// The pattern of the utterances the handler is for
+    pub pattern: IntentPattern,
+
+    /// The name of the handler function
+    pub function: String,
+
+    /// The parameters of the handler function and their types, in declaration order
+    pub parameters: Vec<(String, String)>,
+
+    /// The embedding of the pattern's description, if utterances are routed by embedding
+    pub embedding: Option<VectorEmbedding>,
+}
+
+impl IntentHandler {
+    /// Extract the slot values from an utterance, each bounded by the type of its parameter
+    fn extract(&self, utterance: &str) -> Option<Vec<(String, String)>> {
+        self.pattern.extract(utterance, |slot, value| {
+            let typ = self.parameters.iter().find(|(name, _)| name == slot).map_or("Any", |(_, typ)| typ.as_str());
+            slot_value(value, typ).is_some()
+        })
+    }
+}
+
+/// A handler chosen for an utterance
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntentMatch {
+    /// The name of the handler function
+    pub function: String,
+
+    /// The confidence that the handler is the right one, between 0.0 and 1.0
+    pub confidence: f64,
+
+    /// The slot values, by slot name
+    pub slots: Vec<(String, String)>,
+}
+
+/// A pattern chosen for an utterance by the language model backend
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntentChoice {
+    /// The index of the pattern in the list it was chosen from
+    pub index: usize,
+
+    /// The confidence of the backend in the choice
+    pub confidence: f64,
+
+    /// The slot values, by slot name
+    pub slots: HashMap<String, String>,
+}
+
+/// A router of utterances to intent handlers
+#[derive(Debug, Clone, Default)]
+pub struct IntentRouter {
+    /// The handlers, in the order they were declared
+    handlers: Vec<IntentHandler>,
+
+    /// The name of the fallback handler function, if any
+    fallback: Option<String>,
+}
+
+impl IntentRouter {
+    /// Create a new intent router without handlers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a handler, given the parameters of its function and their types
+    ///
+    /// When utterances are routed by embedding, the pattern's description is
+    /// embedded here, once, rather than for every utterance.
+    pub fn add_handler(&mut self, pattern: IntentPattern, function: &str, parameters: Vec<(String, String)>, routing: IntentRouting) -> Result<(), RuntimeError> {
+        let embedding = match routing {
+            IntentRouting::Embedding => Some(Vector::current().embed(&pattern.description())?),
+            IntentRouting::Model => None,
+        };
+
+        self.handlers.push(IntentHandler {
+            pattern,
+            function: function.to_string(),
+            parameters,
+            embedding,
+        });
+
+        Ok(())
+    }
+
+    /// Set the fallback handler, replacing the previous one
+    pub fn set_fallback(&mut self, function: &str) {
+        self.fallback = Some(function.to_string());
+    }
+
+    /// Get the handlers
+    pub fn handlers(&self) -> &[IntentHandler] {
+        &self.handlers
+    }
+
+    /// Get the name of the fallback handler function, if any
+    pub fn fallback(&self) -> Option<&str> {
+        self.fallback.as_deref()
+    }
+
+    /// Check if no handlers have been declared, including a fallback
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty() && self.fallback.is_none()
+    }
+
+    /// Choose the handler for an utterance
+    ///
+    /// An utterance that matches a pattern exactly goes to its handler with a
+    /// confidence of 1.0. Otherwise the handler is chosen as configured, and
+    /// the confidence is left for the caller to check. Returns `None` if no
+    /// handler can be chosen with all of its slots filled in.
+    pub fn route(&self, utterance: &str, routing: IntentRouting, nlp: &NLP) -> Result<Option<IntentMatch>, RuntimeError> {
+        for handler in &self.handlers {
+            if let Some(slots) = handler.pattern.match_exact(utterance) {
+                return Ok(Some(IntentMatch {
+                    function: handler.function.clone(),
+                    confidence: 1.0,
+                    slots,
+                }));
+            }
+        }
+
+        if self.handlers.is_empty() {
+            return Ok(None);
+        }
+
+        match routing {
+            IntentRouting::Embedding => self.route_by_embedding(utterance),
+            IntentRouting::Model => self.route_by_model(utterance, nlp),
+        }
+    }
+
+    /// Choose the most similar handler whose slots can be filled in
+    fn route_by_embedding(&self, utterance: &str) -> Result<Option<IntentMatch>, RuntimeError> {
+        let vector = Vector::current();
+        let embedding = vector.embed(utterance)?;
+        let mut scored = Vec::new();
+
+        for handler in &self.handlers {
+            let similarity = match &handler.embedding {
+                Some(pattern) => vector.similarity(&embedding, pattern),
+                None => vector.similarity(&embedding, &vector.embed(&handler.pattern.description())?),
+            };
+
+            scored.push((similarity, handler));
+        }
+
+        // The sort is stable, so ties go to the handler declared first
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        Ok(scored.into_iter().find_map(|(confidence, handler)| {
+            handler.extract(utterance).map(|slots| IntentMatch {
+                function: handler.function.clone(),
+                confidence,
+                slots,
+            })
+        }))
+    }
+
+    /// Ask the language model backend to choose the handler and fill in its slots
+    fn route_by_model(&self, utterance: &str, nlp: &NLP) -> Result<Option<IntentMatch>, RuntimeError> {
+        let patterns: Vec<&IntentPattern> = self.handlers.iter().map(|handler| &handler.pattern).collect();
+
+        let mut choice = match nlp.choose_intent(utterance, &patterns)? {
+            Some(choice) => choice,
+            None => return Ok(None),
+        };
+
+        let handler = match self.handlers.get(choice.index) {
+            Some(handler) => handler,
+            None => return Ok(None),
+        };
+
+        // Every slot must be filled in
+        let mut slots = Vec::new();
+
+        for name in handler.pattern.slots() {
+            match choice.slots.remove(name) {
+                Some(value) if !value.trim().is_empty() => slots.push((name.to_string(), value.trim().to_string())),
+                _ => return Ok(None),
+            }
+        }
+
+        Ok(Some(IntentMatch {
+            function: handler.function.clone(),
+            confidence: choice.confidence.clamp(0.0, 1.0),
+            slots,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slots(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_slot_value() {
+        assert_eq!(slot_value("1,200", "Int"), Some(Value::Int(1200)));
+        assert_eq!(slot_value("forty", "Int"), None);
+        assert_eq!(slot_value("2.5", "Float"), Some(Value::Float(2.5)));
+        assert_eq!(slot_value("Yes", "Bool"), Some(Value::Bool(true)));
+        assert_eq!(slot_value("Paris", "String"), Some(Value::String("Paris".to_string())));
+    }
+
+    #[test]
+    fn test_intent_router_route() {
+        let mut router = IntentRouter::new();
+        assert!(router.is_empty());
+
+        let refund = vec![("order_id".to_string(), "Int".to_string())];
+        let tracking = vec![("order_id".to_string(), "String".to_string())];
+        router.add_handler(IntentPattern::parse("refund order {order_id}").unwrap(), "handleRefund", refund, IntentRouting::Embedding).unwrap();
+        router.add_handler(IntentPattern::parse("track order {order_id}").unwrap(), "handleTracking", tracking, IntentRouting::Embedding).unwrap();
+        assert!(router.handlers().iter().all(|handler| handler.embedding.is_some()));
+        router.set_fallback("handleUnknown");
+        assert_eq!(router.fallback(), Some("handleUnknown"));
+
+        let nlp = NLP::new();
+
+        let matched = router.route("track order 7", IntentRouting::Embedding, &nlp).unwrap().unwrap();
+        assert_eq!(matched.function, "handleTracking");
+        assert_eq!(matched.confidence, 1.0);
+        assert_eq!(matched.slots, slots(&[("order_id", "7")]));
+
+        // The mock backend chooses the pattern with the most words in common
+        let matched = router.route("please refund my order 42", IntentRouting::Model, &nlp).unwrap().unwrap();
+        assert_eq!(matched.function, "handleRefund");
+        assert_eq!(matched.slots, slots(&[("order_id", "42")]));
+
+        let matched = router.route("where is order 42", IntentRouting::Embedding, &nlp).unwrap().unwrap();
+        assert_eq!(matched.slots, slots(&[("order_id", "42")]));
+        assert!(matched.confidence < 1.0);
+
+        // Slots are bounded by the types of their parameters
+        let matched = router.route("I'd like a refund for order 42, please", IntentRouting::Embedding, &nlp).unwrap().unwrap();
+        assert_eq!(matched.function, "handleRefund");
+        assert_eq!(matched.slots, slots(&[("order_id", "42")]));
+
+        assert_eq!(router.route("hello there", IntentRouting::Model, &nlp).unwrap(), None);
+        assert_eq!(router.route("hello there", IntentRouting::Embedding, &nlp).unwrap(), None);
+    }
+}
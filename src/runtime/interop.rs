@@ -4,17 +4,30 @@
 //! which allows interoperability with other languages and systems.
 
 use std::collections::HashMap;
-use std::process::Command;
+use std::io::Read;
+use std::process::{Command, Stdio};
+#[cfg(feature = "python_interop")]
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 use crate::Value;
+use super::cancel::CancellationToken;
 use super::error::RuntimeError;
+#[cfg(feature = "python_interop")]
+use super::python::PythonRuntime;
 
 /// A foreign function
 type ForeignFunction = fn(Vec<Value>) -> Result<Value, RuntimeError>;
 
 /// An interoperability manager
+#[derive(Debug, Clone)]
 pub struct Interop {
     /// The registered foreign functions
     functions: HashMap<String, ForeignFunction>,
+    
+    /// The embedded Python interpreter, shared with clones of the manager
+    #[cfg(feature = "python_interop")]
+    python: Arc<PythonRuntime>,
 }
 
 impl Interop {
@@ -22,6 +35,20 @@ impl Interop {
     pub fn new() -> Self {
         Self {
             functions: HashMap::new(),
+            #[cfg(feature = "python_interop")]
+            python: Arc::new(PythonRuntime::new()),
+        }
+    }
+    
+    /// Create a manager for a parallel path
+    ///
+    /// The fork has the same foreign functions, and a Python interpreter
+    /// with its own copy of the globals.
+    pub fn fork(&self) -> Self {
+        Self {
+            functions: self.functions.clone(),
+            #[cfg(feature = "python_interop")]
+            python: Arc::new(self.python.fork()),
         }
     }
     
@@ -48,11 +75,15 @@ impl Interop {
     }
     
     /// Execute a shell command
+    ///
+    /// The command is killed if execution is cancelled while it runs.
     pub fn execute_command(&self, command: &str, arguments: Vec<&str>) -> Result<Value, RuntimeError> {
-        // Execute the command
-        let output = Command::new(command)
+        // Start the command
+        let mut child = Command::new(command)
             .args(arguments)
-            .output()
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
             .map_err(|e| {
                 RuntimeError::new(
                     &format!("Failed to execute command: {}", e),
@@ -60,15 +91,44 @@ impl Interop {
                 )
             })?;
         
+        // Read the output on separate threads so the command never blocks on a full pipe
+        let stdout = Self::read_pipe(child.stdout.take());
+        let stderr = Self::read_pipe(child.stderr.take());
+        
+        // Wait for the command to finish, checking for cancellation
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) => {}
+                Err(e) => {
+                    return Err(RuntimeError::new(
+                        &format!("Failed to wait for command: {}", e),
+                        crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+                    ));
+                }
+            }
+            
+            if let Err(error) = CancellationToken::check_current() {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(error);
+            }
+            
+            thread::sleep(Duration::from_millis(10));
+        };
+        
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        
         // Check if the command was successful
-        if output.status.success() {
+        if status.success() {
             // Convert the output to a string
-            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+            let stdout = String::from_utf8_lossy(&stdout).to_string();
             
             Ok(Value::String(stdout))
         } else {
             // Convert the error output to a string
-            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            let stderr = String::from_utf8_lossy(&stderr).to_string();
             
             Err(RuntimeError::new(
                 &format!("Command failed: {}", stderr),
@@ -77,7 +137,90 @@ impl Interop {
         }
     }
     
+    /// Read a pipe to the end on a separate thread
+    fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buffer);
+            }
+            
+            buffer
+        })
+    }
+    
+    /// Evaluate a Python expression
+    #[cfg(feature = "python_interop")]
+    pub fn python_eval(&self, code: &str) -> Result<Value, RuntimeError> {
+        self.python.eval(code)
+    }
+    
+    /// Evaluate a Python expression
+    ///
+    /// Evaluating code needs the embedded interpreter of the `python_interop` feature.
+    #[cfg(not(feature = "python_interop"))]
+    pub fn python_eval(&self, _code: &str) -> Result<Value, RuntimeError> {
+        Err(RuntimeError::feature_disabled("python_interop", crate::utils::SourceLocation::new(0, 0, 0, 0, "")))
+    }
+    
+    /// Execute Python statements, keeping what they define for later calls
+    #[cfg(feature = "python_interop")]
+    pub fn python_exec(&self, code: &str) -> Result<Value, RuntimeError> {
+        self.python.exec(code).map(|_| Value::Bool(true))
+    }
+    
+    /// Execute Python statements
+    ///
+    /// Executing code needs the embedded interpreter of the `python_interop` feature.
+    #[cfg(not(feature = "python_interop"))]
+    pub fn python_exec(&self, _code: &str) -> Result<Value, RuntimeError> {
+        Err(RuntimeError::feature_disabled("python_interop", crate::utils::SourceLocation::new(0, 0, 0, 0, "")))
+    }
+    
+    /// Import a Python module, keeping it for later calls
+    #[cfg(feature = "python_interop")]
+    pub fn python_import(&self, module: &str) -> Result<Value, RuntimeError> {
+        self.python.import(module).map(|_| Value::String(module.to_string()))
+    }
+    
+    /// Import a Python module
+    ///
+    /// Modules are only kept by the embedded interpreter of the `python_interop` feature.
+    #[cfg(not(feature = "python_interop"))]
+    pub fn python_import(&self, _module: &str) -> Result<Value, RuntimeError> {
+        Err(RuntimeError::feature_disabled("python_interop", crate::utils::SourceLocation::new(0, 0, 0, 0, "")))
+    }
+    
+    /// Call a Python function by its dotted path, such as `math.sqrt`
+    #[cfg(feature = "python_interop")]
+    pub fn python_call(&self, function: &str, arguments: Vec<Value>, keywords: HashMap<String, Value>) -> Result<Value, RuntimeError> {
+        self.python.call(function, &arguments, &keywords)
+    }
+    
+    /// Call a Python function by its dotted path, such as `math.sqrt`
+    ///
+    /// Without the `python_interop` feature, the function runs in a `python`
+    /// subprocess, which does not support keyword arguments. A path without
+    /// a module names a builtin function.
+    #[cfg(not(feature = "python_interop"))]
+    pub fn python_call(&self, function: &str, arguments: Vec<Value>, keywords: HashMap<String, Value>) -> Result<Value, RuntimeError> {
+        if !keywords.is_empty() {
+            return Err(RuntimeError::feature_disabled("python_interop", crate::utils::SourceLocation::new(0, 0, 0, 0, "")));
+        }
+        
+        let (module, function) = function.rsplit_once('.').unwrap_or(("builtins", function));
+        self.call_python_function(module, function, arguments)
+    }
+    
     /// Call a Python function
+    #[cfg(feature = "python_interop")]
+    pub fn call_python_function(&self, module: &str, function: &str, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        self.python.call(&format!("{}.{}", module, function), &arguments, &HashMap::new())
+    }
+    
+    /// Call a Python function
+    #[cfg(not(feature = "python_interop"))]
     pub fn call_python_function(&self, module: &str, function: &str, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
         // Convert the arguments to Python code
         let args = arguments
@@ -136,6 +279,7 @@ impl Interop {
     }
     
     /// Convert a value to Python code
+    #[cfg_attr(feature = "python_interop", allow(dead_code))]
     fn value_to_python(&self, value: &Value) -> Result<String, RuntimeError> {
         match value {
             Value::Void => Ok("None".to_string()),
@@ -164,6 +308,7 @@ impl Interop {
                 
                 Ok(format!("{{{}}}", items))
             }
+            Value::Prob { value, .. } => self.value_to_python(value),
             _ => Err(RuntimeError::new(
                 &format!("Cannot convert value to Python: {:?}", value),
                 crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
@@ -200,6 +345,7 @@ impl Interop {
                 
                 Ok(format!("{{{}}}", items))
             }
+            Value::Prob { value, .. } => self.value_to_javascript(value),
             _ => Err(RuntimeError::new(
                 &format!("Cannot convert value to JavaScript: {:?}", value),
                 crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
@@ -208,6 +354,7 @@ impl Interop {
     }
     
     /// Convert Python output to a value
+    #[cfg_attr(feature = "python_interop", allow(dead_code))]
     fn python_to_value(&self, output: &str) -> Result<Value, RuntimeError> {
         // Parse the Python output
         let output = output.trim();
@@ -384,6 +531,25 @@ mod tests {
         assert_eq!(result.unwrap_err().message, "Unknown foreign function: 'test'");
     }
     
+    #[test]
+    #[cfg(unix)]
+    fn test_interop_execute_command_cancelled() {
+        let interop = Interop::new();
+        
+        let result = interop.execute_command("echo", vec!["hello"]).unwrap();
+        assert_eq!(result, Value::String("hello\n".to_string()));
+        
+        // A cancelled command is killed instead of running to completion
+        let token = CancellationToken::new().with_deadline(Duration::from_millis(50));
+        let _guard = token.enter();
+        
+        let start_time = std::time::Instant::now();
+        let error = interop.execute_command("sleep", vec!["10"]).unwrap_err();
+        
+        assert_eq!(error.message, "Deadline of 50ms exceeded");
+        assert!(start_time.elapsed() < Duration::from_secs(5));
+    }
+    
     #[test]
     fn test_interop_value_to_python() {
         let interop = Interop::new();
@@ -0,0 +1,799 @@
+//! LLM module for the LLM.lang runtime
+//!
+//! This module provides the language model backends used by the natural
+//! language processor, including a backend for servers that implement the
+//! OpenAI API.
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use crate::utils::SourceLocation;
+use super::cancel::CancellationToken;
+use super::error::RuntimeError;
+use super::vector::VectorEmbedding;
+
+/// The model requested from OpenAI-compatible servers when none is given
+pub const DEFAULT_MODEL: &str = "gpt-4o-mini";
+
+/// The embedding model requested from OpenAI-compatible servers when none is given
+pub const DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+
+/// How long to wait for an OpenAI-compatible server to respond, in seconds
+pub const REQUEST_TIMEOUT: u64 = 120;
+
+/// How often a request waiting for a server checks whether it has been cancelled
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A message of a chat conversation
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    /// The role of the author: `system`, `user` or `assistant`
+    pub role: String,
+
+    /// The content of the message
+    pub content: String,
+}
+
+impl ChatMessage {
+    /// Create a new chat message
+    pub fn new(role: &str, content: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    /// Create a system message, which instructs the model
+    pub fn system(content: &str) -> Self {
+        Self::new("system", content)
+    }
+
+    /// Create a user message
+    pub fn user(content: &str) -> Self {
+        Self::new("user", content)
+    }
+
+    /// Create an assistant message, which is a reply of the model
+    pub fn assistant(content: &str) -> Self {
+        Self::new("assistant", content)
+    }
+}
+
+/// A language model backend
+///
+/// The natural language processor sends all of its tasks to a backend.
+pub trait LlmBackend: fmt::Debug + Send + Sync {
+    /// Identify the backend and its model, to key cached responses
+    ///
+    /// The name should change whenever the responses could, such as when
+    /// another model is used, so that responses recorded before are not
+    /// replayed for it.
+    fn name(&self) -> String;
+
+    /// Complete a prompt
+    fn complete(&self, prompt: &str) -> Result<String, RuntimeError>;
+
+    /// Complete a prompt, passing the completion to `chunk` as it is generated
+    ///
+    /// The backend stops generating when `chunk` returns false. Defaults to
+    /// completing the prompt and passing the whole completion as one chunk.
+    fn complete_stream(&self, prompt: &str, chunk: &mut dyn FnMut(&str) -> bool) -> Result<(), RuntimeError> {
+        let completion = self.complete(prompt)?;
+        chunk(&completion);
+
+        Ok(())
+    }
+
+    /// Reply to a conversation
+    fn chat(&self, messages: &[ChatMessage]) -> Result<String, RuntimeError>;
+
+    /// Create a vector embedding from text
+    fn embed(&self, text: &str) -> Result<VectorEmbedding, RuntimeError>;
+
+    /// Create vector embeddings from several texts at once
+    ///
+    /// Defaults to embedding the texts one at a time. Backends that can
+    /// embed a batch in one request override this.
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<VectorEmbedding>, RuntimeError> {
+        texts.iter().map(|text| self.embed(text)).collect()
+    }
+
+    /// Score how well text fits each of the categories
+    ///
+    /// Returns one non-negative score per category, which are all zero when no
+    /// category fits.
+    fn classify(&self, text: &str, categories: &[String]) -> Result<Vec<f64>, RuntimeError>;
+}
+
+/// A backend for servers that implement the OpenAI chat completions and embeddings API
+///
+/// Prompts are completed through the chat completions endpoint, and texts are
+/// classified by asking the model to name a category. Only `http://` URLs are
+/// supported, which covers local model servers and proxies.
+#[derive(Clone)]
+pub struct OpenAiBackend {
+    /// The host and port of the server
+    host: String,
+
+    /// The path the API endpoints are relative to
+    path: String,
+
+    /// The model to request completions from
+    model: String,
+
+    /// The model to request embeddings from
+    embedding_model: String,
+
+    /// The key sent as a bearer token, if any
+    api_key: Option<String>,
+}
+
+impl OpenAiBackend {
+    /// Create a backend for the server at a base URL, such as `http://localhost:8080/v1`
+    pub fn new(base_url: &str, model: &str) -> Result<Self, RuntimeError> {
+        let address = base_url.strip_prefix("http://").ok_or_else(|| {
+            RuntimeError::new(
+                &format!("Unsupported language model URL: {} (only http:// URLs are supported)", base_url),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )
+        })?;
+
+        let (host, path) = match address.find('/') {
+            Some(index) => (&address[..index], address[index..].trim_end_matches('/')),
+            None => (address, ""),
+        };
+
+        if host.is_empty() {
+            return Err(RuntimeError::new(
+                &format!("Invalid language model URL: {}", base_url),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            path: path.to_string(),
+            model: model.to_string(),
+            embedding_model: DEFAULT_EMBEDDING_MODEL.to_string(),
+            api_key: None,
+        })
+    }
+
+    /// Request embeddings from a model other than `DEFAULT_EMBEDDING_MODEL`
+    pub fn with_embedding_model(mut self, embedding_model: &str) -> Self {
+        self.embedding_model = embedding_model.to_string();
+        self
+    }
+
+    /// Send a key as a bearer token with every request
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+
+    /// Get the base URL of the server
+    pub fn base_url(&self) -> String {
+        format!("http://{}{}", self.host, self.path)
+    }
+
+    /// Get the model completions are requested from
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Get the model embeddings are requested from
+    pub fn embedding_model(&self) -> &str {
+        &self.embedding_model
+    }
+
+    /// Create the error for a request to an endpoint that failed
+    fn request_error(&self, endpoint: &str, error: &dyn fmt::Display) -> RuntimeError {
+        RuntimeError::new(
+            &format!("Language model request to {}{} failed: {}", self.base_url(), endpoint, error),
+            SourceLocation::new(0, 0, 0, 0, ""),
+        )
+    }
+
+    /// Create the error for a read from the server that failed, which is the cancellation if it was cancelled
+    fn read_error(&self, endpoint: &str, error: &io::Error) -> RuntimeError {
+        match CancellationToken::check_current() {
+            Err(cancelled) => cancelled,
+            Ok(()) => self.request_error(endpoint, error),
+        }
+    }
+
+    /// Create the error for a response with a status other than success
+    fn status_error(&self, endpoint: &str, status: u16, body: &[u8]) -> RuntimeError {
+        // Servers describe errors as {"error": {"message": ...}}
+        let message = serde_json::from_slice::<serde_json::Value>(body).ok()
+            .and_then(|json| json["error"]["message"].as_str().map(str::to_string))
+            .unwrap_or_else(|| String::from_utf8_lossy(body).trim().to_string());
+
+        self.request_error(endpoint, &format!("status {}: {}", status, message))
+    }
+
+    /// Send a JSON request to an endpoint and return the connection to read the response from
+    fn send(&self, endpoint: &str, body: &serde_json::Value, accept: &str) -> Result<Connection, RuntimeError> {
+        CancellationToken::check_current()?;
+
+        let request_error = |e: &dyn fmt::Display| self.request_error(endpoint, e);
+
+        let address = if self.host.contains(':') {
+            self.host.clone()
+        } else {
+            format!("{}:80", self.host)
+        };
+
+        let mut stream = TcpStream::connect(&address).map_err(|e| request_error(&e))?;
+        stream.set_read_timeout(Some(POLL_INTERVAL)).map_err(|e| request_error(&e))?;
+        stream.set_write_timeout(Some(Duration::from_secs(REQUEST_TIMEOUT))).map_err(|e| request_error(&e))?;
+
+        // The server closes the connection after responding, so the whole response can be read
+        let body = body.to_string();
+        let mut request = format!(
+            "POST {}{} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nAccept: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.path, endpoint, self.host, accept, body.len()
+        );
+
+        if let Some(api_key) = &self.api_key {
+            request.push_str(&format!("Authorization: Bearer {}\r\n", api_key));
+        }
+
+        request.push_str("\r\n");
+        request.push_str(&body);
+
+        stream.write_all(request.as_bytes()).map_err(|e| request_error(&e))?;
+
+        Ok(Connection::new(stream))
+    }
+
+    /// Send a JSON request to an endpoint and parse the JSON response
+    fn post(&self, endpoint: &str, body: &serde_json::Value) -> Result<serde_json::Value, RuntimeError> {
+        let mut stream = self.send(endpoint, body, "application/json")?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).map_err(|e| self.read_error(endpoint, &e))?;
+
+        let (status, body) = parse_response(&response).ok_or_else(|| self.request_error(endpoint, &"malformed HTTP response"))?;
+
+        if !(200..300).contains(&status) {
+            return Err(self.status_error(endpoint, status, &body));
+        }
+
+        serde_json::from_slice(&body).map_err(|e| self.request_error(endpoint, &format!("invalid JSON response: {}", e)))
+    }
+
+    /// Send a streaming chat completion request and pass each piece of the reply to `chunk`
+    ///
+    /// The server sends the reply as server-sent events, one `data:` line per
+    /// piece, and ends it with `data: [DONE]`. The response is read as it
+    /// arrives, and the connection is closed as soon as `chunk` returns false.
+    fn stream_chat(&self, messages: &[ChatMessage], chunk: &mut dyn FnMut(&str) -> bool) -> Result<(), RuntimeError> {
+        let endpoint = "/chat/completions";
+        let request_error = |e: &dyn fmt::Display| self.request_error(endpoint, e);
+
+        let messages: Vec<serde_json::Value> = messages.iter()
+            .map(|message| json!({ "role": message.role, "content": message.content }))
+            .collect();
+
+        let stream = self.send(endpoint, &json!({
+            "model": self.model,
+            "messages": messages,
+            "stream": true,
+        }), "text/event-stream")?;
+
+        let mut reader = BufReader::new(stream);
+        let mut head = String::new();
+
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).map_err(|e| self.read_error(endpoint, &e))? == 0 || line.trim().is_empty() {
+                break;
+            }
+            head.push_str(&line);
+        }
+
+        let status: u16 = head.lines().next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| request_error(&"malformed HTTP response"))?;
+
+        let chunked = head.lines().any(|line| {
+            let line = line.to_lowercase();
+            line.starts_with("transfer-encoding:") && line.contains("chunked")
+        });
+
+        let body: Box<dyn BufRead> = if chunked {
+            Box::new(BufReader::new(ChunkedReader::new(reader)))
+        } else {
+            Box::new(reader)
+        };
+
+        if !(200..300).contains(&status) {
+            let mut body = body;
+            let mut response = Vec::new();
+            body.read_to_end(&mut response).map_err(|e| self.read_error(endpoint, &e))?;
+
+            return Err(self.status_error(endpoint, status, &response));
+        }
+
+        for line in body.lines() {
+            CancellationToken::check_current()?;
+
+            let line = line.map_err(|e| self.read_error(endpoint, &e))?;
+            let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+                continue;
+            };
+
+            if data == "[DONE]" {
+                break;
+            }
+
+            let event: serde_json::Value = serde_json::from_str(data)
+                .map_err(|e| request_error(&format!("invalid JSON event: {}", e)))?;
+
+            if let Some(message) = event["error"]["message"].as_str() {
+                return Err(request_error(&message));
+            }
+
+            // The first and last events usually carry a role or finish reason instead of content
+            let content = event["choices"][0]["delta"]["content"].as_str().unwrap_or("");
+
+            if !content.is_empty() && !chunk(content) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for OpenAiBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OpenAiBackend")
+            .field("base_url", &self.base_url())
+            .field("model", &self.model)
+            .field("embedding_model", &self.embedding_model)
+            .field("api_key", &self.api_key.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl LlmBackend for OpenAiBackend {
+    fn name(&self) -> String {
+        // The server is left out, so responses recorded against one server replay against another
+        format!("openai/{}/{}", self.model, self.embedding_model)
+    }
+
+    fn complete(&self, prompt: &str) -> Result<String, RuntimeError> {
+        self.chat(&[ChatMessage::user(prompt)])
+    }
+
+    fn complete_stream(&self, prompt: &str, chunk: &mut dyn FnMut(&str) -> bool) -> Result<(), RuntimeError> {
+        self.stream_chat(&[ChatMessage::user(prompt)], chunk)
+    }
+
+    fn chat(&self, messages: &[ChatMessage]) -> Result<String, RuntimeError> {
+        let messages: Vec<serde_json::Value> = messages.iter()
+            .map(|message| json!({ "role": message.role, "content": message.content }))
+            .collect();
+
+        let response = self.post("/chat/completions", &json!({
+            "model": self.model,
+            "messages": messages,
+        }))?;
+
+        response["choices"][0]["message"]["content"].as_str()
+            .map(str::to_string)
+            .ok_or_else(|| RuntimeError::new(
+                &format!("Unexpected chat completion response: {}", response),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ))
+    }
+
+    fn embed(&self, text: &str) -> Result<VectorEmbedding, RuntimeError> {
+        let mut embeddings = self.embed_batch(&[text])?;
+        Ok(embeddings.remove(0))
+    }
+
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<VectorEmbedding>, RuntimeError> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let response = self.post("/embeddings", &json!({
+            "model": self.embedding_model,
+            "input": texts,
+        }))?;
+
+        let unexpected = || RuntimeError::new(
+            &format!("Unexpected embedding response: {}", response),
+            SourceLocation::new(0, 0, 0, 0, ""),
+        );
+
+        // The embeddings are matched to the texts by index, in case they arrive out of order
+        let mut embeddings = vec![None; texts.len()];
+
+        for (position, item) in response["data"].as_array().ok_or_else(unexpected)?.iter().enumerate() {
+            let index = item["index"].as_u64().map_or(position, |index| index as usize);
+            let embedding = item["embedding"].as_array()
+                .and_then(|values| values.iter().map(serde_json::Value::as_f64).collect::<Option<Vec<f64>>>())
+                .ok_or_else(unexpected)?;
+
+            *embeddings.get_mut(index).ok_or_else(unexpected)? = Some(embedding);
+        }
+
+        embeddings.into_iter().collect::<Option<Vec<_>>>().ok_or_else(unexpected)
+    }
+
+    fn classify(&self, text: &str, categories: &[String]) -> Result<Vec<f64>, RuntimeError> {
+        if categories.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let instruction = format!(
+            "Classify the user's message into exactly one of these categories: {}. Reply with the category only.",
+            categories.join(", ")
+        );
+        let reply = self.chat(&[ChatMessage::system(&instruction), ChatMessage::user(text)])?;
+        let reply = reply.trim().trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+
+        // Prefer a category the reply names exactly over one it merely mentions
+        let exact = categories.iter().any(|category| category.to_lowercase() == reply);
+
+        Ok(categories.iter()
+            .map(|category| {
+                let category = category.to_lowercase();
+                let fits = if exact { category == reply } else { reply.contains(&category) };
+                if fits { 1.0 } else { 0.0 }
+            })
+            .collect())
+    }
+}
+
+/// A connection to a server that stops waiting for the response when the request is cancelled
+///
+/// Reads give up every `POLL_INTERVAL` to check the current token of the
+/// thread, and fail once it is cancelled or has expired, or once the server has
+/// sent nothing for `REQUEST_TIMEOUT` seconds.
+struct Connection {
+    /// The connection to the server
+    stream: TcpStream,
+
+    /// When the server last sent anything
+    last_read: Instant,
+}
+
+impl Connection {
+    /// Wrap a connection whose read timeout is `POLL_INTERVAL`
+    fn new(stream: TcpStream) -> Self {
+        Self { stream, last_read: Instant::now() }
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if CancellationToken::check_current().is_err() {
+                return Err(io::Error::other("the request was cancelled"));
+            }
+
+            match self.stream.read(buf) {
+                Ok(read) => {
+                    self.last_read = Instant::now();
+                    return Ok(read);
+                }
+                Err(error) if matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    if self.last_read.elapsed() >= Duration::from_secs(REQUEST_TIMEOUT) {
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, "the server did not respond in time"));
+                    }
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+/// Split an HTTP response into its status code and body
+fn parse_response(response: &[u8]) -> Option<(u16, Vec<u8>)> {
+    let end = response.windows(4).position(|window| window == b"\r\n\r\n")?;
+    let head = String::from_utf8_lossy(&response[..end]);
+    let body = &response[end + 4..];
+
+    let mut lines = head.lines();
+    let status = lines.next()?.split_whitespace().nth(1)?.parse().ok()?;
+
+    let chunked = lines.any(|line| {
+        let line = line.to_lowercase();
+        line.starts_with("transfer-encoding:") && line.contains("chunked")
+    });
+
+    if chunked {
+        Some((status, decode_chunked(body)?))
+    } else {
+        Some((status, body.to_vec()))
+    }
+}
+
+/// Reads a body sent with chunked transfer encoding as it arrives
+struct ChunkedReader<R: BufRead> {
+    /// The connection the body is read from
+    inner: R,
+
+    /// The number of bytes left in the current chunk
+    remaining: usize,
+
+    /// Whether the last chunk has been read
+    done: bool,
+}
+
+impl<R: BufRead> ChunkedReader<R> {
+    /// Create a reader for the body that follows the head of a response
+    fn new(inner: R) -> Self {
+        Self { inner, remaining: 0, done: false }
+    }
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.remaining == 0 {
+            // Skip the line break that ends the previous chunk, then read the size of the next
+            let mut line = String::new();
+            while line.trim().is_empty() {
+                line.clear();
+                if self.inner.read_line(&mut line)? == 0 {
+                    self.done = true;
+                    return Ok(0);
+                }
+            }
+
+            let size = line.split(';').next().unwrap_or("").trim();
+            self.remaining = usize::from_str_radix(size, 16).map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, format!("invalid chunk size: {}", size))
+            })?;
+
+            if self.remaining == 0 {
+                self.done = true;
+                return Ok(0);
+            }
+        }
+
+        let length = buf.len().min(self.remaining);
+        let read = self.inner.read(&mut buf[..length])?;
+        self.remaining -= read;
+
+        Ok(read)
+    }
+}
+
+/// Decode a body sent with chunked transfer encoding
+fn decode_chunked(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+
+    loop {
+        let end = body.windows(2).position(|window| window == b"\r\n")?;
+        let size = String::from_utf8_lossy(&body[..end]);
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+
+        if size == 0 {
+            return Some(decoded);
+        }
+
+        let chunk = body.get(end + 2..end + 2 + size)?;
+        decoded.extend_from_slice(chunk);
+        body = body.get(end + 4 + size..)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    /// Serve one canned response per request and return the requests received
+    fn serve(responses: Vec<(u16, String)>) -> (String, JoinHandle<Vec<(String, String)>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v1", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+
+            for (status, body) in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = std::io::BufReader::new(stream);
+
+                // Read the head, then as much body as announced
+                let mut head = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    head.push_str(&line);
+                }
+
+                let length = head.lines()
+                    .find_map(|line| line.strip_prefix("Content-Length: "))
+                    .map_or(0, |length| length.trim().parse().unwrap());
+                let mut request = vec![0; length];
+                reader.read_exact(&mut request).unwrap();
+                requests.push((head, String::from_utf8(request).unwrap()));
+
+                let response = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    status, body.len(), body
+                );
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+
+            requests
+        });
+
+        (url, handle)
+    }
+
+    fn chat_response(content: &str) -> (u16, String) {
+        (200, json!({ "choices": [{ "message": { "role": "assistant", "content": content } }] }).to_string())
+    }
+
+    #[test]
+    fn test_openai_backend_chat() {
+        let (url, server) = serve(vec![chat_response("Hello there!"), chat_response("Sports.")]);
+        let backend = OpenAiBackend::new(&url, "local-model").unwrap().with_api_key("secret");
+
+        let reply = backend.chat(&[ChatMessage::system("Be brief."), ChatMessage::user("Hi")]).unwrap();
+        assert_eq!(reply, "Hello there!");
+
+        let categories = vec!["politics".to_string(), "sports".to_string()];
+        assert_eq!(backend.classify("the team won", &categories).unwrap(), vec![0.0, 1.0]);
+
+        let requests = server.join().unwrap();
+        let (head, body) = &requests[0];
+        assert!(head.starts_with("POST /v1/chat/completions HTTP/1.1"));
+        assert!(head.contains("Authorization: Bearer secret"));
+
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["model"], "local-model");
+        assert_eq!(body["messages"][0], json!({ "role": "system", "content": "Be brief." }));
+        assert_eq!(body["messages"][1], json!({ "role": "user", "content": "Hi" }));
+
+        // The secret is not shown when debugging
+        assert!(!format!("{:?}", backend).contains("secret"));
+    }
+
+    #[test]
+    fn test_openai_backend_embed_batch() {
+        let (url, server) = serve(vec![
+            (200, json!({ "data": [
+                { "index": 1, "embedding": [0.0, 1.0] },
+                { "index": 0, "embedding": [1.0, 0.0] },
+            ] }).to_string()),
+            (200, json!({ "data": [{ "index": 0, "embedding": [1.0, 0.0] }] }).to_string()),
+        ]);
+        let backend = OpenAiBackend::new(&url, DEFAULT_MODEL).unwrap();
+
+        // The texts are sent in one request, and their embeddings are matched by index
+        assert_eq!(backend.embed_batch(&["hello", "world"]).unwrap(), vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert!(backend.embed_batch(&[]).unwrap().is_empty());
+
+        let error = backend.embed_batch(&["hello", "world"]).unwrap_err();
+        assert!(error.message.starts_with("Unexpected embedding response"));
+
+        let requests = server.join().unwrap();
+        let body: serde_json::Value = serde_json::from_str(&requests[0].1).unwrap();
+        assert_eq!(body["input"], json!(["hello", "world"]));
+    }
+
+    #[test]
+    fn test_openai_backend_embed_and_errors() {
+        let (url, server) = serve(vec![
+            (200, json!({ "data": [{ "embedding": [0.5, -0.25, 1.0] }] }).to_string()),
+            (401, json!({ "error": { "message": "Invalid API key" } }).to_string()),
+        ]);
+        let backend = OpenAiBackend::new(&format!("{}/", url), DEFAULT_MODEL).unwrap().with_embedding_model("local-embedder");
+        assert_eq!(backend.base_url(), url);
+
+        assert_eq!(backend.embed("hello").unwrap(), vec![0.5, -0.25, 1.0]);
+
+        let error = backend.complete("hello").unwrap_err();
+        assert!(error.to_string().contains("status 401: Invalid API key"));
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].0.starts_with("POST /v1/embeddings HTTP/1.1"));
+        assert!(!requests[0].0.contains("Authorization"));
+
+        // Embeddings are requested from the embedding model, with the texts as an array
+        let body: serde_json::Value = serde_json::from_str(&requests[0].1).unwrap();
+        assert_eq!(body["model"], "local-embedder");
+        assert_eq!(body["input"], json!(["hello"]));
+
+        assert!(OpenAiBackend::new("https://api.openai.com/v1", DEFAULT_MODEL).is_err());
+    }
+
+    #[test]
+    fn test_openai_backend_stream() {
+        let events = |pieces: &[&str]| -> (u16, String) {
+            let mut body: String = pieces.iter()
+                .map(|piece| format!("data: {}\n\n", json!({ "choices": [{ "delta": { "content": piece } }] })))
+                .collect();
+            body.push_str("data: [DONE]\n\n");
+            (200, body)
+        };
+
+        let (url, server) = serve(vec![
+            events(&["Once", " upon", " a time"]),
+            events(&["a", "b", "c"]),
+            (500, json!({ "error": { "message": "Model overloaded" } }).to_string()),
+        ]);
+        let backend = OpenAiBackend::new(&url, "local-model").unwrap();
+
+        let mut chunks = Vec::new();
+        backend.complete_stream("Tell a story", &mut |chunk| { chunks.push(chunk.to_string()); true }).unwrap();
+        assert_eq!(chunks, vec!["Once", " upon", " a time"]);
+
+        // Returning false stops reading the stream
+        let mut chunks = Vec::new();
+        backend.complete_stream("Count", &mut |chunk| { chunks.push(chunk.to_string()); false }).unwrap();
+        assert_eq!(chunks, vec!["a"]);
+
+        let error = backend.complete_stream("Hi", &mut |_| true).unwrap_err();
+        assert!(error.to_string().contains("status 500: Model overloaded"));
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].0.contains("Accept: text/event-stream"));
+        let body: serde_json::Value = serde_json::from_str(&requests[0].1).unwrap();
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["messages"][0], json!({ "role": "user", "content": "Tell a story" }));
+    }
+
+    #[test]
+    fn test_openai_backend_cancel() {
+        // A server that accepts the request but never responds
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let server = thread::spawn(move || listener.accept().unwrap());
+
+        let backend = OpenAiBackend::new(&url, "local-model").unwrap();
+        let token = CancellationToken::new();
+        let _token = token.enter();
+
+        let cancel = token.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            cancel.cancel();
+        });
+
+        let started = Instant::now();
+        let error = backend.complete("hello").unwrap_err();
+        assert_eq!(error.message, "Execution was cancelled");
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        drop(server.join().unwrap());
+    }
+
+    #[test]
+    fn test_chunked_reader() {
+        let body = b"7\r\ndata: a\r\n3\r\nbc\n\r\n0\r\n\r\n";
+        let mut reader = ChunkedReader::new(&body[..]);
+        let mut decoded = String::new();
+        reader.read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, "data: abc\n");
+    }
+
+    #[test]
+    fn test_decode_chunked() {
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\n{\"a\"\r\n3\r\n:1}\r\n0\r\n\r\n";
+        assert_eq!(parse_response(response), Some((200, b"{\"a\":1}".to_vec())));
+    }
+}
@@ -4,13 +4,22 @@
 //! which allows storing and retrieving values based on semantic meaning.
 
 use std::collections::HashMap;
+use std::sync::Arc;
 use crate::Value;
 use super::error::RuntimeError;
+use super::index::top_k;
+use super::vector::Vector;
+
+/// The number of alternatives kept by a steered recall
+pub const RECALL_ALTERNATIVES: usize = 3;
 
 /// A semantic memory manager
+///
+/// The stored values are shared copy-on-write between clones.
+#[derive(Debug, Clone)]
 pub struct Memory {
     /// The stored values
-    values: HashMap<String, Value>,
+    values: Arc<HashMap<String, Value>>,
     
     /// The current memory usage in bytes
     memory_usage: usize,
@@ -20,7 +29,7 @@ impl Memory {
     /// Create a new memory manager
     pub fn new() -> Self {
         Self {
-            values: HashMap::new(),
+            values: Arc::new(HashMap::new()),
             memory_usage: 0,
         }
     }
@@ -34,7 +43,7 @@ impl Memory {
         self.memory_usage += value_size;
         
         // Store the value
-        self.values.insert(key.to_string(), value);
+        Arc::make_mut(&mut self.values).insert(key.to_string(), value);
     }
     
     /// Recall a value
@@ -62,11 +71,56 @@ impl Memory {
         }
     }
     
+    /// Recall the value most similar to the vector applied in a vector engine
+    ///
+    /// The result is a probabilistic value whose confidence is the similarity,
+    /// with the next closest values as alternatives. Without an applied vector,
+    /// this is the same as `recall_most_relevant`.
+    pub fn recall_steered(&self, vector: &Vector) -> Result<Value, RuntimeError> {
+        let Some(target) = vector.get_current_vector() else {
+            return self.recall_most_relevant();
+        };
+        
+        // Rank in key order so that equal scores always resolve the same way
+        let mut keys: Vec<&String> = self.values.keys().collect();
+        keys.sort();
+        
+        let mut scores = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            let embedding = match &self.values[*key] {
+                Value::Vector(v) => v.clone(),
+                other => vector.embed(&crate::stdlib::StdLib::value_to_string(other))?,
+            };
+            
+            scores.push((i, vector.similarity(&embedding, target)));
+        }
+        
+        let mut ranked = top_k(scores, RECALL_ALTERNATIVES + 1)
+            .into_iter()
+            .map(|(i, score)| (self.values[keys[i]].clone(), score));
+        
+        match ranked.next() {
+            Some((value, confidence)) => Ok(Value::prob(value, confidence, ranked.collect())),
+            None => self.recall_most_relevant(),
+        }
+    }
+    
     /// Get the current memory usage in bytes
     pub fn memory_usage(&self) -> usize {
         self.memory_usage
     }
     
+    /// Get all remembered values
+    pub fn entries(&self) -> &HashMap<String, Value> {
+        &self.values
+    }
+    
+    /// Replace all remembered values
+    pub fn restore(&mut self, values: HashMap<String, Value>) {
+        self.memory_usage = values.values().map(|value| self.calculate_value_size(value)).sum();
+        self.values = Arc::new(values);
+    }
+    
     /// Calculate the memory usage of a value in bytes
     fn calculate_value_size(&self, value: &Value) -> usize {
         match value {
@@ -93,6 +147,13 @@ impl Memory {
             Value::Vector(v) => v.len() * 8,
             Value::Function(name) => name.len(),
             Value::Context(name) => name.len(),
+            Value::Prob { value, alternatives, .. } => {
+                let mut size = self.calculate_value_size(value) + 8;
+                for (alternative, _) in alternatives {
+                    size += self.calculate_value_size(alternative) + 8;
+                }
+                size
+            }
         }
     }
 }
@@ -147,6 +208,33 @@ mod tests {
         assert_eq!(error.message, "No values in memory");
     }
     
+    #[test]
+    fn test_memory_recall_steered() {
+        let mut memory = Memory::new();
+        let mut vector = Vector::new();
+        
+        memory.remember("pet", Value::String("a small cat".to_string()));
+        memory.remember("finance", Value::String("the stock market".to_string()));
+        
+        let steering = vector.embed("stock market").unwrap();
+        vector.apply_vector(steering).unwrap();
+        
+        let recalled = memory.recall_steered(&vector).unwrap();
+        assert_eq!(recalled.most_likely(), &Value::String("the stock market".to_string()));
+        assert!(recalled.confidence() > 0.5 && recalled.confidence() <= 1.0);
+        
+        match recalled {
+            Value::Prob { alternatives, .. } => {
+                assert_eq!(alternatives.len(), 1);
+                assert_eq!(alternatives[0].0, Value::String("a small cat".to_string()));
+            }
+            other => panic!("Expected a probabilistic value, got {:?}", other),
+        }
+        
+        let empty = Memory::new();
+        assert_eq!(empty.recall_steered(&vector).unwrap_err().message, "No values in memory");
+    }
+    
     #[test]
     fn test_memory_calculate_value_size() {
         let memory = Memory::new();
@@ -166,5 +254,24 @@ mod tests {
         assert_eq!(memory.calculate_value_size(&Value::Vector(vec![1.0, 2.0, 3.0])), 24);
         assert_eq!(memory.calculate_value_size(&Value::Function("main".to_string())), 4);
         assert_eq!(memory.calculate_value_size(&Value::Context("MainProgram".to_string())), 11);
+        assert_eq!(
+            memory.calculate_value_size(&Value::prob(Value::Int(42), 0.9, vec![(Value::Int(7), 0.1)])),
+            32
+        );
+    }
+    
+    #[test]
+    fn test_memory_restore() {
+        let mut memory = Memory::new();
+        
+        memory.remember("old", Value::Int(42));
+        memory.restore(HashMap::from([
+            ("new".to_string(), Value::String("hello".to_string()))
+        ]));
+        
+        assert!(memory.recall("old").is_err());
+        assert_eq!(memory.recall("new").unwrap(), Value::String("hello".to_string()));
+        assert_eq!(memory.memory_usage(), 5);
+        assert_eq!(memory.entries().len(), 1);
     }
 }
@@ -11,20 +11,53 @@ pub mod vector;
 pub mod parallel;
 pub mod example;
 pub mod nlp;
+pub mod llm;
+pub mod cache;
+pub mod budget;
+pub mod intent;
+pub mod tools;
+pub mod prompt;
+pub mod stream;
+pub mod rag;
+pub mod text;
+pub mod schema;
 pub mod interop;
+#[cfg(feature = "python_interop")]
+pub mod python;
 pub mod modify;
+pub mod snapshot;
+pub mod cancel;
+pub mod index;
+pub mod synthesis;
+#[cfg(test)]
+pub mod testing;
 
 // Re-export commonly used types and functions
 pub use self::engine::Engine;
-pub use self::error::RuntimeError;
+pub use self::error::{RuntimeError, PythonException, RuntimeErrorKind, SchemaMismatch};
 pub use self::memory::Memory;
 pub use self::context::Context;
-pub use self::vector::Vector;
-pub use self::parallel::Parallel;
-pub use self::example::Example;
-pub use self::nlp::NLP;
+pub use self::vector::{EmbeddingProvider, HashEmbedder, LocalModelEmbedder, Vector};
+pub use self::parallel::{Parallel, ParallelTask};
+pub use self::example::{Example, ExampleCheck};
+pub use self::nlp::{MockBackend, NLP};
+pub use self::llm::{ChatMessage, LlmBackend, OpenAiBackend};
+pub use self::cache::{CacheMode, CachedBackend, CachedEmbedder, ResponseCache};
+pub use self::budget::{MeteredBackend, TokenBudget, TokenCounter, TokenUsage};
+pub use self::intent::{IntentChoice, IntentRouter, IntentRouting};
+pub use self::tools::{Tool, ToolCall};
+pub use self::prompt::Prompt;
+pub use self::stream::TextStream;
+pub use self::rag::Chunk;
 pub use self::interop::Interop;
+#[cfg(feature = "python_interop")]
+pub use self::python::PythonRuntime;
 pub use self::modify::Modify;
+pub use self::snapshot::ContextSnapshot;
+pub use self::cancel::CancellationToken;
+pub use self::index::{FlatIndex, HnswIndex, IndexEntry, SearchResult, VectorIndex};
+pub use self::synthesis::Program;
+pub use self::schema::Schema;
 
 /// The runtime environment for the LLM.lang language
 pub struct Runtime {
@@ -93,6 +126,7 @@ mod tests {
             parallel: true,
             vectors: true,
             nlp: true,
+            ..Default::default()
         };
         
         let runtime = Runtime::new(options);
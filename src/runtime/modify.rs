@@ -11,6 +11,7 @@ use super::error::RuntimeError;
 use crate::utils::SourceLocation;
 
 /// A code modifier
+#[derive(Debug, Clone)]
 pub struct Modify {
     /// The AST cache
     ast_cache: HashMap<String, Ast>,
@@ -412,16 +413,89 @@ impl Modify {
                     self.generate_source_recursive(&node.children[0], source, indent)?;
                 }
             }
-            NodeKind::Intent => {
-                source.push_str(&format!("{}intent: ", indent_str));
+            NodeKind::Deadline => {
+                let timeout = node.get_attribute("timeout").ok_or_else(|| {
+                    RuntimeError::missing_attribute("timeout", node.location.clone())
+                })?;
                 
-                // Add expression
+                source.push_str(&format!("{}deadline {}ms ", indent_str, timeout));
+                
+                // Add body
                 if !node.children.is_empty() {
-                    self.generate_source_recursive(&node.children[0], source, 0)?;
+                    self.generate_source_recursive(&node.children[0], source, indent)?;
+                }
+                
+                // Add otherwise block
+                if node.children.len() > 1 {
+                    source.push_str(" otherwise ");
+                    self.generate_source_recursive(&node.children[1], source, indent)?;
+                }
+            }
+            NodeKind::Budget => {
+                let tokens = node.get_attribute("tokens").ok_or_else(|| {
+                    RuntimeError::missing_attribute("tokens", node.location.clone())
+                })?;
+                
+                source.push_str(&format!("{}budget tokens {} ", indent_str, tokens));
+                
+                // Add body
+                if !node.children.is_empty() {
+                    self.generate_source_recursive(&node.children[0], source, indent)?;
+                }
+                
+                // Add otherwise block
+                if node.children.len() > 1 {
+                    source.push_str(" otherwise ");
+                    self.generate_source_recursive(&node.children[1], source, indent)?;
+                }
+            }
+            NodeKind::Intent => {
+                source.push_str(&format!("{}intent", indent_str));
+                
+                // Add the free text after a colon, or the expression
+                if let Some(expression) = node.children.first() {
+                    match expression.get_attribute("value") {
+                        Some(text) if expression.kind == NodeKind::NaturalLanguage => {
+                            source.push_str(&format!(": {}", text));
+                        }
+                        _ => {
+                            source.push(' ');
+                            self.generate_source_recursive(expression, source, 0)?;
+                        }
+                    }
                 }
                 
                 source.push_str(";");
             }
+            NodeKind::IntentHandler => {
+                // Add the pattern, or `otherwise` for the fallback handler
+                match node.get_attribute("pattern") {
+                    Some(pattern) => source.push_str(&format!("{}on intent \"{}\" ", indent_str, pattern)),
+                    None => source.push_str(&format!("{}on intent otherwise ", indent_str)),
+                }
+                
+                // Add the function, which starts on the same line
+                if let Some(function) = node.children.first() {
+                    let mut function_source = String::new();
+                    self.generate_source_recursive(function, &mut function_source, indent)?;
+                    source.push_str(function_source.trim_start());
+                }
+            }
+            NodeKind::Tools => {
+                source.push_str(&format!("{}tools {{\n", indent_str));
+                
+                // Add the functions, each after its description
+                for child in &node.children {
+                    if let Some(description) = child.get_attribute("description") {
+                        source.push_str(&format!("{}    \"{}\"\n", indent_str, description));
+                    }
+                    
+                    self.generate_source_recursive(child, source, indent + 1)?;
+                    source.push('\n');
+                }
+                
+                source.push_str(&format!("{}}}", indent_str));
+            }
             NodeKind::Parallel => {
                 source.push_str(&format!("{}parallel {{\n", indent_str));
                 
@@ -445,7 +519,44 @@ impl Modify {
                 
                 // Add selection strategy
                 let strategy = node.get_attribute("strategy").map_or("all", |v| v).to_string();
-                source.push_str(&format!("{}}} select {};\n", indent_str, strategy));
+                source.push_str(&format!("{}}} select {}", indent_str, strategy));
+                
+                // Add scorer
+                match node.get_attribute("scorer").map(|s| s.as_str()) {
+                    Some("type") => {
+                        let semantic_type = node.get_attribute("scorer_type").map_or("", |v| v);
+                        source.push_str(&format!(" by ~{}~", semantic_type));
+                    }
+                    Some("judge") => {
+                        let criteria = node.get_attribute("criteria").map_or("", |v| v);
+                        source.push_str(&format!(" by judge \"{}\"", criteria));
+                    }
+                    Some("function") => {
+                        source.push_str(" by ");
+                        
+                        if let Some(scorer) = node.children.iter().find(|child| child.kind != NodeKind::Path) {
+                            self.generate_source_recursive(scorer, source, 0)?;
+                        }
+                    }
+                    Some(scorer) => source.push_str(&format!(" by {}", scorer)),
+                    None => {}
+                }
+                
+                // Add merge function
+                if strategy == "merge" {
+                    source.push_str(" with ");
+                    
+                    if let Some(merger) = node.children.iter().find(|child| child.kind != NodeKind::Path) {
+                        self.generate_source_recursive(merger, source, 0)?;
+                    }
+                }
+                
+                // Add timeout
+                if let Some(timeout) = node.get_attribute("timeout") {
+                    source.push_str(&format!(" timeout {}ms", timeout));
+                }
+                
+                source.push_str(";\n");
             }
             NodeKind::Apply => {
                 source.push_str(&format!("{}apply ", indent_str));
@@ -517,6 +628,16 @@ impl Modify {
                     RuntimeError::missing_attribute("operator", node.location.clone())
                 })?;
                 
+                // Property access names the property instead of having a right operand
+                if operator == "." {
+                    let property = node.get_attribute("name").ok_or_else(|| {
+                        RuntimeError::missing_attribute("name", node.location.clone())
+                    })?;
+                    
+                    source.push_str(&format!(".{}", property));
+                    return Ok(());
+                }
+                
                 source.push_str(&format!(" {} ", operator));
                 
                 // Add right operand
@@ -543,6 +664,28 @@ impl Modify {
                     RuntimeError::missing_attribute("type", node.location.clone())
                 })?;
                 
+                // List literals hold their elements as children
+                if typ == "List" {
+                    source.push('[');
+                    
+                    for (i, element) in node.children.iter().enumerate() {
+                        if i > 0 {
+                            source.push_str(", ");
+                        }
+                        
+                        self.generate_source_recursive(element, source, 0)?;
+                    }
+                    
+                    source.push(']');
+                    return Ok(());
+                }
+                
+                // Null literals have no value
+                if typ == "Null" {
+                    source.push_str("null");
+                    return Ok(());
+                }
+                
                 let value = node.get_attribute("value").ok_or_else(|| {
                     RuntimeError::missing_attribute("value", node.location.clone())
                 })?;
@@ -552,10 +695,8 @@ impl Modify {
                         source.push_str(value);
                     }
                     "String" => {
-                        source.push_str(&format!("\"{}\"", value));
-                    }
-                    "Null" => {
-                        source.push_str("null");
+                        // String literals keep their quotes and escapes
+                        source.push_str(value);
                     }
                     _ => {
                         return Err(RuntimeError::new(
@@ -602,6 +743,11 @@ impl Modify {
                 })?;
                 
                 source.push_str(&format!("#\"{}\"#", value));
+                
+                // Add the output type
+                if let Some(typ) = node.get_attribute("type") {
+                    source.push_str(&format!(" as {}", typ));
+                }
             }
             NodeKind::Vector => {
                 // Add name
@@ -616,6 +762,118 @@ impl Modify {
                     self.generate_source_recursive(&node.children[0], source, 0)?;
                 }
             }
+            NodeKind::Transform => {
+                source.push_str(&format!("{}transform ", indent_str));
+                
+                // Add name
+                if let Some(name) = node.get_attribute("name") {
+                    source.push_str(&format!("{}: ", name));
+                }
+                
+                // Add input and output
+                if node.children.len() > 1 {
+                    self.generate_source_recursive(&node.children[0], source, 0)?;
+                    source.push_str(" into ");
+                    self.generate_source_recursive(&node.children[1], source, 0)?;
+                }
+                
+                source.push(';');
+            }
+            NodeKind::TransformApply => {
+                source.push_str("transform ");
+                
+                // Add value
+                if !node.children.is_empty() {
+                    self.generate_source_recursive(&node.children[0], source, 0)?;
+                }
+                
+                // Add name
+                if let Some(name) = node.get_attribute("name") {
+                    source.push_str(&format!(" with {}", name));
+                }
+            }
+            NodeKind::TypeDeclaration => {
+                // Add name
+                let name = node.get_attribute("name").ok_or_else(|| {
+                    RuntimeError::missing_attribute("name", node.location.clone())
+                })?;
+                
+                source.push_str(&format!("{}type {} {{ ", indent_str, name));
+                
+                // Add fields
+                let mut fields = Vec::new();
+                
+                for field in &node.children {
+                    let field_name = field.get_attribute("name").ok_or_else(|| {
+                        RuntimeError::missing_attribute("name", field.location.clone())
+                    })?;
+                    
+                    let field_type = field.get_attribute("type").map_or("Any", |v| v);
+                    
+                    fields.push(format!("{}: {}", field_name, field_type));
+                }
+                
+                source.push_str(&fields.join(", "));
+                source.push_str(" }");
+            }
+            NodeKind::Prompt => {
+                // Add name
+                let name = node.get_attribute("name").ok_or_else(|| {
+                    RuntimeError::missing_attribute("name", node.location.clone())
+                })?;
+                
+                // Add parameters
+                let mut parameters = Vec::new();
+                
+                for parameter in &node.children {
+                    let parameter_name = parameter.get_attribute("name").ok_or_else(|| {
+                        RuntimeError::missing_attribute("name", parameter.location.clone())
+                    })?;
+                    
+                    let parameter_type = parameter.get_attribute("type").map_or("Any", |v| v);
+                    
+                    parameters.push(format!("{}: {}", parameter_name, parameter_type));
+                }
+                
+                source.push_str(&format!("{}prompt {}({})", indent_str, name, parameters.join(", ")));
+                
+                // Add the reply type and version
+                if let Some(typ) = node.get_attribute("return_type") {
+                    source.push_str(&format!(" -> {}", typ));
+                }
+                
+                if let Some(version) = node.get_attribute("version") {
+                    source.push_str(&format!(" version \"{}\"", version));
+                }
+                
+                // Add template
+                let template = node.get_attribute("template").ok_or_else(|| {
+                    RuntimeError::missing_attribute("template", node.location.clone())
+                })?;
+                
+                source.push_str(&format!(" {{ #\"{}\"# }}", template));
+            }
+            NodeKind::Render => {
+                source.push_str("render ");
+                
+                // Add the call
+                if !node.children.is_empty() {
+                    self.generate_source_recursive(&node.children[0], source, 0)?;
+                }
+            }
+            NodeKind::Stream => {
+                source.push_str("stream ");
+                
+                // Add the prompt
+                if !node.children.is_empty() {
+                    self.generate_source_recursive(&node.children[0], source, 0)?;
+                }
+                
+                // Add the timeout
+                if let Some(timeout) = node.get_attribute("timeout") {
+                    source.push_str(&format!(" timeout {}ms", timeout));
+                }
+            }
             NodeKind::Grouping => {
                 source.push_str("(");
                 
@@ -3,14 +3,20 @@
 //! This module provides the parallel executor for the LLM.lang runtime,
 //! which manages parallel execution of code paths.
 
+use std::collections::VecDeque;
 use std::thread;
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex};
 use std::time::Duration;
 
 use crate::Value;
 use super::error::RuntimeError;
+use super::cancel::CancellationToken;
+
+/// A function that can be executed on a worker thread
+pub type ParallelTask<T = Result<Value, RuntimeError>> = Box<dyn FnOnce() -> T + Send + 'static>;
 
 /// A parallel executor
+#[derive(Debug, Clone)]
 pub struct Parallel {
     /// The maximum number of threads to use
     max_threads: usize,
@@ -34,39 +40,73 @@ impl Parallel {
         self.max_threads
     }
     
-    /// Execute a function in parallel
-    pub fn execute<F>(&self, functions: Vec<(String, F)>) -> Vec<(String, Result<Value, RuntimeError>)>
+    /// Execute functions in parallel
+    ///
+    /// At most `max_threads` functions run at the same time. The results are
+    /// returned in the same order as the functions, regardless of which one
+    /// finished first.
+    pub fn execute<T>(&self, functions: Vec<(String, ParallelTask<T>)>) -> Vec<(String, T)>
+    where
+        T: Send + 'static,
+    {
+        self.execute_streaming(functions, |_, _, _| {})
+    }
+    
+    /// Execute functions in parallel, observing each result as it completes
+    ///
+    /// The callback runs on the calling thread in completion order and receives
+    /// the position, name and result of each function, which allows callers to
+    /// react to early results (e.g., by cancelling the remaining functions).
+    /// The returned results are in the same order as the functions.
+    pub fn execute_streaming<T, C>(&self, functions: Vec<(String, ParallelTask<T>)>, mut on_complete: C) -> Vec<(String, T)>
     where
-        F: FnOnce() -> Result<Value, RuntimeError> + Send + 'static,
+        T: Send + 'static,
+        C: FnMut(usize, &str, &T),
     {
         // Limit the number of threads
-        let num_threads = std::cmp::min(functions.len(), self.max_threads);
+        let num_threads = std::cmp::max(1, std::cmp::min(functions.len(), self.max_threads));
         
-        // Create a thread pool
-        let results = Arc::new(Mutex::new(Vec::new()));
+        // Queue the functions with their position so the results can be ordered
+        let count = functions.len();
+        let queue = Arc::new(Mutex::new(functions.into_iter().enumerate().collect::<VecDeque<_>>()));
+        let (sender, receiver) = mpsc::channel();
         let mut handles = Vec::new();
         
-        // Execute each function in a separate thread
-        for (name, function) in functions {
-            let results = Arc::clone(&results);
+        // Start the worker threads
+        for _ in 0..num_threads {
+            let queue = Arc::clone(&queue);
+            let sender = sender.clone();
             
             let handle = thread::spawn(move || {
-                let result = function();
-                
-                // Store the result
-                let mut results = results.lock().unwrap();
-                results.push((name, result));
+                loop {
+                    // Take the next function from the queue
+                    let next = queue.lock().unwrap().pop_front();
+                    
+                    let (index, (name, function)) = match next {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    
+                    let result = function();
+                    
+                    // Send the result
+                    if sender.send((index, name, result)).is_err() {
+                        break;
+                    }
+                }
             });
             
             handles.push(handle);
-            
-            // Limit the number of concurrent threads
-            if handles.len() >= num_threads {
-                // Wait for a thread to finish
-                if let Some(handle) = handles.pop() {
-                    handle.join().unwrap();
-                }
-            }
+        }
+        
+        // Only the workers hold senders now, so the channel closes when they finish
+        drop(sender);
+        
+        // Observe the results as they complete
+        let mut results = Vec::with_capacity(count);
+        for (index, name, result) in receiver {
+            on_complete(index, &name, &result);
+            results.push((index, name, result));
         }
         
         // Wait for all threads to finish
@@ -74,20 +114,43 @@ impl Parallel {
             handle.join().unwrap();
         }
         
-        // Return the results
-        Arc::try_unwrap(results).unwrap().into_inner().unwrap()
+        // Return the results in their original order
+        results.sort_by_key(|(index, _, _)| *index);
+        
+        results.into_iter().map(|(_, name, result)| (name, result)).collect()
     }
     
     /// Execute a function with a timeout
-    pub fn execute_with_timeout<F>(&self, function: F, timeout: Duration) -> Result<Value, RuntimeError>
+    ///
+    /// The function runs on a separate thread, which is always waited for
+    /// before returning. See `execute_cancellable` for functions that can be
+    /// stopped early.
+    pub fn execute_with_timeout<T, F>(&self, function: F, timeout: Duration) -> Result<T, RuntimeError>
     where
-        F: FnOnce() -> Result<Value, RuntimeError> + Send + 'static,
+        T: Send + 'static,
+        F: FnOnce() -> Result<T, RuntimeError> + Send + 'static,
+    {
+        self.execute_cancellable(function, &CancellationToken::new(), timeout)
+    }
+    
+    /// Execute a function with a timeout, cancelling the token when it runs out of time
+    ///
+    /// The token is the current token of the thread running the function, so
+    /// the engine, the standard library and subprocesses started by the
+    /// function stop once it is cancelled. The thread is joined rather than
+    /// abandoned, so this returns as soon as the function notices.
+    pub fn execute_cancellable<T, F>(&self, function: F, token: &CancellationToken, timeout: Duration) -> Result<T, RuntimeError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T, RuntimeError> + Send + 'static,
     {
         // Create a channel to receive the result
-        let (sender, receiver) = std::sync::mpsc::channel();
+        let (sender, receiver) = mpsc::channel();
         
         // Execute the function in a separate thread
+        let worker_token = token.clone();
         let handle = thread::spawn(move || {
+            let _guard = worker_token.enter();
             let result = function();
             
             // Send the result
@@ -95,19 +158,26 @@ impl Parallel {
         });
         
         // Wait for the result or timeout
-        match receiver.recv_timeout(timeout) {
+        let result = match receiver.recv_timeout(timeout) {
             Ok(result) => {
                 // The function completed within the timeout
                 result
             }
             Err(_) => {
-                // The function timed out
+                // The function timed out, so ask it to stop
+                token.cancel();
+                
                 Err(RuntimeError::new(
                     &format!("Function timed out after {:?}", timeout),
                     crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
                 ))
             }
-        }
+        };
+        
+        // Wait for the thread to stop
+        let _ = handle.join();
+        
+        result
     }
 }
 
@@ -135,10 +205,10 @@ mod tests {
     fn test_parallel_execute() {
         let parallel = Parallel::new();
         
-        let functions = vec![
-            ("a".to_string(), || Ok(Value::Int(1))),
-            ("b".to_string(), || Ok(Value::Int(2))),
-            ("c".to_string(), || Ok(Value::Int(3))),
+        let functions: Vec<(String, ParallelTask)> = vec![
+            ("a".to_string(), Box::new(|| Ok(Value::Int(1)))),
+            ("b".to_string(), Box::new(|| Ok(Value::Int(2)))),
+            ("c".to_string(), Box::new(|| Ok(Value::Int(3)))),
         ];
         
         let results = parallel.execute(functions);
@@ -158,6 +228,79 @@ mod tests {
         assert_eq!(results[2].1.as_ref().unwrap(), &Value::Int(3));
     }
     
+    #[test]
+    fn test_parallel_execute_preserves_order() {
+        let mut parallel = Parallel::new();
+        parallel.set_max_threads(2);
+        
+        // The first function finishes last
+        let functions: Vec<(String, ParallelTask<u64>)> = (0..5u64)
+            .map(|i| {
+                let task: ParallelTask<u64> = Box::new(move || {
+                    thread::sleep(Duration::from_millis(50 - i * 10));
+                    i
+                });
+                (format!("path{}", i), task)
+            })
+            .collect();
+        
+        let results = parallel.execute(functions);
+        
+        let values: Vec<u64> = results.iter().map(|(_, value)| *value).collect();
+        assert_eq!(values, vec![0, 1, 2, 3, 4]);
+        assert_eq!(results[0].0, "path0");
+    }
+    
+    #[test]
+    fn test_parallel_execute_limits_threads() {
+        let mut parallel = Parallel::new();
+        parallel.set_max_threads(2);
+        
+        let running = Arc::new(Mutex::new((0usize, 0usize)));
+        
+        let functions: Vec<(String, ParallelTask<()>)> = (0..6)
+            .map(|i| {
+                let running = Arc::clone(&running);
+                let task: ParallelTask<()> = Box::new(move || {
+                    {
+                        let mut running = running.lock().unwrap();
+                        running.0 += 1;
+                        running.1 = std::cmp::max(running.0, running.1);
+                    }
+                    thread::sleep(Duration::from_millis(20));
+                    running.lock().unwrap().0 -= 1;
+                });
+                (format!("path{}", i), task)
+            })
+            .collect();
+        
+        parallel.execute(functions);
+        
+        assert!(running.lock().unwrap().1 <= 2);
+    }
+    
+    #[test]
+    fn test_parallel_execute_streaming() {
+        let parallel = Parallel::new();
+        
+        // The second function finishes first
+        let functions: Vec<(String, ParallelTask<u64>)> = vec![
+            ("slow".to_string(), Box::new(|| {
+                thread::sleep(Duration::from_millis(100));
+                1
+            })),
+            ("fast".to_string(), Box::new(|| 2)),
+        ];
+        
+        let mut completed = Vec::new();
+        let results = parallel.execute_streaming(functions, |index, name, value| {
+            completed.push((index, name.to_string(), *value));
+        });
+        
+        assert_eq!(completed, vec![(1, "fast".to_string(), 2), (0, "slow".to_string(), 1)]);
+        assert_eq!(results, vec![("slow".to_string(), 1), ("fast".to_string(), 2)]);
+    }
+    
     #[test]
     fn test_parallel_execute_with_timeout() {
         let parallel = Parallel::new();
@@ -185,4 +328,27 @@ mod tests {
             "Function timed out after 100ms"
         );
     }
+    
+    #[test]
+    fn test_parallel_execute_cancellable() {
+        let parallel = Parallel::new();
+        let token = CancellationToken::new();
+        
+        // The function stops once its token is cancelled
+        let start_time = std::time::Instant::now();
+        let result: Result<Value, RuntimeError> = parallel.execute_cancellable(
+            || {
+                loop {
+                    CancellationToken::check_current()?;
+                    thread::sleep(Duration::from_millis(5));
+                }
+            },
+            &token,
+            Duration::from_millis(50),
+        );
+        
+        assert_eq!(result.unwrap_err().message, "Function timed out after 50ms");
+        assert!(token.is_cancelled());
+        assert!(start_time.elapsed() < Duration::from_secs(5));
+    }
 }
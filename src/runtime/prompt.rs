@@ -0,0 +1,257 @@
+//! Prompt module for the LLM.lang runtime
+//!
+//! This module provides the prompts declared with `prompt`. A prompt has typed
+//! parameters and a template in natural language, in which `${name}` stands
+//! for the value of a parameter and `${examples}` for the examples given for
+//! the prompt with `examples for`. Rendering a prompt does not call the
+//! language model; calling the prompt sends the rendered text to the model.
+
+use std::collections::HashMap;
+
+use crate::parser::ast::{Node, NodeKind};
+use crate::parser::template::{self, PromptTemplate, TemplatePart};
+use crate::stdlib::StdLib;
+use crate::utils::SourceLocation;
+use crate::Value;
+use super::error::RuntimeError;
+
+/// A prompt declared with `prompt`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prompt {
+    /// The prompt name
+    pub name: String,
+
+    /// The parameters and their types, in declaration order
+    pub parameters: Vec<(String, String)>,
+
+    /// The type of the model's reply, if it is structured
+    pub return_type: Option<String>,
+
+    /// The declared version, or a hash of the template if none is declared
+    pub version: String,
+
+    /// The template
+    pub template: PromptTemplate,
+}
+
+impl Prompt {
+    /// Create a prompt from its declaration
+    pub fn from_node(node: &Node) -> Result<Self, RuntimeError> {
+        let name = node.get_attribute("name").ok_or_else(|| {
+            RuntimeError::missing_attribute("name", node.location.clone())
+        })?;
+
+        let template = node.get_attribute("template").ok_or_else(|| {
+            RuntimeError::missing_attribute("template", node.location.clone())
+        })?;
+
+        let template = PromptTemplate::parse(template).map_err(|message| {
+            RuntimeError::new(&message, node.location.clone())
+        })?;
+
+        let parameters = node.children.iter()
+            .filter(|child| child.kind == NodeKind::Parameter)
+            .map(|parameter| (
+                parameter.get_attribute("name").cloned().unwrap_or_default(),
+                parameter.get_attribute("type").cloned().unwrap_or_else(|| "Any".to_string()),
+            ))
+            .collect();
+
+        let version = match node.get_attribute("version") {
+            Some(version) => version.clone(),
+            None => template::template_version(template.as_str()),
+        };
+
+        Ok(Self {
+            name: name.clone(),
+            parameters,
+            return_type: node.get_attribute("return_type").cloned(),
+            version,
+            template,
+        })
+    }
+
+    /// Render the prompt
+    ///
+    /// The arguments are given in parameter order. Each example is rendered
+    /// as its input, labelled with the parameter names, and its output.
+    pub fn render(&self, arguments: &[Value], examples: &[(Value, Value)]) -> String {
+        let mut text = String::new();
+
+        for part in self.template.parts() {
+            match part {
+                TemplatePart::Text(part) => text.push_str(part),
+                TemplatePart::Parameter(name) => {
+                    let index = self.parameters.iter().position(|(parameter, _)| parameter == name);
+
+                    if let Some(argument) = index.and_then(|index| arguments.get(index)) {
+                        text.push_str(&StdLib::value_to_string(argument));
+                    }
+                }
+                TemplatePart::Examples => {
+                    let examples: Vec<String> = examples.iter().map(|(input, output)| self.render_example(input, output)).collect();
+                    text.push_str(&examples.join("\n\n"));
+                }
+            }
+        }
+
+        text
+    }
+
+    /// Render an example as its input and output
+    fn render_example(&self, input: &Value, output: &Value) -> String {
+        let mut lines = Vec::new();
+
+        match (input, self.parameters.as_slice()) {
+            (Value::List(items), parameters) if parameters.len() > 1 && items.len() == parameters.len() => {
+                for ((name, _), item) in parameters.iter().zip(items) {
+                    lines.push(format!("{}: {}", name, StdLib::value_to_string(item)));
+                }
+            }
+            (input, [(name, _)]) => lines.push(format!("{}: {}", name, StdLib::value_to_string(input))),
+            (input, _) => lines.push(format!("Input: {}", StdLib::value_to_string(input))),
+        }
+
+        lines.push(format!("Output: {}", StdLib::value_to_string(output)));
+        lines.join("\n")
+    }
+}
+
+/// The prompts of a program, with every version that was declared
+///
+/// The version in use of a prompt is the one declared last, unless another
+/// version was selected with `usePromptVersion`.
+#[derive(Debug, Clone, Default)]
+pub struct Prompts {
+    /// The versions of each prompt, in declaration order
+    versions: HashMap<String, Vec<Prompt>>,
+
+    /// The version selected for each prompt, if not the one declared last
+    selected: HashMap<String, String>,
+}
+
+impl Prompts {
+    /// Create an empty set of prompts
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a prompt, which is used from then on
+    ///
+    /// A version that was already declared is replaced, as happens when a
+    /// program declares its prompts again on the next run.
+    pub fn declare(&mut self, prompt: Prompt) {
+        let versions = self.versions.entry(prompt.name.clone()).or_default();
+        versions.retain(|declared| declared.version != prompt.version);
+
+        self.selected.remove(&prompt.name);
+        versions.push(prompt);
+    }
+
+    /// Check whether a prompt has been declared
+    pub fn contains(&self, name: &str) -> bool {
+        self.versions.contains_key(name)
+    }
+
+    /// Get the version in use of a prompt
+    pub fn get(&self, name: &str) -> Option<&Prompt> {
+        let versions = self.versions.get(name)?;
+
+        match self.selected.get(name) {
+            Some(version) => versions.iter().find(|prompt| &prompt.version == version),
+            None => versions.last(),
+        }
+    }
+
+    /// Get the declared versions of a prompt, in declaration order
+    pub fn versions(&self, name: &str) -> Vec<&str> {
+        self.versions.get(name).map_or_else(Vec::new, |versions| {
+            versions.iter().map(|prompt| prompt.version.as_str()).collect()
+        })
+    }
+
+    /// Use a declared version of a prompt until another version is declared or selected
+    pub fn select(&mut self, name: &str, version: &str, location: &SourceLocation) -> Result<(), RuntimeError> {
+        let versions = self.versions.get(name).ok_or_else(|| {
+            RuntimeError::new(&format!("Undefined prompt: '{}'", name), location.clone())
+        })?;
+
+        if !versions.iter().any(|prompt| prompt.version == version) {
+            return Err(RuntimeError::new(
+                &format!("Prompt '{}' has no version '{}'", name, version),
+                location.clone(),
+            ));
+        }
+
+        self.selected.insert(name.to_string(), version.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prompt(source: &str) -> Prompt {
+        let tokens = crate::lexer::Lexer::new(source).tokenize().unwrap();
+        let ast = crate::parser::Parser::new(tokens).parse().unwrap();
+        Prompt::from_node(&ast.root.children[0]).unwrap()
+    }
+
+    #[test]
+    fn test_prompt_render() {
+        let summarize = prompt(r##"prompt Summarize(text: String, max_words: Int) { #"Summarize ${text} in ${max_words} words."# }"##);
+        assert_eq!(summarize.version, template::template_version("Summarize ${text} in ${max_words} words."));
+        assert_eq!(summarize.return_type, None);
+        assert_eq!(
+            summarize.render(&[Value::String("the report".to_string()), Value::Int(10)], &[]),
+            "Summarize the report in 10 words."
+        );
+
+        let classify = prompt(r##"prompt Classify(ticket: String) -> String version "2" { #"Classify support tickets.
+            ${examples}
+            ticket: ${ticket}
+            Output:"# }"##);
+        assert_eq!(classify.version, "2");
+        assert_eq!(classify.return_type, Some("String".to_string()));
+
+        let examples = vec![
+            (Value::String("I was charged twice".to_string()), Value::String("billing".to_string())),
+            (Value::String("The app crashes".to_string()), Value::String("bug".to_string())),
+        ];
+        assert_eq!(
+            classify.render(&[Value::String("Where is my invoice?".to_string())], &examples),
+            "Classify support tickets.\nticket: I was charged twice\nOutput: billing\n\nticket: The app crashes\nOutput: bug\nticket: Where is my invoice?\nOutput:"
+        );
+
+        // Examples of prompts with several parameters have a list of arguments as input
+        let examples = vec![(Value::List(vec![Value::String("a long report".to_string()), Value::Int(2)]), Value::String("long report".to_string()))];
+        assert_eq!(
+            PromptTemplate::parse("${examples}").map(|template| Prompt { template, ..summarize }).unwrap().render(&[], &examples),
+            "text: a long report\nmax_words: 2\nOutput: long report"
+        );
+    }
+
+    #[test]
+    fn test_prompts_versions() {
+        let mut prompts = Prompts::new();
+        let here = SourceLocation::new(1, 1, 1, 1, "");
+
+        prompts.declare(prompt(r##"prompt Classify(ticket: String) version "1" { #"Classify ${ticket}"# }"##));
+        prompts.declare(prompt(r##"prompt Classify(ticket: String) version "2" { #"Label ${ticket}"# }"##));
+        assert_eq!(prompts.versions("Classify"), vec!["1", "2"]);
+        assert_eq!(prompts.get("Classify").unwrap().version, "2");
+
+        prompts.select("Classify", "1", &here).unwrap();
+        assert_eq!(prompts.get("Classify").unwrap().version, "1");
+        assert_eq!(prompts.select("Classify", "3", &here).unwrap_err().message, "Prompt 'Classify' has no version '3'");
+        assert_eq!(prompts.select("Summarize", "1", &here).unwrap_err().message, "Undefined prompt: 'Summarize'");
+
+        // Declaring a version again uses it from then on
+        prompts.declare(prompt(r##"prompt Classify(ticket: String) version "2" { #"Label ${ticket}"# }"##));
+        assert_eq!(prompts.versions("Classify"), vec!["1", "2"]);
+        assert_eq!(prompts.get("Classify").unwrap().version, "2");
+        assert!(prompts.contains("Classify"));
+        assert!(!prompts.contains("Summarize"));
+    }
+}
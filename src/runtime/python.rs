@@ -0,0 +1,443 @@
+//! Python module for the LLM.lang runtime
+//!
+//! This module embeds a Python interpreter in the runtime, so Python code runs
+//! in-process instead of in a `python` subprocess. Values are converted to
+//! and from Python objects directly. Imported modules and the globals of
+//! evaluated code are kept between calls, and Python exceptions become
+//! runtime errors with their traceback. Code that is still running when
+//! execution is cancelled is interrupted with a `KeyboardInterrupt`.
+
+use std::collections::HashMap;
+use std::os::raw::c_long;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use pyo3::exceptions::{PyKeyboardInterrupt, PyTypeError};
+use pyo3::ffi;
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyFrozenSet, PyList, PyLong, PySet, PyString, PyTuple};
+
+use crate::utils::SourceLocation;
+use crate::Value;
+use super::cancel::CancellationToken;
+use super::error::RuntimeError;
+
+/// How often the watchdog checks whether running Python code is cancelled
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(10);
+
+/// An embedded Python interpreter
+///
+/// The interpreter is shared by every `PythonRuntime` of the process, but
+/// each has its own imported modules and globals. The locks are only held
+/// to read or update them, never while Python code runs, since Python code
+/// can hand the GIL to another thread that then waits for the lock.
+#[derive(Debug, Default)]
+pub struct PythonRuntime {
+    /// The imported modules, by name
+    modules: Mutex<HashMap<String, Py<PyModule>>>,
+
+    /// The globals that code is evaluated and executed in
+    globals: Mutex<Option<Py<PyDict>>>,
+}
+
+impl PythonRuntime {
+    /// Create a new Python runtime
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Evaluate a Python expression
+    pub fn eval(&self, code: &str) -> Result<Value, RuntimeError> {
+        interruptible(|py| {
+            let globals = self.globals(py);
+            let result = py.eval(code, Some(globals), None).map_err(|error| exception(py, error))?;
+            from_python(result)
+        })
+    }
+
+    /// Execute Python statements
+    ///
+    /// The variables, functions and imports they define are kept for later
+    /// code, and can be called with `call`.
+    pub fn exec(&self, code: &str) -> Result<(), RuntimeError> {
+        interruptible(|py| {
+            let globals = self.globals(py);
+            py.run(code, Some(globals), None).map_err(|error| exception(py, error))
+        })
+    }
+
+    /// Import a Python module, keeping it for later calls
+    ///
+    /// The module is also bound in the globals by its top-level name, as the
+    /// `import` statement does.
+    pub fn import(&self, name: &str) -> Result<(), RuntimeError> {
+        interruptible(|py| {
+            self.module(py, name).map_err(|error| exception(py, error))?;
+
+            let top_level = name.split('.').next().unwrap_or(name);
+            let module = self.module(py, top_level).map_err(|error| exception(py, error))?;
+            self.globals(py).set_item(top_level, module).map_err(|error| exception(py, error))
+        })
+    }
+
+    /// Call a Python function with positional and keyword arguments
+    ///
+    /// The function is a dotted path, such as `math.sqrt` or
+    /// `os.path.join`. Its first name is looked up in the globals, and
+    /// otherwise the longest prefix of the path that is a module is imported.
+    pub fn call(&self, function: &str, arguments: &[Value], keywords: &HashMap<String, Value>) -> Result<Value, RuntimeError> {
+        interruptible(|py| {
+            let function = self.resolve(py, function).map_err(|error| exception(py, error))?;
+
+            let arguments = arguments.iter()
+                .map(|argument| to_python(py, argument))
+                .collect::<Result<Vec<PyObject>, RuntimeError>>()?;
+
+            let kwargs = PyDict::new(py);
+            for (name, value) in keywords {
+                kwargs.set_item(name, to_python(py, value)?).map_err(|error| exception(py, error))?;
+            }
+
+            let result = function.call(PyTuple::new(py, arguments), Some(kwargs)).map_err(|error| exception(py, error))?;
+            from_python(result)
+        })
+    }
+
+    /// Create a runtime that starts with the modules and a copy of the globals of this one
+    ///
+    /// The copy is shallow, so objects in the globals are shared, and
+    /// functions defined before the fork still assign the globals of this
+    /// runtime. Names bound by later code are only seen by the fork.
+    pub fn fork(&self) -> Self {
+        if self.globals.lock().unwrap_or_else(PoisonError::into_inner).is_none() {
+            return Self::new();
+        }
+
+        Python::with_gil(|py| {
+            let modules = self.modules.lock().unwrap_or_else(PoisonError::into_inner).iter()
+                .map(|(name, module)| (name.clone(), module.clone_ref(py)))
+                .collect();
+
+            // Copying a dictionary of existing objects does not fail
+            let globals = self.globals(py).copy().ok().map(Py::from);
+
+            Self {
+                modules: Mutex::new(modules),
+                globals: Mutex::new(globals),
+            }
+        })
+    }
+
+    /// Get the globals, creating them on first use
+    fn globals<'py>(&self, py: Python<'py>) -> &'py PyDict {
+        let mut globals = self.globals.lock().unwrap_or_else(PoisonError::into_inner);
+
+        globals.get_or_insert_with(|| PyDict::new(py).into()).clone_ref(py).into_ref(py)
+    }
+
+    /// Get an imported module, importing it on first use
+    fn module<'py>(&self, py: Python<'py>, name: &str) -> PyResult<&'py PyModule> {
+        let imported = self.modules.lock().unwrap_or_else(PoisonError::into_inner).get(name).map(|module| module.clone_ref(py));
+
+        match imported {
+            Some(module) => Ok(module.into_ref(py)),
+            None => {
+                let module = PyModule::import(py, name)?;
+                self.modules.lock().unwrap_or_else(PoisonError::into_inner).insert(name.to_string(), module.into());
+                Ok(module)
+            }
+        }
+    }
+
+    /// Find the object a dotted path names
+    fn resolve<'py>(&self, py: Python<'py>, path: &str) -> PyResult<&'py PyAny> {
+        let names: Vec<&str> = path.split('.').collect();
+
+        if let Some(object) = self.globals(py).get_item(names[0]) {
+            return names[1..].iter().try_fold(object, |object, name| object.getattr(*name));
+        }
+
+        // Try the longest module first, so "os.path.join" finds the module "os.path"
+        let mut error = None;
+
+        for split in (1..=names.len()).rev() {
+            match self.module(py, &names[..split].join(".")) {
+                Ok(module) => return names[split..].iter().try_fold(module.as_ref(), |object, name| object.getattr(*name)),
+                Err(import_error) => error = Some(import_error),
+            }
+        }
+
+        Err(error.unwrap_or_else(|| PyTypeError::new_err(format!("'{}' is not a Python function", path))))
+    }
+}
+
+/// Run Python code with the GIL, interrupting it when the current cancellation token fires
+///
+/// A watchdog thread waits for the token and raises `KeyboardInterrupt` in
+/// the thread running the code, which Python checks between bytecode
+/// instructions. `PyErr_SetInterrupt` would only interrupt the main thread,
+/// so the exception is raised with `PyThreadState_SetAsyncExc` instead.
+fn interruptible<T>(run: impl FnOnce(Python<'_>) -> Result<T, RuntimeError>) -> Result<T, RuntimeError> {
+    let Some(token) = CancellationToken::current() else {
+        return Python::with_gil(run);
+    };
+
+    CancellationToken::check_current()?;
+
+    Python::with_gil(|py| {
+        let thread_id = py.import("threading")
+            .and_then(|threading| threading.getattr("get_ident")?.call0()?.extract::<u64>())
+            .map_err(|error| exception(py, error))? as c_long;
+
+        // The flag is only changed and read with the GIL held, so the interrupt
+        // is never raised after the code has finished
+        let finished = Arc::new(AtomicBool::new(false));
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        let watchdog_finished = Arc::clone(&finished);
+        thread::spawn(move || {
+            loop {
+                match stopped.recv_timeout(WATCHDOG_INTERVAL) {
+                    Err(RecvTimeoutError::Timeout) if token.is_cancelled() => break,
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+            }
+
+            Python::with_gil(|_| {
+                if !watchdog_finished.load(Ordering::SeqCst) {
+                    // SAFETY: the GIL is held, and the exception type is a static object
+                    unsafe { ffi::PyThreadState_SetAsyncExc(thread_id, ffi::PyExc_KeyboardInterrupt) };
+                }
+            });
+        });
+
+        let result = run(py);
+        finished.store(true, Ordering::SeqCst);
+        drop(stop);
+
+        // Clear an interrupt that was raised but not yet delivered when the code finished
+        // SAFETY: the GIL is held, and a null exception clears the pending one
+        unsafe { ffi::PyThreadState_SetAsyncExc(thread_id, std::ptr::null_mut()) };
+
+        result
+    })
+}
+
+/// Convert a value to a Python object
+pub fn to_python(py: Python<'_>, value: &Value) -> Result<PyObject, RuntimeError> {
+    match value {
+        Value::Void => Ok(py.None()),
+        Value::Bool(b) => Ok(b.into_py(py)),
+        Value::Int(i) => Ok(i.into_py(py)),
+        Value::Float(f) => Ok(f.into_py(py)),
+        Value::String(s) => Ok(s.into_py(py)),
+        Value::List(items) => {
+            let items = items.iter().map(|item| to_python(py, item)).collect::<Result<Vec<PyObject>, RuntimeError>>()?;
+            Ok(PyList::new(py, items).into())
+        }
+        Value::Vector(vector) => Ok(PyList::new(py, vector).into()),
+        Value::Map(map) => {
+            let dict = PyDict::new(py);
+            for (key, value) in map {
+                dict.set_item(key, to_python(py, value)?).map_err(|error| exception(py, error))?;
+            }
+            Ok(dict.into())
+        }
+        Value::Prob { value, .. } => to_python(py, value),
+        _ => Err(RuntimeError::new(
+            &format!("Cannot convert value to Python: {:?}", value),
+            SourceLocation::new(0, 0, 0, 0, ""),
+        )),
+    }
+}
+
+/// Convert a Python object to a value
+///
+/// Tuples and sets become lists, and dictionary keys that are not strings
+/// are converted with `str`.
+pub fn from_python(object: &PyAny) -> Result<Value, RuntimeError> {
+    let py = object.py();
+
+    // Booleans are integers in Python, so they are checked first
+    if object.is_none() {
+        Ok(Value::Void)
+    } else if let Ok(b) = object.downcast::<PyBool>() {
+        Ok(Value::Bool(b.is_true()))
+    } else if object.downcast::<PyLong>().is_ok() {
+        object.extract::<i64>().map(Value::Int).map_err(|_| RuntimeError::new(
+            &format!("Cannot convert Python int {} to Int, which has 64 bits", object),
+            SourceLocation::new(0, 0, 0, 0, ""),
+        ))
+    } else if let Ok(f) = object.downcast::<PyFloat>() {
+        Ok(Value::Float(f.value()))
+    } else if let Ok(s) = object.downcast::<PyString>() {
+        Ok(Value::String(s.to_str().map_err(|error| exception(py, error))?.to_string()))
+    } else if let Ok(dict) = object.downcast::<PyDict>() {
+        let mut map = HashMap::new();
+
+        for (key, value) in dict {
+            let key = match key.downcast::<PyString>() {
+                Ok(key) => key.to_str().map_err(|error| exception(py, error))?.to_string(),
+                Err(_) => key.str().map_err(|error| exception(py, error))?.to_string(),
+            };
+
+            map.insert(key, from_python(value)?);
+        }
+
+        Ok(Value::Map(map))
+    } else if object.downcast::<PyList>().is_ok()
+        || object.downcast::<PyTuple>().is_ok()
+        || object.downcast::<PySet>().is_ok()
+        || object.downcast::<PyFrozenSet>().is_ok()
+    {
+        let items = object.iter().map_err(|error| exception(py, error))?;
+
+        Ok(Value::List(items
+            .map(|item| item.map_err(|error| exception(py, error)).and_then(from_python))
+            .collect::<Result<Vec<Value>, RuntimeError>>()?))
+    } else {
+        let type_name = object.get_type().name().unwrap_or("object");
+
+        Err(RuntimeError::new(
+            &format!("Cannot convert Python value of type '{}'", type_name),
+            SourceLocation::new(0, 0, 0, 0, ""),
+        ))
+    }
+}
+
+/// Convert a Python exception to a runtime error with its traceback
+///
+/// A `KeyboardInterrupt` is the interrupt of the watchdog, and becomes the
+/// error of the cancelled token.
+fn exception(py: Python<'_>, error: PyErr) -> RuntimeError {
+    if error.is_instance_of::<PyKeyboardInterrupt>(py) {
+        return CancellationToken::check_current().err()
+            .unwrap_or_else(|| RuntimeError::cancelled(SourceLocation::new(0, 0, 0, 0, "")));
+    }
+
+    let exception_type = error.get_type(py).name().unwrap_or("Exception").to_string();
+    let message = error.value(py).to_string();
+    let traceback = error.traceback(py).and_then(|traceback| traceback.format().ok()).unwrap_or_default();
+
+    RuntimeError::python_exception(&exception_type, &message, &traceback, SourceLocation::new(0, 0, 0, 0, ""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::error::RuntimeErrorKind;
+
+    #[test]
+    fn test_python_values() {
+        let map = Value::Map(HashMap::from([
+            ("a".to_string(), Value::List(vec![Value::Int(1), Value::Float(2.5), Value::Bool(true)])),
+            ("b".to_string(), Value::Void),
+            ("c".to_string(), Value::String("text".to_string())),
+        ]));
+
+        Python::with_gil(|py| {
+            let object = to_python(py, &map).unwrap();
+            assert_eq!(from_python(object.as_ref(py)).unwrap(), map);
+        });
+
+        let python = PythonRuntime::new();
+        assert_eq!(python.eval("(1, {2: 'two'}, 2 ** 70 > 0)").unwrap(), Value::List(vec![
+            Value::Int(1),
+            Value::Map(HashMap::from([("2".to_string(), Value::String("two".to_string()))])),
+            Value::Bool(true),
+        ]));
+        assert_eq!(python.eval("2 ** 63 - 1").unwrap(), Value::Int(i64::MAX));
+
+        let error = python.eval("2 ** 70").unwrap_err();
+        assert_eq!(error.message, "Cannot convert Python int 1180591620717411303424 to Int, which has 64 bits");
+
+        let error = python.eval("object()").unwrap_err();
+        assert_eq!(error.message, "Cannot convert Python value of type 'object'");
+    }
+
+    #[test]
+    fn test_python_calls_keep_state() {
+        let python = PythonRuntime::new();
+
+        python.import("math").unwrap();
+        assert_eq!(python.eval("math.sqrt(16)").unwrap(), Value::Float(4.0));
+
+        python.exec("def greet(name, punctuation='.'):\n    return 'Hello, ' + name + punctuation").unwrap();
+        let keywords = HashMap::from([("punctuation".to_string(), Value::String("!".to_string()))]);
+        assert_eq!(
+            python.call("greet", &[Value::String("Ada".to_string())], &keywords).unwrap(),
+            Value::String("Hello, Ada!".to_string())
+        );
+
+        let path = python.call("os.path.join", &[Value::String("a".to_string()), Value::String("b".to_string())], &HashMap::new()).unwrap();
+        assert_eq!(path, Value::String(format!("a{}b", std::path::MAIN_SEPARATOR)));
+    }
+
+    #[test]
+    fn test_python_exception() {
+        let python = PythonRuntime::new();
+        python.exec("def fail():\n    raise ValueError('bad input')").unwrap();
+
+        let error = python.call("fail", &[], &HashMap::new()).unwrap_err();
+        assert_eq!(error.message, "Python ValueError: bad input");
+
+        let RuntimeErrorKind::PythonException(exception) = &error.kind else { panic!("expected a Python exception, got {:?}", error.kind) };
+        assert_eq!(exception.exception_type, "ValueError");
+        assert!(exception.traceback.starts_with("Traceback (most recent call last):"));
+        assert!(exception.traceback.contains("in fail"));
+
+        let error = python.call("no_such_module.function", &[], &HashMap::new()).unwrap_err();
+        assert!(error.message.starts_with("Python ModuleNotFoundError"), "{}", error.message);
+    }
+
+    #[test]
+    fn test_python_interrupted_by_cancellation() {
+        let python = PythonRuntime::new();
+        let token = CancellationToken::new().with_deadline(Duration::from_millis(50));
+
+        {
+            let _guard = token.enter();
+
+            let error = python.exec("while True:\n    pass").unwrap_err();
+            assert_eq!(error.kind, RuntimeErrorKind::DeadlineExceeded);
+            assert_eq!(error.message, "Deadline of 50ms exceeded");
+        }
+
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            cancel.cancel();
+        });
+
+        {
+            let _guard = token.enter();
+
+            let error = python.exec("while True:\n    pass").unwrap_err();
+            assert_eq!(error.kind, RuntimeErrorKind::Cancelled);
+        }
+
+        handle.join().unwrap();
+
+        // The interpreter is usable after an interrupt
+        assert_eq!(python.eval("1 + 1").unwrap(), Value::Int(2));
+    }
+
+    #[test]
+    fn test_python_fork_has_own_globals() {
+        let python = PythonRuntime::new();
+        python.exec("x = 1").unwrap();
+
+        let fork = python.fork();
+        assert_eq!(fork.eval("x").unwrap(), Value::Int(1));
+
+        fork.exec("x = 2\ny = 3").unwrap();
+        assert_eq!(fork.eval("x").unwrap(), Value::Int(2));
+        assert_eq!(python.eval("x").unwrap(), Value::Int(1));
+        assert!(python.eval("y").is_err());
+    }
+}
@@ -0,0 +1,313 @@
+//! RAG module for the LLM.lang runtime
+//!
+//! This module provides the building blocks of retrieval-augmented
+//! generation: chunkers that split documents into passages, and the
+//! citations that tie an answer back to the passages it was drawn from.
+//! Chunks keep the byte offsets of their text in the document, so a
+//! citation can be checked against the document with `substring`.
+
+use std::collections::HashMap;
+
+use crate::Value;
+use super::budget::TokenCounter;
+
+/// The number of chunks `ragAnswer` retrieves for a question
+pub const DEFAULT_RAG_SOURCES: usize = 4;
+
+/// A passage of a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    /// The text of the passage
+    pub text: String,
+
+    /// The byte offset of the start of the passage in the document
+    pub start: usize,
+
+    /// The byte offset of the end of the passage in the document
+    pub end: usize,
+
+    /// The heading the passage is under, for chunks split by heading
+    pub heading: Option<String>,
+}
+
+impl Chunk {
+    /// Create a chunk of a document from its byte offsets
+    pub fn new(document: &str, start: usize, end: usize) -> Self {
+        Self {
+            text: document[start..end].to_string(),
+            start,
+            end,
+            heading: None,
+        }
+    }
+
+    /// Convert the chunk to a map value, see `to_map`
+    pub fn to_value(&self) -> Value {
+        Value::Map(self.to_map())
+    }
+
+    /// Convert the chunk to a map with its `text`, `start`, `end` and `heading`
+    pub fn to_map(&self) -> HashMap<String, Value> {
+        let mut map = HashMap::new();
+        map.insert("text".to_string(), Value::String(self.text.clone()));
+        map.insert("start".to_string(), Value::Int(self.start as i64));
+        map.insert("end".to_string(), Value::Int(self.end as i64));
+
+        if let Some(heading) = &self.heading {
+            map.insert("heading".to_string(), Value::String(heading.clone()));
+        }
+
+        map
+    }
+
+    /// Convert a map created by `to_value`, or a string, to a chunk
+    ///
+    /// A string is a chunk of itself, from its start to its end.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(text) => Some(Self::new(text, 0, text.len())),
+            Value::Map(map) => {
+                let offset = |key: &str| match map.get(key) {
+                    Some(Value::Int(offset)) if *offset >= 0 => Some(*offset as usize),
+                    _ => None,
+                };
+
+                let Some(Value::String(text)) = map.get("text") else {
+                    return None;
+                };
+
+                let start = offset("start").unwrap_or(0);
+
+                Some(Self {
+                    text: text.clone(),
+                    start,
+                    end: offset("end").unwrap_or(start + text.len()),
+                    heading: match map.get("heading") {
+                        Some(Value::String(heading)) => Some(heading.clone()),
+                        _ => None,
+                    },
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Find the byte offsets of the sentences of text
+///
+/// A sentence ends at `.`, `!` or `?`, and any closing quotes or brackets,
+/// that are followed by whitespace and then not by a lowercase letter, so
+/// that abbreviations such as "e.g. this" stay in one sentence. A blank line
+/// also ends a sentence. The offsets leave out surrounding whitespace.
+pub fn sentence_spans(text: &str) -> Vec<(usize, usize)> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut spans = Vec::new();
+    let mut start = None;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (offset, c) = chars[i];
+
+        if start.is_none() {
+            if !c.is_whitespace() {
+                start = Some(offset);
+            }
+
+            i += 1;
+            continue;
+        }
+
+        if matches!(c, '.' | '!' | '?') {
+            // Include closing punctuation in the sentence
+            let mut end = i + 1;
+            while end < chars.len() && matches!(chars[end].1, '"' | '\'' | ')' | ']' | '\u{201d}' | '\u{2019}') {
+                end += 1;
+            }
+
+            let next = chars[end..].iter().find(|(_, c)| !c.is_whitespace()).map(|(_, c)| *c);
+            let followed_by_space = end == chars.len() || chars[end].1.is_whitespace();
+
+            if followed_by_space && !next.is_some_and(char::is_lowercase) {
+                let end_offset = chars.get(end).map_or(text.len(), |(offset, _)| *offset);
+                spans.push((start.take().unwrap_or(offset), end_offset));
+                i = end;
+                continue;
+            }
+        } else if c == '\n' && chars.get(i + 1).is_some_and(|(_, next)| *next == '\n') {
+            let sentence_start = start.take().unwrap_or(offset);
+            spans.push((sentence_start, sentence_start + text[sentence_start..offset].trim_end().len()));
+        }
+
+        i += 1;
+    }
+
+    if let Some(start) = start {
+        spans.push((start, start + text[start..].trim_end().len()));
+    }
+
+    spans
+}
+
+/// Split text into chunks of up to `sentences` sentences each
+pub fn chunk_by_sentence(text: &str, sentences: usize) -> Vec<Chunk> {
+    sentence_spans(text)
+        .chunks(sentences.max(1))
+        .map(|group| Chunk::new(text, group[0].0, group[group.len() - 1].1))
+        .collect()
+}
+
+/// Split text into chunks of up to `max_tokens` tokens each
+///
+/// Each chunk after the first starts with the last `overlap` tokens of the
+/// chunk before it, so that passages cut at a chunk boundary are also found
+/// whole. The overlap must be smaller than the chunk size.
+pub fn chunk_by_tokens(text: &str, max_tokens: usize, overlap: usize, counter: &TokenCounter) -> Result<Vec<Chunk>, String> {
+    if max_tokens == 0 {
+        return Err("The number of tokens of a chunk must be positive".to_string());
+    }
+
+    if overlap >= max_tokens {
+        return Err(format!("The overlap of {} tokens must be smaller than the chunks of {} tokens", overlap, max_tokens));
+    }
+
+    let spans = counter.spans(text);
+    let mut chunks = Vec::new();
+    let mut first = 0;
+
+    while first < spans.len() {
+        let last = (first + max_tokens).min(spans.len());
+        chunks.push(Chunk::new(text, spans[first].0, spans[last - 1].1));
+
+        if last == spans.len() {
+            break;
+        }
+
+        first = last - overlap;
+    }
+
+    Ok(chunks)
+}
+
+/// Split Markdown text into one chunk per heading
+///
+/// Each chunk runs from a heading line to the next heading, and has the
+/// heading text without its `#` marks. Text before the first heading is a
+/// chunk without a heading.
+pub fn chunk_by_heading(text: &str) -> Vec<Chunk> {
+    let mut sections: Vec<(usize, Option<String>)> = vec![(0, None)];
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        let marks = trimmed.chars().take_while(|c| *c == '#').count();
+
+        if (1..=6).contains(&marks) && trimmed[marks..].starts_with(char::is_whitespace) {
+            sections.push((offset, Some(trimmed[marks..].trim().to_string())));
+        }
+
+        offset += line.len();
+    }
+
+    let mut chunks = Vec::new();
+
+    for (i, (start, heading)) in sections.iter().enumerate() {
+        let end = sections.get(i + 1).map_or(text.len(), |(next, _)| *next);
+        let section = &text[*start..end];
+        let leading = section.len() - section.trim_start().len();
+
+        if section.trim().is_empty() {
+            continue;
+        }
+
+        chunks.push(Chunk {
+            heading: heading.clone(),
+            ..Chunk::new(text, start + leading, start + section.trim_end().len())
+        });
+    }
+
+    chunks
+}
+
+/// Get the sources an answer cites, as numbers from 1 to `count` in order of first citation
+///
+/// Sources are cited by their number in brackets, as in `[2]` or `[1, 3]`.
+pub fn cited_sources(answer: &str, count: usize) -> Vec<usize> {
+    let mut cited = Vec::new();
+    let mut rest = answer;
+
+    while let Some(open) = rest.find('[') {
+        rest = &rest[open + 1..];
+
+        let Some(close) = rest.find(']') else {
+            break;
+        };
+
+        let numbers: Option<Vec<usize>> = rest[..close].split(',').map(|number| number.trim().parse().ok()).collect();
+
+        for number in numbers.unwrap_or_default() {
+            if (1..=count).contains(&number) && !cited.contains(&number) {
+                cited.push(number);
+            }
+        }
+
+        rest = &rest[close + 1..];
+    }
+
+    cited
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_by_sentence() {
+        let text = "  Paris is the capital of France. It is known for e.g. the Eiffel Tower!\n\nRome is \"eternal.\" Is it? Yes";
+        let sentences: Vec<&str> = sentence_spans(text).iter().map(|(start, end)| &text[*start..*end]).collect();
+        assert_eq!(sentences, vec![
+            "Paris is the capital of France.",
+            "It is known for e.g. the Eiffel Tower!",
+            "Rome is \"eternal.\"",
+            "Is it?",
+            "Yes",
+        ]);
+
+        let chunks = chunk_by_sentence(text, 2);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].text, "Paris is the capital of France. It is known for e.g. the Eiffel Tower!");
+        assert_eq!(&text[chunks[1].start..chunks[1].end], "Rome is \"eternal.\" Is it?");
+        assert_eq!(chunks[2], Chunk::new(text, text.len() - 3, text.len()));
+
+        assert_eq!(Chunk::from_value(&chunks[1].to_value()), Some(chunks[1].clone()));
+    }
+
+    #[test]
+    fn test_chunk_by_tokens() {
+        let text = "one two three four five six seven";
+        let chunks = chunk_by_tokens(text, 3, 1, &TokenCounter::new()).unwrap();
+        let texts: Vec<&str> = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+        assert_eq!(texts, vec!["one two three", "three four five", "five six seven"]);
+        assert_eq!((chunks[1].start, chunks[1].end), (8, 23));
+
+        assert!(chunk_by_tokens(text, 3, 3, &TokenCounter::new()).is_err());
+        assert!(chunk_by_tokens("", 3, 0, &TokenCounter::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_chunk_by_heading() {
+        let text = "Introduction text.\n\n# Install\nRun the installer.\n\n## Linux\nUse the package.\n#hashtag is not a heading\n";
+        let chunks = chunk_by_heading(text);
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!((chunks[0].text.as_str(), chunks[0].heading.as_deref()), ("Introduction text.", None));
+        assert_eq!((chunks[1].text.as_str(), chunks[1].heading.as_deref()), ("# Install\nRun the installer.", Some("Install")));
+        assert_eq!(chunks[2].heading.as_deref(), Some("Linux"));
+        assert_eq!(&text[chunks[2].start..chunks[2].end], "## Linux\nUse the package.\n#hashtag is not a heading");
+    }
+
+    #[test]
+    fn test_cited_sources() {
+        assert_eq!(cited_sources("Paris [2]. It has a tower [1, 2] [7] [x].", 3), vec![2, 1]);
+        assert!(cited_sources("No citations", 3).is_empty());
+    }
+}
@@ -0,0 +1,325 @@
+//! Schema module for the LLM.lang runtime
+//!
+//! This module provides the schemas that structured natural language
+//! expressions (`#"..."# as Type`) request from the language model. A schema
+//! is derived from an LLM.lang type, described to the model as JSON Schema,
+//! and used to validate the value parsed from the model's reply.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde_json::json;
+
+use crate::utils::SourceLocation;
+use crate::Value;
+use super::error::RuntimeError;
+use super::nlp::NLP;
+
+/// The fields of the record types declared with `type`, by type name
+///
+/// Each field is a name and the LLM.lang type it was declared with.
+pub type RecordTypes = HashMap<String, Vec<(String, String)>>;
+
+/// The shape of a value requested from a language model
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schema {
+    /// An integer
+    Int,
+
+    /// A floating-point number
+    Float,
+
+    /// A string
+    String,
+
+    /// A boolean
+    Bool,
+
+    /// A vector of numbers
+    Vector,
+
+    /// A list, with the schema of its items if it is known
+    List(Option<Box<Schema>>),
+
+    /// A map, with the schema of its values if it is known
+    Map(Option<Box<Schema>>),
+
+    /// A record with named fields, all of which are required
+    Record {
+        /// The name of the record type
+        name: String,
+
+        /// The fields of the record, in declaration order
+        fields: Vec<(String, Schema)>,
+    },
+
+    /// A string of a semantic type, such as `~EmailAddress~`
+    Semantic(String),
+}
+
+impl Schema {
+    /// Derive a schema from an LLM.lang type, such as `List<User>`
+    pub fn from_type(typ: &str, types: &RecordTypes) -> Result<Self, RuntimeError> {
+        Self::resolve(typ.trim(), types, &mut Vec::new())
+    }
+
+    /// Derive a schema, tracking the record types being resolved to reject recursive types
+    fn resolve(typ: &str, types: &RecordTypes, resolving: &mut Vec<String>) -> Result<Self, RuntimeError> {
+        let generic = |prefix: &str| {
+            typ.strip_prefix(prefix)
+                .and_then(|rest| rest.strip_prefix('<'))
+                .and_then(|rest| rest.strip_suffix('>'))
+        };
+
+        let schema = match typ {
+            "Int" => Schema::Int,
+            "Float" => Schema::Float,
+            "String" => Schema::String,
+            "Bool" => Schema::Bool,
+            "Vector" => Schema::Vector,
+            "List" => Schema::List(None),
+            "Map" => Schema::Map(None),
+            _ if typ.starts_with('~') => Schema::Semantic(typ.trim_matches('~').to_string()),
+            _ => {
+                if let Some(item) = generic("List") {
+                    Schema::List(Some(Box::new(Self::resolve(item, types, resolving)?)))
+                } else if let Some(value) = generic("Map") {
+                    Schema::Map(Some(Box::new(Self::resolve(value, types, resolving)?)))
+                } else if let Some(declared) = types.get(typ) {
+                    if resolving.iter().any(|name| name == typ) {
+                        return Err(RuntimeError::new(
+                            &format!("Recursive type '{}' cannot be requested from a language model", typ),
+                            SourceLocation::new(0, 0, 0, 0, ""),
+                        ));
+                    }
+
+                    resolving.push(typ.to_string());
+
+                    let mut fields = Vec::new();
+                    for (field, field_type) in declared {
+                        fields.push((field.clone(), Self::resolve(field_type, types, resolving)?));
+                    }
+
+                    resolving.pop();
+
+                    Schema::Record {
+                        name: typ.to_string(),
+                        fields,
+                    }
+                } else {
+                    return Err(RuntimeError::unknown_type(typ, SourceLocation::new(0, 0, 0, 0, "")));
+                }
+            }
+        };
+
+        Ok(schema)
+    }
+
+    /// Describe the schema as JSON Schema
+    pub fn to_json_schema(&self) -> serde_json::Value {
+        match self {
+            Schema::Int => json!({ "type": "integer" }),
+            Schema::Float => json!({ "type": "number" }),
+            Schema::String => json!({ "type": "string" }),
+            Schema::Bool => json!({ "type": "boolean" }),
+            Schema::Vector => json!({ "type": "array", "items": { "type": "number" } }),
+            Schema::List(None) => json!({ "type": "array" }),
+            Schema::List(Some(item)) => json!({ "type": "array", "items": item.to_json_schema() }),
+            Schema::Map(None) => json!({ "type": "object" }),
+            Schema::Map(Some(value)) => json!({ "type": "object", "additionalProperties": value.to_json_schema() }),
+            Schema::Record { name, fields } => {
+                let properties: serde_json::Map<String, serde_json::Value> = fields.iter()
+                    .map(|(field, schema)| (field.clone(), schema.to_json_schema()))
+                    .collect();
+                let required: Vec<&String> = fields.iter().map(|(field, _)| field).collect();
+
+                json!({
+                    "title": name,
+                    "type": "object",
+                    "properties": properties,
+                    "required": required,
+                    "additionalProperties": false,
+                })
+            }
+            Schema::Semantic(name) => json!({ "type": "string", "description": format!("A valid {}", name) }),
+        }
+    }
+
+    /// Check that a value matches the schema
+    ///
+    /// Returns the value converted to the schema: integers are accepted as
+    /// floats, whole floats as integers, lists of numbers as vectors, and fields
+    /// that a record does not declare are dropped. Semantic types are checked
+    /// with the natural language processor. On a mismatch, returns the path of
+    /// the offending value and what was wrong with it.
+    pub fn validate(&self, value: Value, nlp: &NLP) -> Result<Value, String> {
+        self.validate_at("$", value, nlp)
+    }
+
+    /// Check that the value at a path matches the schema
+    fn validate_at(&self, path: &str, value: Value, nlp: &NLP) -> Result<Value, String> {
+        let mismatch = |value: &Value| format!("{}: expected {}, got {}", path, self, describe(value));
+
+        match (self, value) {
+            (Schema::Int, Value::Int(i)) => Ok(Value::Int(i)),
+            (Schema::Int, Value::Float(f)) if f.fract() == 0.0 => Ok(Value::Int(f as i64)),
+            (Schema::Float, Value::Float(f)) => Ok(Value::Float(f)),
+            (Schema::Float, Value::Int(i)) => Ok(Value::Float(i as f64)),
+            (Schema::String, Value::String(s)) => Ok(Value::String(s)),
+            (Schema::Bool, Value::Bool(b)) => Ok(Value::Bool(b)),
+            (Schema::Vector, Value::List(items)) => {
+                let mut vector = Vec::new();
+
+                for (i, item) in items.into_iter().enumerate() {
+                    match Schema::Float.validate_at(&format!("{}[{}]", path, i), item, nlp)? {
+                        Value::Float(f) => vector.push(f),
+                        _ => unreachable!("floats validate to floats"),
+                    }
+                }
+
+                Ok(Value::Vector(vector))
+            }
+            (Schema::List(None), Value::List(items)) => Ok(Value::List(items)),
+            (Schema::List(Some(schema)), Value::List(items)) => {
+                let mut list = Vec::new();
+
+                for (i, item) in items.into_iter().enumerate() {
+                    list.push(schema.validate_at(&format!("{}[{}]", path, i), item, nlp)?);
+                }
+
+                Ok(Value::List(list))
+            }
+            (Schema::Map(None), Value::Map(map)) => Ok(Value::Map(map)),
+            (Schema::Map(Some(schema)), Value::Map(map)) => {
+                let mut validated = HashMap::new();
+
+                for (key, value) in map {
+                    let value = schema.validate_at(&format!("{}.{}", path, key), value, nlp)?;
+                    validated.insert(key, value);
+                }
+
+                Ok(Value::Map(validated))
+            }
+            (Schema::Record { fields, .. }, Value::Map(mut map)) => {
+                let mut record = HashMap::new();
+
+                for (field, schema) in fields {
+                    let field_path = format!("{}.{}", path, field);
+                    let value = map.remove(field).ok_or_else(|| format!("{}: missing field", field_path))?;
+                    record.insert(field.clone(), schema.validate_at(&field_path, value, nlp)?);
+                }
+
+                Ok(Value::Map(record))
+            }
+            (Schema::Semantic(name), Value::String(s)) => {
+                let value = Value::String(s);
+
+                if nlp.validate_semantic_type(&value, name) > 0.0 {
+                    Ok(value)
+                } else {
+                    Err(mismatch(&value))
+                }
+            }
+            (_, value) => Err(mismatch(&value)),
+        }
+    }
+}
+
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schema::Int => write!(f, "Int"),
+            Schema::Float => write!(f, "Float"),
+            Schema::String => write!(f, "String"),
+            Schema::Bool => write!(f, "Bool"),
+            Schema::Vector => write!(f, "Vector"),
+            Schema::List(None) => write!(f, "List"),
+            Schema::List(Some(item)) => write!(f, "List<{}>", item),
+            Schema::Map(None) => write!(f, "Map"),
+            Schema::Map(Some(value)) => write!(f, "Map<{}>", value),
+            Schema::Record { name, .. } => write!(f, "{}", name),
+            Schema::Semantic(name) => write!(f, "~{}~", name),
+        }
+    }
+}
+
+/// Describe a value in a validation error
+fn describe(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        Value::Int(_) | Value::Float(_) | Value::Bool(_) => crate::stdlib::StdLib::value_to_string(value),
+        Value::Void => "null".to_string(),
+        Value::List(_) => "a list".to_string(),
+        Value::Map(_) => "a map".to_string(),
+        _ => "an unsupported value".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_types() -> RecordTypes {
+        let mut types = RecordTypes::new();
+        types.insert("User".to_string(), vec![
+            ("name".to_string(), "String".to_string()),
+            ("email".to_string(), "~EmailAddress~".to_string()),
+            ("age".to_string(), "Int".to_string()),
+        ]);
+        types.insert("Node".to_string(), vec![("children".to_string(), "List<Node>".to_string())]);
+        types
+    }
+
+    fn user(name: &str, email: &str, age: Value) -> Value {
+        let mut map = HashMap::new();
+        map.insert("name".to_string(), Value::String(name.to_string()));
+        map.insert("email".to_string(), Value::String(email.to_string()));
+        map.insert("age".to_string(), age);
+        Value::Map(map)
+    }
+
+    #[test]
+    fn test_schema_from_type() {
+        let types = user_types();
+        let schema = Schema::from_type("List<User>", &types).unwrap();
+
+        assert_eq!(schema.to_string(), "List<User>");
+        assert_eq!(schema.to_json_schema()["items"]["required"], json!(["name", "email", "age"]));
+        assert_eq!(schema.to_json_schema()["items"]["properties"]["age"], json!({ "type": "integer" }));
+        assert_eq!(Schema::from_type("Map<Float>", &types).unwrap(), Schema::Map(Some(Box::new(Schema::Float))));
+
+        assert!(Schema::from_type("Order", &types).is_err());
+        assert!(Schema::from_type("Node", &types).is_err());
+    }
+
+    #[test]
+    fn test_schema_validate() {
+        let nlp = NLP::new();
+        let schema = Schema::from_type("List<User>", &user_types()).unwrap();
+
+        // Whole floats are accepted as integers
+        let valid = Value::List(vec![user("Alice", "alice@example.com", Value::Float(30.0))]);
+        assert_eq!(
+            schema.validate(valid, &nlp),
+            Ok(Value::List(vec![user("Alice", "alice@example.com", Value::Int(30))]))
+        );
+
+        let invalid = Value::List(vec![
+            user("Alice", "alice@example.com", Value::Int(30)),
+            user("Bob", "not an email", Value::Int(25)),
+        ]);
+        assert_eq!(
+            schema.validate(invalid, &nlp),
+            Err("$[1].email: expected ~EmailAddress~, got \"not an email\"".to_string())
+        );
+
+        let mut incomplete = HashMap::new();
+        incomplete.insert("name".to_string(), Value::String("Carol".to_string()));
+        assert_eq!(
+            schema.validate(Value::List(vec![Value::Map(incomplete)]), &nlp),
+            Err("$[0].email: missing field".to_string())
+        );
+
+        assert_eq!(Schema::Int.validate(Value::Float(1.5), &nlp), Err("$: expected Int, got 1.5".to_string()));
+    }
+}
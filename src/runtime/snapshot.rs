@@ -0,0 +1,355 @@
+//! Snapshot module for the LLM.lang runtime
+//!
+//! This module provides context snapshots for the LLM.lang runtime,
+//! which capture the variables, functions and memory of an execution
+//! context so that it can be saved to a file, restored and compared.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::parser::ast::Node;
+use crate::Value;
+use super::context::Context;
+use super::error::RuntimeError;
+use super::memory::Memory;
+
+/// A snapshot of an execution context
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContextSnapshot {
+    /// The context name
+    pub name: String,
+
+    /// The variables in the context
+    pub variables: HashMap<String, Value>,
+
+    /// The user-defined functions in the context
+    pub functions: HashMap<String, Node>,
+
+    /// The remembered values
+    pub memory: HashMap<String, Value>,
+}
+
+/// A change to a single entry between two snapshots
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change<T> {
+    /// The entry only exists in the newer snapshot
+    Added {
+        /// The entry name
+        name: String,
+
+        /// The new value
+        value: T,
+    },
+
+    /// The entry only exists in the older snapshot
+    Removed {
+        /// The entry name
+        name: String,
+
+        /// The old value
+        value: T,
+    },
+
+    /// The entry exists in both snapshots with different values
+    Changed {
+        /// The entry name
+        name: String,
+
+        /// The old value
+        before: T,
+
+        /// The new value
+        after: T,
+    },
+}
+
+impl<T> Change<T> {
+    /// Get the name of the changed entry
+    pub fn name(&self) -> &str {
+        match self {
+            Change::Added { name, .. } => name,
+            Change::Removed { name, .. } => name,
+            Change::Changed { name, .. } => name,
+        }
+    }
+}
+
+/// The differences between two context snapshots
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SnapshotDiff {
+    /// The variable changes
+    pub variables: Vec<Change<Value>>,
+
+    /// The function changes
+    pub functions: Vec<Change<Node>>,
+
+    /// The memory changes
+    pub memory: Vec<Change<Value>>,
+}
+
+impl ContextSnapshot {
+    /// Capture a snapshot of a context
+    pub fn capture(context: &Context, memory: &Memory, name: &str) -> Result<Self, RuntimeError> {
+        let (variables, functions) = context.get_context_state(name).ok_or_else(|| {
+            RuntimeError::undefined_context(name, crate::utils::SourceLocation::new(0, 0, 0, 0, ""))
+        })?;
+
+        // Standard library functions are registered by the engine and are not part of the snapshot
+        let functions = functions
+            .into_iter()
+            .filter(|(_, node)| node.location.file != "stdlib")
+            .collect();
+
+        Ok(Self {
+            name: name.to_string(),
+            variables,
+            functions,
+            memory: memory.entries().clone(),
+        })
+    }
+
+    /// Restore a snapshot into a context, replacing its current state
+    ///
+    /// Memory is shared by all contexts, so the whole memory is replaced
+    /// rather than merged with the values remembered since the snapshot.
+    pub fn restore(&self, context: &mut Context, memory: &mut Memory) {
+        // Keep the standard library functions registered in the context
+        let mut functions: HashMap<String, Node> = context
+            .get_context_state(&self.name)
+            .map(|(_, functions)| functions)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, node)| node.location.file == "stdlib")
+            .collect();
+
+        functions.extend(self.functions.clone());
+
+        context.set_context_state(&self.name, self.variables.clone(), functions);
+        memory.restore(self.memory.clone());
+    }
+
+    /// Save the snapshot to a file
+    pub fn save(&self, path: &Path) -> Result<(), RuntimeError> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| {
+            RuntimeError::new(
+                &format!("Failed to serialize context snapshot: {}", e),
+                crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+            )
+        })?;
+
+        std::fs::write(path, json).map_err(|e| {
+            RuntimeError::new(
+                &format!("Failed to save context snapshot to '{}': {}", path.display(), e),
+                crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+            )
+        })
+    }
+
+    /// Load a snapshot from a file
+    pub fn load(path: &Path) -> Result<Self, RuntimeError> {
+        let json = std::fs::read_to_string(path).map_err(|e| {
+            RuntimeError::new(
+                &format!("Failed to load context snapshot from '{}': {}", path.display(), e),
+                crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+            )
+        })?;
+
+        serde_json::from_str(&json).map_err(|e| {
+            RuntimeError::new(
+                &format!("Invalid context snapshot '{}': {}", path.display(), e),
+                crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+            )
+        })
+    }
+
+    /// Compare this snapshot with a newer one
+    pub fn diff(&self, other: &ContextSnapshot) -> SnapshotDiff {
+        SnapshotDiff {
+            variables: diff_maps(&self.variables, &other.variables),
+            functions: diff_maps(&self.functions, &other.functions),
+            memory: diff_maps(&self.memory, &other.memory),
+        }
+    }
+}
+
+impl SnapshotDiff {
+    /// Check if the snapshots were identical
+    pub fn is_empty(&self) -> bool {
+        self.variables.is_empty() && self.functions.is_empty() && self.memory.is_empty()
+    }
+
+    /// Convert the diff into a runtime value
+    ///
+    /// The result is a map with `variables`, `functions` and `memory` sections,
+    /// each containing `added`, `removed` and `changed` entries. Variable and
+    /// memory changes carry their values; function changes only carry names.
+    pub fn to_value(&self) -> Value {
+        let mut result = HashMap::new();
+
+        result.insert("variables".to_string(), Self::values_section(&self.variables));
+        result.insert("memory".to_string(), Self::values_section(&self.memory));
+
+        // Functions are reported by name
+        let mut functions = HashMap::new();
+        let mut added = Vec::new();
+        let mut removed = Vec::new();
+        let mut changed = Vec::new();
+
+        for change in &self.functions {
+            let name = Value::String(change.name().to_string());
+            match change {
+                Change::Added { .. } => added.push(name),
+                Change::Removed { .. } => removed.push(name),
+                Change::Changed { .. } => changed.push(name),
+            }
+        }
+
+        functions.insert("added".to_string(), Value::List(added));
+        functions.insert("removed".to_string(), Value::List(removed));
+        functions.insert("changed".to_string(), Value::List(changed));
+        result.insert("functions".to_string(), Value::Map(functions));
+
+        Value::Map(result)
+    }
+
+    /// Convert a list of value changes into a runtime value
+    fn values_section(changes: &[Change<Value>]) -> Value {
+        let mut added = HashMap::new();
+        let mut removed = HashMap::new();
+        let mut changed = HashMap::new();
+
+        for change in changes {
+            match change {
+                Change::Added { name, value } => {
+                    added.insert(name.clone(), value.clone());
+                }
+                Change::Removed { name, value } => {
+                    removed.insert(name.clone(), value.clone());
+                }
+                Change::Changed { name, before, after } => {
+                    let mut entry = HashMap::new();
+                    entry.insert("before".to_string(), before.clone());
+                    entry.insert("after".to_string(), after.clone());
+                    changed.insert(name.clone(), Value::Map(entry));
+                }
+            }
+        }
+
+        let mut section = HashMap::new();
+        section.insert("added".to_string(), Value::Map(added));
+        section.insert("removed".to_string(), Value::Map(removed));
+        section.insert("changed".to_string(), Value::Map(changed));
+
+        Value::Map(section)
+    }
+}
+
+/// Compare two maps, producing changes sorted by name
+fn diff_maps<T: Clone + PartialEq>(before: &HashMap<String, T>, after: &HashMap<String, T>) -> Vec<Change<T>> {
+    let mut names: Vec<&String> = before.keys().chain(after.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    let mut changes = Vec::new();
+
+    for name in names {
+        match (before.get(name), after.get(name)) {
+            (None, Some(value)) => changes.push(Change::Added {
+                name: name.clone(),
+                value: value.clone(),
+            }),
+            (Some(value), None) => changes.push(Change::Removed {
+                name: name.clone(),
+                value: value.clone(),
+            }),
+            (Some(old), Some(new)) if old != new => changes.push(Change::Changed {
+                name: name.clone(),
+                before: old.clone(),
+                after: new.clone(),
+            }),
+            _ => {}
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_capture_and_restore() {
+        let mut context = Context::new();
+        let mut memory = Memory::new();
+
+        context.register_variable("x", Value::Int(1));
+        memory.remember("fact", Value::String("hello".to_string()));
+
+        let snapshot = ContextSnapshot::capture(&context, &memory, "global").unwrap();
+
+        context.assign_variable("x", Value::Int(2)).unwrap();
+        context.register_variable("y", Value::Bool(true));
+        memory.remember("other", Value::Int(3));
+
+        snapshot.restore(&mut context, &mut memory);
+
+        assert_eq!(context.get_variable("x"), Some(Value::Int(1)));
+        assert_eq!(context.get_variable("y"), None);
+        assert!(memory.recall("other").is_err());
+        assert_eq!(memory.recall("fact").unwrap(), Value::String("hello".to_string()));
+    }
+
+    #[test]
+    fn test_snapshot_capture_undefined_context() {
+        let context = Context::new();
+        let memory = Memory::new();
+
+        let error = ContextSnapshot::capture(&context, &memory, "missing").unwrap_err();
+        assert_eq!(error.message, "Undefined context: 'missing'");
+    }
+
+    #[test]
+    fn test_snapshot_save_and_load() {
+        let mut context = Context::new();
+        let memory = Memory::new();
+
+        context.register_variable("items", Value::List(vec![Value::Int(1), Value::Float(2.5)]));
+
+        let snapshot = ContextSnapshot::capture(&context, &memory, "global").unwrap();
+
+        let path = std::env::temp_dir().join(format!("llm_lang_snapshot_{}.json", std::process::id()));
+        snapshot.save(&path).unwrap();
+        let loaded = ContextSnapshot::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, snapshot);
+    }
+
+    #[test]
+    fn test_snapshot_diff() {
+        let mut context = Context::new();
+        let memory = Memory::new();
+
+        context.register_variable("a", Value::Int(1));
+        context.register_variable("b", Value::Int(2));
+
+        let before = ContextSnapshot::capture(&context, &memory, "global").unwrap();
+
+        context.assign_variable("a", Value::Int(10)).unwrap();
+        context.register_variable("c", Value::Int(3));
+
+        let after = ContextSnapshot::capture(&context, &memory, "global").unwrap();
+        let diff = before.diff(&after);
+
+        assert_eq!(diff.variables, vec![
+            Change::Changed { name: "a".to_string(), before: Value::Int(1), after: Value::Int(10) },
+            Change::Added { name: "c".to_string(), value: Value::Int(3) },
+        ]);
+        assert!(diff.functions.is_empty());
+        assert!(diff.memory.is_empty());
+        assert!(before.diff(&before).is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+//! Stream module for the LLM.lang runtime
+//!
+//! This module provides text streams, which deliver the completion of a
+//! prompt in chunks as the language model generates it. The completion is
+//! generated on its own thread and sent through a bounded channel, so a
+//! backend that is faster than the code reading the stream waits for it
+//! instead of buffering the whole completion.
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::utils::SourceLocation;
+use super::budget::TokenBudget;
+use super::cancel::CancellationToken;
+use super::error::RuntimeError;
+use super::llm::LlmBackend;
+
+/// How many chunks a stream holds before the backend has to wait for them to be read
+pub const DEFAULT_STREAM_BUFFER: usize = 16;
+
+/// How often a stream that is waiting for a chunk checks for cancellation
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A stream of the chunks of a completion
+///
+/// Iterating the stream yields the chunks in order, or an error if the
+/// backend fails, no chunk arrives within the timeout, or execution is
+/// cancelled. Dropping the stream cancels the backend, which stops at its
+/// next chunk, or sooner if it is waiting for a server.
+#[derive(Debug)]
+pub struct TextStream {
+    /// The chunks sent by the thread that runs the backend
+    receiver: Receiver<Result<String, RuntimeError>>,
+
+    /// The cancellation token of the thread that runs the backend
+    stop: CancellationToken,
+
+    /// How long to wait for each chunk, if not indefinitely
+    timeout: Option<Duration>,
+
+    /// The location errors are reported at
+    location: SourceLocation,
+
+    /// Whether the stream has ended
+    done: bool,
+}
+
+impl TextStream {
+    /// Start streaming the completion of a prompt
+    ///
+    /// At most `buffer` chunks are held before the backend waits for them to
+    /// be read. The backend runs with the token budget and call site of the
+    /// calling thread, and a cancellation token that is cancelled with the
+    /// calling thread's or when the stream is dropped.
+    pub fn spawn(backend: Arc<dyn LlmBackend>, prompt: &str, buffer: usize, timeout: Option<Duration>, location: &SourceLocation) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(buffer);
+        let prompt = prompt.to_string();
+
+        let budget = TokenBudget::current();
+        let call_site = TokenBudget::call_site();
+        let stop = CancellationToken::current().map_or_else(CancellationToken::new, |token| token.child());
+        let token = stop.clone();
+
+        thread::spawn(move || {
+            let _budget = budget.as_ref().map(TokenBudget::enter);
+            let _call_site = TokenBudget::enter_call_site(&call_site);
+            let _token = token.enter();
+
+            // Sending fails once the stream has been dropped, which stops the backend too
+            let result = backend.complete_stream(&prompt, &mut |chunk| {
+                !token.is_cancelled() && sender.send(Ok(chunk.to_string())).is_ok()
+            });
+
+            if let Err(error) = result {
+                let _ = sender.send(Err(error));
+            }
+        });
+
+        Self {
+            receiver,
+            stop,
+            timeout,
+            location: location.clone(),
+            done: false,
+        }
+    }
+
+    /// End the stream with an error
+    fn fail(&mut self, error: RuntimeError) -> Option<Result<String, RuntimeError>> {
+        self.done = true;
+        Some(Err(error))
+    }
+}
+
+impl Iterator for TextStream {
+    type Item = Result<String, RuntimeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let started = Instant::now();
+
+        loop {
+            if let Some(token) = CancellationToken::current() {
+                if let Err(error) = token.check(&self.location) {
+                    return self.fail(error);
+                }
+            }
+
+            let wait = match self.timeout {
+                Some(timeout) => match timeout.checked_sub(started.elapsed()) {
+                    Some(left) if !left.is_zero() => left.min(POLL_INTERVAL),
+                    _ => return self.fail(RuntimeError::stream_timeout(timeout.as_millis() as u64, self.location.clone())),
+                },
+                None => POLL_INTERVAL,
+            };
+
+            match self.receiver.recv_timeout(wait) {
+                Ok(Ok(chunk)) => return Some(Ok(chunk)),
+                Ok(Err(error)) => {
+                    let location = self.location.clone();
+                    return self.fail(RuntimeError { location, ..error });
+                }
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.done = true;
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for TextStream {
+    fn drop(&mut self) {
+        self.stop.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::runtime::nlp::MockBackend;
+    use crate::runtime::testing::TestBackend;
+
+    /// A backend that streams numbered chunks after a delay, counting the chunks it produced
+    fn counting(delay: Duration) -> (Arc<TestBackend>, Arc<AtomicUsize>) {
+        let produced = Arc::new(AtomicUsize::new(0));
+        let counter = produced.clone();
+
+        let backend = TestBackend::new().on_stream(move |_, chunk| {
+            thread::sleep(delay);
+
+            for i in 0..10 {
+                counter.fetch_add(1, Ordering::SeqCst);
+                if !chunk(&i.to_string()) {
+                    break;
+                }
+            }
+
+            Ok(())
+        });
+
+        (Arc::new(backend), produced)
+    }
+
+    fn here() -> SourceLocation {
+        SourceLocation::new(2, 1, 2, 20, "test.llm")
+    }
+
+    #[test]
+    fn test_text_stream_mock_chunks() {
+        let chunks: Vec<String> = TextStream::spawn(Arc::new(MockBackend), "Once upon a time", 1, None, &here())
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(&chunks[..4], &["Once ", "upon ", "a ", "time, "]);
+        assert_eq!(chunks.concat(), MockBackend.complete("Once upon a time").unwrap());
+    }
+
+    #[test]
+    fn test_text_stream_back_pressure() {
+        let (backend, produced) = counting(Duration::ZERO);
+        let mut stream = TextStream::spawn(backend.clone(), "count", 2, None, &here());
+
+        // The backend waits once the buffer is full
+        assert_eq!(stream.next().unwrap().unwrap(), "0");
+        thread::sleep(Duration::from_millis(100));
+        assert!(produced.load(Ordering::SeqCst) <= 4);
+
+        // Dropping the stream stops the backend
+        drop(stream);
+        thread::sleep(Duration::from_millis(100));
+        assert!(produced.load(Ordering::SeqCst) < 10);
+
+        let stream = TextStream::spawn(backend.clone(), "count", 2, None, &here());
+        assert_eq!(stream.map(Result::unwrap).collect::<String>(), "0123456789");
+    }
+
+    #[test]
+    fn test_text_stream_timeout_and_cancellation() {
+        let (slow, _) = counting(Duration::from_millis(300));
+
+        let mut stream = TextStream::spawn(slow.clone(), "count", 2, Some(Duration::from_millis(20)), &here());
+        let error = stream.next().unwrap().unwrap_err();
+        assert_eq!(error.message, "No stream chunk received within 20ms");
+        assert_eq!(error.location, here());
+        assert!(stream.next().is_none());
+
+        let token = CancellationToken::new();
+        let _token = token.enter();
+        let mut stream = TextStream::spawn(slow, "count", 2, None, &here());
+        token.cancel();
+        assert_eq!(stream.next().unwrap().unwrap_err().message, "Execution was cancelled");
+    }
+
+    #[test]
+    fn test_text_stream_drop_stops_waiting_backend() {
+        let stopped = Arc::new(AtomicUsize::new(0));
+        let counter = stopped.clone();
+
+        // A backend that waits for a server that never answers, checking for cancellation
+        let backend = TestBackend::new().on_stream(move |_, _| {
+            loop {
+                if let Err(error) = CancellationToken::check_current() {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    return Err(error);
+                }
+
+                thread::sleep(Duration::from_millis(5));
+            }
+        });
+
+        let stream = TextStream::spawn(Arc::new(backend), "wait", 2, None, &here());
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(stopped.load(Ordering::SeqCst), 0);
+
+        drop(stream);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(stopped.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,778 @@
+//! Synthesis module for the LLM.lang runtime
+//!
+//! This module provides the program synthesizer for the LLM.lang runtime,
+//! which searches a small language of string, list and arithmetic operations
+//! for a program that is consistent with all examples of a function.
+//!
+//! The search enumerates expressions bottom-up, from the smallest, and keeps
+//! only one expression for each distinct set of outputs. String outputs can
+//! also be built by concatenating such expressions, in the style of FlashFill.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+use crate::parser::ast::{Ast, Node, NodeKind};
+use crate::stdlib::StdLib;
+use crate::utils::SourceLocation;
+use crate::Value;
+use super::cancel::CancellationToken;
+use super::error::RuntimeError;
+
+/// The largest expression the synthesizer enumerates, in nodes
+pub const MAX_EXPRESSION_SIZE: usize = 7;
+
+/// The largest number of expressions concatenated into a string
+pub const MAX_CONCATENATION: usize = 4;
+
+/// The largest number of distinct expressions kept during a search
+const MAX_EXPRESSIONS: usize = 50_000;
+
+/// The separators used as string constants when they occur in the examples
+const SEPARATORS: &[&str] = &[" ", ",", ".", "-", "_", "@", "/", ":", ";"];
+
+/// The standard library functions that can be passed to `map`
+const MAPPABLE_FUNCTIONS: &[&str] = &["toUpperCase", "toLowerCase", "trim", "length", "reverse"];
+
+/// The type of an expression in the synthesis language
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// A string
+    String,
+
+    /// An integer
+    Int,
+
+    /// A list
+    List,
+
+    /// A standard library function
+    Function,
+}
+
+/// An operation of the synthesis language
+struct Operation {
+    /// The name of the standard library function or operator
+    name: &'static str,
+
+    /// The kinds of the operands
+    operands: &'static [Kind],
+
+    /// Whether the operation is a binary operator
+    operator: bool,
+}
+
+/// The operations of the synthesis language
+///
+/// Every operation is a standard library function or an operator, so that a
+/// synthesized program is also an LLM.lang program.
+const OPERATIONS: &[Operation] = &[
+    Operation { name: "toUpperCase", operands: &[Kind::String], operator: false },
+    Operation { name: "toLowerCase", operands: &[Kind::String], operator: false },
+    Operation { name: "trim", operands: &[Kind::String], operator: false },
+    Operation { name: "reverse", operands: &[Kind::String], operator: false },
+    Operation { name: "length", operands: &[Kind::String], operator: false },
+    Operation { name: "indexOf", operands: &[Kind::String, Kind::String], operator: false },
+    Operation { name: "substring", operands: &[Kind::String, Kind::Int, Kind::Int], operator: false },
+    Operation { name: "split", operands: &[Kind::String, Kind::String], operator: false },
+    Operation { name: "join", operands: &[Kind::List, Kind::String], operator: false },
+    Operation { name: "length", operands: &[Kind::List], operator: false },
+    Operation { name: "first", operands: &[Kind::List], operator: false },
+    Operation { name: "last", operands: &[Kind::List], operator: false },
+    Operation { name: "reverse", operands: &[Kind::List], operator: false },
+    Operation { name: "sort", operands: &[Kind::List], operator: false },
+    Operation { name: "sum", operands: &[Kind::List], operator: false },
+    Operation { name: "max", operands: &[Kind::List], operator: false },
+    Operation { name: "min", operands: &[Kind::List], operator: false },
+    Operation { name: "map", operands: &[Kind::List, Kind::Function], operator: false },
+    Operation { name: "+", operands: &[Kind::Int, Kind::Int], operator: true },
+    Operation { name: "-", operands: &[Kind::Int, Kind::Int], operator: true },
+    Operation { name: "*", operands: &[Kind::Int, Kind::Int], operator: true },
+];
+
+/// A program of the synthesis language
+#[derive(Debug, Clone, PartialEq)]
+pub enum Program {
+    /// An argument of the function, by position
+    Argument(usize),
+
+    /// A constant
+    Constant(Value),
+
+    /// A standard library function passed as a value
+    Function(String),
+
+    /// A call to a standard library function
+    Call(String, Vec<Program>),
+
+    /// A binary operator
+    Binary(String, Box<Program>, Box<Program>),
+}
+
+/// An expression found during a search, with its outputs for every example
+#[derive(Debug, Clone)]
+struct Candidate {
+    /// The expression
+    program: Program,
+
+    /// The output for each example
+    outputs: Vec<Value>,
+
+    /// The kind of the outputs
+    kind: Kind,
+}
+
+impl Program {
+    /// Get the number of nodes in the program
+    pub fn size(&self) -> usize {
+        match self {
+            Program::Argument(_) | Program::Constant(_) | Program::Function(_) => 1,
+            Program::Call(_, arguments) => 1 + arguments.iter().map(Program::size).sum::<usize>(),
+            Program::Binary(_, left, right) => 1 + left.size() + right.size(),
+        }
+    }
+
+    /// Evaluate the program for a list of arguments
+    pub fn evaluate(&self, arguments: &[Value]) -> Result<Value, RuntimeError> {
+        self.evaluate_with(stdlib(), arguments)
+    }
+
+    /// Evaluate the program with a standard library
+    fn evaluate_with(&self, stdlib: &StdLib, arguments: &[Value]) -> Result<Value, RuntimeError> {
+        match self {
+            Program::Argument(i) => arguments.get(*i).cloned().ok_or_else(|| {
+                RuntimeError::new(
+                    &format!("Missing argument {}", i),
+                    SourceLocation::new(0, 0, 0, 0, ""),
+                )
+            }),
+            Program::Constant(value) => Ok(value.clone()),
+            Program::Function(name) => Ok(Value::Function(name.clone())),
+            Program::Call(name, operands) => {
+                let mut values = Vec::new();
+
+                for operand in operands {
+                    values.push(operand.evaluate_with(stdlib, arguments)?);
+                }
+
+                stdlib.call_function(name, values)
+            }
+            Program::Binary(operator, left, right) => {
+                let left = left.evaluate_with(stdlib, arguments)?;
+                let right = right.evaluate_with(stdlib, arguments)?;
+
+                let result = match (operator.as_str(), &left, &right) {
+                    ("+", Value::Int(a), Value::Int(b)) => a.checked_add(*b).map(Value::Int),
+                    ("-", Value::Int(a), Value::Int(b)) => a.checked_sub(*b).map(Value::Int),
+                    ("*", Value::Int(a), Value::Int(b)) => a.checked_mul(*b).map(Value::Int),
+                    ("+", Value::String(a), Value::String(b)) => Some(Value::String(a.clone() + b)),
+                    _ => None,
+                };
+
+                result.ok_or_else(|| {
+                    RuntimeError::invalid_operation(
+                        operator,
+                        &format!("{:?}", left),
+                        &format!("{:?}", right),
+                        SourceLocation::new(0, 0, 0, 0, ""),
+                    )
+                })
+            }
+        }
+    }
+
+    /// Convert the program to the AST of a function
+    ///
+    /// The function has one parameter per argument, named `input` if there is
+    /// only one and `input1`, `input2` and so on otherwise. The types of the
+    /// parameters and the return type are taken from an example.
+    pub fn to_ast(&self, function_name: &str, example: &[Value], output: &Value) -> Ast {
+        let location = SourceLocation::new(0, 0, 0, 0, "");
+        let parameters = Self::parameter_names(example.len());
+
+        let mut function = Self::node(NodeKind::Function, Vec::new());
+        function.attributes.insert("name".to_string(), function_name.to_string());
+        function.attributes.insert("return_type".to_string(), Self::type_name(output).to_string());
+
+        for (name, value) in parameters.iter().zip(example) {
+            let mut parameter = Self::node(NodeKind::Parameter, Vec::new());
+            parameter.attributes.insert("name".to_string(), name.clone());
+            parameter.attributes.insert("type".to_string(), Self::type_name(value).to_string());
+
+            function.children.push(Box::new(parameter));
+        }
+
+        let body = Self::node(NodeKind::Block, vec![
+            Self::node(NodeKind::Return, vec![self.to_node(&parameters)]),
+        ]);
+        function.children.push(Box::new(body));
+
+        let mut program = Self::node(NodeKind::Program, vec![function]);
+        program.location = location;
+
+        Ast::new(program)
+    }
+
+    /// Convert the program to an expression node
+    fn to_node(&self, parameters: &[String]) -> Node {
+        match self {
+            Program::Argument(i) => Self::identifier(&parameters[*i]),
+            Program::Constant(value) => Self::constant(value),
+            Program::Function(name) => Self::identifier(name),
+            Program::Call(name, operands) => {
+                let mut children = vec![Self::identifier(name)];
+                children.extend(operands.iter().map(|operand| operand.to_node(parameters)));
+
+                Self::node(NodeKind::Call, children)
+            }
+            Program::Binary(operator, left, right) => {
+                // Group operands that are operators themselves
+                let operand = |program: &Program| match program {
+                    Program::Binary(..) => Self::node(NodeKind::Grouping, vec![program.to_node(parameters)]),
+                    _ => program.to_node(parameters),
+                };
+
+                let mut binary = Self::node(NodeKind::Binary, vec![operand(left), operand(right)]);
+                binary.attributes.insert("operator".to_string(), operator.clone());
+                binary
+            }
+        }
+    }
+
+    /// Create a literal node for a constant, as the parser would
+    fn constant(value: &Value) -> Node {
+        let mut literal = Self::node(NodeKind::Literal, Vec::new());
+        literal.attributes.insert("type".to_string(), Self::type_name(value).to_string());
+
+        match value {
+            Value::String(s) => {
+                literal.attributes.insert("value".to_string(), Self::quote(s));
+            }
+            Value::List(items) => {
+                literal.children = items.iter().map(|item| Box::new(Self::constant(item))).collect();
+            }
+            _ => {
+                literal.attributes.insert("value".to_string(), StdLib::value_to_string(value));
+            }
+        }
+
+        literal
+    }
+
+    /// Quote a string as a string literal, escaping quotes and backslashes
+    fn quote(string: &str) -> String {
+        let mut quoted = String::with_capacity(string.len() + 2);
+        quoted.push('"');
+
+        for c in string.chars() {
+            match c {
+                '"' => quoted.push_str("\\\""),
+                '\\' => quoted.push_str("\\\\"),
+                '\n' => quoted.push_str("\\n"),
+                '\t' => quoted.push_str("\\t"),
+                _ => quoted.push(c),
+            }
+        }
+
+        quoted.push('"');
+        quoted
+    }
+
+    /// Create a node
+    fn node(kind: NodeKind, children: Vec<Node>) -> Node {
+        Node {
+            kind,
+            location: SourceLocation::new(0, 0, 0, 0, ""),
+            children: children.into_iter().map(Box::new).collect(),
+            attributes: HashMap::new(),
+        }
+    }
+
+    /// Create an identifier node
+    fn identifier(name: &str) -> Node {
+        let mut identifier = Self::node(NodeKind::Identifier, Vec::new());
+        identifier.attributes.insert("name".to_string(), name.to_string());
+        identifier
+    }
+
+    /// Get the names of the parameters of a function with a number of arguments
+    fn parameter_names(count: usize) -> Vec<String> {
+        if count == 1 {
+            vec!["input".to_string()]
+        } else {
+            (1..=count).map(|i| format!("input{}", i)).collect()
+        }
+    }
+
+    /// Get the LLM.lang type name of a value
+    fn type_name(value: &Value) -> &'static str {
+        match value {
+            Value::Int(_) => "Int",
+            Value::Float(_) => "Float",
+            Value::String(_) => "String",
+            Value::Bool(_) => "Bool",
+            Value::List(_) => "List",
+            Value::Map(_) => "Map",
+            Value::Vector(_) => "Vector",
+            _ => "Any",
+        }
+    }
+}
+
+/// Synthesize a program that is consistent with all examples
+///
+/// Each example is a list of arguments and the expected output. Returns the
+/// smallest program found, or `None` if there is none within the search
+/// limits. The search stops with an error if the current token of the calling
+/// thread is cancelled or expires.
+pub fn synthesize(examples: &[(Vec<Value>, Value)]) -> Result<Option<Program>, RuntimeError> {
+    let Some((first, _)) = examples.first() else {
+        return Ok(None);
+    };
+
+    if examples.iter().any(|(arguments, _)| arguments.len() != first.len()) {
+        return Ok(None);
+    }
+
+    Synthesizer::new(examples).search()
+}
+
+/// Get the standard library that programs are evaluated with
+///
+/// It is built once and shared by every search and every evaluation.
+fn stdlib() -> &'static StdLib {
+    static STDLIB: OnceLock<StdLib> = OnceLock::new();
+
+    STDLIB.get_or_init(StdLib::new)
+}
+
+/// The state of a search
+struct Synthesizer<'a> {
+    /// The examples
+    examples: &'a [(Vec<Value>, Value)],
+
+    /// The expected outputs
+    targets: Vec<Value>,
+
+    /// The expressions found, by size
+    bank: Vec<Vec<Candidate>>,
+
+    /// The outputs of the expressions found
+    seen: HashSet<String>,
+
+    /// The standard library used to evaluate expressions
+    stdlib: &'static StdLib,
+}
+
+impl<'a> Synthesizer<'a> {
+    /// Create a new search
+    fn new(examples: &'a [(Vec<Value>, Value)]) -> Self {
+        Self {
+            examples,
+            targets: examples.iter().map(|(_, output)| output.clone()).collect(),
+            bank: vec![Vec::new()],
+            seen: HashSet::new(),
+            stdlib: stdlib(),
+        }
+    }
+
+    /// Search for a program
+    fn search(mut self) -> Result<Option<Program>, RuntimeError> {
+        // Expressions of size one
+        let mut terminals = Vec::new();
+
+        for i in 0..self.examples[0].0.len() {
+            terminals.push(Program::Argument(i));
+        }
+
+        terminals.extend(self.constants().into_iter().map(Program::Constant));
+        terminals.extend(MAPPABLE_FUNCTIONS.iter().map(|name| Program::Function(name.to_string())));
+
+        let mut candidates = Vec::new();
+
+        for program in terminals {
+            CancellationToken::check_current()?;
+
+            if let Some(candidate) = self.evaluate(program) {
+                if candidate.outputs == self.targets {
+                    return Ok(Some(candidate.program));
+                }
+
+                candidates.push(candidate);
+            }
+        }
+
+        self.bank.push(candidates);
+
+        // Larger expressions, built from smaller ones
+        for size in 2..=MAX_EXPRESSION_SIZE {
+            let mut candidates = Vec::new();
+
+            for operation in OPERATIONS {
+                for program in self.combine(operation, size - 1) {
+                    if self.seen.len() >= MAX_EXPRESSIONS {
+                        break;
+                    }
+
+                    CancellationToken::check_current()?;
+
+                    if let Some(candidate) = self.evaluate(program) {
+                        if candidate.outputs == self.targets {
+                            return Ok(Some(candidate.program));
+                        }
+
+                        candidates.push(candidate);
+                    }
+                }
+            }
+
+            self.bank.push(candidates);
+        }
+
+        self.concatenate()
+    }
+
+    /// Get the constants that occur in the examples
+    fn constants(&self) -> Vec<Value> {
+        let mut constants = vec![Value::Int(0), Value::Int(1), Value::Int(2)];
+
+        for (arguments, output) in self.examples {
+            // The differences between numeric outputs and arguments
+            if let Value::Int(output) = output {
+                for argument in arguments {
+                    if let Value::Int(argument) = argument {
+                        if let Some(difference) = output.checked_sub(*argument) {
+                            constants.push(Value::Int(difference.abs()));
+                        }
+                    }
+                }
+            }
+
+            // The text an output adds around a string argument
+            if let Value::String(output) = output {
+                for argument in arguments {
+                    if let Value::String(argument) = argument {
+                        if let Some(position) = output.find(argument.as_str()).filter(|_| !argument.is_empty()) {
+                            constants.push(Value::String(output[..position].to_string()));
+                            constants.push(Value::String(output[position + argument.len()..].to_string()));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Separators that occur in the examples
+        for separator in SEPARATORS {
+            let occurs = |value: &Value| matches!(value, Value::String(s) if s.contains(separator));
+
+            if self.examples.iter().any(|(arguments, output)| occurs(output) || arguments.iter().any(occurs)) {
+                constants.push(Value::String(separator.to_string()));
+            }
+        }
+
+        // A constant output is only a pattern if there are several examples of it
+        if self.examples.len() > 1 && self.targets.iter().all(|target| *target == self.targets[0]) {
+            constants.push(self.targets[0].clone());
+        }
+
+        let mut unique = Vec::new();
+
+        for constant in constants {
+            if constant != Value::String(String::new()) && !unique.contains(&constant) {
+                unique.push(constant);
+            }
+        }
+
+        unique
+    }
+
+    /// Evaluate a program for every example
+    ///
+    /// Returns `None` if the program fails for an example, if its outputs do
+    /// not have a single kind, or if an equivalent program has been found.
+    fn evaluate(&mut self, program: Program) -> Option<Candidate> {
+        let mut outputs = Vec::new();
+
+        for (arguments, _) in self.examples {
+            outputs.push(program.evaluate_with(self.stdlib, arguments).ok()?);
+        }
+
+        let kind = match &program {
+            Program::Function(_) => Kind::Function,
+            _ => {
+                let kinds: Vec<Option<Kind>> = outputs.iter().map(Self::kind).collect();
+
+                match kinds[0] {
+                    Some(kind) if kinds.iter().all(|k| *k == Some(kind)) => kind,
+                    _ => return None,
+                }
+            }
+        };
+
+        let key = match &program {
+            Program::Function(name) => format!("function {}", name),
+            _ => format!("{:?}", outputs),
+        };
+
+        if !self.seen.insert(key) {
+            return None;
+        }
+
+        Some(Candidate { program, outputs, kind })
+    }
+
+    /// Get the kind of a value
+    fn kind(value: &Value) -> Option<Kind> {
+        match value {
+            Value::String(_) => Some(Kind::String),
+            Value::Int(_) => Some(Kind::Int),
+            Value::List(_) => Some(Kind::List),
+            _ => None,
+        }
+    }
+
+    /// Build the programs that apply an operation to expressions of a total size
+    fn combine(&self, operation: &Operation, size: usize) -> Vec<Program> {
+        let mut programs = Vec::new();
+        let mut operands = Vec::new();
+
+        self.combine_operands(operation, size, &mut operands, &mut programs);
+
+        programs
+    }
+
+    /// Choose the remaining operands of an operation
+    fn combine_operands(
+        &self,
+        operation: &Operation,
+        size: usize,
+        operands: &mut Vec<Program>,
+        programs: &mut Vec<Program>,
+    ) {
+        let index = operands.len();
+        let remaining = operation.operands.len() - index;
+
+        if remaining == 0 {
+            if size == 0 {
+                programs.push(if operation.operator {
+                    Program::Binary(
+                        operation.name.to_string(),
+                        Box::new(operands[0].clone()),
+                        Box::new(operands[1].clone()),
+                    )
+                } else {
+                    Program::Call(operation.name.to_string(), operands.clone())
+                });
+            }
+
+            return;
+        }
+
+        // Leave at least one node for each of the other operands
+        for operand_size in 1..=size.saturating_sub(remaining - 1) {
+            for candidate in self.bank.get(operand_size).into_iter().flatten() {
+                if candidate.kind != operation.operands[index] {
+                    continue;
+                }
+
+                operands.push(candidate.program.clone());
+                self.combine_operands(operation, size - operand_size, operands, programs);
+                operands.pop();
+            }
+        }
+    }
+
+    /// Search for a concatenation of expressions that builds the string outputs
+    fn concatenate(&self) -> Result<Option<Program>, RuntimeError> {
+        let targets: Option<Vec<&str>> = self.targets.iter().map(|target| match target {
+            Value::String(s) => Some(s.as_str()),
+            _ => None,
+        }).collect();
+
+        let Some(targets) = targets else {
+            return Ok(None);
+        };
+
+        // Smaller expressions first, so that the first match is the simplest
+        let pieces: Vec<(&Program, Vec<&str>)> = self.bank.iter().flatten()
+            .filter(|candidate| candidate.kind == Kind::String)
+            .map(|candidate| {
+                let outputs = candidate.outputs.iter().map(|output| match output {
+                    Value::String(s) => s.as_str(),
+                    _ => "",
+                }).collect();
+
+                (&candidate.program, outputs)
+            })
+            .filter(|(_, outputs): &(&Program, Vec<&str>)| outputs.iter().any(|output| !output.is_empty()))
+            .collect();
+
+        for count in 2..=MAX_CONCATENATION {
+            let offsets = vec![0; targets.len()];
+            let mut chosen = Vec::new();
+
+            if Self::concatenate_pieces(&targets, &pieces, &offsets, count, &mut chosen)? {
+                let mut program = chosen[0].clone();
+
+                for piece in &chosen[1..] {
+                    program = Program::Binary("+".to_string(), Box::new(program), Box::new((*piece).clone()));
+                }
+
+                return Ok(Some(program));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Choose the remaining pieces of a concatenation
+    fn concatenate_pieces<'p>(
+        targets: &[&str],
+        pieces: &[(&'p Program, Vec<&str>)],
+        offsets: &[usize],
+        count: usize,
+        chosen: &mut Vec<&'p Program>,
+    ) -> Result<bool, RuntimeError> {
+        if count == 0 {
+            return Ok(targets.iter().zip(offsets).all(|(target, offset)| *offset == target.len()));
+        }
+
+        for (program, outputs) in pieces {
+            let matches = targets.iter().zip(offsets).zip(outputs)
+                .all(|((target, offset), output)| target[*offset..].starts_with(output));
+
+            if !matches {
+                continue;
+            }
+
+            CancellationToken::check_current()?;
+
+            let next: Vec<usize> = offsets.iter().zip(outputs).map(|(offset, output)| offset + output.len()).collect();
+            chosen.push(program);
+
+            if Self::concatenate_pieces(targets, pieces, &next, count - 1, chosen)? {
+                return Ok(true);
+            }
+
+            chosen.pop();
+        }
+
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create examples of a function with one string argument
+    fn string_examples(pairs: &[(&str, &str)]) -> Vec<(Vec<Value>, Value)> {
+        pairs.iter()
+            .map(|(input, output)| (vec![Value::String(input.to_string())], Value::String(output.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn test_synthesize_string_programs() {
+        let program = synthesize(&string_examples(&[("hello", "Hello")])).unwrap().unwrap();
+
+        assert_eq!(program.evaluate(&[Value::String("world".to_string())]).unwrap(), Value::String("World".to_string()));
+
+        let program = synthesize(&string_examples(&[("apple", "apples"), ("language", "languages")])).unwrap().unwrap();
+
+        assert_eq!(program.evaluate(&[Value::String("box".to_string())]).unwrap(), Value::String("boxs".to_string()));
+
+        let program = synthesize(&string_examples(&[("john@example.com", "john"), ("ann@test.org", "ann")])).unwrap().unwrap();
+
+        assert_eq!(program.evaluate(&[Value::String("bob@mail.net".to_string())]).unwrap(), Value::String("bob".to_string()));
+    }
+
+    #[test]
+    fn test_synthesize_list_and_arithmetic_programs() {
+        let program = synthesize(&[
+            (vec![Value::Int(2)], Value::Int(4)),
+            (vec![Value::Int(5)], Value::Int(10)),
+        ]).unwrap().unwrap();
+
+        assert_eq!(program.evaluate(&[Value::Int(7)]).unwrap(), Value::Int(14));
+
+        let program = synthesize(&[
+            (vec![Value::Int(1), Value::Int(2)], Value::Int(3)),
+            (vec![Value::Int(5), Value::Int(3)], Value::Int(8)),
+        ]).unwrap().unwrap();
+
+        assert_eq!(program, Program::Binary(
+            "+".to_string(),
+            Box::new(Program::Argument(0)),
+            Box::new(Program::Argument(1)),
+        ));
+
+        let list = |items: &[i64]| Value::List(items.iter().map(|i| Value::Int(*i)).collect());
+        let program = synthesize(&[
+            (vec![list(&[3, 1, 2])], Value::Int(6)),
+            (vec![list(&[4, 4])], Value::Int(8)),
+        ]).unwrap().unwrap();
+
+        assert_eq!(program.evaluate(&[list(&[1, 1, 1])]).unwrap(), Value::Int(3));
+    }
+
+    #[test]
+    fn test_synthesize_no_program() {
+        // No single program maps these inputs to their outputs
+        let examples = string_examples(&[("apple", "apples"), ("box", "boxes"), ("child", "children")]);
+
+        assert_eq!(synthesize(&examples).unwrap(), None);
+        assert_eq!(synthesize(&[]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_synthesize_cancelled() {
+        let token = CancellationToken::new();
+        let _token = token.enter();
+        token.cancel();
+
+        let error = synthesize(&string_examples(&[("hello", "Hello")])).unwrap_err();
+        assert_eq!(error.message, "Execution was cancelled");
+    }
+
+    #[test]
+    fn test_program_to_ast() {
+        let program = synthesize(&string_examples(&[("hello", "Hello"), ("world", "World")])).unwrap().unwrap();
+        let ast = program.to_ast("capitalize", &[Value::String("hello".to_string())], &Value::String("Hello".to_string()));
+
+        let source = super::super::modify::Modify::new().generate_source(&ast).unwrap();
+
+        assert_eq!(
+            source,
+            "fn capitalize(input: String) -> String {\n    return toUpperCase(substring(input, 0, 1)) + substring(input, 1, length(input));\n}\n"
+        );
+
+        // The generated source is a valid program
+        let tokens = crate::Lexer::new(&source).tokenize().unwrap();
+        let ast = crate::Parser::new(tokens).parse().unwrap();
+        assert!(crate::semantic::SemanticAnalyzer::new().analyze(ast).is_ok());
+
+        // String constants are escaped, and list constants keep their elements
+        let program = Program::Binary("+".to_string(), Box::new(Program::Argument(0)), Box::new(Program::Constant(Value::String("\"\\".to_string()))));
+        let ast = program.to_ast("quote", &[Value::String("a".to_string())], &Value::String("a\"\\".to_string()));
+        let source = super::super::modify::Modify::new().generate_source(&ast).unwrap();
+        assert!(source.contains(r#"return input + "\"\\";"#));
+
+        let program = Program::Call("concat".to_string(), vec![
+            Program::Argument(0),
+            Program::Constant(Value::List(vec![Value::Int(1), Value::String("two".to_string())])),
+        ]);
+        let ast = program.to_ast("extend", &[Value::List(Vec::new())], &Value::List(Vec::new()));
+        let source = super::super::modify::Modify::new().generate_source(&ast).unwrap();
+        assert!(source.contains(r#"return concat(input, [1, "two"]);"#));
+
+        let list = |items: &[&str]| Value::List(items.iter().map(|s| Value::String(s.to_string())).collect());
+        let program = synthesize(&[
+            (vec![list(&["a", "b"])], list(&["A", "B"])),
+            (vec![list(&["c"])], list(&["C"])),
+        ]).unwrap().unwrap();
+
+        assert_eq!(program, Program::Call("map".to_string(), vec![
+            Program::Argument(0),
+            Program::Function("toUpperCase".to_string()),
+        ]));
+    }
+}
@@ -0,0 +1,167 @@
+//! Testing module for the LLM.lang runtime
+//!
+//! This module provides a language model backend for tests, whose replies are
+//! configured per test instead of being written as a new backend each time.
+
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::error::RuntimeError;
+use super::llm::{ChatMessage, LlmBackend};
+use super::vector::VectorEmbedding;
+
+type CompleteHandler = Box<dyn Fn(&str) -> Result<String, RuntimeError> + Send + Sync>;
+type ChatHandler = Box<dyn Fn(&[ChatMessage]) -> Result<String, RuntimeError> + Send + Sync>;
+type EmbedHandler = Box<dyn Fn(&str) -> Result<VectorEmbedding, RuntimeError> + Send + Sync>;
+type ClassifyHandler = Box<dyn Fn(&str, &[String]) -> Result<Vec<f64>, RuntimeError> + Send + Sync>;
+type StreamHandler = Box<dyn Fn(&str, &mut dyn FnMut(&str) -> bool) -> Result<(), RuntimeError> + Send + Sync>;
+
+/// A language model backend for tests
+///
+/// Without configuration, completions and chat replies are empty, embeddings
+/// are `[1.0]` and every category scores `1.0`. Each task can be replaced
+/// with a closure, and the backend counts the calls it receives.
+#[derive(Default)]
+pub struct TestBackend {
+    /// The reply to completions
+    complete: Option<CompleteHandler>,
+
+    /// The reply to conversations
+    chat: Option<ChatHandler>,
+
+    /// The embedding of texts
+    embed: Option<EmbedHandler>,
+
+    /// The category scores of texts
+    classify: Option<ClassifyHandler>,
+
+    /// The chunks of streamed completions
+    stream: Option<StreamHandler>,
+
+    /// The number of calls received
+    calls: AtomicUsize,
+}
+
+impl TestBackend {
+    /// Create a backend with the default replies
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reply to completions and conversations with the same text
+    pub fn replying(text: &str) -> Self {
+        let completion = text.to_string();
+        let reply = text.to_string();
+
+        Self::new()
+            .on_complete(move |_| Ok(completion.clone()))
+            .on_chat(move |_| Ok(reply.clone()))
+    }
+
+    /// Complete prompts with a closure
+    pub fn on_complete(mut self, complete: impl Fn(&str) -> Result<String, RuntimeError> + Send + Sync + 'static) -> Self {
+        self.complete = Some(Box::new(complete));
+        self
+    }
+
+    /// Reply to conversations with a closure
+    pub fn on_chat(mut self, chat: impl Fn(&[ChatMessage]) -> Result<String, RuntimeError> + Send + Sync + 'static) -> Self {
+        self.chat = Some(Box::new(chat));
+        self
+    }
+
+    /// Embed texts with a closure
+    pub fn on_embed(mut self, embed: impl Fn(&str) -> Result<VectorEmbedding, RuntimeError> + Send + Sync + 'static) -> Self {
+        self.embed = Some(Box::new(embed));
+        self
+    }
+
+    /// Score categories with a closure
+    pub fn on_classify(mut self, classify: impl Fn(&str, &[String]) -> Result<Vec<f64>, RuntimeError> + Send + Sync + 'static) -> Self {
+        self.classify = Some(Box::new(classify));
+        self
+    }
+
+    /// Stream completions with a closure
+    pub fn on_stream(mut self, stream: impl Fn(&str, &mut dyn FnMut(&str) -> bool) -> Result<(), RuntimeError> + Send + Sync + 'static) -> Self {
+        self.stream = Some(Box::new(stream));
+        self
+    }
+
+    /// Score the categories by their position, so the last category fits best
+    pub fn preferring_last_category(self) -> Self {
+        self.on_classify(|_, categories| Ok((0..categories.len()).map(|i| i as f64).collect()))
+    }
+
+    /// Get the number of calls received
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    fn count(&self) {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl fmt::Debug for TestBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TestBackend").field("calls", &self.calls()).finish()
+    }
+}
+
+impl LlmBackend for TestBackend {
+    fn name(&self) -> String {
+        "test".to_string()
+    }
+
+    fn complete(&self, prompt: &str) -> Result<String, RuntimeError> {
+        self.count();
+
+        match &self.complete {
+            Some(complete) => complete(prompt),
+            None => Ok(String::new()),
+        }
+    }
+
+    fn complete_stream(&self, prompt: &str, chunk: &mut dyn FnMut(&str) -> bool) -> Result<(), RuntimeError> {
+        match &self.stream {
+            Some(stream) => {
+                self.count();
+                stream(prompt, chunk)
+            }
+            None => {
+                let completion = self.complete(prompt)?;
+                chunk(&completion);
+
+                Ok(())
+            }
+        }
+    }
+
+    fn chat(&self, messages: &[ChatMessage]) -> Result<String, RuntimeError> {
+        self.count();
+
+        match &self.chat {
+            Some(chat) => chat(messages),
+            None => Ok(String::new()),
+        }
+    }
+
+    fn embed(&self, text: &str) -> Result<VectorEmbedding, RuntimeError> {
+        self.count();
+
+        match &self.embed {
+            Some(embed) => embed(text),
+            None => Ok(vec![1.0]),
+        }
+    }
+
+    fn classify(&self, text: &str, categories: &[String]) -> Result<Vec<f64>, RuntimeError> {
+        self.count();
+
+        match &self.classify {
+            Some(classify) => classify(text, categories),
+            None => Ok(vec![1.0; categories.len()]),
+        }
+    }
+}
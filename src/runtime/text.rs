@@ -0,0 +1,565 @@
+//! Text module for the LLM.lang runtime
+//!
+//! This module provides deterministic text analysis that needs no model:
+//! tokenization, sentence splitting, Porter stemming, rule-based
+//! lemmatization, TF-IDF keywords and lexicon-based sentiment. The mock
+//! backend answers the text analysis tasks of the natural language processor
+//! with these, so they work offline.
+
+use std::collections::HashMap;
+
+use super::rag;
+
+/// The number of keywords `extractKeywords` returns
+pub const DEFAULT_KEYWORD_COUNT: usize = 10;
+
+/// Words that carry too little meaning to be keywords
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "after", "again", "all", "also", "am", "an", "and", "any", "are", "as", "at",
+    "be", "because", "been", "before", "being", "between", "both", "but", "by", "can", "could",
+    "did", "do", "does", "doing", "down", "during", "each", "few", "for", "from", "further",
+    "had", "has", "have", "having", "he", "her", "here", "hers", "him", "his", "how", "i", "if",
+    "in", "into", "is", "it", "its", "just", "me", "more", "most", "my", "no", "nor", "not", "now",
+    "of", "off", "on", "once", "only", "or", "other", "our", "out", "over", "own", "same", "she",
+    "should", "so", "some", "such", "than", "that", "the", "their", "them", "then", "there",
+    "these", "they", "this", "those", "through", "to", "too", "under", "until", "up", "very",
+    "was", "we", "were", "what", "when", "where", "which", "while", "who", "whom", "why", "will",
+    "with", "would", "you", "your",
+];
+
+/// Words with a sentiment, from -3 (very negative) to 3 (very positive)
+const SENTIMENT_LEXICON: &[(&str, f64)] = &[
+    ("amazing", 3.0), ("awesome", 3.0), ("excellent", 3.0), ("fantastic", 3.0), ("love", 3.0),
+    ("outstanding", 3.0), ("perfect", 3.0), ("wonderful", 3.0), ("brilliant", 3.0),
+    ("beautiful", 2.0), ("delighted", 2.0), ("enjoy", 2.0), ("glad", 2.0), ("great", 2.0),
+    ("happy", 2.0), ("impressive", 2.0), ("like", 2.0), ("pleased", 2.0), ("recommend", 2.0),
+    ("best", 2.0), ("fun", 2.0), ("thank", 2.0), ("thanks", 2.0),
+    ("easy", 1.0), ("fast", 1.0), ("fine", 1.0), ("good", 1.0), ("helpful", 1.0), ("nice", 1.0),
+    ("reliable", 1.0), ("works", 1.0), ("useful", 1.0), ("clean", 1.0), ("ok", 1.0),
+    ("broken", -2.0), ("bug", -1.0), ("confusing", -2.0), ("difficult", -1.0), ("disappointed", -2.0),
+    ("fail", -2.0), ("failed", -2.0), ("fails", -2.0), ("hard", -1.0), ("poor", -2.0),
+    ("problem", -1.0), ("slow", -1.0), ("sad", -2.0), ("unhappy", -2.0), ("wrong", -2.0),
+    ("worse", -2.0), ("annoying", -2.0), ("crash", -2.0), ("crashes", -2.0), ("error", -1.0),
+    ("awful", -3.0), ("hate", -3.0), ("horrible", -3.0), ("terrible", -3.0), ("useless", -3.0),
+    ("worst", -3.0), ("disaster", -3.0),
+];
+
+/// Words that invert the sentiment of the words after them
+const NEGATIONS: &[&str] = &["not", "no", "never", "none", "nothing", "neither", "nor", "without", "cannot"];
+
+/// How many words after a negation it applies to
+const NEGATION_SCOPE: usize = 3;
+
+/// Words that strengthen or weaken the sentiment of the next word, and by how much
+const INTENSIFIERS: &[(&str, f64)] = &[
+    ("very", 1.5), ("really", 1.5), ("extremely", 2.0), ("so", 1.3), ("too", 1.3), ("quite", 1.2),
+    ("slightly", 0.5), ("somewhat", 0.7), ("barely", 0.5),
+];
+
+/// Scores within this distance from zero are neutral
+const NEUTRAL_SENTIMENT: f64 = 0.05;
+
+/// Words whose lemma no suffix rule finds
+const IRREGULAR_LEMMAS: &[(&str, &str)] = &[
+    ("am", "be"), ("are", "be"), ("is", "be"), ("was", "be"), ("were", "be"), ("been", "be"), ("being", "be"),
+    ("has", "have"), ("had", "have"), ("having", "have"), ("does", "do"), ("did", "do"), ("done", "do"),
+    ("went", "go"), ("gone", "go"), ("goes", "go"), ("made", "make"), ("said", "say"), ("saw", "see"),
+    ("seen", "see"), ("took", "take"), ("taken", "take"), ("came", "come"), ("knew", "know"),
+    ("known", "know"), ("got", "get"), ("gave", "give"), ("given", "give"), ("found", "find"),
+    ("thought", "think"), ("told", "tell"), ("became", "become"), ("left", "leave"), ("felt", "feel"),
+    ("brought", "bring"), ("began", "begin"), ("begun", "begin"), ("kept", "keep"), ("held", "hold"),
+    ("wrote", "write"), ("written", "write"), ("stood", "stand"), ("ran", "run"), ("ate", "eat"),
+    ("eaten", "eat"), ("bought", "buy"), ("taught", "teach"), ("caught", "catch"), ("sent", "send"),
+    ("built", "build"), ("spent", "spend"), ("better", "good"), ("best", "good"), ("worse", "bad"),
+    ("worst", "bad"), ("men", "man"), ("women", "woman"), ("children", "child"), ("people", "person"),
+    ("mice", "mouse"), ("feet", "foot"), ("teeth", "tooth"), ("geese", "goose"), ("data", "datum"),
+];
+
+/// Words ending in "us" that keep it when inflected, unlike "cause" and "use"
+const US_WORDS: &[&str] = &[
+    "bonus", "bus", "campus", "census", "chorus", "circus", "focus", "genus", "status", "surplus", "virus",
+];
+
+/// Split text into word and punctuation tokens
+///
+/// Words are runs of letters and digits, which may contain apostrophes and
+/// hyphens between them, as in "don't" and "state-of-the-art". Every other
+/// character that is not whitespace is a token of its own.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+
+    for (i, c) in chars.iter().enumerate() {
+        let joins = matches!(c, '\'' | '-' | '\u{2019}')
+            && !word.is_empty()
+            && chars.get(i + 1).is_some_and(|next| next.is_alphanumeric());
+
+        if c.is_alphanumeric() || joins {
+            word.push(*c);
+            continue;
+        }
+
+        if !word.is_empty() {
+            tokens.push(std::mem::take(&mut word));
+        }
+
+        if !c.is_whitespace() {
+            tokens.push(c.to_string());
+        }
+    }
+
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+
+    tokens
+}
+
+/// Split text into sentences
+///
+/// See `rag::sentence_spans` for where sentences end.
+pub fn split_sentences(text: &str) -> Vec<String> {
+    rag::sentence_spans(text).into_iter().map(|(start, end)| text[start..end].to_string()).collect()
+}
+
+/// Whether the letter at a position of a word is a consonant, as the Porter stemmer defines it
+fn is_consonant(word: &[u8], i: usize) -> bool {
+    match word[i] {
+        b'a' | b'e' | b'i' | b'o' | b'u' => false,
+        b'y' => i == 0 || !is_consonant(word, i - 1),
+        _ => true,
+    }
+}
+
+/// The number of vowel-consonant sequences in a word stem
+fn measure(word: &[u8]) -> usize {
+    let mut count = 0;
+    let mut previous_vowel = false;
+
+    for i in 0..word.len() {
+        let consonant = is_consonant(word, i);
+
+        if consonant && previous_vowel {
+            count += 1;
+        }
+
+        previous_vowel = !consonant;
+    }
+
+    count
+}
+
+/// Whether a word stem contains a vowel
+fn has_vowel(word: &[u8]) -> bool {
+    (0..word.len()).any(|i| !is_consonant(word, i))
+}
+
+/// Whether a word stem ends with a double consonant
+fn ends_with_double_consonant(word: &[u8]) -> bool {
+    let n = word.len();
+    n >= 2 && word[n - 1] == word[n - 2] && is_consonant(word, n - 1)
+}
+
+/// Whether a word stem ends consonant-vowel-consonant, where the last consonant is not w, x or y
+fn ends_cvc(word: &[u8]) -> bool {
+    let n = word.len();
+    n >= 3
+        && is_consonant(word, n - 3)
+        && !is_consonant(word, n - 2)
+        && is_consonant(word, n - 1)
+        && !matches!(word[n - 1], b'w' | b'x' | b'y')
+}
+
+/// Replace a suffix of a word if the stem before it has a measure greater than `min_measure`
+///
+/// Returns whether the word ends with the suffix, whether or not it was replaced.
+fn replace_suffix(word: &mut Vec<u8>, suffix: &str, replacement: &str, min_measure: usize) -> bool {
+    if !word.ends_with(suffix.as_bytes()) {
+        return false;
+    }
+
+    let stem = word.len() - suffix.len();
+
+    if measure(&word[..stem]) > min_measure {
+        word.truncate(stem);
+        word.extend_from_slice(replacement.as_bytes());
+    }
+
+    true
+}
+
+/// Reduce a word to its stem with the Porter stemming algorithm
+///
+/// The word is lowercased first. Words of one or two letters, and words
+/// that are not ASCII letters, are returned lowercased.
+pub fn stem(word: &str) -> String {
+    let lower = word.to_lowercase();
+
+    if lower.len() <= 2 || !lower.bytes().all(|b| b.is_ascii_lowercase()) {
+        return lower;
+    }
+
+    let mut word = lower.into_bytes();
+
+    // Step 1a: plurals
+    if word.ends_with(b"sses") || word.ends_with(b"ies") {
+        word.truncate(word.len() - 2);
+    } else if word.ends_with(b"s") && !word.ends_with(b"ss") {
+        word.pop();
+    }
+
+    // Step 1b: past tenses and gerunds
+    if word.ends_with(b"eed") {
+        if measure(&word[..word.len() - 3]) > 0 {
+            word.pop();
+        }
+    } else {
+        let suffix = [&b"ed"[..], &b"ing"[..]].into_iter().find(|suffix| {
+            word.ends_with(suffix) && has_vowel(&word[..word.len() - suffix.len()])
+        });
+
+        if let Some(suffix) = suffix {
+            word.truncate(word.len() - suffix.len());
+
+            if word.ends_with(b"at") || word.ends_with(b"bl") || word.ends_with(b"iz") {
+                word.push(b'e');
+            } else if ends_with_double_consonant(&word) && !matches!(word[word.len() - 1], b'l' | b's' | b'z') {
+                word.pop();
+            } else if measure(&word) == 1 && ends_cvc(&word) {
+                word.push(b'e');
+            }
+        }
+    }
+
+    // Step 1c: a final y becomes i when the stem contains a vowel
+    if word.ends_with(b"y") && has_vowel(&word[..word.len() - 1]) {
+        let last = word.len() - 1;
+        word[last] = b'i';
+    }
+
+    // Step 2: double suffixes
+    const STEP_2: &[(&str, &str)] = &[
+        ("ational", "ate"), ("tional", "tion"), ("enci", "ence"), ("anci", "ance"), ("izer", "ize"),
+        ("abli", "able"), ("alli", "al"), ("entli", "ent"), ("eli", "e"), ("ousli", "ous"),
+        ("ization", "ize"), ("ation", "ate"), ("ator", "ate"), ("alism", "al"), ("iveness", "ive"),
+        ("fulness", "ful"), ("ousness", "ous"), ("aliti", "al"), ("iviti", "ive"), ("biliti", "ble"),
+    ];
+
+    for (suffix, replacement) in STEP_2 {
+        if replace_suffix(&mut word, suffix, replacement, 0) {
+            break;
+        }
+    }
+
+    // Step 3: -ic-, -full, -ness and the like
+    const STEP_3: &[(&str, &str)] = &[
+        ("icate", "ic"), ("ative", ""), ("alize", "al"), ("iciti", "ic"), ("ical", "ic"), ("ful", ""), ("ness", ""),
+    ];
+
+    for (suffix, replacement) in STEP_3 {
+        if replace_suffix(&mut word, suffix, replacement, 0) {
+            break;
+        }
+    }
+
+    // Step 4: suffixes of longer stems
+    const STEP_4: &[&str] = &[
+        "al", "ance", "ence", "er", "ic", "able", "ible", "ant", "ement", "ment", "ent", "ion", "ou",
+        "ism", "ate", "iti", "ous", "ive", "ize",
+    ];
+
+    // The longest matching suffix is removed, so "ement" is tried before "ment" and "ent"
+    let suffix = STEP_4.iter()
+        .filter(|suffix| word.ends_with(suffix.as_bytes()))
+        .max_by_key(|suffix| suffix.len());
+
+    if let Some(suffix) = suffix {
+        let stem = word.len() - suffix.len();
+        let allowed = *suffix != "ion" || matches!(word[..stem].last(), Some(b's' | b't'));
+
+        if allowed && measure(&word[..stem]) > 1 {
+            word.truncate(stem);
+        }
+    }
+
+    // Step 5a: a final e
+    if word.ends_with(b"e") {
+        let stem = &word[..word.len() - 1];
+        let m = measure(stem);
+
+        if m > 1 || (m == 1 && !ends_cvc(stem)) {
+            word.pop();
+        }
+    }
+
+    // Step 5b: a final double l
+    if measure(&word) > 1 && word.ends_with(b"ll") {
+        word.pop();
+    }
+
+    String::from_utf8(word).unwrap_or_default()
+}
+
+/// Reduce a word to its dictionary form
+///
+/// Irregular forms are looked up, and regular plurals, past tenses and
+/// gerunds have their suffixes removed. The word is lowercased.
+pub fn lemmatize(word: &str) -> String {
+    let lower = word.to_lowercase();
+
+    if let Some((_, lemma)) = IRREGULAR_LEMMAS.iter().find(|(form, _)| *form == lower) {
+        return lemma.to_string();
+    }
+
+    if lower.len() <= 3 || !lower.bytes().all(|b| b.is_ascii_lowercase()) {
+        return lower;
+    }
+
+    // Plurals and third person verbs
+    if let Some(stem) = lower.strip_suffix("ies") {
+        return format!("{}y", stem);
+    }
+
+    for suffix in ["sses", "shes", "ches", "xes", "zes"] {
+        if lower.ends_with(suffix) {
+            return lower[..lower.len() - 2].to_string();
+        }
+    }
+
+    if let Some(stem) = lower.strip_suffix("es").filter(|stem| US_WORDS.contains(stem)) {
+        return stem.to_string();
+    }
+
+    if lower.ends_with('s') && !lower.ends_with("ss") && !lower.ends_with("us") && !lower.ends_with("is") {
+        return lower[..lower.len() - 1].to_string();
+    }
+
+    // Past tenses and gerunds, leaving words such as "need" and "agreed" as they are
+    if let Some(stem) = lower.strip_suffix("ied") {
+        return format!("{}y", stem);
+    }
+
+    if lower.ends_with("eed") {
+        return lower;
+    }
+
+    for suffix in ["ing", "ed"] {
+        let Some(stem) = lower.strip_suffix(suffix) else {
+            continue;
+        };
+
+        let bytes = stem.as_bytes();
+
+        if stem.len() < 2 || !has_vowel(bytes) {
+            return lower;
+        }
+
+        // "running" is "run", but "falling" is "fall"
+        if ends_with_double_consonant(bytes) && !matches!(bytes[bytes.len() - 1], b'l' | b's' | b'z') {
+            return stem[..stem.len() - 1].to_string();
+        }
+
+        // "making" is "make", and "created" is "create", but "focused" is "focus"
+        let ends_use = stem.ends_with("us") && !US_WORDS.contains(&stem);
+
+        if (measure(bytes) == 1 && ends_cvc(bytes)) || stem.ends_with("at") || stem.ends_with("iz") || ends_use {
+            return format!("{}e", stem);
+        }
+
+        return stem.to_string();
+    }
+
+    lower
+}
+
+/// Find the keywords of text by TF-IDF, most important first
+///
+/// The sentences of the text are the documents, so words that are frequent
+/// in some sentences but not in all of them score highest. Words with the
+/// same stem count as one keyword, named by their most frequent form. The
+/// scores are scaled so that the best keyword scores 1.
+pub fn keywords(text: &str, count: usize) -> Vec<(String, f64)> {
+    let sentences = split_sentences(text);
+    let mut frequencies: HashMap<String, usize> = HashMap::new();
+    let mut document_frequencies: HashMap<String, usize> = HashMap::new();
+    let mut forms: HashMap<String, HashMap<String, usize>> = HashMap::new();
+    let mut order: Vec<String> = Vec::new();
+
+    for sentence in &sentences {
+        let mut seen = Vec::new();
+
+        for token in tokenize(sentence) {
+            let word = token.to_lowercase();
+
+            if word.chars().count() < 3 || !word.chars().any(char::is_alphabetic) || STOP_WORDS.contains(&word.as_str()) {
+                continue;
+            }
+
+            let stem = stem(&word);
+
+            if !frequencies.contains_key(&stem) {
+                order.push(stem.clone());
+            }
+
+            *frequencies.entry(stem.clone()).or_default() += 1;
+            *forms.entry(stem.clone()).or_default().entry(word).or_default() += 1;
+
+            if !seen.contains(&stem) {
+                *document_frequencies.entry(stem.clone()).or_default() += 1;
+                seen.push(stem);
+            }
+        }
+    }
+
+    let documents = sentences.len() as f64;
+    let total: usize = frequencies.values().sum();
+
+    let mut scored: Vec<(String, f64)> = order.into_iter().map(|stem| {
+        let tf = frequencies[&stem] as f64 / total as f64;
+        let idf = ((1.0 + documents) / (1.0 + document_frequencies[&stem] as f64)).ln() + 1.0;
+
+        // The most frequent form names the keyword, and the alphabetically first of them on ties
+        let form_counts = &forms[&stem];
+        let form = form_counts.iter()
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+            .map(|(form, _)| form.clone())
+            .unwrap_or(stem);
+
+        (form, tf * idf)
+    }).collect();
+
+    // The sort is stable, so ties keep the order the words first appear in
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(count);
+
+    let best = scored.first().map_or(1.0, |(_, score)| *score);
+
+    scored.into_iter().map(|(keyword, score)| (keyword, score / best)).collect()
+}
+
+/// Score the sentiment of text from -1 (negative) to 1 (positive)
+///
+/// Words are scored with a lexicon. A negation inverts the words after it,
+/// and an intensifier scales the word after it. The sum is normalized into
+/// the range without bounding it by the length of the text.
+pub fn sentiment(text: &str) -> f64 {
+    let words: Vec<String> = tokenize(text)
+        .into_iter()
+        .map(|token| token.to_lowercase())
+        .filter(|token| token.chars().any(char::is_alphanumeric))
+        .collect();
+
+    let mut sum = 0.0;
+    let mut negated_until = 0;
+    let mut intensity = 1.0;
+
+    for (i, word) in words.iter().enumerate() {
+        if NEGATIONS.contains(&word.as_str()) || word.ends_with("n't") {
+            negated_until = i + NEGATION_SCOPE + 1;
+            continue;
+        }
+
+        if let Some((_, factor)) = INTENSIFIERS.iter().find(|(intensifier, _)| intensifier == word) {
+            intensity = *factor;
+            continue;
+        }
+
+        let valence = SENTIMENT_LEXICON.iter()
+            .find(|(entry, _)| entry == word)
+            .or_else(|| {
+                let word_stem = stem(word);
+                SENTIMENT_LEXICON.iter().find(|(entry, _)| stem(entry) == word_stem)
+            })
+            .map_or(0.0, |(_, valence)| *valence);
+
+        if valence != 0.0 {
+            let negation = if i < negated_until { -0.75 } else { 1.0 };
+            sum += valence * intensity * negation;
+        }
+
+        intensity = 1.0;
+    }
+
+    // Normalize as VADER does
+    sum / (sum * sum + 15.0).sqrt()
+}
+
+/// Label a sentiment score as positive, negative or neutral
+pub fn sentiment_label(score: f64) -> &'static str {
+    if score > NEUTRAL_SENTIMENT {
+        "positive"
+    } else if score < -NEUTRAL_SENTIMENT {
+        "negative"
+    } else {
+        "neutral"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("Don't stop, it's state-of-the-art! -5"),
+            vec!["Don't", "stop", ",", "it's", "state-of-the-art", "!", "-", "5"]
+        );
+        assert!(tokenize("  ").is_empty());
+        assert_eq!(split_sentences("One. Two? Three"), vec!["One.", "Two?", "Three"]);
+    }
+
+    #[test]
+    fn test_stem() {
+        let cases = [
+            ("caresses", "caress"), ("ponies", "poni"), ("cats", "cat"), ("feed", "feed"), ("agreed", "agre"),
+            ("plastered", "plaster"), ("motoring", "motor"), ("sing", "sing"), ("conflated", "conflat"),
+            ("troubled", "troubl"), ("sized", "size"), ("hopping", "hop"), ("falling", "fall"), ("filing", "file"),
+            ("happy", "happi"), ("relational", "relat"), ("conditional", "condit"), ("rational", "ration"),
+            ("generalization", "gener"), ("electrical", "electr"), ("adjustment", "adjust"), ("adoption", "adopt"),
+            ("controlling", "control"), ("roll", "roll"), ("Running", "run"), ("hopefulness", "hope"),
+        ];
+
+        for (word, expected) in cases {
+            assert_eq!(stem(word), expected, "stem of {}", word);
+        }
+    }
+
+    #[test]
+    fn test_lemmatize() {
+        let cases = [
+            ("Cities", "city"), ("boxes", "box"), ("cats", "cat"), ("glass", "glass"), ("running", "run"),
+            ("falling", "fall"), ("making", "make"), ("created", "create"), ("walked", "walk"), ("studied", "study"),
+            ("went", "go"), ("children", "child"), ("is", "be"), ("sing", "sing"), ("focused", "focus"),
+            ("focusing", "focus"), ("focuses", "focus"), ("caused", "cause"), ("causes", "cause"), ("viruses", "virus"),
+        ];
+
+        for (word, expected) in cases {
+            assert_eq!(lemmatize(word), expected, "lemma of {}", word);
+        }
+    }
+
+    #[test]
+    fn test_keywords() {
+        let text = "Rust programs are fast. Rust programs are safe. The compiler checks programs. Memory safety matters.";
+        let keywords = keywords(text, 3);
+
+        assert_eq!(keywords.len(), 3);
+        assert_eq!(keywords[0], ("programs".to_string(), 1.0));
+        assert_eq!(keywords[1].0, "rust");
+        assert!(keywords[2].1 < keywords[1].1);
+        assert!(super::keywords("", 3).is_empty());
+    }
+
+    #[test]
+    fn test_sentiment() {
+        assert!(sentiment("I love this library, it is excellent!") > 0.5);
+        assert!(sentiment("This is terrible and the build is broken.") < -0.5);
+        assert_eq!(sentiment("The file has three lines."), 0.0);
+        assert!(sentiment("This is not good.") < 0.0);
+        assert!(sentiment("very good") > sentiment("good"));
+        assert_eq!(sentiment_label(sentiment("I really enjoyed it")), "positive");
+        assert_eq!(sentiment_label(0.01), "neutral");
+    }
+}
@@ -0,0 +1,210 @@
+//! Tools module for the LLM.lang runtime
+//!
+//! This module provides the tools that the language model can call back into
+//! the program with. Functions declared in a `tools` block are described to
+//! the model with JSON Schema, and `agent(task)` runs a loop in which the
+//! model either calls a tool, whose result is sent back to it, or answers.
+
+use serde_json::json;
+
+use crate::semantic::analyzer::Symbol;
+use crate::utils::SourceLocation;
+use crate::Value;
+use super::error::RuntimeError;
+use super::nlp::NLP;
+use super::schema::{RecordTypes, Schema};
+
+/// The number of tool calls an agent may make before it must answer, unless configured otherwise
+pub const DEFAULT_MAX_TOOL_STEPS: usize = 8;
+
+/// A function that the language model can call
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tool {
+    /// The function name
+    pub name: String,
+
+    /// What the function does, as told to the model
+    pub description: String,
+
+    /// The parameters and their schemas, in declaration order
+    pub parameters: Vec<(String, Schema)>,
+
+    /// The schema of the return value, if it can be described
+    pub returns: Option<Schema>,
+}
+
+impl Tool {
+    /// Describe a function as a tool, given its symbol and description
+    ///
+    /// The record types are used to describe parameters of declared types.
+    pub fn from_symbol(symbol: &Symbol, description: &str, types: &RecordTypes, location: &SourceLocation) -> Result<Self, RuntimeError> {
+        let Symbol::Function { name, parameters: declared, return_type } = symbol else {
+            return Err(RuntimeError::new("Only functions can be described as tools", location.clone()));
+        };
+
+        let mut parameters = Vec::new();
+
+        for (parameter, typ) in declared {
+            let schema = Schema::from_type(typ, types).map_err(|error| RuntimeError::new(
+                &format!("Parameter '{}' of tool '{}' cannot be described to a language model: {}", parameter, name, error.message),
+                location.clone(),
+            ))?;
+
+            parameters.push((parameter.clone(), schema));
+        }
+
+        // Functions without a describable return type return whatever they return
+        let returns = Schema::from_type(return_type, types).ok();
+
+        Ok(Self {
+            name: name.clone(),
+            description: description.to_string(),
+            parameters,
+            returns,
+        })
+    }
+
+    /// Describe the tool as a JSON object with JSON Schemas of its parameters and result
+    pub fn to_json_schema(&self) -> serde_json::Value {
+        let properties: serde_json::Map<String, serde_json::Value> = self.parameters.iter()
+            .map(|(name, schema)| (name.clone(), schema.to_json_schema()))
+            .collect();
+        let required: Vec<&String> = self.parameters.iter().map(|(name, _)| name).collect();
+
+        let mut tool = json!({
+            "name": self.name,
+            "description": self.description,
+            "parameters": {
+                "type": "object",
+                "properties": properties,
+                "required": required,
+                "additionalProperties": false,
+            },
+        });
+
+        if let Some(returns) = &self.returns {
+            tool["returns"] = returns.to_json_schema();
+        }
+
+        tool
+    }
+
+    /// Check the arguments the model called the tool with
+    ///
+    /// Returns the arguments in parameter order, converted to the parameter
+    /// schemas, or what was wrong with them.
+    pub fn arguments(&self, arguments: &Value, nlp: &NLP) -> Result<Vec<Value>, String> {
+        let mut arguments = match arguments {
+            Value::Map(arguments) => arguments.clone(),
+            Value::Void => Default::default(),
+            other => return Err(format!("expected an object of arguments, got {}", value_to_json(other))),
+        };
+
+        let mut values = Vec::new();
+
+        for (name, schema) in &self.parameters {
+            let value = arguments.remove(name).ok_or_else(|| format!("missing argument '{}'", name))?;
+            values.push(schema.validate(value, nlp).map_err(|reason| format!("argument '{}': {}", name, reason))?);
+        }
+
+        let mut unknown: Vec<&String> = arguments.keys().collect();
+        unknown.sort();
+
+        match unknown.first() {
+            Some(name) => Err(format!("unknown argument '{}'", name)),
+            None => Ok(values),
+        }
+    }
+}
+
+/// A call of a tool by the language model
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    /// The name of the tool
+    pub tool: String,
+
+    /// The arguments, as sent by the model
+    pub arguments: Value,
+
+    /// The result, or the error that was sent back to the model
+    pub result: Result<Value, String>,
+}
+
+/// Convert a value to JSON to send to the language model
+///
+/// Functions and contexts are sent as their names, and probabilistic values as
+/// their most likely value.
+pub fn value_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Void => serde_json::Value::Null,
+        Value::Bool(b) => json!(b),
+        Value::Int(i) => json!(i),
+        Value::Float(f) => json!(f),
+        Value::String(s) | Value::Function(s) | Value::Context(s) => json!(s),
+        Value::List(items) => serde_json::Value::Array(items.iter().map(value_to_json).collect()),
+        Value::Map(map) => {
+            let object: serde_json::Map<String, serde_json::Value> = map.iter()
+                .map(|(key, value)| (key.clone(), value_to_json(value)))
+                .collect();
+            serde_json::Value::Object(object)
+        }
+        Value::Vector(vector) => json!(vector),
+        Value::Prob { .. } => value_to_json(&value.clone().into_most_likely()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn describe(source: &str, types: &RecordTypes) -> Result<Tool, RuntimeError> {
+        let tokens = crate::lexer::Lexer::new(source).tokenize().unwrap();
+        let ast = crate::parser::Parser::new(tokens).parse().unwrap();
+        let function = &ast.root.children[0];
+        Tool::from_symbol(&Symbol::function(function).unwrap(), "", types, &function.location)
+    }
+
+    #[test]
+    fn test_tool_from_symbol() {
+        let mut types = RecordTypes::new();
+        types.insert("Order".to_string(), vec![("id".to_string(), "Int".to_string())]);
+
+        let tool = describe("fn refund(order: Order, reason: String) -> Bool { true; }", &types).unwrap();
+        assert_eq!(tool.name, "refund");
+        assert_eq!(tool.returns, Some(Schema::Bool));
+
+        let schema = tool.to_json_schema();
+        assert_eq!(schema["parameters"]["required"], json!(["order", "reason"]));
+        assert_eq!(schema["parameters"]["properties"]["reason"], json!({ "type": "string" }));
+        assert_eq!(schema["parameters"]["properties"]["order"]["title"], json!("Order"));
+        assert_eq!(schema["returns"], json!({ "type": "boolean" }));
+
+        let error = describe("fn refund(order: Invoice) { true; }", &types).unwrap_err();
+        assert_eq!(error.message, "Parameter 'order' of tool 'refund' cannot be described to a language model: Unknown type: 'Invoice'");
+    }
+
+    #[test]
+    fn test_tool_arguments() {
+        let tool = describe("fn add(a: Int, b: Float) { a + b; }", &RecordTypes::new()).unwrap();
+        assert_eq!(tool.returns, None);
+
+        let nlp = NLP::new();
+        let arguments = |pairs: &[(&str, Value)]| Value::Map(pairs.iter().map(|(k, v)| (k.to_string(), v.clone())).collect());
+
+        assert_eq!(
+            tool.arguments(&arguments(&[("a", Value::Int(1)), ("b", Value::Int(2))]), &nlp),
+            Ok(vec![Value::Int(1), Value::Float(2.0)])
+        );
+        assert_eq!(
+            tool.arguments(&arguments(&[("a", Value::Int(1))]), &nlp),
+            Err("missing argument 'b'".to_string())
+        );
+        assert_eq!(
+            tool.arguments(&arguments(&[("a", Value::Int(1)), ("b", Value::Float(2.0)), ("c", Value::Int(3))]), &nlp),
+            Err("unknown argument 'c'".to_string())
+        );
+        assert!(tool.arguments(&arguments(&[("a", Value::String("one".to_string())), ("b", Value::Float(2.0))]), &nlp)
+            .unwrap_err()
+            .starts_with("argument 'a': "));
+    }
+}
@@ -3,47 +3,90 @@
 //! This module provides the vector engine for the LLM.lang runtime,
 //! which manages semantic vector embeddings.
 
+use std::cell::RefCell;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
 use super::error::RuntimeError;
 
 /// A semantic vector embedding
 pub type VectorEmbedding = Vec<f64>;
 
-/// A vector engine
-pub struct Vector {
-    /// The current vector
-    current_vector: Option<VectorEmbedding>,
+/// The default number of dimensions of an embedding
+pub const DEFAULT_EMBEDDING_DIMENSION: usize = 32;
+
+thread_local! {
+    /// The embedding provider used by code that has no access to the engine
+    static CURRENT_PROVIDER: RefCell<Option<Arc<dyn EmbeddingProvider>>> = RefCell::new(None);
 }
 
-impl Vector {
-    /// Create a new vector engine
-    pub fn new() -> Self {
-        Self {
-            current_vector: None,
-        }
-    }
+/// How strongly the applied vector biases rankings
+///
+/// Rankings add this weight times the similarity between a candidate and the
+/// applied vector to the candidate's own score.
+pub const STEERING_WEIGHT: f64 = 0.25;
+
+/// A provider of semantic vector embeddings
+pub trait EmbeddingProvider: fmt::Debug + Send + Sync {
+    /// Identify the provider and its model, to key cached embeddings
+    ///
+    /// The name should change whenever the embeddings could, such as when
+    /// another model or number of dimensions is used.
+    fn name(&self) -> String;
     
-    /// Set the current vector
-    pub fn set_current_vector(&mut self, vector: VectorEmbedding) {
-        self.current_vector = Some(vector);
-    }
+    /// Create a vector embedding from text
+    fn embed(&self, text: &str) -> Result<VectorEmbedding, RuntimeError>;
     
-    /// Clear the current vector
-    pub fn clear_current_vector(&mut self) {
-        self.current_vector = None;
+    /// Create vector embeddings from several texts at once
+    ///
+    /// Defaults to embedding the texts one at a time. Providers that can
+    /// embed a batch faster than its texts override this.
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<VectorEmbedding>, RuntimeError> {
+        texts.iter().map(|text| self.embed(text)).collect()
     }
     
-    /// Get the current vector
-    pub fn get_current_vector(&self) -> Option<&VectorEmbedding> {
-        self.current_vector.as_ref()
+    /// Get the number of dimensions of the embeddings
+    fn dimension(&self) -> usize;
+}
+
+/// An embedding provider that hashes words into a fixed number of dimensions
+///
+/// This is the default provider. It needs no model and is deterministic.
+#[derive(Debug, Clone)]
+pub struct HashEmbedder {
+    /// The number of dimensions
+    dimension: usize,
+}
+
+impl HashEmbedder {
+    /// Create a new hash embedder
+    pub fn new(dimension: usize) -> Self {
+        Self {
+            dimension: std::cmp::max(1, dimension),
+        }
+    }
+}
+
+impl Default for HashEmbedder {
+    fn default() -> Self {
+        Self::new(DEFAULT_EMBEDDING_DIMENSION)
+    }
+}
+
+impl EmbeddingProvider for HashEmbedder {
+    fn name(&self) -> String {
+        format!("hash/{}", self.dimension)
     }
     
-    /// Create a vector embedding from text
-    pub fn embed(&self, text: &str) -> Result<VectorEmbedding, RuntimeError> {
+    fn embed(&self, text: &str) -> Result<VectorEmbedding, RuntimeError> {
         // This is a more sophisticated embedding algorithm that simulates
         // how a real embedding model might work, but still deterministic
         
-        // Use a larger embedding size for more realistic embeddings
-        let target_size = 32;
+        let target_size = self.dimension;
+        
+        // The semantic features each use a quarter of the embedding
+        let band = target_size / 4;
         let mut embedding = vec![0.0; target_size];
         
         // Process the text in a more sophisticated way
@@ -64,7 +107,8 @@ impl Vector {
             // Distribute the hash across the embedding
             for j in 0..target_size {
                 let position = (hash.wrapping_add(j as u64)) % target_size as u64;
-                let value = ((hash >> j) & 0xFF) as f64 / 255.0;
+                // The shift wraps so that embeddings can have more than 64 dimensions
+                let value = (hash.wrapping_shr(j as u32) & 0xFF) as f64 / 255.0;
                 
                 // Add the value to the embedding, with position-dependent weighting
                 embedding[position as usize] += value * (1.0 / (1.0 + i as f64));
@@ -86,29 +130,29 @@ impl Vector {
         // Check for question words
         if text.contains("what") || text.contains("who") || text.contains("when") || 
            text.contains("where") || text.contains("why") || text.contains("how") {
-            for i in 0..8 {
-                embedding[i] += 0.2;
+            for value in &mut embedding[..band] {
+                *value += 0.2;
             }
         }
         
         // Check for programming-related terms
         if text.contains("code") || text.contains("program") || text.contains("function") || 
            text.contains("class") || text.contains("variable") || text.contains("algorithm") {
-            for i in 8..16 {
-                embedding[i] += 0.2;
+            for value in &mut embedding[band..band * 2] {
+                *value += 0.2;
             }
         }
         
         // Check for sentiment words
         if text.contains("good") || text.contains("great") || text.contains("excellent") || 
            text.contains("amazing") || text.contains("wonderful") || text.contains("positive") {
-            for i in 16..24 {
-                embedding[i] += 0.2;
+            for value in &mut embedding[band * 2..band * 3] {
+                *value += 0.2;
             }
         } else if text.contains("bad") || text.contains("terrible") || text.contains("awful") || 
                   text.contains("horrible") || text.contains("negative") || text.contains("poor") {
-            for i in 16..24 {
-                embedding[i] -= 0.2;
+            for value in &mut embedding[band * 2..band * 3] {
+                *value -= 0.2;
             }
         }
         
@@ -135,6 +179,308 @@ impl Vector {
         Ok(embedding)
     }
     
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+}
+
+/// An embedding provider backed by a local model
+///
+/// The model directory contains a `tokenizer.json` file and an `embeddings.bin`
+/// file with one row of little-endian `f32` weights per token id. A text is
+/// embedded as the normalized mean of the rows of its tokens. The model runs
+/// on the CPU.
+pub struct LocalModelEmbedder {
+    /// The model directory
+    path: PathBuf,
+    
+    /// The tokenizer
+    tokenizer: tokenizers::Tokenizer,
+    
+    /// The token embedding weights, one row per token id
+    weights: Vec<f32>,
+    
+    /// The number of dimensions
+    dimension: usize,
+}
+
+impl LocalModelEmbedder {
+    /// Load a model from a directory
+    pub fn load(path: &Path, dimension: usize) -> Result<Self, RuntimeError> {
+        let tokenizer_path = path.join("tokenizer.json");
+        let tokenizer = tokenizers::Tokenizer::from_file(&tokenizer_path).map_err(|e| {
+            RuntimeError::new(
+                &format!("Failed to load tokenizer from '{}': {}", tokenizer_path.display(), e),
+                crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+            )
+        })?;
+        
+        let weights_path = path.join("embeddings.bin");
+        let bytes = std::fs::read(&weights_path).map_err(|e| {
+            RuntimeError::new(
+                &format!("Failed to load embedding weights from '{}': {}", weights_path.display(), e),
+                crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+            )
+        })?;
+        
+        // The weights must hold a whole number of rows
+        if dimension == 0 || bytes.len() % (dimension * 4) != 0 {
+            return Err(RuntimeError::new(
+                &format!(
+                    "Embedding weights in '{}' do not match dimension {}",
+                    weights_path.display(),
+                    dimension
+                ),
+                crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        let weights = bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect();
+        
+        Ok(Self {
+            path: path.to_path_buf(),
+            tokenizer,
+            weights,
+            dimension,
+        })
+    }
+    
+    /// Get the number of tokens the model has weights for
+    pub fn vocabulary_size(&self) -> usize {
+        self.weights.len() / self.dimension
+    }
+}
+
+impl fmt::Debug for LocalModelEmbedder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalModelEmbedder")
+            .field("path", &self.path)
+            .field("dimension", &self.dimension)
+            .field("vocabulary_size", &self.vocabulary_size())
+            .finish()
+    }
+}
+
+impl EmbeddingProvider for LocalModelEmbedder {
+    fn name(&self) -> String {
+        // The directory name rather than the path, which differs between machines
+        let model = self.path.file_name().map_or(self.path.to_string_lossy(), |name| name.to_string_lossy());
+        format!("local/{}/{}", model, self.dimension)
+    }
+    
+    fn embed(&self, text: &str) -> Result<VectorEmbedding, RuntimeError> {
+        let encoding = self.tokenizer.encode(text, false).map_err(|e| {
+            RuntimeError::new(
+                &format!("Failed to tokenize text: {}", e),
+                crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+            )
+        })?;
+        
+        Ok(self.pool(encoding.get_ids()))
+    }
+    
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<VectorEmbedding>, RuntimeError> {
+        // Tokenize the whole batch at once
+        let encodings = self.tokenizer.encode_batch(texts.to_vec(), false).map_err(|e| {
+            RuntimeError::new(
+                &format!("Failed to tokenize texts: {}", e),
+                crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
+            )
+        })?;
+        
+        Ok(encodings.iter().map(|encoding| self.pool(encoding.get_ids())).collect())
+    }
+    
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+}
+
+impl LocalModelEmbedder {
+    /// Pool the rows of token ids into a normalized embedding
+    fn pool(&self, ids: &[u32]) -> VectorEmbedding {
+        // Average the rows of the tokens that have weights
+        let mut embedding = vec![0.0; self.dimension];
+        let mut count = 0;
+        
+        for id in ids {
+            let start = *id as usize * self.dimension;
+            
+            if let Some(row) = self.weights.get(start..start + self.dimension) {
+                for (value, weight) in embedding.iter_mut().zip(row) {
+                    *value += *weight as f64;
+                }
+                
+                count += 1;
+            }
+        }
+        
+        if count > 0 {
+            for value in &mut embedding {
+                *value /= count as f64;
+            }
+        }
+        
+        // Normalize the vector
+        let magnitude = embedding.iter().map(|x| x * x).sum::<f64>().sqrt();
+        
+        if magnitude > 0.0 {
+            for x in &mut embedding {
+                *x /= magnitude;
+            }
+        }
+        
+        embedding
+    }
+}
+
+/// Restores the previous current embedding provider when dropped
+pub struct ProviderGuard {
+    /// The provider that was current before
+    previous: Option<Arc<dyn EmbeddingProvider>>,
+}
+
+impl Drop for ProviderGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT_PROVIDER.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// A vector engine
+#[derive(Debug, Clone)]
+pub struct Vector {
+    /// The current vector
+    current_vector: Option<VectorEmbedding>,
+    
+    /// The vectors applied by the enclosing `apply` blocks, outermost first
+    applied: Vec<VectorEmbedding>,
+    
+    /// The embedding provider
+    provider: Arc<dyn EmbeddingProvider>,
+}
+
+impl Vector {
+    /// Create a new vector engine
+    pub fn new() -> Self {
+        Self::with_provider(Arc::new(HashEmbedder::default()))
+    }
+    
+    /// Create a new vector engine with an embedding provider
+    pub fn with_provider(provider: Arc<dyn EmbeddingProvider>) -> Self {
+        Self {
+            current_vector: None,
+            applied: Vec::new(),
+            provider,
+        }
+    }
+    
+    /// Create a vector engine that uses the current embedding provider of the calling thread
+    ///
+    /// This is used by the standard library, which has no access to the engine.
+    /// Without a current provider, the default provider is used.
+    pub fn current() -> Self {
+        match CURRENT_PROVIDER.with(|current| current.borrow().clone()) {
+            Some(provider) => Self::with_provider(provider),
+            None => Self::new(),
+        }
+    }
+    
+    /// Make the embedding provider current for the calling thread until the guard is dropped
+    pub fn enter(&self) -> ProviderGuard {
+        let previous = CURRENT_PROVIDER.with(|current| current.replace(Some(Arc::clone(&self.provider))));
+        
+        ProviderGuard { previous }
+    }
+    
+    /// Set the embedding provider
+    pub fn set_embedding_provider(&mut self, provider: Arc<dyn EmbeddingProvider>) {
+        self.provider = provider;
+    }
+    
+    /// Get the embedding provider
+    pub fn embedding_provider(&self) -> Arc<dyn EmbeddingProvider> {
+        Arc::clone(&self.provider)
+    }
+    
+    /// Get the number of dimensions of the embeddings
+    pub fn dimension(&self) -> usize {
+        self.provider.dimension()
+    }
+    
+    /// Set the current vector
+    pub fn set_current_vector(&mut self, vector: VectorEmbedding) {
+        self.current_vector = Some(vector);
+    }
+    
+    /// Clear the current vector
+    pub fn clear_current_vector(&mut self) {
+        self.current_vector = None;
+    }
+    
+    /// Get the current vector
+    pub fn get_current_vector(&self) -> Option<&VectorEmbedding> {
+        self.current_vector.as_ref()
+    }
+    
+    /// Apply a vector on top of the vectors already applied
+    ///
+    /// The current vector is the weighted combination of every applied vector,
+    /// computed the same way whether one block or several nested blocks apply
+    /// them. Each vector is weighted by its length, so scaling a vector changes
+    /// its weight. The vector must have the same dimension as the others.
+    pub fn apply_vector(&mut self, vector: VectorEmbedding) -> Result<(), RuntimeError> {
+        self.applied.push(vector);
+        
+        match self.combine_applied() {
+            Ok(combined) => {
+                self.current_vector = Some(combined);
+                Ok(())
+            }
+            Err(error) => {
+                self.applied.pop();
+                Err(error)
+            }
+        }
+    }
+    
+    /// Remove the vector applied last, restoring the current vector from before it was applied
+    pub fn unapply_vector(&mut self) {
+        self.applied.pop();
+        
+        // The remaining vectors were combined before, so this only fails when none are left
+        self.current_vector = self.combine_applied().ok();
+    }
+    
+    /// Combine the applied vectors, each with its own length as its weight
+    fn combine_applied(&self) -> Result<VectorEmbedding, RuntimeError> {
+        let weights = vec![1.0; self.applied.len()];
+        self.combine_vectors(&self.applied, Some(&weights))
+    }
+    
+    /// Calculate the bias the current vector gives to a candidate embedding
+    ///
+    /// This is zero when no vector is applied.
+    pub fn steering_bias(&self, embedding: &VectorEmbedding) -> f64 {
+        match &self.current_vector {
+            Some(current) => STEERING_WEIGHT * self.similarity(embedding, current),
+            None => 0.0,
+        }
+    }
+    
+    /// Create a vector embedding from text
+    pub fn embed(&self, text: &str) -> Result<VectorEmbedding, RuntimeError> {
+        self.provider.embed(text)
+    }
+    
+    /// Create vector embeddings from several texts at once
+    pub fn embed_batch(&self, texts: &[&str]) -> Result<Vec<VectorEmbedding>, RuntimeError> {
+        self.provider.embed_batch(texts)
+    }
+    
     /// Calculate the similarity between two vectors
     pub fn similarity(&self, a: &VectorEmbedding, b: &VectorEmbedding) -> f64 {
         // Calculate the cosine similarity
@@ -176,18 +522,13 @@ impl Vector {
         }
         
         // Calculate the similarity between the vector and each vector in the list
-        let mut similarities: Vec<(usize, f64)> = vectors
+        let similarities = vectors
             .iter()
             .enumerate()
-            .map(|(i, v)| (i, self.similarity(vector, v)))
-            .collect();
-        
-        // Sort by similarity (descending)
-        similarities.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+            .map(|(i, v)| (i, self.similarity(vector, v)));
         
         // Return the indices of the k nearest vectors (or all if k > vectors.len())
-        let k = std::cmp::min(k, vectors.len());
-        similarities.iter().take(k).map(|(i, _)| *i).collect()
+        super::index::top_k(similarities, k).into_iter().map(|(i, _)| i).collect()
     }
     
     /// Create a vector embedding from a list of words
@@ -214,8 +555,9 @@ impl Vector {
         // Check that all vectors have the same size
         for v in vectors.iter().skip(1) {
             if v.len() != size {
-                return Err(RuntimeError::new(
-                    "All vectors must have the same size",
+                return Err(RuntimeError::dimension_mismatch(
+                    size,
+                    v.len(),
                     crate::utils::SourceLocation::new(0, 0, 0, 0, ""),
                 ));
             }
@@ -252,15 +594,34 @@ impl Vector {
         }
         
         // Normalize the result
-        let magnitude = result.iter().map(|x| x * x).sum::<f64>().sqrt();
+        Ok(Self::normalize(&result))
+    }
+    
+    /// Calculate the dot product of two vectors
+    pub fn dot(a: &[f64], b: &[f64]) -> f64 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+    
+    /// Calculate the magnitude of a vector
+    pub fn magnitude(vector: &[f64]) -> f64 {
+        Self::dot(vector, vector).sqrt()
+    }
+    
+    /// Scale a vector to unit length, leaving zero vectors unchanged
+    pub fn normalize(vector: &[f64]) -> VectorEmbedding {
+        let magnitude = Self::magnitude(vector);
         
         if magnitude > 0.0 {
-            for x in &mut result {
-                *x /= magnitude;
-            }
+            vector.iter().map(|x| x / magnitude).collect()
+        } else {
+            vector.to_vec()
         }
-        
-        Ok(result)
+    }
+}
+
+impl Default for Vector {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -335,4 +696,121 @@ mod tests {
         assert_eq!(nearest.len(), 2);
         assert_eq!(nearest[0], 0); // b is most similar to a
     }
+    
+    #[test]
+    fn test_vector_apply_vector() {
+        let mut vector = Vector::new();
+        
+        assert_eq!(vector.steering_bias(&vec![1.0, 0.0]), 0.0);
+        
+        // A single vector is combined like nested ones
+        vector.apply_vector(vec![2.0, 0.0]).unwrap();
+        assert_eq!(vector.get_current_vector(), Some(&vec![1.0, 0.0]));
+        
+        // Nested vectors are weighted by their lengths
+        vector.apply_vector(vec![0.0, 1.0]).unwrap();
+        let expected = vector.combine_vectors(&[vec![2.0, 0.0], vec![0.0, 1.0]], Some(&[1.0, 1.0])).unwrap();
+        assert_eq!(vector.get_current_vector(), Some(&expected));
+        assert!((vector.steering_bias(&vec![2.0, 1.0]) - STEERING_WEIGHT).abs() < 1e-9);
+        
+        // The weighting is the same at every depth
+        vector.apply_vector(vec![0.0, 1.0]).unwrap();
+        assert!((vector.steering_bias(&vec![1.0, 1.0]) - STEERING_WEIGHT).abs() < 1e-9);
+        
+        vector.unapply_vector();
+        assert_eq!(vector.get_current_vector(), Some(&expected));
+        
+        vector.unapply_vector();
+        assert_eq!(vector.get_current_vector(), Some(&vec![1.0, 0.0]));
+        
+        vector.unapply_vector();
+        assert!(vector.get_current_vector().is_none());
+        
+        // A vector of another dimension is not applied
+        vector.apply_vector(vec![1.0]).unwrap();
+        assert!(vector.apply_vector(vec![1.0, 0.0]).is_err());
+        assert_eq!(vector.get_current_vector(), Some(&vec![1.0]));
+        
+        vector.unapply_vector();
+        assert!(vector.get_current_vector().is_none());
+    }
+    
+    #[test]
+    fn test_vector_dot_and_normalize() {
+        assert_eq!(Vector::dot(&[1.0, 2.0], &[3.0, 4.0]), 11.0);
+        assert_eq!(Vector::magnitude(&[3.0, 4.0]), 5.0);
+        assert_eq!(Vector::normalize(&[3.0, 4.0]), vec![0.6, 0.8]);
+        assert_eq!(Vector::normalize(&[0.0, 0.0]), vec![0.0, 0.0]);
+        
+        let error = Vector::new().combine_vectors(&[vec![1.0, 0.0], vec![1.0]], None).unwrap_err();
+        assert_eq!(error.message, "Vector dimension mismatch: expected 2, got 1");
+    }
+    
+    #[test]
+    fn test_hash_embedder_dimension() {
+        let embedder = HashEmbedder::new(128);
+        
+        let embedding = embedder.embed("what is good code").unwrap();
+        
+        assert_eq!(embedder.dimension(), 128);
+        assert_eq!(embedding.len(), 128);
+    }
+    
+    #[test]
+    fn test_vector_current_provider() {
+        let vector = Vector::with_provider(Arc::new(HashEmbedder::new(8)));
+        
+        assert_eq!(Vector::current().dimension(), DEFAULT_EMBEDDING_DIMENSION);
+        
+        {
+            let _provider = vector.enter();
+            assert_eq!(Vector::current().embed("hello").unwrap().len(), 8);
+        }
+        
+        assert_eq!(Vector::current().dimension(), DEFAULT_EMBEDDING_DIMENSION);
+    }
+    
+    #[test]
+    fn test_local_model_embedder() {
+        let path = std::env::temp_dir().join(format!("llm_lang_model_{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        
+        // A word-level tokenizer with three tokens
+        std::fs::write(path.join("tokenizer.json"), r#"{
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": [],
+            "normalizer": null,
+            "pre_tokenizer": { "type": "Whitespace" },
+            "post_processor": null,
+            "decoder": null,
+            "model": {
+                "type": "WordLevel",
+                "vocab": { "[UNK]": 0, "hello": 1, "world": 2 },
+                "unk_token": "[UNK]"
+            }
+        }"#).unwrap();
+        
+        // Two dimensions per token
+        let weights: Vec<u8> = [0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0]
+            .iter()
+            .flat_map(|weight| weight.to_le_bytes())
+            .collect();
+        std::fs::write(path.join("embeddings.bin"), weights).unwrap();
+        
+        let embedder = LocalModelEmbedder::load(&path, 2).unwrap();
+        let hello = embedder.embed("hello").unwrap();
+        let both = embedder.embed("hello world").unwrap();
+        let batch = embedder.embed_batch(&["hello", "hello world"]).unwrap();
+        let error = LocalModelEmbedder::load(&path, 4).unwrap_err();
+        
+        std::fs::remove_dir_all(&path).unwrap();
+        
+        assert_eq!(embedder.vocabulary_size(), 3);
+        assert_eq!(hello, vec![1.0, 0.0]);
+        assert!((both[0] - both[1]).abs() < 1e-10);
+        assert_eq!(batch, vec![hello.clone(), both.clone()]);
+        assert!(error.message.contains("do not match dimension 4"));
+    }
 }
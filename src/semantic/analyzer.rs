@@ -3,8 +3,9 @@
 //! This module provides the semantic analyzer for the LLM.lang language,
 //! which performs type checking and other semantic analyses on the AST.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use crate::parser::ast::{Ast, Node, NodeKind};
+use crate::parser::template::{self, IntentPattern, PromptTemplate, EXAMPLES_SLOT};
 use crate::utils::SourceLocation;
 use super::error::{SemanticError, SemanticResult};
 
@@ -45,6 +46,34 @@ pub enum Symbol {
     },
 }
 
+impl Symbol {
+    /// Create the symbol of a function declaration
+    ///
+    /// Parameters without a type are of type `Any`, and a function without a
+    /// return type returns `Void`.
+    pub fn function(node: &Node) -> SemanticResult<Self> {
+        let name = node.get_attribute("name").ok_or_else(|| {
+            SemanticError::missing_attribute("name", node.location.clone())
+        })?;
+        
+        let mut parameters = Vec::new();
+        
+        for child in node.children.iter().filter(|child| child.kind == NodeKind::Parameter) {
+            let parameter = child.get_attribute("name").ok_or_else(|| {
+                SemanticError::missing_attribute("name", child.location.clone())
+            })?;
+            
+            parameters.push((parameter.clone(), child.get_attribute("type").cloned().unwrap_or_else(|| "Any".to_string())));
+        }
+        
+        Ok(Symbol::Function {
+            name: name.clone(),
+            parameters,
+            return_type: node.get_attribute("return_type").cloned().unwrap_or_else(|| "Void".to_string()),
+        })
+    }
+}
+
 /// A scope in the symbol table
 #[derive(Debug, Clone)]
 pub struct Scope {
@@ -134,6 +163,21 @@ pub struct SemanticAnalyzer {
     
     /// The current function return type
     current_return_type: Option<String>,
+    
+    /// The record types declared with `type`
+    types: HashSet<String>,
+    
+    /// The versions of the prompts declared with `prompt` that have been analyzed, by prompt name
+    prompts: HashMap<String, Vec<String>>,
+    
+    /// The location of the first declaration of each function registered by the first pass
+    declarations: HashMap<String, SourceLocation>,
+    
+    /// The transformations of each named transformer, whose inputs and outputs give its signature
+    transformations: HashMap<String, Vec<Node>>,
+    
+    /// The number of required arguments of each standard library function whose last parameters are optional
+    required_arguments: HashMap<String, usize>,
 }
 
 impl SemanticAnalyzer {
@@ -144,6 +188,11 @@ impl SemanticAnalyzer {
             in_function: false,
             in_loop: false,
             current_return_type: None,
+            types: HashSet::new(),
+            prompts: HashMap::new(),
+            declarations: HashMap::new(),
+            transformations: HashMap::new(),
+            required_arguments: HashMap::new(),
         };
         
         // Register standard library functions
@@ -164,6 +213,15 @@ impl SemanticAnalyzer {
         self.register_stdlib_function("length", vec![("collection", "Any")], "Int");
         self.register_stdlib_function("isEmpty", vec![("collection", "Any")], "Bool");
         self.register_stdlib_function("contains", vec![("collection", "Any"), ("value", "Any")], "Bool");
+        self.register_stdlib_function("map", vec![("collection", "List"), ("function", "Any")], "List");
+        self.register_stdlib_function("filter", vec![("collection", "List"), ("function", "Any")], "List");
+        self.register_stdlib_function("reverse", vec![("collection", "Any")], "Any");
+        self.register_stdlib_function("sort", vec![("collection", "List")], "List");
+        self.register_stdlib_function("first", vec![("collection", "List")], "Any");
+        self.register_stdlib_function("last", vec![("collection", "List")], "Any");
+        self.register_stdlib_function("sum", vec![("collection", "List")], "Any");
+        self.register_stdlib_function("max", vec![("collection", "List")], "Any");
+        self.register_stdlib_function("min", vec![("collection", "List")], "Any");
         
         // String functions
         self.register_stdlib_function("substring", vec![("string", "String"), ("start", "Int"), ("end", "Int")], "String");
@@ -171,14 +229,78 @@ impl SemanticAnalyzer {
         self.register_stdlib_function("toLowerCase", vec![("string", "String")], "String");
         self.register_stdlib_function("toUpperCase", vec![("string", "String")], "String");
         self.register_stdlib_function("trim", vec![("string", "String")], "String");
+        self.register_stdlib_function("split", vec![("string", "String"), ("separator", "String")], "List");
+        self.register_stdlib_function("join", vec![("collection", "List"), ("separator", "String")], "String");
+        
+        // Natural language functions
+        self.register_stdlib_function("tokenize", vec![("text", "String")], "List");
+        self.register_stdlib_function("splitSentences", vec![("text", "String")], "List");
+        self.register_stdlib_function("stemming", vec![("word", "String")], "String");
+        self.register_stdlib_function("lemmatize", vec![("word", "String")], "String");
+        self.register_stdlib_function("extractKeywords", vec![("text", "String")], "List");
+        self.register_stdlib_function("extractSentiment", vec![("text", "String")], "Map");
+        self.register_stdlib_function("extractEntities", vec![("text", "String")], "List");
+        self.register_stdlib_function("extractSummary", vec![("text", "String")], "String");
+        self.register_stdlib_function("translateText", vec![("text", "String"), ("language", "String")], "String");
+        self.register_stdlib_function("answerQuestion", vec![("question", "String"), ("context", "String")], "String");
         
         // Vector functions
         self.register_stdlib_function("embed", vec![("text", "String")], "Vector");
         self.register_stdlib_function("similarity", vec![("vector1", "Vector"), ("vector2", "Vector")], "Float");
+        self.register_stdlib_function("combineConcepts", vec![("concepts", "List"), ("weights", "Any")], "Vector");
+        self.register_stdlib_function("normalize", vec![("vector", "Vector")], "Vector");
+        self.register_stdlib_function("cosine", vec![("vector1", "Vector"), ("vector2", "Vector")], "Float");
+        
+        // Probability functions
+        self.register_stdlib_function("prob", vec![("value", "Any"), ("confidence", "Float")], "Any");
+        self.register_stdlib_function("confidence", vec![("value", "Any")], "Float");
+        self.register_stdlib_function("probAnd", vec![("values", "List")], "Any");
+        self.register_stdlib_function("probOr", vec![("values", "List")], "Any");
+        
+        // Natural language functions
+        self.register_stdlib_function("classify", vec![("text", "String"), ("categories", "List")], "Any");
+        self.register_stdlib_function("routeIntent", vec![("text", "String")], "Any");
+        self.register_stdlib_function("agent", vec![("task", "String")], "Any");
+        self.register_stdlib_function("promptVersion", vec![("prompt", "String")], "String");
+        self.register_stdlib_function("usePromptVersion", vec![("prompt", "String"), ("version", "String")], "Void");
         
         // Context functions
         self.register_stdlib_function("currentContext", vec![], "Context");
         self.register_stdlib_function("switchContext", vec![("name", "String")], "Context");
+        self.register_stdlib_function("saveContext", vec![("context", "Context"), ("path", "String")], "Bool");
+        self.register_stdlib_function("loadContext", vec![("path", "String")], "Context");
+        self.register_stdlib_function("diffContexts", vec![("before", "Any"), ("after", "Any")], "Map");
+        
+        // Parallel functions
+        self.register_stdlib_function("pathScores", vec![], "Map");
+        
+        // Index functions
+        self.register_stdlib_function("indexCreate", vec![("name", "String"), ("kind", "String")], "String");
+        self.register_stdlib_function("indexAdd", vec![("index", "String"), ("id", "String"), ("item", "Any")], "Bool");
+        self.register_stdlib_function("indexSearch", vec![("index", "String"), ("query", "Any"), ("count", "Int")], "List");
+        self.register_stdlib_function("indexSearchWhere", vec![("index", "String"), ("query", "Any"), ("count", "Int"), ("filter", "Map")], "List");
+        self.register_stdlib_function("indexRemove", vec![("index", "String"), ("id", "String")], "Bool");
+        self.register_stdlib_function("indexSize", vec![("index", "String")], "Int");
+        self.register_stdlib_function("indexSave", vec![("index", "String"), ("path", "String")], "Bool");
+        self.register_stdlib_function("indexLoad", vec![("name", "String"), ("path", "String")], "String");
+        
+        // Retrieval-augmented generation functions
+        self.register_stdlib_function("chunkBySentence", vec![("text", "String"), ("sentences", "Int")], "List");
+        self.register_stdlib_function("chunkByTokens", vec![("text", "String"), ("maxTokens", "Int"), ("overlap", "Int")], "List");
+        self.register_stdlib_function("chunkByHeading", vec![("text", "String")], "List");
+        self.register_stdlib_function("embedBatch", vec![("texts", "List")], "List");
+        self.register_stdlib_function("indexAddChunks", vec![("index", "String"), ("source", "String"), ("chunks", "List")], "Int");
+        self.register_stdlib_function("ragRetrieve", vec![("index", "String"), ("question", "String"), ("count", "Int")], "List");
+        self.register_stdlib_function("ragAnswer", vec![("question", "String"), ("index", "String")], "Map");
+        
+        // Python interoperability functions
+        self.register_stdlib_function("pythonImport", vec![("moduleName", "String")], "String");
+        self.register_stdlib_function("pythonEval", vec![("code", "String")], "Any");
+        self.register_stdlib_function("pythonExec", vec![("code", "String")], "Bool");
+        self.register_stdlib_function("pythonCall", vec![("function", "String"), ("args", "Any"), ("kwargs", "Any")], "Any");
+        
+        // The positional and keyword arguments of pythonCall default to null
+        self.required_arguments.insert("pythonCall".to_string(), 1);
     }
     
     /// Register a standard library function
@@ -198,6 +320,7 @@ impl SemanticAnalyzer {
         let mut new_ast = Ast::new((*ast.root()).clone());
         
         // First pass: register all function declarations
+        self.declarations.clear();
         self.register_functions(new_ast.root())?;
         
         // Second pass: analyze the root node
@@ -215,34 +338,16 @@ impl SemanticAnalyzer {
                     SemanticError::missing_attribute("name", node.location.clone())
                 })?;
                 
-                // Get the function return type
-                let return_type = node.get_attribute("return_type").unwrap_or(&"Void".to_string()).clone();
-                
-                // Get the parameters
-                let mut parameters = Vec::new();
+                // Define the function
+                let function = Symbol::function(node)?;
                 
-                for i in 0..node.child_count() {
-                    if let Some(child) = node.get_child(i) {
-                        if child.kind == NodeKind::Parameter {
-                            let param_name = child.get_attribute("name").ok_or_else(|| {
-                                SemanticError::missing_attribute("name", child.location.clone())
-                            })?;
-                            
-                            let param_type = child.get_attribute("type").unwrap_or(&"Any".to_string()).clone();
-                            
-                            parameters.push((param_name.clone(), param_type));
-                        }
-                    }
+                // A named transformer is a function too
+                if self.transformations.contains_key(name) {
+                    return Err(SemanticError::redefined_function(name, node.location.clone()));
                 }
                 
-                // Define the function
-                let function = Symbol::Function {
-                    name: name.clone(),
-                    parameters: parameters.clone(),
-                    return_type: return_type.clone(),
-                };
-                
                 self.scope.define(name, function);
+                self.declarations.entry(name.clone()).or_insert_with(|| node.location.clone());
             },
             NodeKind::Examples => {
                 // Get the function name
@@ -250,15 +355,11 @@ impl SemanticAnalyzer {
                     SemanticError::missing_attribute("function", node.location.clone())
                 })?;
                 
-                // Define the function with a single string parameter and string return type
-                let function = Symbol::Function {
-                    name: function_name.clone(),
-                    parameters: vec![("input".to_string(), "String".to_string())],
-                    return_type: "String".to_string(),
-                };
-                
-                // Register the function in the current scope
-                self.scope.define(function_name, function);
+                // Define the function with the signature of its examples, unless it is a prompt or declared with `fn`
+                if !self.prompts.contains_key(function_name) && !self.declarations.contains_key(function_name) {
+                    let function = self.example_function(function_name, node)?;
+                    self.scope.define(function_name, function);
+                }
                 
                 // Recursively register functions in all children
                 for i in 0..node.child_count() {
@@ -267,6 +368,32 @@ impl SemanticAnalyzer {
                     }
                 }
             },
+            NodeKind::Transform => {
+                // Named transformers can be called like functions
+                if let Some(name) = node.get_attribute("name") {
+                    if self.declarations.contains_key(name) {
+                        return Err(SemanticError::redefined_function(name, node.location.clone()));
+                    }
+                    
+                    self.transformations.entry(name.clone()).or_default().push(node.clone());
+                    self.scope.define(name, self.transformer_function(name)?);
+                }
+            },
+            NodeKind::TypeDeclaration => {
+                // Record types can be used before they are declared
+                if let Some(name) = node.get_attribute("name") {
+                    self.types.insert(name.clone());
+                }
+            },
+            NodeKind::Prompt => {
+                // Prompts are called like functions that return the model's reply
+                let name = node.get_attribute("name").ok_or_else(|| {
+                    SemanticError::missing_attribute("name", node.location.clone())
+                })?;
+                
+                self.scope.define(name, Self::prompt_function(node));
+                self.prompts.entry(name.clone()).or_default();
+            },
             _ => {
                 // Recursively register functions in all children
                 for i in 0..node.child_count() {
@@ -295,12 +422,22 @@ impl SemanticAnalyzer {
             NodeKind::Return => self.analyze_return(node),
             NodeKind::With => self.analyze_with(node),
             NodeKind::Within => self.analyze_within(node),
+            NodeKind::Deadline => self.analyze_deadline(node),
+            NodeKind::Budget => self.analyze_budget(node),
             NodeKind::Intent => self.analyze_intent(node),
+            NodeKind::IntentHandler => self.analyze_intent_handler(node),
+            NodeKind::Tools => self.analyze_tools(node),
+            NodeKind::Prompt => self.analyze_prompt(node),
+            NodeKind::Render => self.analyze_render(node),
+            NodeKind::Stream => self.analyze_stream(node),
             NodeKind::Parallel => self.analyze_parallel(node),
             NodeKind::Apply => self.analyze_apply(node),
             NodeKind::Semantic => self.analyze_semantic(node),
             NodeKind::Examples => self.analyze_examples(node),
             NodeKind::Example => self.analyze_example(node),
+            NodeKind::Transform => self.analyze_transform(node),
+            NodeKind::TransformApply => self.analyze_transform_apply(node),
+            NodeKind::TypeDeclaration => self.analyze_type_declaration(node),
             NodeKind::Assignment => self.analyze_assignment(node),
             NodeKind::Binary => self.analyze_binary(node),
             NodeKind::Unary => self.analyze_unary(node),
@@ -374,11 +511,6 @@ impl SemanticAnalyzer {
         // Get the function return type
         let return_type = node.get_attribute("return_type").unwrap_or(&"Void".to_string()).clone();
         
-        // Check if the function is already defined
-        if self.scope.contains(name) {
-            return Err(SemanticError::redefined_function(name, node.location.clone()));
-        }
-        
         // Get the parameters
         let mut parameters = Vec::new();
         
@@ -396,6 +528,11 @@ impl SemanticAnalyzer {
             }
         }
         
+        // Check if the function is already defined, other than by the first pass registering this declaration
+        if self.scope.contains(name) && self.declarations.get(name) != Some(&node.location) {
+            return Err(SemanticError::redefined_function(name, node.location.clone()));
+        }
+        
         // Define the function
         let function = Symbol::Function {
             name: name.clone(),
@@ -458,7 +595,7 @@ impl SemanticAnalyzer {
         };
         
         // Get the variable type
-        let typ = node.get_attribute("type").unwrap_or(&"Any".to_string()).clone();
+        let mut typ = node.get_attribute("type").unwrap_or(&"Any".to_string()).clone();
         
         // Get the variable mutability
         let mutable = node.get_attribute("mutable").map_or(false, |m| m == "true");
@@ -476,6 +613,13 @@ impl SemanticAnalyzer {
             if let Some(initializer) = node.get_child_mut(0) {
                 self.analyze_node(initializer)?;
             }
+            
+            // Untyped variables take the type of their initializer when it is known
+            if typ == "Any" {
+                if let Some(inferred) = self.infer_type(&node.children[0]) {
+                    typ = inferred;
+                }
+            }
         }
         
         // Define the variable
@@ -546,6 +690,50 @@ impl SemanticAnalyzer {
         Ok(())
     }
     
+    /// Analyze a deadline node
+    fn analyze_deadline(&mut self, node: &mut Node) -> SemanticResult<()> {
+        // Check the timeout
+        node.get_attribute("timeout").ok_or_else(|| {
+            SemanticError::missing_attribute("timeout", node.location.clone())
+        })?;
+        
+        // Analyze the body
+        if let Some(body) = node.get_child_mut(0) {
+            self.analyze_node(body)?;
+        } else {
+            return Err(SemanticError::missing_child(0, node.location.clone()));
+        }
+        
+        // Analyze the otherwise block if it exists
+        if let Some(otherwise) = node.get_child_mut(1) {
+            self.analyze_node(otherwise)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Analyze a budget node
+    fn analyze_budget(&mut self, node: &mut Node) -> SemanticResult<()> {
+        // Check the number of tokens
+        node.get_attribute("tokens").ok_or_else(|| {
+            SemanticError::missing_attribute("tokens", node.location.clone())
+        })?;
+        
+        // Analyze the body
+        if let Some(body) = node.get_child_mut(0) {
+            self.analyze_node(body)?;
+        } else {
+            return Err(SemanticError::missing_child(0, node.location.clone()));
+        }
+        
+        // Analyze the otherwise block if it exists
+        if let Some(otherwise) = node.get_child_mut(1) {
+            self.analyze_node(otherwise)?;
+        }
+        
+        Ok(())
+    }
+    
     /// Analyze a when node
     fn analyze_when(&mut self, node: &mut Node) -> SemanticResult<()> {
         // Analyze the expression
@@ -619,10 +807,11 @@ impl SemanticAnalyzer {
         // Replace the current scope with a new one that has the clone as parent
         let old_scope = std::mem::replace(&mut self.scope, Scope::with_parent(current_scope_clone));
         
-        // Define the loop variable
+        // Define the loop variable, which holds the chunks of a stream as strings
+        let streamed = node.get_child(0).is_some_and(|collection| collection.kind == NodeKind::Stream);
         let loop_var = Symbol::Variable {
             name: variable.clone(),
-            typ: "Any".to_string(),
+            typ: if streamed { "String" } else { "Any" }.to_string(),
             mutable: false,
         };
         
@@ -756,13 +945,265 @@ impl SemanticAnalyzer {
         Ok(())
     }
     
+    /// Analyze an intent handler node
+    fn analyze_intent_handler(&mut self, node: &mut Node) -> SemanticResult<()> {
+        let location = node.location.clone();
+        let pattern = node.get_attribute("pattern").cloned();
+        
+        // Analyze the handler function
+        let function = node.get_child_mut(0).ok_or_else(|| {
+            SemanticError::missing_child(0, location.clone())
+        })?;
+        
+        self.analyze_node(function)?;
+        
+        let name = function.get_attribute("name").cloned().unwrap_or_default();
+        let parameters: Vec<&String> = function.children.iter()
+            .filter(|child| child.kind == NodeKind::Parameter)
+            .filter_map(|child| child.get_attribute("name"))
+            .collect();
+        
+        // The fallback handler is passed the intent, if anything
+        let pattern = match pattern {
+            Some(pattern) => pattern,
+            None if parameters.len() <= 1 => return Ok(()),
+            None => {
+                return Err(SemanticError::new(
+                    &format!("Fallback intent handler '{}' takes at most one parameter, the intent", name),
+                    location,
+                ));
+            }
+        };
+        
+        // The slots of the pattern are the parameters of the handler
+        let pattern = IntentPattern::parse(&pattern).map_err(|message| SemanticError::new(&message, location.clone()))?;
+        let slots = pattern.slots();
+        
+        if let Some(parameter) = parameters.iter().find(|parameter| !slots.contains(&parameter.as_str())) {
+            return Err(SemanticError::new(
+                &format!("Parameter '{}' of intent handler '{}' has no slot in '{}'", parameter, name, pattern),
+                location,
+            ));
+        }
+        
+        if let Some(slot) = slots.iter().find(|slot| !parameters.iter().any(|parameter| parameter == *slot)) {
+            return Err(SemanticError::new(
+                &format!("Slot '{}' of '{}' is not a parameter of intent handler '{}'", slot, pattern, name),
+                location,
+            ));
+        }
+        
+        Ok(())
+    }
+    
+    /// Analyze a tools node
+    fn analyze_tools(&mut self, node: &mut Node) -> SemanticResult<()> {
+        let location = node.location.clone();
+        
+        for i in 0..node.child_count() {
+            let function = node.get_child_mut(i).ok_or_else(|| {
+                SemanticError::missing_child(i, location.clone())
+            })?;
+            
+            self.analyze_node(function)?;
+            
+            // The parameters are described to the model, which can only pass data
+            let name = function.get_attribute("name").cloned().unwrap_or_default();
+            
+            if let Some(Symbol::Function { parameters, .. }) = self.scope.get(&name) {
+                for (parameter, typ) in parameters.iter().filter(|(_, typ)| typ != "Any") {
+                    self.check_type(typ, &function.location)?;
+                    
+                    if typ == "Context" {
+                        return Err(SemanticError::new(
+                            &format!("Parameter '{}' of tool '{}' is a Context, which a language model cannot pass", parameter, name),
+                            function.location.clone(),
+                        ));
+                    }
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Analyze a prompt node
+    fn analyze_prompt(&mut self, node: &mut Node) -> SemanticResult<()> {
+        let location = node.location.clone();
+        let name = node.get_attribute("name").ok_or_else(|| {
+            SemanticError::missing_attribute("name", location.clone())
+        })?.clone();
+        
+        // Check the parameter types and the type of the reply
+        let Symbol::Function { parameters, return_type, .. } = Self::prompt_function(node) else {
+            unreachable!("prompts are functions");
+        };
+        
+        for (parameter, typ) in &parameters {
+            if parameter == EXAMPLES_SLOT {
+                return Err(SemanticError::new(
+                    &format!("Parameter '{}' of prompt '{}' has the name of the examples slot", parameter, name),
+                    location,
+                ));
+            }
+            
+            if typ != "Any" {
+                self.check_type(typ, &location)?;
+            }
+        }
+        
+        self.check_type(&return_type, &location)?;
+        
+        // The placeholders of the template are the parameters of the prompt
+        let template = node.get_attribute("template").ok_or_else(|| {
+            SemanticError::missing_attribute("template", location.clone())
+        })?;
+        
+        let template = PromptTemplate::parse(template).map_err(|message| SemanticError::new(&message, location.clone()))?;
+        let placeholders = template.parameters();
+        
+        if let Some(placeholder) = placeholders.iter().find(|placeholder| !parameters.iter().any(|(parameter, _)| parameter == *placeholder)) {
+            return Err(SemanticError::new(
+                &format!("Placeholder '${{{}}}' of prompt '{}' is not one of its parameters", placeholder, name),
+                location,
+            ));
+        }
+        
+        if let Some((parameter, _)) = parameters.iter().find(|(parameter, _)| !placeholders.contains(&parameter.as_str())) {
+            return Err(SemanticError::new(
+                &format!("Parameter '{}' of prompt '{}' is not used in its template", parameter, name),
+                location,
+            ));
+        }
+        
+        // Prompts can be declared again with another version
+        let version = match node.get_attribute("version") {
+            Some(version) => version.clone(),
+            None => template::template_version(template.as_str()),
+        };
+        
+        let versions = self.prompts.entry(name.clone()).or_default();
+        
+        if versions.contains(&version) {
+            return Err(SemanticError::new(
+                &format!("Prompt '{}' version '{}' is already declared", name, version),
+                location,
+            ));
+        }
+        
+        versions.push(version);
+        
+        Ok(())
+    }
+    
+    /// Create the symbol of a prompt
+    ///
+    /// Prompts without a declared type return the model's reply as a string.
+    fn prompt_function(node: &Node) -> Symbol {
+        let parameters = node.children.iter()
+            .filter(|child| child.kind == NodeKind::Parameter)
+            .map(|parameter| (
+                parameter.get_attribute("name").cloned().unwrap_or_default(),
+                parameter.get_attribute("type").cloned().unwrap_or_else(|| "Any".to_string()),
+            ))
+            .collect();
+        
+        Symbol::Function {
+            name: node.get_attribute("name").cloned().unwrap_or_default(),
+            parameters,
+            return_type: node.get_attribute("return_type").cloned().unwrap_or_else(|| "String".to_string()),
+        }
+    }
+    
+    /// Analyze a render node
+    fn analyze_render(&mut self, node: &mut Node) -> SemanticResult<()> {
+        let location = node.location.clone();
+        let call = node.get_child_mut(0).ok_or_else(|| {
+            SemanticError::missing_child(0, location.clone())
+        })?;
+        
+        // Only prompts can be rendered
+        let name = call.get_child(0).and_then(|callee| callee.get_attribute("name")).cloned().unwrap_or_default();
+        
+        if !self.prompts.contains_key(&name) {
+            return Err(SemanticError::new(&format!("Cannot render '{}', which is not a prompt", name), location));
+        }
+        
+        self.analyze_node(call)
+    }
+    
+    /// Analyze a stream node
+    fn analyze_stream(&mut self, node: &mut Node) -> SemanticResult<()> {
+        let location = node.location.clone();
+        let prompt = node.get_child_mut(0).ok_or_else(|| {
+            SemanticError::missing_child(0, location.clone())
+        })?;
+        
+        // Replies that are parsed as a type cannot be used before they are complete
+        if prompt.kind == NodeKind::NaturalLanguage {
+            if let Some(typ) = prompt.get_attribute("type") {
+                return Err(SemanticError::new(
+                    &format!("Cannot stream natural language as {}, since it is parsed once it is complete", typ),
+                    prompt.location.clone(),
+                ));
+            }
+            
+            return Ok(());
+        }
+        
+        self.analyze_node(prompt)?;
+        
+        let typ = self.infer_type(prompt);
+        let name = prompt.get_child(0).filter(|_| prompt.kind == NodeKind::Call).and_then(|callee| callee.get_attribute("name"));
+        
+        match (typ, name) {
+            (Some(typ), Some(name)) if self.prompts.contains_key(name) && typ != "String" => Err(SemanticError::new(
+                &format!("Cannot stream prompt '{}' as {}, since it is parsed once it is complete", name, typ),
+                prompt.location.clone(),
+            )),
+            (Some(typ), _) if typ != "String" && typ != "Any" => Err(SemanticError::invalid_type("String", &typ, prompt.location.clone())),
+            _ => Ok(()),
+        }
+    }
+    
+    /// Check the types of the arguments of a call to a prompt, where they are known
+    fn check_prompt_arguments(&self, node: &Node) -> SemanticResult<()> {
+        let Some(name) = node.get_child(0).filter(|callee| callee.kind == NodeKind::Identifier).and_then(|callee| callee.get_attribute("name")) else {
+            return Ok(());
+        };
+        
+        let Some(Symbol::Function { parameters, .. }) = self.scope.get(name).filter(|_| self.prompts.contains_key(name)) else {
+            return Ok(());
+        };
+        
+        for ((parameter, expected), argument) in parameters.iter().zip(node.children.iter().skip(1)) {
+            let Some(actual) = self.infer_type(argument) else {
+                continue;
+            };
+            
+            let accepted = expected == "Any"
+                || *expected == actual
+                || (expected == "Float" && actual == "Int")
+                || expected.starts_with(&format!("{}<", actual));
+            
+            if !accepted {
+                return Err(SemanticError::new(
+                    &format!("Argument '{}' of prompt '{}' should be {}, got {}", parameter, name, expected, actual),
+                    argument.location.clone(),
+                ));
+            }
+        }
+        
+        Ok(())
+    }
+    
     /// Analyze a parallel node
     fn analyze_parallel(&mut self, node: &mut Node) -> SemanticResult<()> {
         // Get the selection strategy
         let strategy = node.get_attribute("strategy").unwrap_or(&"all".to_string()).clone();
         
         // Check if the strategy is valid
-        if strategy != "all" && strategy != "fastest" && strategy != "best" {
+        if !["all", "fastest", "best", "first_success", "vote", "merge"].contains(&strategy.as_str()) {
             return Err(SemanticError::invalid_strategy(&strategy, node.location.clone()));
         }
         
@@ -786,6 +1227,25 @@ impl SemanticAnalyzer {
                     } else {
                         return Err(SemanticError::missing_child(0, child.location.clone()));
                     }
+                } else {
+                    // Analyze the scorer or merge expression
+                    self.analyze_node(child)?;
+                    
+                    // A scorer receives one path result and a merge function receives the list of results
+                    if child.kind == NodeKind::Identifier {
+                        if let Some(name) = child.get_attribute("name") {
+                            if let Some(Symbol::Function { parameters, .. }) = self.scope.get(name) {
+                                if parameters.len() != 1 {
+                                    return Err(SemanticError::invalid_argument_count(
+                                        name,
+                                        1,
+                                        parameters.len(),
+                                        child.location.clone(),
+                                    ));
+                                }
+                            }
+                        }
+                    }
                 }
             }
         }
@@ -878,7 +1338,7 @@ impl SemanticAnalyzer {
     /// Analyze a binary node
     fn analyze_binary(&mut self, node: &mut Node) -> SemanticResult<()> {
         // Get the operator
-        let operator = node.get_attribute("operator").ok_or_else(|| {
+        let operator = node.get_attribute("operator").cloned().ok_or_else(|| {
             SemanticError::missing_attribute("operator", node.location.clone())
         })?;
         
@@ -889,6 +1349,11 @@ impl SemanticAnalyzer {
             return Err(SemanticError::missing_child(0, node.location.clone()));
         }
         
+        // Property access names the property instead of having a right operand
+        if operator == "." {
+            return self.check_property_access(node);
+        }
+        
         // Analyze the right operand
         if let Some(right) = node.get_child_mut(1) {
             self.analyze_node(right)?;
@@ -896,9 +1361,135 @@ impl SemanticAnalyzer {
             return Err(SemanticError::missing_child(1, node.location.clone()));
         }
         
+        // A fallback can have any type
+        if operator == "??" {
+            return Ok(());
+        }
+        
+        // Check vector arithmetic when the operand types are known
+        let left = self.infer_type(&node.children[0]);
+        let right = self.infer_type(&node.children[1]);
+        
+        if let (Some(left), Some(right)) = (left, right) {
+            Self::vector_operation_type(&operator, &left, &right, &node.location)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Check a property access on a value whose type is known
+    fn check_property_access(&self, node: &Node) -> SemanticResult<()> {
+        let property = node.get_attribute("name").ok_or_else(|| {
+            SemanticError::missing_attribute("name", node.location.clone())
+        })?;
+        
+        if self.infer_type(&node.children[0]).as_deref() == Some("Vector") && Self::vector_property_type(property).is_none() {
+            return Err(SemanticError::new(
+                &format!("Unknown vector property: '{}'", property),
+                node.location.clone(),
+            ));
+        }
+        
         Ok(())
     }
     
+    /// Get the type of a vector property
+    fn vector_property_type(property: &str) -> Option<&'static str> {
+        match property {
+            "normalized" => Some("Vector"),
+            "magnitude" => Some("Float"),
+            "dimension" => Some("Int"),
+            _ => None,
+        }
+    }
+    
+    /// Get the result type of an arithmetic operation involving a vector
+    ///
+    /// Vectors can be added to and subtracted from vectors, scaled by numbers,
+    /// and multiplied by vectors to get their dot product. Operations without
+    /// a vector operand are not checked and return `None`.
+    fn vector_operation_type(operator: &str, left: &str, right: &str, location: &SourceLocation) -> SemanticResult<Option<&'static str>> {
+        if left != "Vector" && right != "Vector" {
+            return Ok(None);
+        }
+        
+        let numeric = |typ: &str| typ == "Int" || typ == "Float";
+        
+        let result = match operator {
+            "+" | "-" if left == right => "Vector",
+            "*" if left == right => "Float",
+            "*" if numeric(left) || numeric(right) => "Vector",
+            "/" if numeric(right) => "Vector",
+            "==" | "!=" | "and" | "or" => return Ok(Some("Bool")),
+            _ => return Err(SemanticError::invalid_operation(operator, left, right, location.clone())),
+        };
+        
+        Ok(Some(result))
+    }
+    
+    /// Infer the type of an expression, if it can be known before execution
+    fn infer_type(&self, node: &Node) -> Option<String> {
+        let typ = match node.kind {
+            NodeKind::Literal => node.get_attribute("type")?.clone(),
+            NodeKind::NaturalLanguage => node.get_attribute("type")?.clone(),
+            NodeKind::Render => "String".to_string(),
+            NodeKind::Stream => "List".to_string(),
+            NodeKind::Grouping => return self.infer_type(node.get_child(0)?),
+            NodeKind::Identifier => match self.scope.get(node.get_attribute("name")?)? {
+                Symbol::Variable { typ, .. } => typ.clone(),
+                _ => return None,
+            },
+            NodeKind::Call => {
+                let callee = node.get_child(0)?;
+                
+                if callee.kind != NodeKind::Identifier {
+                    return None;
+                }
+                
+                match self.scope.get(callee.get_attribute("name")?)? {
+                    Symbol::Function { return_type, .. } => return_type.clone(),
+                    _ => return None,
+                }
+            }
+            NodeKind::Unary => match node.get_attribute("operator")?.as_str() {
+                "-" => return self.infer_type(node.get_child(0)?),
+                _ => "Bool".to_string(),
+            },
+            NodeKind::Binary => {
+                let operator = node.get_attribute("operator")?;
+                
+                if operator == "." {
+                    if self.infer_type(node.get_child(0)?)? != "Vector" {
+                        return None;
+                    }
+                    
+                    return Self::vector_property_type(node.get_attribute("name")?).map(str::to_string);
+                }
+                
+                let left = self.infer_type(node.get_child(0)?)?;
+                let right = self.infer_type(node.get_child(1)?)?;
+                
+                // The type of a fallback is only known if it is the type of the value
+                if operator == "??" {
+                    return (left == right).then_some(left);
+                }
+                
+                match Self::vector_operation_type(operator, &left, &right, &node.location) {
+                    Ok(Some(typ)) => typ.to_string(),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        
+        // Values of unknown type are not checked
+        if typ == "Any" {
+            None
+        } else {
+            Some(typ)
+        }
+    }
+    
     /// Analyze a unary node
     fn analyze_unary(&mut self, node: &mut Node) -> SemanticResult<()> {
         // Get the operator
@@ -918,7 +1509,13 @@ impl SemanticAnalyzer {
     
     /// Analyze a literal node
     fn analyze_literal(&mut self, node: &mut Node) -> SemanticResult<()> {
-        // Nothing to do for literals
+        // Analyze the elements of list literals
+        for i in 0..node.child_count() {
+            if let Some(element) = node.get_child_mut(i) {
+                self.analyze_node(element)?;
+            }
+        }
+        
         Ok(())
     }
     
@@ -960,14 +1557,15 @@ impl SemanticAnalyzer {
                 if let Some(symbol) = self.scope.get(name) {
                     match symbol {
                         Symbol::Function { parameters, .. } => {
-                            // Check if the argument count matches
+                            // Check if the argument count matches, allowing optional arguments to be omitted
                             let expected = parameters.len();
+                            let required = self.required_arguments.get(name).copied().unwrap_or(expected);
                             let actual = child_count - 1;
                             
-                            if expected != actual {
+                            if actual < required || actual > expected {
                                 return Err(SemanticError::invalid_argument_count(
                                     name,
-                                    expected,
+                                    if actual < required { required } else { expected },
                                     actual,
                                     location,
                                 ));
@@ -992,13 +1590,16 @@ impl SemanticAnalyzer {
             }
         }
         
-        Ok(())
+        self.check_prompt_arguments(node)
     }
     
     /// Analyze a natural language node
     fn analyze_natural_language(&mut self, node: &mut Node) -> SemanticResult<()> {
-        // Nothing to do for natural language expressions
-        Ok(())
+        // Check the type of structured output, if any
+        match node.get_attribute("type") {
+            Some(typ) => self.check_type(typ, &node.location),
+            None => Ok(()),
+        }
     }
     
     /// Analyze a vector node
@@ -1032,15 +1633,11 @@ impl SemanticAnalyzer {
             SemanticError::missing_attribute("function", node.location.clone())
         })?;
         
-        // Define the function with a single string parameter and string return type
-        let function = Symbol::Function {
-            name: function_name.clone(),
-            parameters: vec![("input".to_string(), "String".to_string())],
-            return_type: "String".to_string(),
-        };
-        
-        // Register the function in the current scope
-        self.scope.define(function_name, function);
+        // Define the function with the signature of its examples, unless they are the examples of a prompt or a declared function
+        if !self.prompts.contains_key(function_name) && !self.declarations.contains_key(function_name) {
+            let function = self.example_function(function_name, node)?;
+            self.scope.define(function_name, function);
+        }
         
         // Analyze all examples
         for i in 0..node.child_count() {
@@ -1052,6 +1649,173 @@ impl SemanticAnalyzer {
         Ok(())
     }
     
+    /// Create the symbol of a function defined by examples
+    ///
+    /// The function takes a single argument, unless its examples give several
+    /// in parentheses, in which case it takes one argument per value. Parameter
+    /// and return types are those of the examples when they all agree, and
+    /// `Any` otherwise.
+    fn example_function(&self, name: &str, node: &Node) -> SemanticResult<Symbol> {
+        let examples: Vec<&Node> = node.children.iter()
+            .filter(|child| child.kind == NodeKind::Example)
+            .map(|child| child.as_ref())
+            .collect();
+        
+        Self::check_example_arity(name, &examples)?;
+        
+        Ok(self.function_from_examples(name, &examples))
+    }
+    
+    /// Get the number of arguments the input of an example or a transformation gives
+    fn example_arity(example: &Node) -> usize {
+        example.get_attribute("arity").and_then(|arity| arity.parse().ok()).unwrap_or(1)
+    }
+    
+    /// Check that every example of a function gives the same number of arguments
+    fn check_example_arity(name: &str, examples: &[&Node]) -> SemanticResult<()> {
+        let Some(first) = examples.first() else {
+            return Ok(());
+        };
+        
+        let expected = Self::example_arity(first);
+        
+        for example in examples {
+            let arity = Self::example_arity(example);
+            
+            if arity != expected {
+                return Err(SemanticError::example_arity_mismatch(name, expected, arity, example.location.clone()));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Create the symbol of a function from its examples, whose first child is the input and second the output
+    ///
+    /// The examples are expected to give the same number of arguments, as
+    /// `check_example_arity` checks.
+    fn function_from_examples(&self, name: &str, examples: &[&Node]) -> Symbol {
+        let arity = examples.first().map_or(1, |example| Self::example_arity(example));
+        
+        // The common type of the nodes, or `Any`
+        let common_type = |nodes: Vec<Option<&Node>>| -> String {
+            let types: Vec<Option<String>> = nodes.into_iter().map(|node| node.and_then(|node| self.infer_type(node))).collect();
+            
+            match types.first() {
+                Some(Some(first)) if types.iter().all(|typ| typ.as_ref() == Some(first)) => first.clone(),
+                _ => "Any".to_string(),
+            }
+        };
+        
+        let parameters = if arity == 1 {
+            let inputs = examples.iter().map(|example| example.get_child(0)).collect();
+            
+            vec![("input".to_string(), common_type(inputs))]
+        } else {
+            (0..arity).map(|i| {
+                let items = examples.iter().map(|example| example.get_child(0).and_then(|arguments| arguments.get_child(i))).collect();
+                
+                (format!("input{}", i + 1), common_type(items))
+            }).collect()
+        };
+        
+        let outputs = examples.iter().map(|example| example.get_child(1)).collect();
+        
+        Symbol::Function {
+            name: name.to_string(),
+            parameters,
+            return_type: common_type(outputs),
+        }
+    }
+    
+    /// Analyze a transform node
+    fn analyze_transform(&mut self, node: &mut Node) -> SemanticResult<()> {
+        // Register named transformers in the current scope
+        if let Some(name) = node.get_attribute("name").cloned() {
+            self.scope.define(&name, self.transformer_function(&name)?);
+        }
+        
+        // Analyze the input and output
+        for i in 0..2 {
+            match node.get_child_mut(i) {
+                Some(child) => self.analyze_node(child)?,
+                None => return Err(SemanticError::missing_child(i, node.location.clone())),
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Analyze a transform application node
+    fn analyze_transform_apply(&mut self, node: &mut Node) -> SemanticResult<()> {
+        // Check that a named transformer is defined
+        if let Some(name) = node.get_attribute("name") {
+            if !matches!(self.scope.get(name), Some(Symbol::Function { .. })) {
+                return Err(SemanticError::undefined_function(name, node.location.clone()));
+            }
+        }
+        
+        // Analyze the value
+        if let Some(value) = node.get_child_mut(0) {
+            self.analyze_node(value)
+        } else {
+            Err(SemanticError::missing_child(0, node.location.clone()))
+        }
+    }
+    
+    /// Analyze a type declaration node
+    fn analyze_type_declaration(&mut self, node: &mut Node) -> SemanticResult<()> {
+        let mut fields = HashSet::new();
+        
+        for field in &node.children {
+            let name = field.get_attribute("name").ok_or_else(|| {
+                SemanticError::missing_attribute("name", field.location.clone())
+            })?;
+            
+            if !fields.insert(name) {
+                return Err(SemanticError::new(
+                    &format!("Duplicate field: '{}'", name),
+                    field.location.clone(),
+                ));
+            }
+            
+            let typ = field.get_attribute("type").ok_or_else(|| {
+                SemanticError::missing_attribute("type", field.location.clone())
+            })?;
+            
+            self.check_type(typ, &field.location)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Check that a type is built in, semantic, or a declared record type
+    fn check_type(&self, typ: &str, location: &SourceLocation) -> SemanticResult<()> {
+        for prefix in ["List<", "Map<"] {
+            if let Some(inner) = typ.strip_prefix(prefix).and_then(|rest| rest.strip_suffix('>')) {
+                return self.check_type(inner, location);
+            }
+        }
+        
+        match typ {
+            "Int" | "Float" | "String" | "Bool" | "List" | "Map" | "Vector" | "Context" => Ok(()),
+            _ if typ.starts_with('~') || self.types.contains(typ) => Ok(()),
+            _ => Err(SemanticError::undefined_type(typ, location.clone())),
+        }
+    }
+    
+    /// Create the symbol of a named transformer
+    ///
+    /// The transformations are the examples of the transformer, so it takes
+    /// one argument per value if they give several in parentheses.
+    fn transformer_function(&self, name: &str) -> SemanticResult<Symbol> {
+        let transformations: Vec<&Node> = self.transformations.get(name).map(|nodes| nodes.iter().collect()).unwrap_or_default();
+        
+        Self::check_example_arity(name, &transformations)?;
+        
+        Ok(self.function_from_examples(name, &transformations))
+    }
+    
     /// Analyze an example node
     fn analyze_example(&mut self, node: &mut Node) -> SemanticResult<()> {
         // Analyze the input
@@ -1120,4 +1884,321 @@ mod tests {
             mutable: false,
         }));
     }
+    
+    /// Parse and analyze source code
+    fn analyze_source(source: &str) -> SemanticResult<Ast> {
+        let tokens = crate::Lexer::new(source).tokenize().unwrap();
+        let ast = crate::Parser::new(tokens).parse().unwrap();
+        
+        SemanticAnalyzer::new().analyze(ast)
+    }
+    
+    #[test]
+    fn test_analyze_top_level_function() {
+        assert!(analyze_source("fn f(a: Int) -> Int { return a; }").is_ok());
+        
+        let error = analyze_source("fn f(a: Int) -> Int { return a; }\nfn f() -> Int { return 1; }").unwrap_err();
+        assert_eq!(error.message, "Redefined function: 'f'");
+        
+        let error = analyze_source("fn f(a: Int) -> Int { return a; }\nfn f(a: Int) -> Int { return 1; }").unwrap_err();
+        assert_eq!(error.message, "Redefined function: 'f'");
+    }
+    
+    #[test]
+    fn test_analyze_optional_arguments() {
+        assert!(analyze_source("pythonCall(\"len\");").is_ok());
+        assert!(analyze_source("pythonCall(\"len\", [[1, 2]]);").is_ok());
+        assert!(analyze_source("pythonCall(\"len\", [[1, 2]], null);").is_ok());
+        
+        let error = analyze_source("pythonCall();").unwrap_err();
+        assert_eq!(error.message, "Invalid argument count for function 'pythonCall': expected 1, got 0");
+        
+        let error = analyze_source("pythonCall(\"len\", [], null, null);").unwrap_err();
+        assert_eq!(error.message, "Invalid argument count for function 'pythonCall': expected 3, got 4");
+    }
+    
+    #[test]
+    fn test_analyze_parallel_scorer_arity() {
+        let source = r#"
+            fn score(a: String, b: String) -> Float { return 1.0; }
+            var r = parallel {
+                a: { "x"; }
+            } select best by score;
+        "#;
+        
+        let error = analyze_source(source).unwrap_err();
+        assert_eq!(error.message, "Invalid argument count for function 'score': expected 1, got 2");
+    }
+    
+    #[test]
+    fn test_analyze_parallel_strategies() {
+        let source = r#"
+            var r = parallel {
+                a: { "x"; }
+                b: { "y"; }
+            } select vote timeout 500ms;
+        "#;
+        
+        assert!(analyze_source(source).is_ok());
+        
+        let source = r#"
+            fn combine(a: String, b: String) -> String { return a; }
+            var r = parallel {
+                a: { "x"; }
+            } select merge with combine;
+        "#;
+        
+        let error = analyze_source(source).unwrap_err();
+        assert_eq!(error.message, "Invalid argument count for function 'combine': expected 1, got 2");
+    }
+    
+    #[test]
+    fn test_analyze_vector_arithmetic() {
+        let source = r#"
+            vector a = embed("cat");
+            var b = embed("dog");
+            var c = a * 0.7 + b * 0.3;
+            var d = (c - a) / 2;
+            var score = a.normalized * b.normalized + d.magnitude;
+        "#;
+        
+        assert!(analyze_source(source).is_ok());
+        
+        let error = analyze_source("var a = embed(\"cat\"); var b = a + 1;").unwrap_err();
+        assert_eq!(error.message, "Invalid operation: Vector + Int");
+        
+        let error = analyze_source("var a = embed(\"cat\"); var b = 2.0 / a;").unwrap_err();
+        assert_eq!(error.message, "Invalid operation: Float / Vector");
+        
+        // The type of a vector combination is inferred
+        let error = analyze_source("var a = embed(\"cat\") * 2; var b = a + \"text\";").unwrap_err();
+        assert_eq!(error.message, "Invalid operation: Vector + String");
+        
+        let error = analyze_source("var a = embed(\"cat\"); var b = a.length;").unwrap_err();
+        assert_eq!(error.message, "Unknown vector property: 'length'");
+    }
+    
+    #[test]
+    fn test_analyze_example_functions() {
+        let source = r#"
+            examples for double {
+                2 -> 4;
+                3 -> 6;
+            }
+            examples for add {
+                (1, 2) -> 3;
+                (2, 2) -> 4;
+            }
+            var a = double(2) + add(1, 2);
+        "#;
+        
+        assert!(analyze_source(source).is_ok());
+        
+        let error = analyze_source("examples for double { 2 -> 4; } var a = double(2, 3);").unwrap_err();
+        assert_eq!(error.message, "Invalid argument count for function 'double': expected 1, got 2");
+        
+        // The return type is inferred from the example outputs
+        let error = analyze_source("examples for double { 2 -> 4; } var a = embed(\"cat\") + double(2);").unwrap_err();
+        assert_eq!(error.message, "Invalid operation: Vector + Int");
+        
+        // A list is a single argument, whatever its length
+        assert!(analyze_source("examples for rev { [1, 2, 3] -> [3, 2, 1]; [4, 5, 6] -> [6, 5, 4]; } var a = rev([7, 8, 9]);").is_ok());
+        
+        let error = analyze_source("examples for rev { [1, 2, 3] -> [3, 2, 1]; } var a = rev(7, 8, 9);").unwrap_err();
+        assert_eq!(error.message, "Invalid argument count for function 'rev': expected 1, got 3");
+        
+        let error = analyze_source("examples for add { (1, 2) -> 3; 4 -> 4; }").unwrap_err();
+        assert_eq!(error.message, "Examples for 'add' give 2 arguments, but this one gives 1");
+        
+        // A declared function keeps its own signature
+        assert!(analyze_source("fn add(a: Int, b: Int) -> Int { return a + b; } examples for add { [1, 2] -> 3; } var a = add(1, 2);").is_ok());
+    }
+    
+    #[test]
+    fn test_analyze_transform() {
+        let source = r#"
+            transform "hello" into "Hello";
+            transform plural: "apple" into "apples";
+            var a = transform "world";
+            var b = transform "box" with plural;
+            var c = plural("cat");
+        "#;
+        
+        assert!(analyze_source(source).is_ok());
+        
+        let error = analyze_source("var a = transform \"box\" with plural;").unwrap_err();
+        assert_eq!(error.message, "Undefined function: 'plural'");
+        
+        // A transformer whose inputs are lists takes one argument per item
+        let source = r#"
+            var a = add(4, 4);
+            transform add: (1, 2) into 3;
+            transform add: (2, 5) into 7;
+        "#;
+        
+        assert!(analyze_source(source).is_ok());
+        
+        let error = analyze_source("transform add: (1, 2) into 3; var a = add(4);").unwrap_err();
+        assert_eq!(error.message, "Invalid argument count for function 'add': expected 2, got 1");
+        
+        // A named transformer cannot share its name with a declared function
+        let error = analyze_source("fn plural(word: String) -> String { return word + \"s\"; } transform plural: \"box\" into \"boxes\";").unwrap_err();
+        assert_eq!(error.message, "Redefined function: 'plural'");
+        
+        let error = analyze_source("transform plural: \"box\" into \"boxes\"; fn plural(word: String) -> String { return word + \"s\"; }").unwrap_err();
+        assert_eq!(error.message, "Redefined function: 'plural'");
+    }
+    
+    #[test]
+    fn test_analyze_probabilities() {
+        let source = r#"
+            examples for name {
+                1 -> "one";
+                2 -> "two";
+            }
+            var a = name(3) ?? "unknown";
+            var b = probOr([prob("cat", 0.6), classify("a new app", ["technology", "sports"])]);
+            var c = a.confidence + confidence(b);
+            var d = embed("cat") ?? "none";
+        "#;
+        
+        assert!(analyze_source(source).is_ok());
+        
+        // The type of a fallback of the same type is known
+        let error = analyze_source("var a = embed(\"cat\") ?? embed(\"dog\"); var b = a + 1;").unwrap_err();
+        assert_eq!(error.message, "Invalid operation: Vector + Int");
+    }
+    
+    #[test]
+    fn test_analyze_structured_output() {
+        let source = r##"
+            var users = #"find premium users"# as List<User>;
+            var count = #"how many users are there"# as Int;
+            type User { name: String, email: ~EmailAddress~, tags: List<String> }
+        "##;
+        
+        assert!(analyze_source(source).is_ok());
+        
+        let error = analyze_source("var a = #\"find premium users\"# as List<Customer>;").unwrap_err();
+        assert_eq!(error.message, "Undefined type: 'Customer'");
+        
+        let error = analyze_source("type User { name: String, name: Int }").unwrap_err();
+        assert_eq!(error.message, "Duplicate field: 'name'");
+        
+        // The type of a structured output is known
+        let error = analyze_source("var a = #\"how many users are there\"# as Int; var b = a + embed(\"cat\");").unwrap_err();
+        assert_eq!(error.message, "Invalid operation: Int + Vector");
+    }
+    
+    #[test]
+    fn test_analyze_tools() {
+        let source = r#"
+            type Order { id: Int, status: String }
+            tools {
+                "Look up an order by its id"
+                fn lookupOrder(id: Int) -> Order { return lookup(id); }
+            }
+            fn lookup(id: Int) -> Order { return id; }
+            var answer = agent("where is order 42?");
+        "#;
+        
+        assert!(analyze_source(source).is_ok());
+        
+        let error = analyze_source("tools { fn lookupOrder(id: OrderId) { id; } }").unwrap_err();
+        assert_eq!(error.message, "Undefined type: 'OrderId'");
+        
+        let error = analyze_source("tools { fn switchTo(target: Context) { target; } }").unwrap_err();
+        assert_eq!(error.message, "Parameter 'target' of tool 'switchTo' is a Context, which a language model cannot pass");
+    }
+    
+    #[test]
+    fn test_analyze_prompt() {
+        let source = r##"
+            type Label { name: String, confidence: Float }
+            prompt Classify(ticket: String) -> Label { #"Classify the ticket.
+                ${examples}
+                ticket: ${ticket}"# }
+            examples for Classify {
+                "I was charged twice" -> "billing";
+            }
+            prompt Classify(ticket: String) -> Label version "2" { #"Label ${ticket}"# }
+            var label = Classify("Where is my invoice?");
+            var text = render Classify("Where is my invoice?");
+            var size = length(text);
+        "##;
+        
+        let result = analyze_source(source);
+        assert!(result.is_ok(), "{:?}", result);
+        
+        let summarize = r##"prompt Summarize(text: String, max_words: Int) { #"Summarize ${text} in ${max_words} words"# }"##;
+        let error = analyze_source(&format!("{} Summarize(\"report\", \"ten\");", summarize)).unwrap_err();
+        assert_eq!(error.message, "Argument 'max_words' of prompt 'Summarize' should be Int, got String");
+        
+        let error = analyze_source(&format!("{} Summarize(\"report\");", summarize)).unwrap_err();
+        assert!(error.message.contains("Summarize"), "{}", error.message);
+        
+        let error = analyze_source(r##"prompt Summarize(text: String) { #"Summarize ${txt}"# }"##).unwrap_err();
+        assert_eq!(error.message, "Placeholder '${txt}' of prompt 'Summarize' is not one of its parameters");
+        
+        let error = analyze_source(r##"prompt Summarize(text: String, max_words: Int) { #"Summarize ${text}"# }"##).unwrap_err();
+        assert_eq!(error.message, "Parameter 'max_words' of prompt 'Summarize' is not used in its template");
+        
+        let error = analyze_source(r##"prompt Summarize(text: Report) { #"Summarize ${text}"# }"##).unwrap_err();
+        assert_eq!(error.message, "Undefined type: 'Report'");
+        
+        let error = analyze_source(&format!("{} {}", summarize, summarize)).unwrap_err();
+        assert!(error.message.starts_with("Prompt 'Summarize' version '"), "{}", error.message);
+        
+        let error = analyze_source("fn summarize(text: String) -> String { return text; } var text = render summarize(\"report\");").unwrap_err();
+        assert_eq!(error.message, "Cannot render 'summarize', which is not a prompt");
+    }
+    
+    #[test]
+    fn test_analyze_stream() {
+        let source = r##"
+            prompt Summarize(text: String) { #"Summarize ${text}"# }
+            for chunk in stream Summarize("the report") timeout 2s {
+                var size = length(chunk) + 1;
+            }
+            var chunks = stream #"Tell me a story"#;
+            var text = "Tell me a joke";
+            for (chunk in stream text) { print(chunk); }
+        "##;
+        
+        let result = analyze_source(source);
+        assert!(result.is_ok(), "{:?}", result);
+        
+        let error = analyze_source(r##"type Label { name: String } var label = stream #"Label this"# as Label;"##).unwrap_err();
+        assert_eq!(error.message, "Cannot stream natural language as Label, since it is parsed once it is complete");
+        
+        let error = analyze_source(r##"type Label { name: String } prompt Classify(text: String) -> Label { #"Label ${text}"# } var label = stream Classify("a");"##).unwrap_err();
+        assert_eq!(error.message, "Cannot stream prompt 'Classify' as Label, since it is parsed once it is complete");
+        
+        let error = analyze_source("var count = 3; var chunks = stream count;").unwrap_err();
+        assert_eq!(error.message, "Invalid type: expected String, got Int");
+    }
+    
+    #[test]
+    fn test_analyze_intent_handler() {
+        let source = r#"
+            on intent "refund order {order_id}" fn handleRefund(order_id: Int) -> Int { return order_id; }
+            on intent otherwise fn handleUnknown(text: String) -> String { return text; }
+            intent "refund order 42";
+            routeIntent("refund order 42");
+        "#;
+        
+        assert!(analyze_source(source).is_ok());
+        
+        let error = analyze_source("on intent \"refund order\" fn handleRefund(order_id: Int) { order_id; }").unwrap_err();
+        assert_eq!(error.message, "Parameter 'order_id' of intent handler 'handleRefund' has no slot in 'refund order'");
+        
+        let error = analyze_source("on intent \"refund order {id}\" fn handleRefund() { 1; }").unwrap_err();
+        assert_eq!(error.message, "Slot 'id' of 'refund order {id}' is not a parameter of intent handler 'handleRefund'");
+        
+        let error = analyze_source("on intent otherwise fn handleUnknown(a: String, b: String) { a; }").unwrap_err();
+        assert_eq!(error.message, "Fallback intent handler 'handleUnknown' takes at most one parameter, the intent");
+        
+        let error = analyze_source("on intent \"refund {order\" fn handleRefund(order: Int) { order; }").unwrap_err();
+        assert_eq!(error.message, "Unclosed slot in intent pattern 'refund {order'");
+    }
 }
@@ -162,6 +162,17 @@ impl SemanticError {
         Self::new(&format!("Invalid semantic token: '{}'", token), location)
     }
     
+    /// Create a new "example arity mismatch" error
+    pub fn example_arity_mismatch(function: &str, expected: usize, actual: usize, location: SourceLocation) -> Self {
+        Self::new(
+            &format!(
+                "Examples for '{}' give {} arguments, but this one gives {}",
+                function, expected, actual
+            ),
+            location,
+        )
+    }
+    
     /// Create a new "missing attribute" error
     pub fn missing_attribute(attribute: &str, location: SourceLocation) -> Self {
         Self::new(&format!("Missing attribute: '{}'", attribute), location)
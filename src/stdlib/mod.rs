@@ -7,23 +7,51 @@
 use std::collections::HashMap;
 use std::io::Write;
 use crate::Value;
+use crate::runtime::cancel::CancellationToken;
 use crate::runtime::error::RuntimeError;
+use crate::runtime::nlp::NLP;
+use crate::runtime::rag;
+use crate::runtime::vector::Vector;
 use crate::utils::SourceLocation;
 
+/// Functions that take probabilistic values as they are, instead of their most likely values
+pub const PROBABILITY_FUNCTIONS: &[&str] = &["prob", "confidence", "probAnd", "probOr"];
+
+/// Natural language functions that need a language model, and are unavailable when NLP is disabled
+pub const MODEL_FUNCTIONS: &[&str] = &["extractSummary", "translateText", "answerQuestion"];
+
 /// The standard library for the LLM.lang language
 pub struct StdLib {
     /// The functions in the standard library
     pub functions: HashMap<String, StdLibFunction>,
+    
+    /// The natural language functions in the standard library
+    pub nlp_functions: HashMap<String, NlpFunction>,
+    
+    /// The natural language processor the natural language functions are sent to
+    nlp: NLP,
 }
 
 /// A standard library function
 type StdLibFunction = fn(Vec<Value>) -> Result<Value, RuntimeError>;
 
+/// A standard library function that is sent to a natural language processor
+type NlpFunction = fn(&NLP, Vec<Value>) -> Result<Value, RuntimeError>;
+
 impl StdLib {
     /// Create a new standard library
+    ///
+    /// The natural language functions use the mock backend.
     pub fn new() -> Self {
+        Self::with_nlp(NLP::new())
+    }
+    
+    /// Create a new standard library whose natural language functions are sent to a processor
+    pub fn with_nlp(nlp: NLP) -> Self {
         let mut stdlib = Self {
             functions: HashMap::new(),
+            nlp_functions: HashMap::new(),
+            nlp,
         };
         
         // Register core functions
@@ -39,6 +67,13 @@ impl StdLib {
         stdlib.register_function("map", Self::map);
         stdlib.register_function("filter", Self::filter);
         stdlib.register_function("reduce", Self::reduce);
+        stdlib.register_function("reverse", Self::reverse);
+        stdlib.register_function("sort", Self::sort);
+        stdlib.register_function("first", Self::first);
+        stdlib.register_function("last", Self::last);
+        stdlib.register_function("sum", Self::sum);
+        stdlib.register_function("max", Self::max);
+        stdlib.register_function("min", Self::min);
         
         // Register string functions
         stdlib.register_function("substring", Self::substring);
@@ -46,11 +81,39 @@ impl StdLib {
         stdlib.register_function("toLowerCase", Self::to_lower_case);
         stdlib.register_function("toUpperCase", Self::to_upper_case);
         stdlib.register_function("trim", Self::trim);
+        stdlib.register_function("split", Self::split);
+        stdlib.register_function("join", Self::join);
+        
+        // Register natural language functions
+        stdlib.register_nlp_function("tokenize", Self::tokenize);
+        stdlib.register_nlp_function("splitSentences", Self::split_sentences);
+        stdlib.register_nlp_function("stemming", Self::stemming);
+        stdlib.register_nlp_function("lemmatize", Self::lemmatize);
+        stdlib.register_nlp_function("extractKeywords", Self::extract_keywords);
+        stdlib.register_nlp_function("extractSentiment", Self::extract_sentiment);
+        stdlib.register_nlp_function("extractEntities", Self::extract_entities);
+        stdlib.register_nlp_function("extractSummary", Self::extract_summary);
+        stdlib.register_nlp_function("translateText", Self::translate_text);
+        stdlib.register_nlp_function("answerQuestion", Self::answer_question);
         
         // Register vector functions
         stdlib.register_function("embed", Self::embed);
         stdlib.register_function("similarity", Self::similarity);
         stdlib.register_function("nearest", Self::nearest);
+        stdlib.register_function("combineConcepts", Self::combine_concepts);
+        stdlib.register_function("normalize", Self::normalize);
+        stdlib.register_function("cosine", Self::cosine);
+        
+        // Register retrieval functions
+        stdlib.register_function("chunkBySentence", Self::chunk_by_sentence);
+        stdlib.register_function("chunkByHeading", Self::chunk_by_heading);
+        stdlib.register_function("embedBatch", Self::embed_batch);
+        
+        // Register probability functions
+        stdlib.register_function("prob", Self::prob);
+        stdlib.register_function("confidence", Self::confidence);
+        stdlib.register_function("probAnd", Self::prob_and);
+        stdlib.register_function("probOr", Self::prob_or);
         
         // Register context functions
         stdlib.register_function("currentContext", Self::current_context);
@@ -65,15 +128,34 @@ impl StdLib {
         self.functions.insert(name.to_string(), function);
     }
     
+    /// Register a natural language function in the standard library
+    pub fn register_nlp_function(&mut self, name: &str, function: NlpFunction) {
+        self.nlp_functions.insert(name.to_string(), function);
+    }
+    
     /// Get a function from the standard library
     pub fn get_function(&self, name: &str) -> Option<&StdLibFunction> {
         self.functions.get(name)
     }
     
+    /// Check if the standard library has a function
+    pub fn has_function(&self, name: &str) -> bool {
+        self.functions.contains_key(name) || self.nlp_functions.contains_key(name)
+    }
+    
+    /// Get the names of the functions in the standard library
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.functions.keys().chain(self.nlp_functions.keys())
+    }
+    
     /// Call a function from the standard library
     pub fn call_function(&self, name: &str, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        CancellationToken::check_current()?;
+        
         if let Some(function) = self.functions.get(name) {
             function(arguments)
+        } else if let Some(function) = self.nlp_functions.get(name) {
+            function(&self.nlp, arguments)
         } else {
             Err(RuntimeError::new(
                 &format!("Unknown function: '{}'", name),
@@ -269,6 +351,8 @@ impl StdLib {
                 let mut result = Vec::new();
                 
                 for item in items {
+                    CancellationToken::check_current()?;
+                    
                     match function {
                         Value::Function(name) => {
                             // Create a new standard library to call the function
@@ -323,6 +407,8 @@ impl StdLib {
                 let mut result = Vec::new();
                 
                 for (key, value) in map {
+                    CancellationToken::check_current()?;
+                    
                     match function {
                         Value::Function(name) => {
                             // Create a new standard library to call the function
@@ -374,6 +460,8 @@ impl StdLib {
                 let mut result = Vec::new();
                 
                 for item in items {
+                    CancellationToken::check_current()?;
+                    
                     match predicate {
                         Value::Function(name) => {
                             // Create a new standard library to call the function
@@ -434,6 +522,8 @@ impl StdLib {
                 let mut result = HashMap::new();
                 
                 for (key, value) in map {
+                    CancellationToken::check_current()?;
+                    
                     match predicate {
                         Value::Function(name) => {
                             // Create a new standard library to call the function
@@ -513,6 +603,8 @@ impl StdLib {
                 let start_index = if initial_value.is_some() { 0 } else { 1 };
                 
                 for i in start_index..items.len() {
+                    CancellationToken::check_current()?;
+                    
                     match function {
                         Value::Function(name) => {
                             // Create a new standard library to call the function
@@ -684,9 +776,9 @@ impl StdLib {
                         ));
                     }
                     
-                    Ok(Value::String(s[start_idx..end_idx].to_string()))
+                    Self::slice(s, start_idx, end_idx)
                 } else {
-                    Ok(Value::String(s[start_idx..].to_string()))
+                    Self::slice(s, start_idx, s.len())
                 }
             } else {
                 Err(RuntimeError::new(
@@ -702,6 +794,200 @@ impl StdLib {
         }
     }
     
+    /// Reverse a string or list
+    fn reverse(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        if arguments.len() != 1 {
+            return Err(RuntimeError::new(
+                "reverse() requires exactly one argument",
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        match &arguments[0] {
+            Value::String(s) => Ok(Value::String(s.chars().rev().collect())),
+            Value::List(list) => Ok(Value::List(list.iter().rev().cloned().collect())),
+            arg => Err(RuntimeError::new(
+                &format!("Cannot reverse {:?}", arg),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+        }
+    }
+    
+    /// Sort a list of numbers or strings
+    fn sort(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        if arguments.len() != 1 {
+            return Err(RuntimeError::new(
+                "sort() requires exactly one argument",
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        let Value::List(list) = &arguments[0] else {
+            return Err(RuntimeError::new(
+                &format!("Cannot sort {:?}", arguments[0]),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        };
+        
+        let mut sorted = list.clone();
+        
+        if list.iter().all(|item| matches!(item, Value::String(_))) {
+            sorted.sort_by(|a, b| Self::value_to_string(a).cmp(&Self::value_to_string(b)));
+        } else {
+            let numbers = Self::numbers(list, "sort")?;
+            let mut order: Vec<usize> = (0..numbers.len()).collect();
+            
+            order.sort_by(|a, b| numbers[*a].total_cmp(&numbers[*b]));
+            sorted = order.into_iter().map(|i| list[i].clone()).collect();
+        }
+        
+        Ok(Value::List(sorted))
+    }
+    
+    /// Get the first item of a list
+    fn first(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        Self::list_item(arguments, "first", |list| list.first())
+    }
+    
+    /// Get the last item of a list
+    fn last(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        Self::list_item(arguments, "last", |list| list.last())
+    }
+    
+    /// Get an item of a non-empty list
+    fn list_item(
+        arguments: Vec<Value>,
+        name: &str,
+        item: fn(&[Value]) -> Option<&Value>,
+    ) -> Result<Value, RuntimeError> {
+        if arguments.len() != 1 {
+            return Err(RuntimeError::new(
+                &format!("{}() requires exactly one argument", name),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        match &arguments[0] {
+            Value::List(list) => item(list).cloned().ok_or_else(|| {
+                RuntimeError::new(
+                    &format!("{}() requires a non-empty list", name),
+                    SourceLocation::new(0, 0, 0, 0, ""),
+                )
+            }),
+            arg => Err(RuntimeError::new(
+                &format!("{}() requires a list, got {:?}", name, arg),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+        }
+    }
+    
+    /// Add up a list of numbers
+    ///
+    /// The sum is an `Int` if every item is an `Int`, and a `Float` otherwise.
+    fn sum(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        if arguments.len() != 1 {
+            return Err(RuntimeError::new(
+                "sum() requires exactly one argument",
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        let Value::List(list) = &arguments[0] else {
+            return Err(RuntimeError::new(
+                &format!("sum() requires a list, got {:?}", arguments[0]),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        };
+        
+        if list.iter().all(|item| matches!(item, Value::Int(_))) {
+            let mut total: i64 = 0;
+            
+            for item in list {
+                if let Value::Int(i) = item {
+                    total = total.checked_add(*i).ok_or_else(|| {
+                        RuntimeError::new("Integer overflow in sum()", SourceLocation::new(0, 0, 0, 0, ""))
+                    })?;
+                }
+            }
+            
+            Ok(Value::Int(total))
+        } else {
+            Ok(Value::Float(Self::numbers(list, "sum")?.iter().sum()))
+        }
+    }
+    
+    /// Get the largest number in a list
+    fn max(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        Self::extreme(arguments, "max", |a, b| a > b)
+    }
+    
+    /// Get the smallest number in a list
+    fn min(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        Self::extreme(arguments, "min", |a, b| a < b)
+    }
+    
+    /// Get the number of a non-empty list that is preferred over all others
+    fn extreme(
+        arguments: Vec<Value>,
+        name: &str,
+        preferred: fn(f64, f64) -> bool,
+    ) -> Result<Value, RuntimeError> {
+        if arguments.len() != 1 {
+            return Err(RuntimeError::new(
+                &format!("{}() requires exactly one argument", name),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        let Value::List(list) = &arguments[0] else {
+            return Err(RuntimeError::new(
+                &format!("{}() requires a list, got {:?}", name, arguments[0]),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        };
+        
+        let numbers = Self::numbers(list, name)?;
+        
+        if numbers.is_empty() {
+            return Err(RuntimeError::new(
+                &format!("{}() requires a non-empty list", name),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        let mut best = 0;
+        
+        for (i, number) in numbers.iter().enumerate() {
+            if preferred(*number, numbers[best]) {
+                best = i;
+            }
+        }
+        
+        Ok(list[best].clone())
+    }
+    
+    /// Convert the items of a list to numbers
+    fn numbers(list: &[Value], name: &str) -> Result<Vec<f64>, RuntimeError> {
+        list.iter().map(|item| match item {
+            Value::Int(i) => Ok(*i as f64),
+            Value::Float(f) => Ok(*f),
+            _ => Err(RuntimeError::new(
+                &format!("{}() requires a list of numbers, got {:?}", name, item),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+        }).collect()
+    }
+    
+    /// Get the part of a string between two byte indices
+    fn slice(s: &str, start: usize, end: usize) -> Result<Value, RuntimeError> {
+        s.get(start..end).map(|part| Value::String(part.to_string())).ok_or_else(|| {
+            RuntimeError::new(
+                &format!("Indices {}..{} are not at character boundaries", start, end),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )
+        })
+    }
+    
     /// Find the index of a substring
     fn index_of(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
         if arguments.len() != 2 {
@@ -734,6 +1020,50 @@ impl StdLib {
         }
     }
     
+    /// Split a string at each occurrence of a separator
+    fn split(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        if arguments.len() != 2 {
+            return Err(RuntimeError::new(
+                "split() requires exactly two arguments",
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        match (&arguments[0], &arguments[1]) {
+            (Value::String(_), Value::String(separator)) if separator.is_empty() => Err(RuntimeError::new(
+                "split() requires a non-empty separator",
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+            (Value::String(s), Value::String(separator)) => Ok(Value::List(
+                s.split(separator.as_str()).map(|part| Value::String(part.to_string())).collect()
+            )),
+            (string, separator) => Err(RuntimeError::new(
+                &format!("Cannot split {:?} by {:?}", string, separator),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+        }
+    }
+    
+    /// Join the items of a list with a separator
+    fn join(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        if arguments.len() != 2 {
+            return Err(RuntimeError::new(
+                "join() requires exactly two arguments",
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        match (&arguments[0], &arguments[1]) {
+            (Value::List(list), Value::String(separator)) => Ok(Value::String(
+                list.iter().map(Self::value_to_string).collect::<Vec<_>>().join(separator)
+            )),
+            (list, separator) => Err(RuntimeError::new(
+                &format!("Cannot join {:?} with {:?}", list, separator),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+        }
+    }
+    
     /// Convert a string to lowercase
     fn to_lower_case(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
         if arguments.len() != 1 {
@@ -797,6 +1127,93 @@ impl StdLib {
         }
     }
     
+    /// Get the text argument of a natural language function that takes one string
+    fn text_argument(name: &str, arguments: Vec<Value>) -> Result<String, RuntimeError> {
+        match <[Value; 1]>::try_from(arguments) {
+            Ok([Value::String(text)]) => Ok(text),
+            Ok([other]) => Err(RuntimeError::new(
+                &format!("{}() expects a string, got {:?}", name, other),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+            Err(_) => Err(RuntimeError::new(
+                &format!("{}() requires exactly one argument", name),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+        }
+    }
+    
+    /// Get the two text arguments of a natural language function that takes two strings
+    fn text_arguments(name: &str, arguments: Vec<Value>, usage: &str) -> Result<(String, String), RuntimeError> {
+        match <[Value; 2]>::try_from(arguments) {
+            Ok([Value::String(first), Value::String(second)]) => Ok((first, second)),
+            Ok([first, second]) => Err(RuntimeError::new(
+                &format!("{}() expects two strings, got {:?} and {:?}", name, first, second),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+            Err(_) => Err(RuntimeError::new(
+                &format!("{}() requires exactly two arguments: {}", name, usage),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+        }
+    }
+    
+    /// Split text into word and punctuation tokens
+    ///
+    /// Like the other natural language functions, this is sent to the NLP
+    /// backend of the standard library, and the mock backend used offline
+    /// answers it with a local implementation.
+    fn tokenize(nlp: &NLP, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        nlp.tokenize(&Self::text_argument("tokenize", arguments)?)
+    }
+    
+    /// Split text into sentences
+    fn split_sentences(nlp: &NLP, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        nlp.split_sentences(&Self::text_argument("splitSentences", arguments)?)
+    }
+    
+    /// Reduce a word to its stem with the Porter stemming algorithm
+    fn stemming(nlp: &NLP, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        nlp.stem(&Self::text_argument("stemming", arguments)?)
+    }
+    
+    /// Reduce a word to its dictionary form
+    fn lemmatize(nlp: &NLP, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        nlp.lemmatize(&Self::text_argument("lemmatize", arguments)?)
+    }
+    
+    /// Extract the keywords of text, as maps with the `keyword` and its `score`
+    fn extract_keywords(nlp: &NLP, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let text = Self::text_argument("extractKeywords", arguments)?;
+        nlp.extract_keywords(&text, crate::runtime::text::DEFAULT_KEYWORD_COUNT)
+    }
+    
+    /// Rate the sentiment of text, as a map with the `label` and the `score` between -1 and 1
+    fn extract_sentiment(nlp: &NLP, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        nlp.analyze_sentiment(&Self::text_argument("extractSentiment", arguments)?)
+    }
+    
+    /// Extract the named entities, dates and numbers of text
+    fn extract_entities(nlp: &NLP, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        nlp.extract_entities(&Self::text_argument("extractEntities", arguments)?)
+    }
+    
+    /// Summarize text
+    fn extract_summary(nlp: &NLP, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        nlp.summarize_text(&Self::text_argument("extractSummary", arguments)?)
+    }
+    
+    /// Translate text into the language with a language code
+    fn translate_text(nlp: &NLP, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let (text, language) = Self::text_arguments("translateText", arguments, "text and language")?;
+        nlp.translate_text(&text, &language)
+    }
+    
+    /// Answer a question from a context, or from general knowledge if it is empty
+    fn answer_question(nlp: &NLP, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let (question, context) = Self::text_arguments("answerQuestion", arguments, "question and context")?;
+        nlp.answer_question(&question, &context)
+    }
+    
     /// Convert text to a semantic vector embedding
     fn embed(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
         if arguments.len() != 1 {
@@ -809,9 +1226,8 @@ impl StdLib {
         let text = &arguments[0];
         
         if let Value::String(s) = text {
-            // This is a placeholder implementation
-            // In a real implementation, this would use a vector embedding model
-            let vector = vec![0.1, 0.2, 0.3, 0.4, 0.5];
+            // Use the embedding provider of the running engine
+            let vector = Vector::current().embed(s)?;
             Ok(Value::Vector(vector))
         } else {
             Err(RuntimeError::new(
@@ -845,6 +1261,107 @@ impl StdLib {
         }
     }
     
+    /// Combine concepts into a single normalized vector
+    ///
+    /// Concepts can be strings, which are embedded, or vectors. Without
+    /// weights, every concept contributes equally.
+    fn combine_concepts(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        if arguments.is_empty() || arguments.len() > 2 {
+            return Err(RuntimeError::new(
+                "combineConcepts() requires one or two arguments: concepts and optional weights",
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        let vector = Vector::current();
+        
+        let concepts = match &arguments[0] {
+            Value::List(concepts) => concepts,
+            other => return Err(RuntimeError::new(
+                &format!("First argument to combineConcepts() must be a list, got {:?}", other),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+        };
+        
+        let mut vectors = Vec::new();
+        for concept in concepts {
+            match concept {
+                Value::String(text) => vectors.push(vector.embed(text)?),
+                Value::Vector(v) => vectors.push(v.clone()),
+                other => return Err(RuntimeError::new(
+                    &format!("Concepts must be strings or vectors, got {:?}", other),
+                    SourceLocation::new(0, 0, 0, 0, ""),
+                )),
+            }
+        }
+        
+        let weights = match arguments.get(1) {
+            None | Some(Value::Void) => None,
+            Some(Value::List(weights)) => {
+                let mut result = Vec::new();
+                for weight in weights {
+                    match weight {
+                        Value::Int(n) => result.push(*n as f64),
+                        Value::Float(f) => result.push(*f),
+                        other => return Err(RuntimeError::new(
+                            &format!("Weights must be numbers, got {:?}", other),
+                            SourceLocation::new(0, 0, 0, 0, ""),
+                        )),
+                    }
+                }
+                Some(result)
+            }
+            Some(other) => return Err(RuntimeError::new(
+                &format!("Second argument to combineConcepts() must be a list, got {:?}", other),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+        };
+        
+        Ok(Value::Vector(vector.combine_vectors(&vectors, weights.as_deref())?))
+    }
+    
+    /// Scale a vector to unit length
+    fn normalize(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        if arguments.len() != 1 {
+            return Err(RuntimeError::new(
+                "normalize() requires exactly one argument",
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        if let Value::Vector(v) = &arguments[0] {
+            Ok(Value::Vector(Vector::normalize(v)))
+        } else {
+            Err(RuntimeError::new(
+                &format!("Cannot normalize {:?}", arguments[0]),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ))
+        }
+    }
+    
+    /// Calculate the cosine similarity between two vectors, between -1.0 and 1.0
+    fn cosine(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        if arguments.len() != 2 {
+            return Err(RuntimeError::new(
+                "cosine() requires exactly two arguments",
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        if let (Value::Vector(a), Value::Vector(b)) = (&arguments[0], &arguments[1]) {
+            if a.len() != b.len() {
+                return Err(RuntimeError::dimension_mismatch(a.len(), b.len(), SourceLocation::new(0, 0, 0, 0, "")));
+            }
+            
+            Ok(Value::Float(Self::calculate_cosine_similarity(a, b)))
+        } else {
+            Err(RuntimeError::new(
+                &format!("Cannot calculate cosine similarity between {:?} and {:?}", arguments[0], arguments[1]),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ))
+        }
+    }
+    
     /// Find the nearest vectors to a given vector
     fn nearest(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
         if arguments.len() < 2 || arguments.len() > 3 {
@@ -893,6 +1410,8 @@ impl StdLib {
         let mut similarities = Vec::new();
         
         for (i, item) in vector_list.iter().enumerate() {
+            CancellationToken::check_current()?;
+            
             if let Value::Vector(v) = item {
                 // Calculate cosine similarity
                 let similarity = Self::calculate_cosine_similarity(query_vector, v);
@@ -905,12 +1424,9 @@ impl StdLib {
             }
         }
         
-        // Sort by similarity (highest first)
-        similarities.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-        
-        // Take the top 'count' items
+        // Take the top 'count' items (highest similarity first)
         let mut result = Vec::new();
-        for (i, _) in similarities.into_iter().take(count) {
+        for (i, _) in crate::runtime::index::top_k(similarities, count) {
             result.push(vector_list[i].clone());
         }
         
@@ -945,6 +1461,188 @@ impl StdLib {
         }
     }
     
+    /// Split text into chunks of a number of sentences each
+    ///
+    /// Each chunk is a map with its `text` and the byte offsets of the text
+    /// in the document as `start` and `end`.
+    fn chunk_by_sentence(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        match arguments.as_slice() {
+            [Value::String(text), Value::Int(sentences)] if *sentences > 0 => {
+                let chunks = rag::chunk_by_sentence(text, *sentences as usize);
+                Ok(Value::List(chunks.iter().map(rag::Chunk::to_value).collect()))
+            }
+            [text, sentences] => Err(RuntimeError::new(
+                &format!("chunkBySentence() expects a string and a positive number of sentences, got {:?} and {:?}", text, sentences),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+            _ => Err(RuntimeError::new(
+                "chunkBySentence() requires exactly two arguments: text and sentences",
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+        }
+    }
+    
+    /// Split Markdown text into chunks by heading
+    ///
+    /// Each chunk is a map like those of `chunkBySentence`, with the text of
+    /// its heading as `heading`.
+    fn chunk_by_heading(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        match arguments.as_slice() {
+            [Value::String(text)] => {
+                let chunks = rag::chunk_by_heading(text);
+                Ok(Value::List(chunks.iter().map(rag::Chunk::to_value).collect()))
+            }
+            [text] => Err(RuntimeError::new(
+                &format!("Cannot chunk {:?}", text),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+            _ => Err(RuntimeError::new(
+                "chunkByHeading() requires exactly one argument",
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+        }
+    }
+    
+    /// Convert a list of texts to semantic vector embeddings in one batch
+    fn embed_batch(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let texts = match arguments.as_slice() {
+            [Value::List(texts)] => texts,
+            [other] => return Err(RuntimeError::new(
+                &format!("embedBatch() expects a list of strings, got {:?}", other),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+            _ => return Err(RuntimeError::new(
+                "embedBatch() requires exactly one argument",
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+        };
+        
+        // Chunks are embedded by their text
+        let mut batch = Vec::new();
+        for text in texts {
+            match text {
+                Value::String(text) => batch.push(text.as_str()),
+                Value::Map(chunk) => match chunk.get("text") {
+                    Some(Value::String(text)) => batch.push(text.as_str()),
+                    _ => return Err(RuntimeError::new(
+                        &format!("Cannot embed {:?}", chunk),
+                        SourceLocation::new(0, 0, 0, 0, ""),
+                    )),
+                },
+                other => return Err(RuntimeError::new(
+                    &format!("Cannot embed {:?}", other),
+                    SourceLocation::new(0, 0, 0, 0, ""),
+                )),
+            }
+        }
+        
+        let vectors = Vector::current().embed_batch(&batch)?;
+        Ok(Value::List(vectors.into_iter().map(Value::Vector).collect()))
+    }
+    
+    /// Create a probabilistic value
+    fn prob(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        if arguments.len() != 2 {
+            return Err(RuntimeError::new(
+                "prob() requires exactly two arguments: value and confidence",
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        let confidence = match &arguments[1] {
+            Value::Int(n) => *n as f64,
+            Value::Float(f) => *f,
+            other => return Err(RuntimeError::new(
+                &format!("Confidence must be a number, got {:?}", other),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            )),
+        };
+        
+        if !(0.0..=1.0).contains(&confidence) {
+            return Err(RuntimeError::new(
+                &format!("Confidence must be between 0.0 and 1.0, got {}", confidence),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        let mut arguments = arguments;
+        Ok(Value::prob(arguments.swap_remove(0), confidence, Vec::new()))
+    }
+    
+    /// Get the confidence in a value, which is 1.0 unless it is probabilistic
+    fn confidence(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        if arguments.len() != 1 {
+            return Err(RuntimeError::new(
+                "confidence() requires exactly one argument",
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        Ok(Value::Float(arguments[0].confidence()))
+    }
+    
+    /// Combine probabilistic values that must all be correct
+    ///
+    /// The result is the list of the most likely values, as likely as all of
+    /// them together if they are independent.
+    fn prob_and(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let values = Self::probabilities("probAnd", arguments)?;
+        
+        let confidence = values.iter().map(Value::confidence).product();
+        let values = values.into_iter().map(Value::into_most_likely).collect();
+        
+        Ok(Value::prob(Value::List(values), confidence, Vec::new()))
+    }
+    
+    /// Combine probabilistic values that are independent evidence for their values
+    ///
+    /// Values that agree support each other, so the confidence in each distinct
+    /// value is the chance that at least one of them is correct. The result is
+    /// the most likely value, with the others as alternatives.
+    fn prob_or(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let values = Self::probabilities("probOr", arguments)?;
+        
+        // The chance that all values supporting a distinct value are wrong
+        let mut doubts: Vec<(Value, f64)> = Vec::new();
+        
+        for value in values {
+            let confidence = value.confidence();
+            let value = value.into_most_likely();
+            
+            match doubts.iter_mut().find(|(other, _)| *other == value) {
+                Some((_, doubt)) => *doubt *= 1.0 - confidence,
+                None => doubts.push((value, 1.0 - confidence)),
+            }
+        }
+        
+        let mut ranked: Vec<(Value, f64)> = doubts.into_iter().map(|(value, doubt)| (value, 1.0 - doubt)).collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        
+        let (value, confidence) = ranked.remove(0);
+        
+        Ok(Value::prob(value, confidence, ranked))
+    }
+    
+    /// Get the values combined by a probability function
+    ///
+    /// The values are either the arguments or the items of a single list argument.
+    fn probabilities(name: &str, arguments: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+        let values = match <[Value; 1]>::try_from(arguments) {
+            Ok([Value::List(items)]) => items,
+            Ok([value]) => vec![value],
+            Err(arguments) => arguments,
+        };
+        
+        if values.is_empty() {
+            return Err(RuntimeError::new(
+                &format!("{}() requires at least one value", name),
+                SourceLocation::new(0, 0, 0, 0, ""),
+            ));
+        }
+        
+        Ok(values)
+    }
+    
     /// Get the current context
     fn current_context(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
         if !arguments.is_empty() {
@@ -1026,7 +1724,7 @@ impl StdLib {
     }
     
     /// Convert a value to a string
-    fn value_to_string(value: &Value) -> String {
+    pub fn value_to_string(value: &Value) -> String {
         match value {
             Value::Void => "void".to_string(),
             Value::Bool(b) => b.to_string(),
@@ -1047,6 +1745,7 @@ impl StdLib {
             Value::Function(name) => format!("<function {}>", name),
             Value::Vector(v) => format!("<vector with {} dimensions>", v.len()),
             Value::Context(name) => format!("<context {}>", name),
+            Value::Prob { value, .. } => Self::value_to_string(value),
         }
     }
 }
@@ -1069,14 +1768,28 @@ mod tests {
         assert!(stdlib.get_function("map").is_some());
         assert!(stdlib.get_function("filter").is_some());
         assert!(stdlib.get_function("reduce").is_some());
+        assert!(stdlib.get_function("reverse").is_some());
+        assert!(stdlib.get_function("sort").is_some());
+        assert!(stdlib.get_function("first").is_some());
+        assert!(stdlib.get_function("last").is_some());
+        assert!(stdlib.get_function("sum").is_some());
+        assert!(stdlib.get_function("max").is_some());
+        assert!(stdlib.get_function("min").is_some());
         assert!(stdlib.get_function("substring").is_some());
         assert!(stdlib.get_function("indexOf").is_some());
         assert!(stdlib.get_function("toLowerCase").is_some());
         assert!(stdlib.get_function("toUpperCase").is_some());
         assert!(stdlib.get_function("trim").is_some());
+        assert!(stdlib.get_function("split").is_some());
+        assert!(stdlib.get_function("join").is_some());
         assert!(stdlib.get_function("embed").is_some());
         assert!(stdlib.get_function("similarity").is_some());
         assert!(stdlib.get_function("nearest").is_some());
+        assert!(stdlib.get_function("combineConcepts").is_some());
+        assert!(stdlib.get_function("normalize").is_some());
+        assert!(stdlib.get_function("cosine").is_some());
+        assert!(stdlib.get_function("prob").is_some());
+        assert!(stdlib.get_function("probOr").is_some());
         assert!(stdlib.get_function("currentContext").is_some());
         assert!(stdlib.get_function("switchContext").is_some());
         assert!(stdlib.get_function("mergeContexts").is_some());
@@ -1229,6 +1942,65 @@ mod tests {
         assert_eq!(result, Value::String("hello".to_string()));
     }
     
+    #[test]
+    fn test_stdlib_split_and_join() {
+        let stdlib = StdLib::new();
+        
+        let parts = stdlib.call_function(
+            "split",
+            vec![Value::String("a,b,c".to_string()), Value::String(",".to_string())]
+        ).unwrap();
+        
+        assert_eq!(parts, Value::List(vec![
+            Value::String("a".to_string()),
+            Value::String("b".to_string()),
+            Value::String("c".to_string()),
+        ]));
+        
+        let result = stdlib.call_function("join", vec![parts, Value::String("-".to_string())]).unwrap();
+        
+        assert_eq!(result, Value::String("a-b-c".to_string()));
+        
+        let error = stdlib.call_function(
+            "split",
+            vec![Value::String("abc".to_string()), Value::String("".to_string())]
+        ).unwrap_err();
+        
+        assert_eq!(error.message, "split() requires a non-empty separator");
+    }
+    
+    #[test]
+    fn test_stdlib_list_functions() {
+        let stdlib = StdLib::new();
+        let list = Value::List(vec![Value::Int(3), Value::Int(1), Value::Int(2)]);
+        
+        assert_eq!(
+            stdlib.call_function("sort", vec![list.clone()]).unwrap(),
+            Value::List(vec![Value::Int(1), Value::Int(2), Value::Int(3)])
+        );
+        assert_eq!(
+            stdlib.call_function("reverse", vec![list.clone()]).unwrap(),
+            Value::List(vec![Value::Int(2), Value::Int(1), Value::Int(3)])
+        );
+        assert_eq!(
+            stdlib.call_function("reverse", vec![Value::String("abc".to_string())]).unwrap(),
+            Value::String("cba".to_string())
+        );
+        assert_eq!(stdlib.call_function("first", vec![list.clone()]).unwrap(), Value::Int(3));
+        assert_eq!(stdlib.call_function("last", vec![list.clone()]).unwrap(), Value::Int(2));
+        assert_eq!(stdlib.call_function("sum", vec![list.clone()]).unwrap(), Value::Int(6));
+        assert_eq!(stdlib.call_function("max", vec![list.clone()]).unwrap(), Value::Int(3));
+        assert_eq!(stdlib.call_function("min", vec![list]).unwrap(), Value::Int(1));
+        
+        assert_eq!(
+            stdlib.call_function("sum", vec![Value::List(vec![Value::Int(1), Value::Float(0.5)])]).unwrap(),
+            Value::Float(1.5)
+        );
+        
+        let error = stdlib.call_function("first", vec![Value::List(Vec::new())]).unwrap_err();
+        assert_eq!(error.message, "first() requires a non-empty list");
+    }
+    
     #[test]
     fn test_stdlib_embed() {
         let stdlib = StdLib::new();
@@ -1239,12 +2011,64 @@ mod tests {
         ).unwrap();
         
         if let Value::Vector(v) = result {
-            assert_eq!(v.len(), 5);
+            assert_eq!(v.len(), crate::runtime::vector::DEFAULT_EMBEDDING_DIMENSION);
         } else {
             panic!("Expected Vector, got {:?}", result);
         }
     }
     
+    #[test]
+    fn test_stdlib_chunk_and_embed_batch() {
+        let stdlib = StdLib::new();
+        let text = "# Cats\nCats sleep. Cats purr.\n# Dogs\nDogs bark.";
+        
+        let result = stdlib.call_function("chunkBySentence", vec![Value::String(text.to_string()), Value::Int(2)]).unwrap();
+        let Value::List(chunks) = result else { panic!("Expected List, got {:?}", result) };
+        assert_eq!(chunks.len(), 2);
+        
+        let result = stdlib.call_function("chunkByHeading", vec![Value::String(text.to_string())]).unwrap();
+        let Value::List(sections) = result else { panic!("Expected List, got {:?}", result) };
+        let Value::Map(dogs) = &sections[1] else { panic!("Expected Map, got {:?}", sections[1]) };
+        assert_eq!(dogs["heading"], Value::String("Dogs".to_string()));
+        assert_eq!(dogs["start"], Value::Int(30));
+        
+        let result = stdlib.call_function("embedBatch", vec![Value::List(sections.clone())]).unwrap();
+        let vector = Vector::current();
+        assert_eq!(result, Value::List(vec![
+            Value::Vector(vector.embed("# Cats\nCats sleep. Cats purr.").unwrap()),
+            Value::Vector(vector.embed("# Dogs\nDogs bark.").unwrap()),
+        ]));
+        
+        assert!(stdlib.call_function("chunkBySentence", vec![Value::String(text.to_string()), Value::Int(0)]).is_err());
+    }
+    
+    #[test]
+    fn test_stdlib_natural_language() {
+        let stdlib = StdLib::new();
+        let text = |s: &str| vec![Value::String(s.to_string())];
+        
+        let result = stdlib.call_function("tokenize", text("Hello, world!")).unwrap();
+        assert_eq!(result, Value::List(["Hello", ",", "world", "!"].iter().map(|t| Value::String(t.to_string())).collect()));
+        
+        assert_eq!(stdlib.call_function("stemming", text("generalization")).unwrap(), Value::String("gener".to_string()));
+        assert_eq!(stdlib.call_function("lemmatize", text("studied")).unwrap(), Value::String("study".to_string()));
+        
+        let result = stdlib.call_function("extractSentiment", text("I love it, it works great")).unwrap();
+        let Value::Map(sentiment) = result else { panic!("Expected Map, got {:?}", result) };
+        assert_eq!(sentiment["label"], Value::String("positive".to_string()));
+        
+        let result = stdlib.call_function("translateText", vec![Value::String("Hello".to_string()), Value::String("fr".to_string())]).unwrap();
+        assert_eq!(result, Value::String("Bonjour".to_string()));
+        
+        let error = stdlib.call_function("stemming", vec![Value::Int(1)]).unwrap_err();
+        assert_eq!(error.message, "stemming() expects a string, got Int(1)");
+        
+        // The natural language functions are sent to the processor of the standard library
+        let backend = crate::runtime::testing::TestBackend::new().on_chat(|_| Ok("[\"from\", \"backend\"]".to_string()));
+        let stdlib = StdLib::with_nlp(NLP::with_backend(std::sync::Arc::new(backend)));
+        assert_eq!(stdlib.call_function("tokenize", text("Hello")).unwrap(), Value::List(vec![Value::String("from".to_string()), Value::String("backend".to_string())]));
+    }
+    
     #[test]
     fn test_stdlib_similarity() {
         let stdlib = StdLib::new();
@@ -1264,6 +2088,78 @@ mod tests {
         }
     }
     
+    #[test]
+    fn test_stdlib_vector_helpers() {
+        let stdlib = StdLib::new();
+        
+        let result = stdlib.call_function("normalize", vec![Value::Vector(vec![3.0, 4.0])]).unwrap();
+        assert_eq!(result, Value::Vector(vec![0.6, 0.8]));
+        
+        let result = stdlib.call_function(
+            "cosine",
+            vec![Value::Vector(vec![1.0, 0.0]), Value::Vector(vec![-1.0, 0.0])]
+        ).unwrap();
+        assert_eq!(result, Value::Float(-1.0));
+        
+        let error = stdlib.call_function(
+            "cosine",
+            vec![Value::Vector(vec![1.0, 0.0]), Value::Vector(vec![1.0])]
+        ).unwrap_err();
+        assert_eq!(error.message, "Vector dimension mismatch: expected 2, got 1");
+        
+        let result = stdlib.call_function(
+            "combineConcepts",
+            vec![
+                Value::List(vec![Value::Vector(vec![1.0, 0.0]), Value::Vector(vec![0.0, 1.0])]),
+                Value::List(vec![Value::Float(3.0), Value::Int(4)]),
+            ]
+        ).unwrap();
+        assert_eq!(result, Value::Vector(vec![0.6, 0.8]));
+        
+        let result = stdlib.call_function(
+            "combineConcepts",
+            vec![Value::List(vec![Value::String("cat".to_string()), Value::String("dog".to_string())])]
+        ).unwrap();
+        assert!(matches!(result, Value::Vector(v) if v.len() == crate::runtime::vector::DEFAULT_EMBEDDING_DIMENSION));
+    }
+    
+    #[test]
+    fn test_stdlib_probability_functions() {
+        let stdlib = StdLib::new();
+        
+        let likely = stdlib.call_function("prob", vec![Value::String("cat".to_string()), Value::Float(0.8)]).unwrap();
+        let unlikely = stdlib.call_function("prob", vec![Value::String("cat".to_string()), Value::Float(0.5)]).unwrap();
+        let other = stdlib.call_function("prob", vec![Value::String("dog".to_string()), Value::Float(0.6)]).unwrap();
+        
+        assert_eq!(stdlib.call_function("confidence", vec![likely.clone()]).unwrap(), Value::Float(0.8));
+        assert_eq!(stdlib.call_function("confidence", vec![Value::Int(1)]).unwrap(), Value::Float(1.0));
+        
+        let error = stdlib.call_function("prob", vec![Value::Int(1), Value::Float(1.5)]).unwrap_err();
+        assert_eq!(error.message, "Confidence must be between 0.0 and 1.0, got 1.5");
+        
+        // Both values must be correct
+        let result = stdlib.call_function("probAnd", vec![likely.clone(), other.clone()]).unwrap();
+        assert_eq!(result.most_likely(), &Value::List(vec![Value::String("cat".to_string()), Value::String("dog".to_string())]));
+        assert!((result.confidence() - 0.48).abs() < 1e-9);
+        
+        // Agreeing values support each other
+        let result = stdlib.call_function("probOr", vec![Value::List(vec![likely, unlikely, other])]).unwrap();
+        assert_eq!(result.most_likely(), &Value::String("cat".to_string()));
+        assert!((result.confidence() - 0.9).abs() < 1e-9);
+        
+        match result {
+            Value::Prob { alternatives, .. } => {
+                assert_eq!(alternatives.len(), 1);
+                assert_eq!(alternatives[0].0, Value::String("dog".to_string()));
+                assert!((alternatives[0].1 - 0.6).abs() < 1e-9);
+            }
+            other => panic!("Expected a probabilistic value, got {:?}", other),
+        }
+        
+        let error = stdlib.call_function("probOr", vec![]).unwrap_err();
+        assert_eq!(error.message, "probOr() requires at least one value");
+    }
+    
     #[test]
     fn test_stdlib_current_context() {
         let stdlib = StdLib::new();
@@ -1310,5 +2206,6 @@ mod tests {
         assert_eq!(StdLib::value_to_string(&Value::Function("test".to_string())), "<function test>");
         assert_eq!(StdLib::value_to_string(&Value::Vector(vec![0.1, 0.2, 0.3])), "<vector with 3 dimensions>");
         assert_eq!(StdLib::value_to_string(&Value::Context("TestContext".to_string())), "<context TestContext>");
+        assert_eq!(StdLib::value_to_string(&Value::prob(Value::Int(42), 0.9, Vec::new())), "42");
     }
 }
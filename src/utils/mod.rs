@@ -3,7 +3,7 @@
 //! This module provides utility functions and types for the LLM.lang programming language.
 
 /// A source location in a file
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct SourceLocation {
     /// The start line
     pub start_line: usize,
@@ -197,6 +197,20 @@ impl SourceCode {
     }
 }
 
+/// Hash text with 64-bit FNV-1a, as 16 hexadecimal digits
+///
+/// The hash is stable across runs and platforms, unlike the standard library's.
+pub fn stable_hash(text: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    
+    format!("{:016x}", hash)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -336,4 +350,11 @@ mod tests {
         assert_eq!(location.end_column, 6);
         assert_eq!(location.file, "test.llm");
     }
+    
+    #[test]
+    fn test_stable_hash() {
+        assert_eq!(stable_hash(""), "cbf29ce484222325");
+        assert_eq!(stable_hash("prompt"), stable_hash("prompt"));
+        assert_ne!(stable_hash("prompt"), stable_hash("prompts"));
+    }
 }
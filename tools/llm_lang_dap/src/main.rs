@@ -211,6 +211,7 @@ impl Debugger {
             vectors: true,
             nlp: true,
             self_modifying: true,
+            ..Default::default()
         };
         
         let mut engine = llm_lang::runtime::engine::Engine::new(options);
@@ -737,6 +738,7 @@ impl DebugServer {
                             llm_lang::Value::Vector(_) => "Vector",
                             llm_lang::Value::Function(_) => "Function",
                             llm_lang::Value::Context(_) => "Context",
+                            llm_lang::Value::Prob { .. } => "Prob",
                         },
                         "variablesReference": 0,
                     })
@@ -784,6 +786,7 @@ impl DebugServer {
                                     llm_lang::Value::Vector(_) => "Vector",
                                     llm_lang::Value::Function(_) => "Function",
                                     llm_lang::Value::Context(_) => "Context",
+                                    llm_lang::Value::Prob { .. } => "Prob",
                                 },
                                 "variablesReference": 0,
                             })),